@@ -0,0 +1,143 @@
+use std::net::IpAddr;
+
+use adns_proto::{Class, Name, Question, ResponseCode, Type, TypeData};
+use tokio::net::ToSocketAddrs;
+
+use crate::{recursive::MAX_CNAME_CHAIN, DnsClient, DnsQueryError};
+
+impl DnsClient {
+    /// the A and AAAA addresses of `name` from the resolver at `server`. CNAMEs the server didn't follow within
+    /// its response are queried for again, up to a limit. a name that doesn't exist has no addresses.
+    pub async fn resolve_addresses(
+        &mut self,
+        server: impl ToSocketAddrs + Clone,
+        name: Name,
+    ) -> Result<Vec<IpAddr>, DnsQueryError> {
+        let mut out = vec![];
+        for type_ in [Type::A, Type::AAAA] {
+            let mut target = name.clone();
+            let mut hops = 0;
+            'queries: loop {
+                let response = self
+                    .query(
+                        server.clone(),
+                        vec![Question {
+                            name: target.clone(),
+                            type_,
+                            class: Class::IN,
+                        }],
+                    )
+                    .await?;
+                match response.header.response_code {
+                    ResponseCode::NoError => (),
+                    ResponseCode::NameError => break,
+                    code => return Err(DnsQueryError::ResponseCode(code)),
+                }
+                let queried = target.clone();
+                loop {
+                    let addresses: Vec<IpAddr> = response
+                        .answers
+                        .iter()
+                        .filter(|x| x.name == target)
+                        .filter_map(|x| match &x.data {
+                            TypeData::A(ip) if type_ == Type::A => Some((*ip).into()),
+                            TypeData::AAAA(ip) if type_ == Type::AAAA => Some((*ip).into()),
+                            _ => None,
+                        })
+                        .collect();
+                    if !addresses.is_empty() {
+                        out.extend(addresses);
+                        break 'queries;
+                    }
+                    let Some(next) = response.answers.iter().find_map(|x| match &x.data {
+                        TypeData::CNAME(next) if x.name == target => Some(next.clone()),
+                        _ => None,
+                    }) else {
+                        break;
+                    };
+                    hops += 1;
+                    if hops > MAX_CNAME_CHAIN {
+                        return Err(DnsQueryError::ResolutionLimit);
+                    }
+                    target = next;
+                }
+                // NODATA, unless the chain left the response
+                if target == queried {
+                    break;
+                }
+            }
+        }
+        out.dedup();
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use adns_proto::{Packet, QueryResponse, Record};
+    use tokio::net::UdpSocket;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resolve_addresses() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            loop {
+                let (size, from) = server.recv_from(&mut buf).await.unwrap();
+                let (query, _) = Packet::parse(&buf[..size]).unwrap();
+                let question = &query.questions[0];
+                let mut response = query.clone();
+                response.header.query_response = QueryResponse::Response;
+                let data = match (question.name.raw(), question.type_) {
+                    // the chain continues outside the server's data
+                    ("www.example.com", _) => Some(TypeData::cname("alias.example.net").unwrap()),
+                    ("alias.example.net", Type::A) => {
+                        Some(TypeData::A("192.0.2.1".parse().unwrap()))
+                    }
+                    ("alias.example.net", Type::AAAA) => {
+                        Some(TypeData::AAAA("2001:db8::1".parse().unwrap()))
+                    }
+                    ("loop.example.com", _) => Some(TypeData::cname("loop.example.com").unwrap()),
+                    _ => {
+                        response.header.response_code = ResponseCode::NameError;
+                        None
+                    }
+                };
+                response
+                    .answers
+                    .extend(data.map(|data| Record::new(question.name.clone(), 300, data)));
+                server
+                    .send_to(&response.serialize(512), from)
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let mut client = DnsClient::new().await.unwrap();
+        let addresses = client
+            .resolve_addresses(addr, "www.example.com".parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            addresses,
+            vec![
+                "192.0.2.1".parse::<IpAddr>().unwrap(),
+                "2001:db8::1".parse().unwrap()
+            ]
+        );
+        assert!(client
+            .resolve_addresses(addr, "missing.example.com".parse().unwrap())
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(matches!(
+            client
+                .resolve_addresses(addr, "loop.example.com".parse().unwrap())
+                .await,
+            Err(DnsQueryError::ResolutionLimit)
+        ));
+    }
+}
@@ -0,0 +1,157 @@
+use std::net::SocketAddr;
+
+use adns_proto::{
+    tsig::{self, TsigMode},
+    Class, Name, Packet, Question, Record, ResponseCode, Type,
+};
+use tokio::{io::AsyncWriteExt, net::TcpStream};
+
+use crate::{read_message, validate_response_tsig, DnsClient, DnsQueryError};
+
+impl DnsClient {
+    /// transfers `zone` from `server` over TCP (RFC 5936), optionally signed with a TSIG `(key name, algorithm, key)`.
+    /// returns the zone's records, starting with its SOA, without the closing SOA.
+    pub async fn axfr(
+        &mut self,
+        server: SocketAddr,
+        zone: Name,
+        key: Option<(Name, Name, Vec<u8>)>,
+    ) -> Result<Vec<Record>, DnsQueryError> {
+        let mut packet = Self::build_query(vec![Question {
+            name: zone,
+            type_: Type::AXFR,
+            class: Class::IN,
+        }]);
+        packet.header.recursion_desired = false;
+        let id = packet.header.id;
+        let (serialized, mut mac) = match &key {
+            Some((key_name, algorithm, key)) => {
+                self.check_tsig_algorithm(algorithm)?;
+                let signed = tsig::serialize_packet(
+                    |_| Some(key.clone()),
+                    packet,
+                    u16::MAX as usize,
+                    key_name.clone(),
+                    algorithm.clone(),
+                    self.allow_md5_tsig,
+                    TsigMode::Normal,
+                    None,
+                );
+                (signed.packet, signed.mac)
+            }
+            None => (packet.serialize(u16::MAX as usize), vec![]),
+        };
+
+        let mut client = tokio::time::timeout(self.timeout, TcpStream::connect(server))
+            .await
+            .map_err(|_| DnsQueryError::Timeout)??;
+        client
+            .write_u16(
+                serialized
+                    .len()
+                    .try_into()
+                    .map_err(|_| DnsQueryError::PacketTooLarge)?,
+            )
+            .await?;
+        client.write_all(&serialized).await?;
+
+        let mut records: Vec<Record> = vec![];
+        let mut mode = TsigMode::Normal;
+        loop {
+            let message = tokio::time::timeout(self.timeout, read_message(&mut client))
+                .await
+                .map_err(|_| DnsQueryError::Timeout)??;
+            let (packet, tsig) = Packet::parse(&message)?;
+            if packet.header.id != id {
+                return Err(DnsQueryError::IDMismatch);
+            }
+            if packet.header.response_code != ResponseCode::NoError {
+                return Err(DnsQueryError::ResponseCode(packet.header.response_code));
+            }
+            if let Some((key_name, _, key)) = &key {
+                // each message after the first chains off the previous MAC (RFC 8945 section 5.3.1)
+                mac = validate_response_tsig(
+                    &packet,
+                    tsig,
+                    key_name,
+                    key,
+                    self.allow_md5_tsig,
+                    mode,
+                    &mac,
+                )?;
+                mode = TsigMode::TimersOnly;
+            }
+            for record in packet.answers {
+                match (records.is_empty(), record.type_) {
+                    (true, Type::SOA) => (),
+                    (true, _) => return Err(DnsQueryError::MalformedTransfer),
+                    (false, Type::SOA) => return Ok(records),
+                    (false, _) => (),
+                }
+                records.push(record);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use adns_proto::{Header, QueryResponse, SoaData, TypeData};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_axfr() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut client, _) = listener.accept().await.unwrap();
+            let query = Packet::parse(&read_message(&mut client).await.unwrap())
+                .unwrap()
+                .0;
+            let soa = Record::new(
+                "example.com".parse().unwrap(),
+                300,
+                TypeData::SOA(SoaData {
+                    mname: "ns1.example.com".parse().unwrap(),
+                    rname: "admin.example.com".parse().unwrap(),
+                    serial: 1,
+                    refresh: 3600,
+                    retry: 600,
+                    expire: 86400,
+                    minimum: 300,
+                }),
+            );
+            let a = Record::new(
+                "www.example.com".parse().unwrap(),
+                300,
+                TypeData::A("10.0.0.1".parse().unwrap()),
+            );
+            for answers in [vec![soa.clone(), a.clone()], vec![a, soa]] {
+                let response = Packet {
+                    header: Header {
+                        id: query.header.id,
+                        query_response: QueryResponse::Response,
+                        ..Default::default()
+                    },
+                    questions: query.questions.clone(),
+                    answers,
+                    ..Default::default()
+                }
+                .serialize(u16::MAX as usize);
+                client.write_u16(response.len() as u16).await.unwrap();
+                client.write_all(&response).await.unwrap();
+            }
+        });
+
+        let mut client = DnsClient::new().await.unwrap();
+        let records = client
+            .axfr(addr, "example.com".parse().unwrap(), None)
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].type_, Type::SOA);
+        assert!(records[1..].iter().all(|x| x.type_ == Type::A));
+    }
+}
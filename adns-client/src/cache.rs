@@ -0,0 +1,192 @@
+use std::{collections::HashMap, time::Duration};
+
+use adns_proto::{Class, Name, Packet, Question, ResponseCode, Type, TypeData};
+use tokio::time::Instant;
+
+/// default cap on how long NXDOMAIN/NODATA responses are cached (RFC 2308 section 5)
+pub const DEFAULT_MAX_NEGATIVE_TTL: Duration = Duration::from_secs(3 * 60 * 60);
+
+struct CacheEntry {
+    response: Packet,
+    stored: Instant,
+    expires: Instant,
+}
+
+/// responses to single-question queries, keyed by that question
+pub struct DnsCache {
+    capacity: usize,
+    max_negative_ttl: Duration,
+    entries: HashMap<(Name, Type, Class), CacheEntry>,
+}
+
+impl DnsCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            max_negative_ttl: DEFAULT_MAX_NEGATIVE_TTL,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// caps how long NXDOMAIN/NODATA responses are cached. defaults to [`DEFAULT_MAX_NEGATIVE_TTL`]
+    pub fn with_max_negative_ttl(mut self, max_negative_ttl: Duration) -> Self {
+        self.max_negative_ttl = max_negative_ttl;
+        self
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn max_negative_ttl(&self) -> Duration {
+        self.max_negative_ttl
+    }
+
+    fn key(question: &Question) -> (Name, Type, Class) {
+        (question.name.clone(), question.type_, question.class)
+    }
+
+    /// a cached response with TTLs reduced by the time spent in the cache
+    pub fn get(&mut self, question: &Question) -> Option<Packet> {
+        let key = Self::key(question);
+        let entry = self.entries.get(&key)?;
+        let now = Instant::now();
+        if entry.expires <= now {
+            self.entries.remove(&key);
+            return None;
+        }
+        let elapsed = now.duration_since(entry.stored).as_secs() as u32;
+        let mut response = entry.response.clone();
+        for record in response
+            .answers
+            .iter_mut()
+            .chain(response.nameservers.iter_mut())
+            .chain(response.additional_records.iter_mut())
+        {
+            record.ttl = record.ttl.saturating_sub(elapsed);
+        }
+        Some(response)
+    }
+
+    pub fn insert(&mut self, question: &Question, response: &Packet) {
+        if self.capacity == 0 {
+            return;
+        }
+        let Some(ttl) = self.ttl(response) else {
+            return;
+        };
+        if ttl.is_zero() {
+            return;
+        }
+        let now = Instant::now();
+        let key = Self::key(question);
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            self.entries.retain(|_, entry| entry.expires > now);
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            // evict whatever would have expired first
+            if let Some(soonest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.expires)
+                .map(|(key, _)| key.clone())
+            {
+                self.entries.remove(&soonest);
+            }
+        }
+        self.entries.insert(
+            key,
+            CacheEntry {
+                response: response.clone(),
+                stored: now,
+                expires: now + ttl,
+            },
+        );
+    }
+
+    /// how long `response` may be cached: the minimum TTL of its answers, or for negative responses the
+    /// SOA minimum capped by the SOA's own TTL and `max_negative_ttl` (RFC 2308 section 5)
+    fn ttl(&self, response: &Packet) -> Option<Duration> {
+        if response.header.is_truncated {
+            return None;
+        }
+        match response.header.response_code {
+            ResponseCode::NoError if !response.answers.is_empty() => response
+                .answers
+                .iter()
+                .map(|x| x.ttl)
+                .min()
+                .map(|ttl| Duration::from_secs(ttl as u64)),
+            ResponseCode::NoError | ResponseCode::NameError => {
+                let ttl = response.nameservers.iter().find_map(|x| match &x.data {
+                    TypeData::SOA(soa) => Some(soa.minimum.min(x.ttl)),
+                    _ => None,
+                })?;
+                Some(Duration::from_secs(ttl as u64).min(self.max_negative_ttl))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use adns_proto::{Record, SoaData};
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cache() {
+        let mut cache = DnsCache::new(1);
+        let question = Question::new(Type::A, "example.com").unwrap();
+        let mut response = Packet::default();
+        response.answers.push(Record::new(
+            "example.com".parse().unwrap(),
+            60,
+            TypeData::A("192.0.2.1".parse().unwrap()),
+        ));
+        response.answers.push(Record::new(
+            "example.com".parse().unwrap(),
+            30,
+            TypeData::A("192.0.2.2".parse().unwrap()),
+        ));
+        cache.insert(&question, &response);
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        let cached = cache.get(&question).unwrap();
+        assert_eq!(cached.answers[0].ttl, 50);
+        assert_eq!(cached.answers[1].ttl, 20);
+        tokio::time::advance(Duration::from_secs(20)).await;
+        assert!(cache.get(&question).is_none());
+
+        // negative answers live for the SOA minimum, up to the cap
+        let mut cache = cache.with_max_negative_ttl(Duration::from_secs(100));
+        let missing = Question::new(Type::A, "missing.example.com").unwrap();
+        let mut negative = Packet::default();
+        negative.header.response_code = ResponseCode::NameError;
+        negative.nameservers.push(Record::new(
+            "example.com".parse().unwrap(),
+            3600,
+            TypeData::SOA(SoaData {
+                mname: "ns.example.com".parse().unwrap(),
+                rname: "hostmaster.example.com".parse().unwrap(),
+                serial: 1,
+                refresh: 3600,
+                retry: 600,
+                expire: 86400,
+                minimum: 300,
+            }),
+        ));
+        cache.insert(&missing, &negative);
+        tokio::time::advance(Duration::from_secs(99)).await;
+        assert!(cache.get(&missing).is_some());
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(cache.get(&missing).is_none());
+
+        // at capacity, the entry expiring first is evicted
+        cache.insert(&question, &response);
+        cache.insert(&missing, &negative);
+        assert!(cache.get(&question).is_none());
+        assert!(cache.get(&missing).is_some());
+    }
+}
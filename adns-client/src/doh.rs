@@ -0,0 +1,56 @@
+use adns_proto::{Packet, Question};
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+
+use crate::{DnsClient, DnsQueryError};
+
+const DNS_MESSAGE: &str = "application/dns-message";
+
+impl DnsClient {
+    /// queries over DNS-over-HTTPS (RFC 8484) by POSTing to `url`, i.e. `https://dns.google/dns-query`
+    pub async fn query_doh(
+        &mut self,
+        url: &str,
+        questions: Vec<Question>,
+    ) -> Result<Packet, DnsQueryError> {
+        let mut packet = Self::build_query(questions);
+        // RFC 8484 section 4.1: ID 0 keeps responses cacheable by HTTP caches
+        packet.header.id = 0;
+        let response = self
+            .http
+            .get_or_insert_with(reqwest::Client::new)
+            .post(url)
+            .header(CONTENT_TYPE, DNS_MESSAGE)
+            .header(ACCEPT, DNS_MESSAGE)
+            .body(packet.serialize(usize::MAX))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(DnsQueryError::HttpStatus(response.status().as_u16()));
+        }
+        let body = response.bytes().await?;
+        Ok(Packet::parse(&body)?.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use adns_proto::Type;
+
+    use super::*;
+
+    #[tokio::test]
+    #[ignore = "queries dns.google over the internet"]
+    async fn test_query_doh() {
+        let mut client = DnsClient::new().await.unwrap();
+        let response = client
+            .query_doh(
+                "https://dns.google/dns-query",
+                vec![Question::new(Type::A, "google.com").unwrap()],
+            )
+            .await
+            .unwrap();
+        for answer in &response.answers {
+            println!("{answer}");
+        }
+    }
+}
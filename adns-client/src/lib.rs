@@ -1,15 +1,48 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, time::Duration};
 
-use adns_proto::{Header, Packet, PacketParseError, Question};
+use adns_proto::{
+    tsig::{self, TsigError, TsigMode},
+    Class, Header, Name, Opcode, Packet, PacketBuilder, PacketParseError, Question, ResponseCode,
+    Type, ValidatableTsig,
+};
 use rand::{thread_rng, Rng};
 use thiserror::Error;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     net::{TcpStream, ToSocketAddrs, UdpSocket},
 };
 
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(feature = "tls")]
+pub use tls::TlsVerification;
+
+#[cfg(feature = "doh")]
+mod doh;
+
+mod axfr;
+
+mod recursive;
+pub use recursive::ROOT_HINTS;
+
+mod addresses;
+
+mod cache;
+pub use cache::{DnsCache, DEFAULT_MAX_NEGATIVE_TTL};
+
 pub struct DnsClient {
     udp: UdpSocket,
+    timeout: Duration,
+    retries: usize,
+    root_hints: Vec<SocketAddr>,
+    cache: Option<DnsCache>,
+    max_negative_ttl: Duration,
+    allow_md5_tsig: bool,
+    #[cfg(feature = "tls")]
+    tls_verification: TlsVerification,
+    /// built on the first `query_doh`
+    #[cfg(feature = "doh")]
+    http: Option<reqwest::Client>,
 }
 
 #[derive(Error, Debug)]
@@ -22,51 +55,228 @@ pub enum DnsQueryError {
     IoError(#[from] std::io::Error),
     #[error("dns parse error {0}")]
     PacketParseError(#[from] PacketParseError),
+    #[error("TSIG error: {0}")]
+    Tsig(#[from] TsigError),
+    #[error("response was not signed")]
+    Unsigned,
+    #[error("server responded with {0:?}")]
+    ResponseCode(ResponseCode),
+    #[error("zone transfer did not start with an SOA record")]
+    MalformedTransfer,
+    #[error("response had no SOA record")]
+    MissingSoa,
+    #[error("timed out waiting for a response")]
+    Timeout,
+    #[error("no reachable name servers for {0}")]
+    NoNameServers(Name),
+    #[error("resolution exceeded its query limit")]
+    ResolutionLimit,
+    #[error("invalid TLS server name")]
+    InvalidServerName,
+    #[cfg(feature = "doh")]
+    #[error("http error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("http status {0}")]
+    HttpStatus(u16),
 }
 
 impl DnsClient {
     pub async fn new() -> Result<Self, DnsQueryError> {
         Ok(Self {
             udp: UdpSocket::bind("[::]:0".parse::<SocketAddr>().unwrap()).await?,
+            timeout: Duration::from_secs(5),
+            retries: 2,
+            root_hints: ROOT_HINTS
+                .iter()
+                .map(|ip| SocketAddr::new((*ip).into(), 53))
+                .collect(),
+            cache: None,
+            max_negative_ttl: DEFAULT_MAX_NEGATIVE_TTL,
+            allow_md5_tsig: false,
+            #[cfg(feature = "tls")]
+            tls_verification: Default::default(),
+            #[cfg(feature = "doh")]
+            http: None,
         })
     }
 
+    /// how long to wait for each UDP attempt, or for a whole TCP exchange. defaults to 5 seconds
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// how many times a UDP query is resent after timing out. defaults to 2
+    pub fn with_retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// caches up to `capacity` responses to single-question `query` calls until their TTLs expire.
+    /// entries are keyed by question only, so they are shared between the servers queried
+    pub fn with_cache(mut self, capacity: usize) -> Self {
+        self.cache = Some(DnsCache::new(capacity).with_max_negative_ttl(self.max_negative_ttl));
+        self
+    }
+
+    /// caps how long NXDOMAIN/NODATA responses are cached, before or after `with_cache`. defaults to
+    /// [`DEFAULT_MAX_NEGATIVE_TTL`]
+    pub fn with_max_negative_ttl(mut self, max_negative_ttl: Duration) -> Self {
+        self.max_negative_ttl = max_negative_ttl;
+        self.cache = self
+            .cache
+            .map(|cache| cache.with_max_negative_ttl(max_negative_ttl));
+        self
+    }
+
+    /// whether signed queries and zone transfers may use HMAC-MD5, which RFC 8945 deprecates. defaults to false
+    pub fn with_md5_tsig(mut self, allow: bool) -> Self {
+        self.allow_md5_tsig = allow;
+        self
+    }
+
+    /// refuses to sign with HMAC-MD5 unless `with_md5_tsig` enabled it, rather than sending a query the server can't verify
+    fn check_tsig_algorithm(&self, algorithm: &Name) -> Result<(), DnsQueryError> {
+        if !self.allow_md5_tsig && algorithm.raw() == "hmac-md5.sig-alg.reg.int" {
+            return Err(TsigError::UnknownAlgorithm.into());
+        }
+        Ok(())
+    }
+
+    fn build_query(questions: Vec<Question>) -> Packet {
+        questions
+            .into_iter()
+            .fold(Packet::builder(), PacketBuilder::question)
+            .id(thread_rng().gen())
+            .recursion_desired(true)
+            .recursion_available(true)
+            .build()
+    }
+
     pub async fn query(
         &mut self,
         servers: impl ToSocketAddrs,
         questions: Vec<Question>,
     ) -> Result<Packet, DnsQueryError> {
-        let id: u16 = thread_rng().gen();
-        let packet = Packet {
-            header: Header {
-                id,
-                recursion_desired: true,
-                recursion_available: true,
-                ..Default::default()
-            },
-            questions,
-            ..Default::default()
+        let cached_question = match (&mut self.cache, &questions[..]) {
+            (Some(cache), [question]) => {
+                if let Some(response) = cache.get(question) {
+                    return Ok(response);
+                }
+                Some(question.clone())
+            }
+            _ => None,
         };
-        let serialized = packet.serialize(usize::MAX);
+        let response = self
+            .query_with(Self::build_query(questions), servers)
+            .await?;
+        if let (Some(cache), Some(question)) = (&mut self.cache, cached_question) {
+            cache.insert(&question, &response);
+        }
+        Ok(response)
+    }
+
+    /// sends a pre-built packet as-is (any opcode), matching the response by the packet's ID
+    pub async fn query_with(
+        &mut self,
+        packet: Packet,
+        servers: impl ToSocketAddrs,
+    ) -> Result<Packet, DnsQueryError> {
+        let id = packet.header.id;
+        let response = self
+            .exchange(servers, id, &packet.serialize(usize::MAX))
+            .await?;
+        Ok(Packet::parse(&response)?.0)
+    }
+
+    /// tells a secondary that `zone` changed (RFC 1996)
+    pub async fn notify(
+        &mut self,
+        servers: impl ToSocketAddrs,
+        zone: Name,
+    ) -> Result<Packet, DnsQueryError> {
+        let mut packet = Self::build_query(vec![Question {
+            name: zone,
+            type_: Type::SOA,
+            class: Class::IN,
+        }]);
+        packet.header.opcode = Opcode::Notify;
+        packet.header.is_authoritative = true;
+        packet.header.recursion_desired = false;
+        packet.header.recursion_available = false;
+        self.query_with(packet, servers).await
+    }
+
+    /// signs the query with TSIG (RFC 8945) and checks the response is signed with the same key
+    pub async fn query_signed(
+        &mut self,
+        servers: impl ToSocketAddrs,
+        questions: Vec<Question>,
+        key_name: Name,
+        algorithm: Name,
+        key: Vec<u8>,
+    ) -> Result<Packet, DnsQueryError> {
+        self.check_tsig_algorithm(&algorithm)?;
+        let packet = Self::build_query(questions);
+        let id = packet.header.id;
+        let signed = tsig::serialize_packet(
+            |_| Some(key.clone()),
+            packet,
+            u16::MAX as usize,
+            key_name.clone(),
+            algorithm,
+            self.allow_md5_tsig,
+            TsigMode::Normal,
+            None,
+        );
+        let response = self.exchange(servers, id, &signed.packet).await?;
+        let (packet, tsig) = Packet::parse(&response)?;
+        validate_response_tsig(
+            &packet,
+            tsig,
+            &key_name,
+            &key,
+            self.allow_md5_tsig,
+            TsigMode::Normal,
+            &signed.mac,
+        )?;
+        Ok(packet)
+    }
+
+    /// sends a serialized packet over UDP, falling back to TCP for large or truncated messages, and returns the raw response
+    async fn exchange(
+        &mut self,
+        servers: impl ToSocketAddrs,
+        id: u16,
+        serialized: &[u8],
+    ) -> Result<Vec<u8>, DnsQueryError> {
         if serialized.len() > 512 {
-            self.query_tcp(&servers, id, &serialized).await
-        } else {
-            self.udp.send_to(&serialized, &servers).await?;
-            let mut response = [0u8; 512];
-            let mut size;
-            loop {
-                size = self.udp.recv(&mut response).await?;
-                if size < 2 || u16::from_be_bytes(response[..2].try_into().unwrap()) != id {
-                    continue;
+            return self.query_tcp(&servers, id, serialized).await;
+        }
+        let mut response = [0u8; 512];
+        let size = 'attempts: {
+            for _ in 0..=self.retries {
+                self.udp.send_to(serialized, &servers).await?;
+                // responses with the wrong ID are skipped, but still count against the attempt's deadline
+                let deadline = tokio::time::Instant::now() + self.timeout;
+                while let Ok(size) =
+                    tokio::time::timeout_at(deadline, self.udp.recv(&mut response)).await
+                {
+                    let size = size?;
+                    if size >= 2 && u16::from_be_bytes(response[..2].try_into().unwrap()) == id {
+                        break 'attempts size;
+                    }
                 }
-                break;
-            }
-            match Packet::parse(&response[..size]) {
-                Ok(packet) => Ok(packet.0),
-                Err(PacketParseError::Truncated) => self.query_tcp(&servers, id, &serialized).await,
-                Err(e) => Err(e.into()),
             }
+            return Err(DnsQueryError::Timeout);
+        };
+        if matches!(
+            Packet::parse(&response[..size]),
+            Err(PacketParseError::Truncated)
+        ) {
+            return self.query_tcp(&servers, id, serialized).await;
         }
+        Ok(response[..size].to_vec())
     }
 
     async fn query_tcp(
@@ -74,8 +284,21 @@ impl DnsClient {
         servers: impl ToSocketAddrs,
         id: u16,
         packet: &[u8],
-    ) -> Result<Packet, DnsQueryError> {
-        let mut client = TcpStream::connect(servers).await?;
+    ) -> Result<Vec<u8>, DnsQueryError> {
+        tokio::time::timeout(self.timeout, async {
+            let mut client = TcpStream::connect(servers).await?;
+            Self::exchange_stream(&mut client, id, packet).await
+        })
+        .await
+        .map_err(|_| DnsQueryError::Timeout)?
+    }
+
+    /// sends a length-prefixed packet and reads back the raw response, as over TCP (RFC 1035 section 4.2.2)
+    async fn exchange_stream(
+        client: &mut (impl AsyncRead + AsyncWrite + Unpin),
+        id: u16,
+        packet: &[u8],
+    ) -> Result<Vec<u8>, DnsQueryError> {
         client
             .write_u16(
                 packet
@@ -85,22 +308,61 @@ impl DnsClient {
             )
             .await?;
         client.write_all(packet).await?;
-        let len = client.read_u16().await?;
-        let mut response = vec![0u8; len as usize];
-        client.read_exact(&mut response).await?;
+        let response = read_message(client).await?;
 
-        let packet = Packet::parse(&response)?.0;
-        if packet.header.id != id {
+        if response.len() < 2 || u16::from_be_bytes(response[..2].try_into().unwrap()) != id {
             return Err(DnsQueryError::IDMismatch);
         }
-        Ok(packet)
+        Ok(response)
+    }
+}
+
+async fn read_message(client: &mut (impl AsyncRead + Unpin)) -> Result<Vec<u8>, DnsQueryError> {
+    let len = client.read_u16().await?;
+    let mut message = vec![0u8; len as usize];
+    client.read_exact(&mut message).await?;
+    Ok(message)
+}
+
+/// checks a response is signed by `key_name`, returning its MAC for the next message in a multi-message response
+fn validate_response_tsig(
+    packet: &Packet,
+    tsig: Option<ValidatableTsig<'_>>,
+    key_name: &Name,
+    key: &[u8],
+    allow_md5: bool,
+    mode: TsigMode,
+    request_mac: &[u8],
+) -> Result<Vec<u8>, DnsQueryError> {
+    let Some(ValidatableTsig {
+        name,
+        data,
+        hmac_slice,
+    }) = tsig
+    else {
+        return Err(DnsQueryError::Unsigned);
+    };
+    if name != *key_name {
+        return Err(TsigError::MissingKey.into());
     }
+    // the MAC covers the response as it was before the TSIG record was added
+    let mut unsigned = hmac_slice.to_vec();
+    let mut header = packet.header.clone();
+    header.additional_record_count -= 1;
+    unsigned[..Header::LENGTH].copy_from_slice(&header.to_bytes());
+    Ok(tsig::validate(
+        |_| Some(key.to_vec()),
+        &unsigned,
+        &name,
+        &data,
+        allow_md5,
+        mode,
+        Some(request_mac),
+    )?)
 }
 
 #[cfg(test)]
 mod tests {
-    use adns_proto::Type;
-
     use super::*;
 
     #[tokio::test]
@@ -117,4 +379,132 @@ mod tests {
             println!("{answer}");
         }
     }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let silent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut client = DnsClient::new()
+            .await
+            .unwrap()
+            .with_timeout(Duration::from_millis(50))
+            .with_retries(1);
+        let result = client
+            .query(
+                silent.local_addr().unwrap(),
+                vec![Question::new(Type::A, "google.com").unwrap()],
+            )
+            .await;
+        assert!(matches!(result, Err(DnsQueryError::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn test_max_negative_ttl() {
+        let max_negative_ttl = Duration::from_secs(100);
+        let before = DnsClient::new()
+            .await
+            .unwrap()
+            .with_max_negative_ttl(max_negative_ttl)
+            .with_cache(16);
+        let after = DnsClient::new()
+            .await
+            .unwrap()
+            .with_cache(16)
+            .with_max_negative_ttl(max_negative_ttl);
+        for client in [before, after] {
+            assert_eq!(client.cache.unwrap().max_negative_ttl(), max_negative_ttl);
+        }
+    }
+
+    /// answers one query signed with `key`, echoing it back signed with the same key
+    async fn signing_server(key: Vec<u8>, allow_md5: bool) -> SocketAddr {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            let (size, from) = server.recv_from(&mut buf).await.unwrap();
+            let (query, tsig) = Packet::parse(&buf[..size]).unwrap();
+            let tsig = tsig.unwrap();
+            let mut unsigned = tsig.hmac_slice.to_vec();
+            let mut header = query.header.clone();
+            header.additional_record_count -= 1;
+            unsigned[..Header::LENGTH].copy_from_slice(&header.to_bytes());
+            let request_mac = tsig::validate(
+                |_| Some(key.clone()),
+                &unsigned,
+                &tsig.name,
+                &tsig.data,
+                allow_md5,
+                TsigMode::Normal,
+                None,
+            )
+            .unwrap();
+            let mut response = query.clone();
+            response.header.query_response = adns_proto::QueryResponse::Response;
+            let signed = tsig::serialize_packet(
+                |_| Some(key),
+                response,
+                512,
+                tsig.name,
+                tsig.data.algorithm,
+                allow_md5,
+                TsigMode::Normal,
+                Some(&request_mac),
+            );
+            server.send_to(&signed.packet, from).await.unwrap();
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_query_signed() {
+        let key = b"secret".to_vec();
+        let key_name: Name = "key.example.com".parse().unwrap();
+        let algorithm: Name = "hmac-sha256".parse().unwrap();
+        let addr = signing_server(key.clone(), false).await;
+
+        let mut client = DnsClient::new().await.unwrap();
+        let response = client
+            .query_signed(
+                addr,
+                vec![Question::new(Type::A, "example.com").unwrap()],
+                key_name,
+                algorithm,
+                key,
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.questions.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_query_signed_md5() {
+        let key = b"secret".to_vec();
+        let key_name: Name = "key.example.com".parse().unwrap();
+        let algorithm: Name = "hmac-md5.sig-alg.reg.int".parse().unwrap();
+        let question = Question::new(Type::A, "example.com").unwrap();
+
+        // refused before anything is sent
+        let mut client = DnsClient::new().await.unwrap();
+        let result = client
+            .query_signed(
+                "127.0.0.1:9".parse::<SocketAddr>().unwrap(),
+                vec![question.clone()],
+                key_name.clone(),
+                algorithm.clone(),
+                key.clone(),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(DnsQueryError::Tsig(TsigError::UnknownAlgorithm))
+        ));
+
+        let addr = signing_server(key.clone(), true).await;
+        let mut client = DnsClient::new().await.unwrap().with_md5_tsig(true);
+        let response = client
+            .query_signed(addr, vec![question], key_name, algorithm, key)
+            .await
+            .unwrap();
+        assert_eq!(response.questions.len(), 1);
+    }
 }
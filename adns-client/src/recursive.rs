@@ -0,0 +1,290 @@
+use std::{
+    future::Future,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    pin::Pin,
+};
+
+use adns_proto::{Class, Name, Packet, Question, Record, ResponseCode, Type, TypeData};
+
+use crate::{DnsClient, DnsQueryError};
+
+/// IPv4 addresses of the root servers a.root-servers.net through m.root-servers.net
+pub const ROOT_HINTS: [Ipv4Addr; 13] = [
+    Ipv4Addr::new(198, 41, 0, 4),
+    Ipv4Addr::new(170, 247, 170, 2),
+    Ipv4Addr::new(192, 33, 4, 12),
+    Ipv4Addr::new(199, 7, 91, 13),
+    Ipv4Addr::new(192, 203, 230, 10),
+    Ipv4Addr::new(192, 5, 5, 241),
+    Ipv4Addr::new(192, 112, 36, 4),
+    Ipv4Addr::new(198, 97, 190, 53),
+    Ipv4Addr::new(192, 36, 148, 17),
+    Ipv4Addr::new(192, 58, 128, 30),
+    Ipv4Addr::new(193, 0, 14, 129),
+    Ipv4Addr::new(199, 7, 83, 42),
+    Ipv4Addr::new(202, 12, 27, 33),
+];
+
+/// queries (referrals followed or labels added) allowed for one name
+const MAX_STEPS: usize = 64;
+/// CNAMEs followed across zones
+pub(crate) const MAX_CNAME_CHAIN: usize = 8;
+/// nested resolutions of glueless name server names
+const MAX_DEPTH: usize = 4;
+
+type ResolveFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<Record>, DnsQueryError>> + Send + 'a>>;
+
+impl DnsClient {
+    /// servers `resolve` starts from. defaults to the root servers in [`ROOT_HINTS`]
+    pub fn with_root_hints(mut self, root_hints: Vec<SocketAddr>) -> Self {
+        self.root_hints = root_hints;
+        self
+    }
+
+    /// resolves `name` iteratively from the root hints, following NS delegations and CNAMEs.
+    /// queries are minimized (RFC 9156): each server is only asked about the name one label below its zone.
+    pub async fn resolve(&mut self, name: Name, type_: Type) -> Result<Vec<Record>, DnsQueryError> {
+        self.resolve_depth(name, type_, 0).await
+    }
+
+    fn resolve_depth(&mut self, name: Name, type_: Type, depth: usize) -> ResolveFuture<'_> {
+        Box::pin(async move {
+            if depth > MAX_DEPTH {
+                return Err(DnsQueryError::ResolutionLimit);
+            }
+            let mut records = vec![];
+            let mut current = name;
+            for _ in 0..=MAX_CNAME_CHAIN {
+                let answers = self.resolve_name(&current, type_, depth).await?;
+                // the server may have followed CNAMEs within its own data already
+                let mut target = current.clone();
+                for _ in 0..MAX_CNAME_CHAIN {
+                    if type_ == Type::CNAME
+                        || answers.iter().any(|x| x.type_ == type_ && x.name == target)
+                    {
+                        break;
+                    }
+                    let Some(next) = answers.iter().find_map(|x| match &x.data {
+                        TypeData::CNAME(next) if x.name == target => Some(next.clone()),
+                        _ => None,
+                    }) else {
+                        break;
+                    };
+                    if next == current {
+                        break;
+                    }
+                    target = next;
+                }
+                records.extend(answers);
+                if target == current || records.iter().any(|x| x.type_ == type_ && x.name == target)
+                {
+                    return Ok(records);
+                }
+                current = target;
+            }
+            Err(DnsQueryError::ResolutionLimit)
+        })
+    }
+
+    async fn resolve_name(
+        &mut self,
+        name: &Name,
+        type_: Type,
+        depth: usize,
+    ) -> Result<Vec<Record>, DnsQueryError> {
+        let total = name.segments().count();
+        let mut zone = Name::default();
+        let mut servers = self.root_hints.clone();
+        let mut labels = total.min(1);
+        for _ in 0..MAX_STEPS {
+            let minimized = labels < total;
+            let qname = suffix(name, labels);
+            // minimized queries ask for A, as any ordinary query would (RFC 9156 section 3)
+            let qtype = if minimized { Type::A } else { type_ };
+            let response = self
+                .query_servers(&servers, &zone, qname.clone(), qtype)
+                .await?;
+            if response.header.response_code != ResponseCode::NoError {
+                // NXDOMAIN for an ancestor means nothing below it exists either (RFC 8020)
+                return Err(DnsQueryError::ResponseCode(response.header.response_code));
+            }
+            let referral = response.nameservers.iter().find_map(|x| match &x.data {
+                TypeData::NS(_)
+                    if x.name != zone && x.name.ends_with(&zone) && qname.ends_with(&x.name) =>
+                {
+                    Some(x.name.clone())
+                }
+                _ => None,
+            });
+            let Some(child) = referral else {
+                if !minimized {
+                    return Ok(response.answers);
+                }
+                // no zone cut here, the same servers are authoritative for the next label too
+                labels += 1;
+                continue;
+            };
+            servers = self.delegation_servers(&response, &child, depth).await?;
+            labels = (child.segments().count() + 1).min(total);
+            zone = child;
+        }
+        Err(DnsQueryError::ResolutionLimit)
+    }
+
+    /// addresses of the name servers for `child` in a referral, from glue or by resolving their names
+    async fn delegation_servers(
+        &mut self,
+        referral: &Packet,
+        child: &Name,
+        depth: usize,
+    ) -> Result<Vec<SocketAddr>, DnsQueryError> {
+        let targets: Vec<Name> = referral
+            .nameservers
+            .iter()
+            .filter_map(|x| match &x.data {
+                TypeData::NS(target) if x.name == *child => Some(target.clone()),
+                _ => None,
+            })
+            .collect();
+        let mut servers = glue_addresses(&referral.additional_records, &targets);
+        if !servers.is_empty() {
+            return Ok(servers);
+        }
+        for target in targets {
+            // a name server inside the zone it serves can't be resolved without glue
+            if target.ends_with(child) {
+                continue;
+            }
+            let Ok(records) = self.resolve_depth(target.clone(), Type::A, depth + 1).await else {
+                continue;
+            };
+            servers.extend(glue_addresses(&records, &[target]));
+            if !servers.is_empty() {
+                break;
+            }
+        }
+        if servers.is_empty() {
+            return Err(DnsQueryError::NoNameServers(child.clone()));
+        }
+        Ok(servers)
+    }
+
+    /// sends a non-recursive query to each server in turn until one answers
+    async fn query_servers(
+        &mut self,
+        servers: &[SocketAddr],
+        zone: &Name,
+        name: Name,
+        type_: Type,
+    ) -> Result<Packet, DnsQueryError> {
+        for server in servers {
+            let mut packet = Self::build_query(vec![Question {
+                name: name.clone(),
+                type_,
+                class: Class::IN,
+            }]);
+            packet.header.recursion_desired = false;
+            packet.header.recursion_available = false;
+            match self.query_with(packet, *server).await {
+                Ok(response)
+                    if !matches!(
+                        response.header.response_code,
+                        ResponseCode::ServerFailure | ResponseCode::Refused
+                    ) =>
+                {
+                    return Ok(response);
+                }
+                _ => (),
+            }
+        }
+        Err(DnsQueryError::NoNameServers(zone.clone()))
+    }
+}
+
+/// the last `count` labels of `name`
+fn suffix(name: &Name, count: usize) -> Name {
+    let mut segments: Vec<&str> = name.segments().rev().take(count).collect();
+    segments.reverse();
+    Name::from_segments(segments).expect("suffix of a valid name")
+}
+
+/// A and AAAA addresses for `targets`, IPv4 first
+fn glue_addresses(records: &[Record], targets: &[Name]) -> Vec<SocketAddr> {
+    let mut addresses: Vec<IpAddr> = records
+        .iter()
+        .filter(|x| targets.contains(&x.name))
+        .filter_map(|x| match &x.data {
+            TypeData::A(ip) => Some(IpAddr::V4(*ip)),
+            TypeData::AAAA(ip) => Some(IpAddr::V6(*ip)),
+            _ => None,
+        })
+        .collect();
+    addresses.sort_by_key(|x| x.is_ipv6());
+    addresses
+        .into_iter()
+        .map(|ip| SocketAddr::new(ip, 53))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use adns_proto::QueryResponse;
+    use tokio::net::UdpSocket;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resolve_minimized() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            loop {
+                let (size, from) = server.recv_from(&mut buf).await.unwrap();
+                let (query, _) = Packet::parse(&buf[..size]).unwrap();
+                let question = query.questions[0].clone();
+                let mut response = query.clone();
+                response.header.query_response = QueryResponse::Response;
+                response.header.is_authoritative = true;
+                if question.name == "www.example.com" {
+                    response.answers.push(Record::new(
+                        question.name.clone(),
+                        300,
+                        TypeData::AAAA("2001:db8::1".parse().unwrap()),
+                    ));
+                }
+                sender.send((question.name, question.type_)).unwrap();
+                server
+                    .send_to(&response.serialize(512), from)
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let mut client = DnsClient::new().await.unwrap().with_root_hints(vec![addr]);
+        let records = client
+            .resolve("www.example.com".parse().unwrap(), Type::AAAA)
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            records[0].data,
+            TypeData::AAAA("2001:db8::1".parse().unwrap())
+        );
+
+        let mut queries = vec![];
+        while let Ok(query) = receiver.try_recv() {
+            queries.push(query);
+        }
+        assert_eq!(
+            queries,
+            vec![
+                ("com".parse().unwrap(), Type::A),
+                ("example.com".parse().unwrap(), Type::A),
+                ("www.example.com".parse().unwrap(), Type::AAAA),
+            ]
+        );
+    }
+}
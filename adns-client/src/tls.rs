@@ -0,0 +1,131 @@
+use std::{net::SocketAddr, sync::Arc, time::SystemTime};
+
+use adns_proto::{Packet, Question};
+use sha2::{Digest, Sha256};
+use tokio::net::TcpStream;
+use tokio_rustls::{
+    rustls::{
+        self,
+        client::{ServerCertVerified, ServerCertVerifier},
+        Certificate, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName,
+    },
+    TlsConnector,
+};
+
+use crate::{DnsClient, DnsQueryError};
+
+/// How `query_tls` authenticates the server's certificate
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TlsVerification {
+    /// verify the chain against the Mozilla root store and check the server name
+    #[default]
+    WebPki,
+    /// accept only a leaf certificate whose DER encoding has this SHA-256 fingerprint
+    Pinned([u8; 32]),
+    /// accept any certificate. only useful for testing
+    Insecure,
+}
+
+struct PinnedVerifier(Option<[u8; 32]>);
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match self.0 {
+            Some(pin) if Sha256::digest(&end_entity.0)[..] != pin[..] => {
+                Err(rustls::Error::InvalidCertificate(
+                    rustls::CertificateError::ApplicationVerificationFailure,
+                ))
+            }
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+}
+
+impl TlsVerification {
+    fn client_config(&self) -> ClientConfig {
+        let mut roots = RootCertStore::empty();
+        if *self == TlsVerification::WebPki {
+            roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+                OwnedTrustAnchor::from_subject_spki_name_constraints(
+                    anchor.subject,
+                    anchor.spki,
+                    anchor.name_constraints,
+                )
+            }));
+        }
+        let mut config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        match self {
+            TlsVerification::WebPki => (),
+            TlsVerification::Pinned(pin) => config
+                .dangerous()
+                .set_certificate_verifier(Arc::new(PinnedVerifier(Some(*pin)))),
+            TlsVerification::Insecure => config
+                .dangerous()
+                .set_certificate_verifier(Arc::new(PinnedVerifier(None))),
+        }
+        config
+    }
+}
+
+impl DnsClient {
+    /// sets how DNS-over-TLS servers are authenticated
+    pub fn with_tls_verification(mut self, verification: TlsVerification) -> Self {
+        self.tls_verification = verification;
+        self
+    }
+
+    /// queries over DNS-over-TLS (RFC 7858), usually on port 853
+    pub async fn query_tls(
+        &mut self,
+        server: SocketAddr,
+        server_name: &str,
+        questions: Vec<Question>,
+    ) -> Result<Packet, DnsQueryError> {
+        let server_name =
+            ServerName::try_from(server_name).map_err(|_| DnsQueryError::InvalidServerName)?;
+        let connector = TlsConnector::from(Arc::new(self.tls_verification.client_config()));
+        let stream = TcpStream::connect(server).await?;
+        let mut stream = connector.connect(server_name, stream).await?;
+
+        let packet = Self::build_query(questions);
+        let id = packet.header.id;
+        let response =
+            Self::exchange_stream(&mut stream, id, &packet.serialize(usize::MAX)).await?;
+        Ok(Packet::parse(&response)?.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use adns_proto::Type;
+
+    use super::*;
+
+    #[tokio::test]
+    #[ignore = "queries 1.1.1.1 over the internet"]
+    async fn test_query_tls() {
+        let mut client = DnsClient::new().await.unwrap();
+        let response = client
+            .query_tls(
+                "1.1.1.1:853".parse().unwrap(),
+                "one.one.one.one",
+                vec![Question::new(Type::A, "google.com").unwrap()],
+            )
+            .await
+            .unwrap();
+        for answer in &response.answers {
+            println!("{answer}");
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::{Edns, Opcode, Packet, QueryResponse, Question, Record, ResponseCode};
+
+/// builds a [`Packet`] without touching header fields directly. counts are filled in by `build`
+#[derive(Default)]
+pub struct PacketBuilder {
+    packet: Packet,
+}
+
+impl Packet {
+    pub fn builder() -> PacketBuilder {
+        PacketBuilder::default()
+    }
+}
+
+impl PacketBuilder {
+    pub fn id(mut self, id: u16) -> Self {
+        self.packet.header.id = id;
+        self
+    }
+
+    pub fn opcode(mut self, opcode: Opcode) -> Self {
+        self.packet.header.opcode = opcode;
+        self
+    }
+
+    /// marks the packet as a response rather than a query
+    pub fn response(mut self) -> Self {
+        self.packet.header.query_response = QueryResponse::Response;
+        self
+    }
+
+    pub fn authoritative(mut self, authoritative: bool) -> Self {
+        self.packet.header.is_authoritative = authoritative;
+        self
+    }
+
+    pub fn recursion_desired(mut self, recursion_desired: bool) -> Self {
+        self.packet.header.recursion_desired = recursion_desired;
+        self
+    }
+
+    pub fn recursion_available(mut self, recursion_available: bool) -> Self {
+        self.packet.header.recursion_available = recursion_available;
+        self
+    }
+
+    pub fn response_code(mut self, response_code: ResponseCode) -> Self {
+        self.packet.header.response_code = response_code;
+        self
+    }
+
+    pub fn edns(mut self, edns: Edns) -> Self {
+        self.packet.edns = Some(edns);
+        self
+    }
+
+    pub fn question(mut self, question: Question) -> Self {
+        self.packet.questions.push(question);
+        self
+    }
+
+    pub fn answer(mut self, record: Record) -> Self {
+        self.packet.answers.push(record);
+        self
+    }
+
+    /// adds a record to the authority (nameservers) section
+    pub fn authority(mut self, record: Record) -> Self {
+        self.packet.nameservers.push(record);
+        self
+    }
+
+    pub fn additional(mut self, record: Record) -> Self {
+        self.packet.additional_records.push(record);
+        self
+    }
+
+    pub fn build(self) -> Packet {
+        let mut packet = self.packet;
+        packet.header.question_count = packet.questions.len() as u16;
+        packet.header.answer_count = packet.answers.len() as u16;
+        packet.header.nameserver_count = packet.nameservers.len() as u16;
+        packet.header.additional_record_count =
+            packet.additional_records.len() as u16 + packet.edns.is_some() as u16;
+        packet
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Type, TypeData};
+
+    #[test]
+    fn test_builder_round_trip() {
+        let question = Question::new(Type::A, "www.example.com").unwrap();
+        let packet = Packet::builder()
+            .id(1234)
+            .response()
+            .authoritative(true)
+            .response_code(ResponseCode::NoError)
+            .question(question.clone())
+            .answer(Record::new(
+                question.name.clone(),
+                300,
+                TypeData::A("192.0.2.1".parse().unwrap()),
+            ))
+            .authority(Record::new(
+                "example.com".parse().unwrap(),
+                300,
+                TypeData::NS("ns1.example.com".parse().unwrap()),
+            ))
+            .build();
+        assert_eq!(packet.header.answer_count, 1);
+        assert_eq!(packet.header.nameserver_count, 1);
+
+        let (parsed, _) = Packet::parse(&packet.serialize(u16::MAX as usize)).unwrap();
+        assert_eq!(parsed.header.id, 1234);
+        assert_eq!(parsed.header.query_response, QueryResponse::Response);
+        assert!(parsed.header.is_authoritative);
+        assert_eq!(parsed.questions[0].name, question.name);
+        assert_eq!(parsed.answers, packet.answers);
+        assert_eq!(parsed.nameservers, packet.nameservers);
+    }
+}
@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use smallvec::{smallvec, SmallVec};
+use smallvec::SmallVec;
 
 use crate::{maybe_concat::MaybeConcat, Header, Name, PacketParseError};
 
@@ -9,9 +9,19 @@ pub struct SerializeContext {
     current_packet: Vec<u8>,
     // map of `.` concatenated labels to ptr index
     known_labels: HashMap<MaybeConcat<'static>, u16>,
+    canonical: bool,
 }
 
 impl SerializeContext {
+    /// writes compressible names lowercased and uncompressed, the canonical form signed by DNSSEC (RFC 4034 section 6.2).
+    /// names written with `write_name_uncompressed`, like the NSEC next name, keep their case
+    pub fn canonical() -> Self {
+        Self {
+            canonical: true,
+            ..Default::default()
+        }
+    }
+
     pub fn capture_len_u16(&mut self, mut func: impl FnMut(&mut Self)) {
         let len_index = self.current_packet.len();
         // reserved room for length
@@ -38,6 +48,18 @@ impl SerializeContext {
     }
 
     pub fn write_name(&mut self, name: &Name) {
+        // U-labels go out as punycode, resolvers reject raw UTF-8
+        if self.canonical {
+            let name = name.ascii_form();
+            for segment in name.segments() {
+                self.current_packet.push(segment.len() as u8);
+                self.current_packet
+                    .extend(segment.as_bytes().iter().map(u8::to_ascii_lowercase));
+            }
+            self.current_packet.push(0u8);
+            return;
+        }
+        let name = name.ascii_form();
         let segments = name.segments().collect::<SmallVec<[&str; 6]>>();
         for (i, segment) in segments.iter().enumerate() {
             if let Some(ptr) = self
@@ -64,6 +86,20 @@ impl SerializeContext {
         self.current_packet.push(0u8);
     }
 
+    /// writes a name without compression, for RDATA where compression is forbidden (RFC 3597)
+    /// writes `name` as-is, also in canonical form
+    pub fn write_name_uncompressed(&mut self, name: &Name) {
+        let name = name.ascii_form();
+        for segment in name.segments() {
+            if segment.len() > 63 {
+                panic!("name segment too long");
+            }
+            self.current_packet.push(segment.len() as u8);
+            self.current_packet.extend(segment.as_bytes());
+        }
+        self.current_packet.push(0u8);
+    }
+
     pub fn current(&self) -> &[u8] {
         &self.current_packet
     }
@@ -96,7 +132,7 @@ impl<'a> DeserializeContext<'a> {
         mut func: impl FnMut(&mut Self) -> Result<T, PacketParseError>,
     ) -> Result<T, PacketParseError> {
         if self.index + length > self.max_length {
-            return Err(PacketParseError::UnexpectedEOF);
+            return Err(PacketParseError::UnexpectedEOF(self.index));
         }
         let old_length = self.max_length;
         let end = self.index + length;
@@ -120,7 +156,7 @@ impl<'a> DeserializeContext<'a> {
 
     pub fn read_u8(&mut self) -> Result<u8, PacketParseError> {
         if self.index + 1 > self.max_length {
-            return Err(PacketParseError::UnexpectedEOF);
+            return Err(PacketParseError::UnexpectedEOF(self.index));
         }
         let out = self.packet[self.index];
         self.index += 1;
@@ -129,27 +165,45 @@ impl<'a> DeserializeContext<'a> {
 
     pub fn read_n<const N: usize>(&mut self) -> Result<[u8; N], PacketParseError> {
         if self.index + N > self.max_length {
-            return Err(PacketParseError::UnexpectedEOF);
+            return Err(PacketParseError::UnexpectedEOF(self.index));
         }
         let out = self.packet[self.index..self.index + N].try_into().unwrap();
         self.index += N;
         Ok(out)
     }
 
-    pub fn read_all(&mut self, data: &mut [u8]) -> Result<(), PacketParseError> {
-        if self.index + data.len() > self.max_length {
-            return Err(PacketParseError::UnexpectedEOF);
+    /// borrows the next `len` bytes from the packet, so callers copy them at most once into a buffer of their choice
+    pub fn read_slice(&mut self, len: usize) -> Result<&'a [u8], PacketParseError> {
+        if self.index + len > self.max_length {
+            return Err(PacketParseError::UnexpectedEOF(self.index));
         }
-        data.copy_from_slice(&self.packet[self.index..self.index + data.len()]);
-        self.index += data.len();
-        Ok(())
+        let out = &self.packet[self.index..self.index + len];
+        self.index += len;
+        Ok(out)
+    }
+
+    /// reads all remaining bytes in the current restriction into an exactly sized buffer
+    pub fn read_remaining(&mut self) -> Result<Vec<u8>, PacketParseError> {
+        Ok(self.read_slice(self.remaining())?.to_vec())
     }
 
+    /// reads a length prefixed string, validated in place before its one allocation
     pub fn read_cstring(&mut self) -> Result<String, PacketParseError> {
         let len = self.read_u8()?;
-        let mut raw = vec![0u8; len as usize];
-        self.read_all(&mut raw)?;
-        Ok(String::from_utf8(raw).map_err(|e| e.utf8_error())?)
+        Ok(std::str::from_utf8(self.read_slice(len as usize)?)?.to_owned())
+    }
+
+    /// how many length prefixed strings the rest of the current restriction holds, as a capacity hint. a truncated
+    /// last string still counts, reading it fails later
+    pub fn count_cstrings(&self) -> usize {
+        let rest = &self.packet[self.index..self.max_length];
+        let mut count = 0;
+        let mut i = 0;
+        while i < rest.len() {
+            i += rest[i] as usize + 1;
+            count += 1;
+        }
+        count
     }
 
     pub fn remaining(&self) -> usize {
@@ -173,13 +227,14 @@ impl<'a> DeserializeContext<'a> {
         let mut continue_index = None::<usize>;
         let mut indirection_count = 0usize;
         loop {
+            let label_index = self.index;
             let start = self.read_u8()?;
             if start >> 6 == 0b11 {
                 // pointer
                 self.index -= 1;
                 let new_index = (self.read(u16::from_be_bytes)? & 0b0011111111111111) as usize;
                 if new_index >= start_index || new_index > self.max_length {
-                    return Err(PacketParseError::CorruptName);
+                    return Err(PacketParseError::CorruptName(label_index));
                 }
                 if continue_index.is_none() {
                     continue_index = Some(self.index);
@@ -190,21 +245,20 @@ impl<'a> DeserializeContext<'a> {
                 break;
             } else if start >> 6 == 0 {
                 // raw segment
-                let mut segment: SmallVec<[u8; 64]> = smallvec![0u8; start as usize];
-                self.read_all(&mut segment)?;
-                let segment = std::str::from_utf8(&segment)?;
+                let segment = std::str::from_utf8(self.read_slice(start as usize)?)?;
                 out.push_segment(segment).unwrap();
             } else {
-                return Err(PacketParseError::CorruptName);
+                return Err(PacketParseError::CorruptName(label_index));
             }
             indirection_count += 1;
             if indirection_count > MAX_NAME_INDIRECTION {
-                return Err(PacketParseError::CorruptName);
+                return Err(PacketParseError::CorruptName(label_index));
             }
         }
         if let Some(continue_index) = continue_index {
             self.index = continue_index;
         }
-        Ok(out)
+        // names on the wire are always absolute
+        Ok(out.into_fqdn())
     }
 }
@@ -0,0 +1,179 @@
+use crate::{context::SerializeContext, Class, Name, Record, Type};
+
+/// RFC 4034 section 2 DNSKEY RDATA
+#[derive(Clone, PartialEq, Eq, Debug, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DnskeyData {
+    pub flags: u16,
+    /// always 3
+    pub protocol: u8,
+    pub algorithm: u8,
+    pub public_key: Vec<u8>,
+}
+
+impl DnskeyData {
+    /// the zone key flag, set on every key that signs a zone
+    pub const ZONE_KEY: u16 = 0x0100;
+    /// the secure entry point flag, set on keys referenced by a DS in the parent
+    pub const SECURE_ENTRY_POINT: u16 = 0x0001;
+
+    pub(crate) fn serialize(&self, context: &mut SerializeContext) {
+        context.write_blob(self.flags.to_be_bytes());
+        context.write_blob(self.protocol.to_be_bytes());
+        context.write_blob(self.algorithm.to_be_bytes());
+        context.write_blob(&self.public_key);
+    }
+
+    /// the key tag identifying this key in RRSIG and DS records (RFC 4034 appendix B)
+    pub fn key_tag(&self) -> u16 {
+        let mut context = SerializeContext::default();
+        self.serialize(&mut context);
+        let mut tag: u32 = 0;
+        for (i, byte) in context.finalize().into_iter().enumerate() {
+            tag += if i % 2 == 0 {
+                (byte as u32) << 8
+            } else {
+                byte as u32
+            };
+        }
+        tag += (tag >> 16) & 0xffff;
+        (tag & 0xffff) as u16
+    }
+}
+
+/// RFC 4034 section 3 RRSIG RDATA. times are seconds since the unix epoch
+#[derive(Clone, PartialEq, Eq, Debug, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RrsigData {
+    pub type_covered: Type,
+    pub algorithm: u8,
+    /// labels in the owner name, not counting a leading `*`
+    pub labels: u8,
+    pub original_ttl: u32,
+    pub expiration: u32,
+    pub inception: u32,
+    pub key_tag: u16,
+    pub signer: Name,
+    pub signature: Vec<u8>,
+}
+
+impl RrsigData {
+    /// the fields before the signer name
+    fn write_header(&self, context: &mut SerializeContext) {
+        context.write_blob(u16::from(self.type_covered).to_be_bytes());
+        context.write_blob(self.algorithm.to_be_bytes());
+        context.write_blob(self.labels.to_be_bytes());
+        context.write_blob(self.original_ttl.to_be_bytes());
+        context.write_blob(self.expiration.to_be_bytes());
+        context.write_blob(self.inception.to_be_bytes());
+        context.write_blob(self.key_tag.to_be_bytes());
+    }
+
+    pub(crate) fn serialize(&self, context: &mut SerializeContext) {
+        self.write_header(context);
+        context.write_name_uncompressed(&self.signer);
+        context.write_blob(&self.signature);
+    }
+
+    /// the data `signature` is computed over for the RRset `records` (RFC 4034 section 3.1.8.1):
+    /// these fields without the signature, then the records in canonical form and order
+    pub fn signed_data(&self, records: &[Record]) -> Vec<u8> {
+        let mut context = SerializeContext::canonical();
+        self.write_header(&mut context);
+        context.write_name(&self.signer);
+        let mut rdatas: Vec<Vec<u8>> = records
+            .iter()
+            .map(|record| {
+                let mut rdata = SerializeContext::canonical();
+                record.data.serialize(&mut rdata);
+                rdata.finalize()
+            })
+            .collect();
+        rdatas.sort();
+        rdatas.dedup();
+        let (name, class) = records
+            .first()
+            .map(|x| (x.name.clone(), x.class))
+            .unwrap_or_default();
+        for rdata in rdatas {
+            context.write_name(&name);
+            context.write_blob(u16::from(self.type_covered).to_be_bytes());
+            context.write_blob(<Class as Into<u16>>::into(class).to_be_bytes());
+            context.write_blob(self.original_ttl.to_be_bytes());
+            context.write_blob((rdata.len() as u16).to_be_bytes());
+            context.write_blob(rdata);
+        }
+        context.finalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TypeData;
+
+    #[test]
+    fn test_key_tag() {
+        let key = DnskeyData {
+            flags: 256,
+            protocol: 3,
+            algorithm: 13,
+            public_key: vec![1, 2],
+        };
+        // the RDATA 01 00 03 0d 01 02, summed as 16 bit words
+        assert_eq!(key.key_tag(), 0x0100 + 0x030d + 0x0102);
+    }
+
+    #[test]
+    fn test_rrsig_presentation() {
+        let record = TypeData::parse_str(
+            Type::RRSIG,
+            "A 13 3 3600 20100909100439 20100812100439 55648 example.net. qx6w LYqm",
+        )
+        .unwrap();
+        assert_eq!(
+            record.to_string(),
+            "A 13 3 3600 20100909100439 20100812100439 55648 example.net qx6wLYqm"
+        );
+    }
+
+    #[test]
+    fn test_signed_data_case() {
+        let rrsig = |type_covered| RrsigData {
+            type_covered,
+            algorithm: 13,
+            labels: 2,
+            original_ttl: 300,
+            expiration: 0,
+            inception: 0,
+            key_tag: 0,
+            signer: "Example.NET".parse().unwrap(),
+            signature: vec![],
+        };
+        let contains = |data: &[u8], label: &[u8]| data.windows(label.len()).any(|x| x == label);
+
+        let mx = [Record::new(
+            "WWW.example.net".parse().unwrap(),
+            300,
+            TypeData::MX {
+                preference: 10,
+                exchange: "Mail.example.net".parse().unwrap(),
+            },
+        )];
+        let data = rrsig(Type::MX).signed_data(&mx);
+        assert!(contains(&data, b"\x07example"));
+        assert!(contains(&data, b"\x03www") && contains(&data, b"\x04mail"));
+
+        // the NSEC next name keeps its case (RFC 6840 section 5.1)
+        let nsec = [Record::new(
+            "a.example.net".parse().unwrap(),
+            300,
+            TypeData::NSEC {
+                next: "B.example.net".parse().unwrap(),
+                types: vec![Type::A],
+            },
+        )];
+        let data = rrsig(Type::NSEC).signed_data(&nsec);
+        assert!(contains(&data, b"\x01B\x07example") && !contains(&data, b"\x01b\x07example"));
+    }
+}
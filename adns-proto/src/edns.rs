@@ -0,0 +1,99 @@
+use crate::{Class, Name, OptData, OptItem, Record, Type, TypeData};
+
+/// option code of an Extended DNS Error (RFC 8914)
+pub const EXTENDED_ERROR_OPTION: u16 = 15;
+
+/// Extended DNS Error info-codes (RFC 8914 section 4)
+pub mod extended_error {
+    pub const OTHER: u16 = 0;
+    pub const STALE_ANSWER: u16 = 3;
+    pub const PROHIBITED: u16 = 18;
+    pub const NOT_AUTHORITATIVE: u16 = 20;
+    pub const NOT_SUPPORTED: u16 = 21;
+    pub const NO_REACHABLE_AUTHORITY: u16 = 22;
+    pub const NETWORK_ERROR: u16 = 23;
+}
+
+/// EDNS(0) pseudo-record (RFC 6891), carried as an OPT record in the additional section
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edns {
+    /// stored in the OPT record's class field
+    pub udp_payload_size: u16,
+    /// upper 8 bits of the 12 bit extended response code, stored in the OPT record's TTL field
+    pub extended_rcode: u8,
+    pub version: u8,
+    pub dnssec_ok: bool,
+    /// remaining 15 bits of flags
+    pub z: u16,
+    /// in wire order. codes we don't understand are kept as-is, so they serialize back byte for byte
+    pub options: OptData,
+}
+
+impl Edns {
+    pub fn new(udp_payload_size: u16) -> Self {
+        Self {
+            udp_payload_size,
+            extended_rcode: 0,
+            version: 0,
+            dnssec_ok: false,
+            z: 0,
+            options: Default::default(),
+        }
+    }
+
+    /// attaches an Extended DNS Error with an info-code from [`extended_error`] and optional text
+    pub fn push_extended_error(&mut self, info_code: u16, text: &str) {
+        let mut data = info_code.to_be_bytes().to_vec();
+        data.extend_from_slice(text.as_bytes());
+        self.options.items.push(OptItem {
+            code: EXTENDED_ERROR_OPTION,
+            data,
+        });
+    }
+
+    /// the first Extended DNS Error attached, as info-code and text
+    pub fn extended_error(&self) -> Option<(u16, String)> {
+        let item = self
+            .options
+            .items
+            .iter()
+            .find(|x| x.code == EXTENDED_ERROR_OPTION && x.data.len() >= 2)?;
+        Some((
+            u16::from_be_bytes([item.data[0], item.data[1]]),
+            String::from_utf8_lossy(&item.data[2..]).into_owned(),
+        ))
+    }
+
+    /// returns `None` if `record` is not an OPT record
+    pub fn from_record(record: Record) -> Option<Self> {
+        if record.type_ != Type::OPT {
+            return None;
+        }
+        let options = match record.data {
+            TypeData::OPT(options) => options,
+            _ => Default::default(),
+        };
+        let [extended_rcode, version, flags_hi, flags_lo] = record.ttl.to_be_bytes();
+        let flags = u16::from_be_bytes([flags_hi, flags_lo]);
+        Some(Self {
+            udp_payload_size: record.class.into(),
+            extended_rcode,
+            version,
+            dnssec_ok: flags & 0x8000 != 0,
+            z: flags & 0x7fff,
+            options,
+        })
+    }
+
+    pub fn to_record(&self) -> Record {
+        let flags = ((self.dnssec_ok as u16) << 15) | (self.z & 0x7fff);
+        let [flags_hi, flags_lo] = flags.to_be_bytes();
+        Record {
+            name: Name::default(),
+            type_: Type::OPT,
+            class: Class::from(self.udp_payload_size),
+            ttl: u32::from_be_bytes([self.extended_rcode, self.version, flags_hi, flags_lo]),
+            data: TypeData::OPT(self.options.clone()),
+        }
+    }
+}
@@ -17,6 +17,7 @@ pub enum Opcode {
     Query,
     InverseQuery,
     Status,
+    Notify,
     Update,
     Other(u8),
 }
@@ -27,6 +28,7 @@ impl From<u8> for Opcode {
             0 => Opcode::Query,
             1 => Opcode::InverseQuery,
             2 => Opcode::Status,
+            4 => Opcode::Notify,
             5 => Opcode::Update,
             3..=15 => Opcode::Other(value),
             _ => panic!("invalid range of value for opcode"),
@@ -40,6 +42,7 @@ impl From<Opcode> for u8 {
             Opcode::Query => 0,
             Opcode::InverseQuery => 1,
             Opcode::Status => 2,
+            Opcode::Notify => 4,
             Opcode::Update => 5,
             Opcode::Other(x) => x,
         }
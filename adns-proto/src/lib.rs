@@ -13,9 +13,18 @@ pub use name::*;
 mod packet;
 pub use packet::*;
 
+mod builder;
+pub use builder::*;
+
 mod types;
 pub use types::*;
 
+mod edns;
+pub use edns::*;
+
+mod dnssec;
+pub use dnssec::*;
+
 #[cfg(feature = "tsig")]
 pub mod tsig;
 
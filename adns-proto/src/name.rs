@@ -3,6 +3,7 @@ use std::{
     borrow::Cow,
     cmp::Ordering,
     hash::{Hash, Hasher},
+    net::IpAddr,
     str::FromStr,
 };
 
@@ -13,12 +14,14 @@ use thiserror::Error;
 pub struct Name {
     full: String,
     segment_indices: SmallVec<[u16; 8]>,
+    /// written with a trailing dot, or read off the wire. not part of equality, ordering or hashing
+    fqdn: bool,
 }
 
 #[cfg(feature = "serde")]
 impl serde::Serialize for Name {
     fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        self.full.serialize(serializer)
+        serializer.collect_str(self)
     }
 }
 
@@ -30,64 +33,97 @@ impl<'de> serde::Deserialize<'de> for Name {
     }
 }
 
+// names with U-labels compare by their A-label form, so `münchen.de` == `xn--mnchen-3ya.de`.
+// equality, hashing and ordering all go by labels, so `a\.b.c` (two labels) != `a.b.c` (three)
 impl PartialEq for Name {
     fn eq(&self, other: &Self) -> bool {
-        self.full.eq_ignore_ascii_case(&other.full)
+        let (lhs, rhs) = (self.ascii_form(), other.ascii_form());
+        lhs.segment_indices == rhs.segment_indices && lhs.full.eq_ignore_ascii_case(&rhs.full)
     }
 }
 
 impl PartialEq<str> for Name {
     fn eq(&self, other: &str) -> bool {
-        self.full.eq_ignore_ascii_case(other)
+        if self.full.is_ascii()
+            && other.is_ascii()
+            && !other.contains('\\')
+            && !self.has_dotted_label()
+        {
+            return self.full.eq_ignore_ascii_case(other);
+        }
+        other.parse::<Name>().is_ok_and(|other| *self == other)
     }
 }
 
 impl PartialEq<&str> for Name {
     fn eq(&self, other: &&str) -> bool {
-        self.full.eq_ignore_ascii_case(other)
+        *self == **other
     }
 }
 
 impl PartialOrd for Name {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        let l = self.full.len().min(other.full.len());
-        let lhs = &self.full.as_bytes()[..l];
-        let rhs = &other.full.as_bytes()[..l];
-        for i in 0..l {
-            match lhs[i]
-                .to_ascii_lowercase()
-                .cmp(&rhs[i].to_ascii_lowercase())
-            {
-                Ordering::Equal => (),
-                non_eq => return Some(non_eq),
-            }
-        }
-        Some(self.full.len().cmp(&other.full.len()))
+        Some(self.cmp(other))
     }
 }
 
+/// the canonical order, see `Name::canonical_cmp`. maps keyed by names keep their insertion order regardless
 impl Ord for Name {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.partial_cmp(other).unwrap()
+        self.canonical_cmp(other)
+    }
+}
+
+impl Name {
+    /// RFC 4034 section 6.1 canonical order: labels compared right to left as lowercased octets (of the A-label
+    /// form), so names sort after their ancestors and next to their siblings. the order NSEC chains follow
+    pub fn canonical_cmp(&self, other: &Self) -> std::cmp::Ordering {
+        let (lhs, rhs) = (self.ascii_form(), other.ascii_form());
+        lhs.cmp_ascii(&rhs)
+    }
+
+    fn cmp_ascii(&self, other: &Self) -> std::cmp::Ordering {
+        for (lhs, rhs) in self.segments().rev().zip(other.segments().rev()) {
+            let lhs = lhs.bytes().map(|x| x.to_ascii_lowercase());
+            match lhs.cmp(rhs.bytes().map(|x| x.to_ascii_lowercase())) {
+                Ordering::Equal => (),
+                non_eq => return non_eq,
+            }
+        }
+        self.segment_indices.len().cmp(&other.segment_indices.len())
     }
 }
 
-// impl AsRef<str> for Name {
-//     fn as_ref(&self) -> &str {
-//         &self.full
-//     }
-// }
+/// the name as written, without a trailing dot. the same as `Name::raw`
+impl AsRef<str> for Name {
+    fn as_ref(&self) -> &str {
+        &self.full
+    }
+}
 
 impl fmt::Display for Name {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // labels containing dots are escaped so the name parses back the same
+        if self.has_dotted_label() {
+            for (i, segment) in self.segments().enumerate() {
+                if i > 0 {
+                    f.write_str(".")?;
+                }
+                f.write_str(&segment.replace('.', "\\."))?;
+            }
+            return Ok(());
+        }
         write!(f, "{}", self.full)
     }
 }
 
 impl Hash for Name {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        for x in self.full.as_bytes() {
-            state.write_u8(x.to_ascii_lowercase());
+        for segment in self.ascii_form().segments() {
+            state.write_u8(segment.len() as u8);
+            for x in segment.as_bytes() {
+                state.write_u8(x.to_ascii_lowercase());
+            }
         }
         state.write_u8(0xff);
     }
@@ -97,23 +133,69 @@ impl Hash for Name {
 pub enum NameParseError {
     #[error("name label segment over 63 char long")]
     NameLabelTooLong,
-    #[error("name over 255 char long")]
+    #[error("name over 255 octets long in wire format")]
     NameTooLong,
+    #[error("label is not a valid internationalized domain name")]
+    InvalidIdna,
+    #[error("name contains an empty label")]
+    EmptyLabel,
+    #[error("email address has no `@`")]
+    InvalidEmail,
+}
+
+/// longest name allowed, in wire format (RFC 1035 section 2.3.4)
+const MAX_WIRE_LEN: usize = 255;
+
+/// splits master file style labels on dots, except `\.` which is a dot within a label (i.e. an SOA rname local part)
+fn split_escaped(s: &str) -> Vec<String> {
+    let mut labels = vec![String::new()];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('.') => labels.last_mut().unwrap().push('.'),
+                Some(c) => {
+                    labels.last_mut().unwrap().push('\\');
+                    labels.last_mut().unwrap().push(c);
+                }
+                None => labels.last_mut().unwrap().push('\\'),
+            },
+            '.' => labels.push(String::new()),
+            c => labels.last_mut().unwrap().push(c),
+        }
+    }
+    labels
 }
 
 impl FromStr for Name {
     type Err = NameParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() > 255 {
-            return Err(NameParseError::NameTooLong);
-        }
+        // a single trailing dot marks a fully qualified name, any other empty label is an error
+        let fqdn = s.ends_with('.') && !s.ends_with("\\.");
         let mut out = Name {
             full: String::with_capacity(s.len() + 1),
             segment_indices: Default::default(),
+            fqdn,
         };
-        for x in s.split('.') {
-            out.push_segment(x)?;
+        let s = if fqdn { &s[..s.len() - 1] } else { s };
+        if s.is_empty() {
+            return Ok(out);
+        }
+        if s.contains('\\') {
+            for x in split_escaped(s) {
+                if x.is_empty() {
+                    return Err(NameParseError::EmptyLabel);
+                }
+                out.push_segment(x)?;
+            }
+        } else {
+            for x in s.split('.') {
+                if x.is_empty() {
+                    return Err(NameParseError::EmptyLabel);
+                }
+                out.push_segment(x)?;
+            }
         }
         Ok(out)
     }
@@ -130,10 +212,20 @@ impl Name {
         unsafe { std::mem::transmute(out) }
     }
 
+    /// whether a label contains a dot, i.e. `full` doesn't split back into the same labels
+    fn has_dotted_label(&self) -> bool {
+        self.full.matches('.').count() + 1 > self.segment_indices.len().max(1)
+    }
+
     pub fn raw(&self) -> &str {
         &self.full
     }
 
+    /// octets in wire format: each label with its length octet, then the root label
+    pub fn wire_len(&self) -> usize {
+        self.segments().map(|x| x.len() + 1).sum::<usize>() + 1
+    }
+
     pub fn len(&self) -> usize {
         self.full.len()
     }
@@ -143,9 +235,10 @@ impl Name {
     }
 
     pub fn ends_with(&self, other: &Name) -> bool {
-        if self.full.eq_ignore_ascii_case(&other.full) {
-            return true;
-        }
+        self.ascii_form().ends_with_ascii(&other.ascii_form())
+    }
+
+    fn ends_with_ascii(&self, other: &Name) -> bool {
         if self.segment_indices.len() < other.segment_indices.len() {
             return false;
         }
@@ -159,7 +252,11 @@ impl Name {
 
     /// matches ** -> any number of segments (prefix only), *+ -> matches one or more segments, * -> any one segment, @ -> empty
     pub fn contains(&self, other: &Name) -> bool {
-        if self.full.eq_ignore_ascii_case(&other.full) {
+        self.ascii_form().contains_ascii(&other.ascii_form())
+    }
+
+    fn contains_ascii(&self, other: &Name) -> bool {
+        if self == other {
             return true;
         }
 
@@ -207,12 +304,29 @@ impl Name {
         for segment in segments {
             out.push_segment(segment.as_ref())?;
         }
-        if out.full.len() > 255 {
-            return Err(NameParseError::NameTooLong);
-        }
         Ok(out)
     }
 
+    /// whether the name is absolute: written with a trailing dot, read off the wire, or qualified
+    pub fn is_fqdn(&self) -> bool {
+        self.fqdn
+    }
+
+    /// marks the name absolute, as if written with a trailing dot
+    pub fn into_fqdn(mut self) -> Self {
+        self.fqdn = true;
+        self
+    }
+
+    /// appends `origin` to a relative name, as master files do (RFC 1035 section 5.1).
+    /// fully qualified names are returned as-is. `origin` is taken to be absolute, so the result always is.
+    pub fn qualify(&self, origin: &Name) -> Result<Name, NameParseError> {
+        if self.fqdn {
+            return Ok(self.clone());
+        }
+        Ok(Name::from_segments(self.segments().chain(origin.segments()))?.into_fqdn())
+    }
+
     pub fn push_segment(&mut self, segment: impl AsRef<str>) -> Result<(), NameParseError> {
         let segment = segment.as_ref();
         if segment.is_empty() {
@@ -221,19 +335,91 @@ impl Name {
         if segment.len() > 63 {
             return Err(NameParseError::NameLabelTooLong);
         }
+        if self.wire_len() + segment.len() + 1 > MAX_WIRE_LEN {
+            return Err(NameParseError::NameTooLong);
+        }
         self.full.reserve(segment.len() + 1);
         if !self.full.is_empty() {
             self.full.push('.');
         }
         let start = self.full.len();
         self.full.push_str(segment);
-        if self.full.len() > 255 {
-            return Err(NameParseError::NameTooLong);
-        }
         self.segment_indices.push(start.try_into().unwrap());
         Ok(())
     }
 
+    /// the `in-addr.arpa`/`ip6.arpa` name used for PTR lookups of `ip`
+    pub fn reverse_pointer(ip: IpAddr) -> Self {
+        let mut segments: Vec<String> = match ip {
+            IpAddr::V4(ip) => ip.octets().iter().rev().map(|x| x.to_string()).collect(),
+            IpAddr::V6(ip) => ip
+                .octets()
+                .iter()
+                .rev()
+                .flat_map(|x| [x & 0xf, x >> 4])
+                .map(|x| format!("{x:x}"))
+                .collect(),
+        };
+        match ip {
+            IpAddr::V4(_) => segments.extend(["in-addr".to_string(), "arpa".to_string()]),
+            IpAddr::V6(_) => segments.extend(["ip6".to_string(), "arpa".to_string()]),
+        }
+        Self::from_segments(segments).unwrap()
+    }
+
+    /// parses a name, converting any U-labels to punycode A-labels (`münchen.de` -> `xn--mnchen-3ya.de`)
+    pub fn from_unicode(name: &str) -> Result<Name, NameParseError> {
+        name.parse::<Name>()?.to_ascii()
+    }
+
+    /// the A-label form used for comparisons and on the wire, only allocating for names with non-ASCII segments
+    pub(crate) fn ascii_form(&self) -> Cow<'_, Name> {
+        if self.full.is_ascii() {
+            Cow::Borrowed(self)
+        } else {
+            Cow::Owned(self.to_ascii().unwrap_or_else(|_| self.clone()))
+        }
+    }
+
+    /// converts non-ASCII (U-label) segments to punycode A-labels (UTS #46), leaving ASCII segments as-is
+    pub fn to_ascii(&self) -> Result<Name, NameParseError> {
+        if self.full.is_ascii() {
+            return Ok(self.clone());
+        }
+        let mut out = Name::from_segments(
+            self.segments()
+                .map(|segment| {
+                    if segment.is_ascii() {
+                        Ok(Cow::Borrowed(segment))
+                    } else {
+                        idna::domain_to_ascii(segment)
+                            .map(Cow::Owned)
+                            .map_err(|_| NameParseError::InvalidIdna)
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        )?;
+        out.fqdn = self.fqdn;
+        Ok(out)
+    }
+
+    /// converts punycode A-label segments back to Unicode for display
+    pub fn to_unicode(&self) -> String {
+        self.segments()
+            .map(|segment| {
+                if segment.len() > 4 && segment[..4].eq_ignore_ascii_case("xn--") {
+                    match idna::domain_to_unicode(segment) {
+                        (unicode, Ok(())) => Cow::Owned(unicode),
+                        (_, Err(_)) => Cow::Borrowed(segment),
+                    }
+                } else {
+                    Cow::Borrowed(segment)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
     pub fn segments(&self) -> SegmentIterator<'_> {
         SegmentIterator {
             name: self,
@@ -297,6 +483,34 @@ impl<'a> DoubleEndedIterator for SegmentIterator<'a> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_as_ref() {
+        let name: Name = "www.Example.com".parse().unwrap();
+        let text: &str = name.as_ref();
+        assert_eq!(text, name.raw());
+        assert_eq!(text, "www.Example.com");
+    }
+
+    #[test]
+    fn test_idna() {
+        let name: Name = "_sip.München.example".parse().unwrap();
+        let ascii = name.to_ascii().unwrap();
+        assert_eq!(ascii, "_sip.xn--mnchen-3ya.example");
+        assert_eq!(ascii.to_unicode(), "_sip.münchen.example");
+
+        let name: Name = "*.Example.com".parse().unwrap();
+        assert_eq!(name.to_ascii().unwrap().raw(), "*.Example.com");
+
+        let unicode: Name = "www.münchen.de".parse().unwrap();
+        let ascii = Name::from_unicode("www.münchen.de").unwrap();
+        assert_eq!(ascii.raw(), "www.xn--mnchen-3ya.de");
+        assert_eq!(unicode, ascii);
+        assert_eq!(unicode, "WWW.xn--mnchen-3ya.de");
+        assert!(unicode.ends_with(&"xn--mnchen-3ya.de".parse().unwrap()));
+        assert!(ascii.ends_with(&"münchen.de".parse().unwrap()));
+        assert!("*.münchen.de".parse::<Name>().unwrap().contains(&ascii));
+    }
+
     #[test]
     fn test_name() {
         let name: Name = "test.com".parse().unwrap();
@@ -326,4 +540,128 @@ mod tests {
         assert!(name2.ends_with(&name));
         assert!(!name.ends_with(&name2));
     }
+
+    #[test]
+    fn test_escaped_dot_identity() {
+        use std::{collections::hash_map::DefaultHasher, hash::BuildHasher};
+
+        let escaped: Name = "hostmaster\\.team.example.com".parse().unwrap();
+        let plain: Name = "hostmaster.team.example.com".parse().unwrap();
+        assert_ne!(escaped, plain);
+        assert_ne!(escaped.cmp(&plain), Ordering::Equal);
+        assert_ne!(escaped, "hostmaster.team.example.com");
+        assert_eq!(escaped, "hostmaster\\.team.example.com");
+        assert!(!escaped.ends_with(&plain));
+        assert!(!plain.ends_with(&escaped));
+
+        let hasher = std::hash::BuildHasherDefault::<DefaultHasher>::default();
+        assert_ne!(hasher.hash_one(&escaped), hasher.hash_one(&plain));
+        let upper: Name = "HOSTMASTER\\.team.example.com".parse().unwrap();
+        assert_eq!(escaped, upper);
+        assert_eq!(hasher.hash_one(&escaped), hasher.hash_one(&upper));
+        assert_eq!(escaped.cmp(&upper), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_empty_label() {
+        assert_eq!(
+            "test.com.".parse::<Name>().unwrap(),
+            "test.com".parse::<Name>().unwrap()
+        );
+        assert!("".parse::<Name>().unwrap().is_empty());
+        assert!(".".parse::<Name>().unwrap().is_empty());
+        for name in ["a..b", ".a", "a.b..", ".."] {
+            assert!(matches!(
+                name.parse::<Name>(),
+                Err(NameParseError::EmptyLabel)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_name_too_long() {
+        // 3 labels of 63 and one of 61: 4 * (63 + 1) - 2 + 1 = 255 octets on the wire
+        let label = |len| "a".repeat(len);
+        let longest = format!("{0}.{0}.{0}.{1}", label(63), label(61));
+        assert_eq!(longest.len(), 253);
+        let name: Name = longest.parse().unwrap();
+        assert_eq!(name.wire_len(), 255);
+        assert!(matches!(
+            format!("{longest}b").parse::<Name>(),
+            Err(NameParseError::NameTooLong)
+        ));
+        // the length octets count too, short labels run out well before 255 characters
+        let short_labels = vec!["a"; 128];
+        assert!(matches!(
+            Name::from_segments(&short_labels),
+            Err(NameParseError::NameTooLong)
+        ));
+        assert_eq!(
+            Name::from_segments(&short_labels[..127])
+                .unwrap()
+                .wire_len(),
+            255
+        );
+        // an escaped dot is a single octet
+        let escaped = format!("{}\\..{}", label(62), &longest[64..]);
+        assert_eq!(escaped.parse::<Name>().unwrap().wire_len(), 255);
+    }
+
+    #[test]
+    fn test_canonical_order() {
+        // RFC 4034 section 6.1, with `\001` written out. `\200` isn't ASCII, so has no place here
+        let ordered = [
+            "example",
+            "a.example",
+            "yljkjljk.a.example",
+            "Z.a.example",
+            "zABC.a.EXAMPLE",
+            "z.example",
+            "\u{1}.z.example",
+            "*.z.example",
+        ];
+        let mut names: Vec<Name> = ordered.iter().rev().map(|x| x.parse().unwrap()).collect();
+        names.sort_by(Name::canonical_cmp);
+        let sorted: Vec<&str> = names.iter().map(|x| x.raw()).collect();
+        assert_eq!(sorted, ordered);
+        names.reverse();
+        names.sort();
+        let sorted: Vec<&str> = names.iter().map(|x| x.raw()).collect();
+        assert_eq!(sorted, ordered);
+        // the dotted strings would sort the other way around
+        assert!("*.z.example" < "z.example");
+    }
+
+    #[test]
+    fn test_fqdn() {
+        let origin: Name = "example.com.".parse().unwrap();
+        assert!(origin.is_fqdn());
+        let relative: Name = "www".parse().unwrap();
+        assert!(!relative.is_fqdn());
+        assert!(".".parse::<Name>().unwrap().is_fqdn());
+        // an escaped trailing dot belongs to the last label
+        let escaped: Name = "first\\.".parse().unwrap();
+        assert!(!escaped.is_fqdn());
+        assert_eq!(escaped.segments().next(), Some("first."));
+
+        let qualified = relative.qualify(&origin).unwrap();
+        assert!(qualified.is_fqdn());
+        assert_eq!(qualified.raw(), "www.example.com");
+        let absolute: Name = "www.example.net.".parse().unwrap();
+        assert_eq!(absolute.qualify(&origin).unwrap().raw(), "www.example.net");
+        // the flag doesn't affect equality
+        assert_eq!(origin, "example.com".parse::<Name>().unwrap());
+    }
+
+    #[test]
+    fn test_reverse_pointer() {
+        assert_eq!(
+            Name::reverse_pointer("192.0.2.1".parse().unwrap()),
+            "1.2.0.192.in-addr.arpa"
+        );
+        assert_eq!(
+            Name::reverse_pointer("2001:db8::1".parse().unwrap()),
+            "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa"
+        );
+    }
 }
@@ -2,16 +2,34 @@ use thiserror::Error;
 
 use crate::{
     context::{DeserializeContext, SerializeContext},
-    Header, Name, Question, Record, TsigData, Type, TypeData,
+    Edns, Header, Name, Question, Record, TsigData, Type, TypeData,
 };
 
-#[derive(Default, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct Packet {
     pub header: Header,
     pub questions: Vec<Question>,
     pub answers: Vec<Record>,
     pub nameservers: Vec<Record>,
+    /// excludes the EDNS OPT record and TSIG record
     pub additional_records: Vec<Record>,
+    pub edns: Option<Edns>,
+    /// UDP payload size advertised by the sender in an EDNS OPT record, or 512 without EDNS
+    pub udp_max_size: u16,
+}
+
+impl Default for Packet {
+    fn default() -> Self {
+        Self {
+            header: Default::default(),
+            questions: Default::default(),
+            answers: Default::default(),
+            nameservers: Default::default(),
+            additional_records: Default::default(),
+            edns: None,
+            udp_max_size: Packet::MIN_UDP_SIZE,
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -22,14 +40,14 @@ pub enum PacketParseError {
     Truncated,
     #[error("the header was invalid")]
     InvalidHeader,
-    #[error("unexpected EOF")]
-    UnexpectedEOF,
-    #[error("corrupt name, invalid label tag, length, or ptr")]
-    CorruptName,
+    #[error("unexpected EOF at offset {0}")]
+    UnexpectedEOF(usize),
+    #[error("corrupt name at offset {0}, invalid label tag, length, or ptr")]
+    CorruptName(usize),
     #[error("invalid UTF8 in name: {0}")]
     UTF8Error(#[from] std::str::Utf8Error),
-    #[error("invalid record bytes")]
-    CorruptRecord,
+    #[error("invalid record bytes at offset {0}")]
+    CorruptRecord(usize),
 }
 
 pub struct ValidatableTsig<'a> {
@@ -39,6 +57,34 @@ pub struct ValidatableTsig<'a> {
 }
 
 impl Packet {
+    /// the maximum UDP payload size without EDNS, and the lowest size EDNS may advertise
+    pub const MIN_UDP_SIZE: u16 = 512;
+    /// root name, type and class
+    const MIN_QUESTION_SIZE: usize = 5;
+    /// root name, type, class, TTL and data length
+    const MIN_RECORD_SIZE: usize = 11;
+
+    /// an empty packet with room for the sections `header` counts. the counts are untrusted, so only as much is
+    /// reserved as the `remaining` bytes after the header could hold
+    fn with_capacity(header: Header, remaining: usize) -> Packet {
+        let capacity = |count: u16, min_size: usize| (count as usize).min(remaining / min_size);
+        Packet {
+            questions: Vec::with_capacity(capacity(header.question_count, Self::MIN_QUESTION_SIZE)),
+            answers: Vec::with_capacity(capacity(header.answer_count, Self::MIN_RECORD_SIZE)),
+            nameservers: Vec::with_capacity(capacity(
+                header.nameserver_count,
+                Self::MIN_RECORD_SIZE,
+            )),
+            additional_records: Vec::with_capacity(capacity(
+                header.additional_record_count,
+                Self::MIN_RECORD_SIZE,
+            )),
+            header,
+            edns: None,
+            udp_max_size: Packet::MIN_UDP_SIZE,
+        }
+    }
+
     pub fn parse(bytes: &[u8]) -> Result<(Packet, Option<ValidatableTsig<'_>>), PacketParseError> {
         if bytes.len() < Header::LENGTH {
             return Err(PacketParseError::HeaderTruncated);
@@ -50,13 +96,7 @@ impl Packet {
         if header.is_truncated {
             return Err(PacketParseError::Truncated);
         }
-        let mut packet = Packet {
-            questions: Vec::with_capacity(header.question_count as usize),
-            answers: Vec::with_capacity(header.answer_count as usize),
-            nameservers: Vec::with_capacity(header.nameserver_count as usize),
-            additional_records: Vec::with_capacity(header.additional_record_count as usize),
-            header,
-        };
+        let mut packet = Self::with_capacity(header, bytes.len() - Header::LENGTH);
         let mut context = DeserializeContext::new_post_header(bytes);
         for _ in 0..packet.header.question_count {
             packet.questions.push(Question::parse(&mut context)?);
@@ -83,55 +123,88 @@ impl Packet {
                 });
                 continue;
             }
+            if record.type_ == Type::OPT && packet.edns.is_none() {
+                let edns = Edns::from_record(record).unwrap();
+                packet.udp_max_size = edns.udp_payload_size.max(Packet::MIN_UDP_SIZE);
+                packet.edns = Some(edns);
+                continue;
+            }
             packet.additional_records.push(record);
         }
 
         Ok((packet, tsig))
     }
 
-    pub(crate) fn serialize_open(&self) -> (Header, SerializeContext) {
+    /// serializes the first `record_limit` records across the answer, authority and additional sections,
+    /// returning the end offset of the questions and of each record written
+    fn serialize_records(&self, record_limit: usize) -> (Header, SerializeContext, Vec<usize>) {
         let mut context = SerializeContext::default();
 
+        let answers = record_limit.min(self.answers.len());
+        let nameservers = (record_limit - answers).min(self.nameservers.len());
+        let additional_records =
+            (record_limit - answers - nameservers).min(self.additional_records.len());
+
         let mut header = self.header.clone();
         header.question_count = self.questions.len().try_into().unwrap();
-        header.answer_count = self.answers.len().try_into().unwrap();
-        header.nameserver_count = self.nameservers.len().try_into().unwrap();
-        header.additional_record_count = self.additional_records.len().try_into().unwrap();
+        header.answer_count = answers.try_into().unwrap();
+        header.nameserver_count = nameservers.try_into().unwrap();
+        header.additional_record_count = (additional_records + self.edns.is_some() as usize)
+            .try_into()
+            .unwrap();
+        if answers + nameservers + additional_records < self.record_count() {
+            header.is_truncated = true;
+        }
         context.write_blob(header.to_bytes());
 
         for question in &self.questions {
             question.serialize(&mut context);
         }
-        for record in &self.answers {
-            record.serialize(&mut context);
-        }
-        for record in &self.nameservers {
+        let mut ends = vec![context.current().len()];
+        for record in self.answers[..answers]
+            .iter()
+            .chain(&self.nameservers[..nameservers])
+            .chain(&self.additional_records[..additional_records])
+        {
             record.serialize(&mut context);
+            ends.push(context.current().len());
         }
-        for record in &self.additional_records {
-            record.serialize(&mut context);
+        if let Some(edns) = &self.edns {
+            edns.to_record().serialize(&mut context);
         }
 
-        (header, context)
+        (header, context, ends)
     }
 
-    pub fn serialize(&self, max_size: usize) -> Vec<u8> {
-        let (mut header, context) = self.serialize_open();
+    fn record_count(&self) -> usize {
+        self.answers.len() + self.nameservers.len() + self.additional_records.len()
+    }
 
-        let mut out = context.finalize();
-        if out.len() > max_size {
-            out.truncate(max_size);
-            header.is_truncated = true;
-            out[..Header::LENGTH].copy_from_slice(&header.to_bytes());
+    /// serializes as many whole records as fit in `max_size` (answers, then authority, then additional),
+    /// setting TC if any were dropped. the OPT record is always kept.
+    pub(crate) fn serialize_open(&self, max_size: usize) -> (Header, SerializeContext) {
+        let (header, context, ends) = self.serialize_records(self.record_count());
+        if context.current().len() <= max_size {
+            return (header, context);
         }
-        out
+        let trailer = context.current().len() - ends.last().unwrap();
+        let keep = ends
+            .iter()
+            .rposition(|end| end + trailer <= max_size)
+            .unwrap_or_default();
+        let (header, context, _) = self.serialize_records(keep);
+        (header, context)
+    }
+
+    pub fn serialize(&self, max_size: usize) -> Vec<u8> {
+        self.serialize_open(max_size).1.finalize()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{test_data::*, Class, Type, TypeData};
+    use crate::{extended_error, test_data::*, Class, Edns, OptItem, Type, TypeData};
 
     #[test]
     fn test_packet_parse() {
@@ -142,6 +215,12 @@ mod tests {
         assert_eq!(question.name, "google.com");
         assert_eq!(question.type_, Type::A);
         assert_eq!(question.class, Class::IN);
+        assert_eq!(packet.udp_max_size, 1232);
+        assert!(packet.additional_records.is_empty());
+        let edns = packet.edns.as_ref().unwrap();
+        assert_eq!(edns.udp_payload_size, 1232);
+        assert_eq!(edns.options.items.len(), 1);
+        assert_eq!(edns.options.items[0].code, 10);
 
         assert_eq!(&DNS_QUERY[..], &packet.serialize(512));
 
@@ -162,4 +241,184 @@ mod tests {
 
         assert_eq!(&DNS_RESPONSE[..], &packet.serialize(512));
     }
+
+    #[test]
+    fn test_truncation() {
+        let mut packet = Packet::parse(&DNS_RESPONSE).unwrap().0;
+        let answer = packet.answers[0].clone();
+        packet.answers = vec![answer; 200];
+        packet.edns = Some(Edns::new(512));
+
+        let mut serialized = packet.serialize(512);
+        assert!(serialized.len() <= 512);
+        assert!(matches!(
+            Packet::parse(&serialized),
+            Err(PacketParseError::Truncated)
+        ));
+        // parse refuses TC packets outright, so clear it to check the rest is well formed
+        serialized[2] &= !0x02;
+        let truncated = Packet::parse(&serialized).unwrap().0;
+        assert!(!truncated.answers.is_empty() && truncated.answers.len() < 200);
+        assert!(truncated.edns.is_some());
+
+        let full = Packet::parse(&packet.serialize(u16::MAX as usize))
+            .unwrap()
+            .0;
+        assert!(!full.header.is_truncated);
+        assert_eq!(full.answers.len(), 200);
+    }
+
+    #[test]
+    fn test_nsec_round_trip() {
+        let mut packet = Packet::parse(&DNS_RESPONSE).unwrap().0;
+        let nsec = Record::new(
+            "a.example.com".parse().unwrap(),
+            300,
+            TypeData::NSEC {
+                next: "b.example.com".parse().unwrap(),
+                types: vec![Type::A, Type::RRSIG, Type::NSEC, Type::CAA],
+            },
+        );
+        packet.answers = vec![nsec.clone()];
+        let parsed = Packet::parse(&packet.serialize(u16::MAX as usize))
+            .unwrap()
+            .0;
+        assert_eq!(parsed.answers, vec![nsec]);
+    }
+
+    #[test]
+    fn test_large_rdata_round_trip() {
+        let mut packet = Packet::parse(&DNS_RESPONSE).unwrap().0;
+        let strings: Vec<String> = (0..20).map(|i| format!("{i:0>255}")).collect();
+        let txt = Record::new(
+            "a.example.com".parse().unwrap(),
+            300,
+            TypeData::TXT(strings.into()),
+        );
+        let key = Record::new(
+            "a.example.com".parse().unwrap(),
+            300,
+            TypeData::OPENPGPKEY((0..4000).map(|i| i as u8).collect()),
+        );
+        packet.answers = vec![txt.clone(), key.clone()];
+        let parsed = Packet::parse(&packet.serialize(u16::MAX as usize))
+            .unwrap()
+            .0;
+        assert_eq!(parsed.answers, vec![txt, key]);
+        // the strings are counted up front, so the list is allocated once at its exact size
+        let TypeData::TXT(strings) = &parsed.answers[0].data else {
+            panic!("not TXT");
+        };
+        assert_eq!(strings.capacity(), 20);
+    }
+
+    #[test]
+    fn test_unicode_name_on_wire() {
+        let mut packet = Packet::parse(&DNS_QUERY).unwrap().0;
+        packet.questions = vec![Question::new(Type::A, "www.münchen.example").unwrap()];
+        packet.answers = vec![Record::new(
+            "www.münchen.example".parse().unwrap(),
+            300,
+            TypeData::CNAME("münchen.example".parse().unwrap()),
+        )];
+        let serialized = packet.serialize(u16::MAX as usize);
+        assert!(!serialized.windows(2).any(|x| x == "ü".as_bytes()));
+        let parsed = Packet::parse(&serialized).unwrap().0;
+        assert_eq!(parsed.questions[0].name.raw(), "www.xn--mnchen-3ya.example");
+        assert_eq!(
+            parsed.answers[0].data,
+            TypeData::CNAME(Name::from_unicode("münchen.example").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_extended_error_round_trip() {
+        let mut packet = Packet::parse(&DNS_RESPONSE).unwrap().0;
+        let mut edns = Edns::new(1232);
+        edns.push_extended_error(extended_error::PROHIBITED, "not allowed");
+        packet.edns = Some(edns);
+        let parsed = Packet::parse(&packet.serialize(u16::MAX as usize))
+            .unwrap()
+            .0;
+        assert_eq!(
+            parsed.edns.unwrap().extended_error(),
+            Some((extended_error::PROHIBITED, "not allowed".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_unknown_option_round_trip() {
+        let (packet, _) = Packet::parse(&DNS_QUERY).unwrap();
+        assert_eq!(packet.serialize(u16::MAX as usize), DNS_QUERY);
+
+        let mut packet = packet;
+        let unknown = OptItem {
+            code: 65001,
+            data: vec![0xde, 0xad, 0x00, 0xbe, 0xef],
+        };
+        packet
+            .edns
+            .as_mut()
+            .unwrap()
+            .options
+            .items
+            .push(unknown.clone());
+        let serialized = packet.serialize(u16::MAX as usize);
+        assert!(serialized.ends_with(&[0xfd, 0xe9, 0x00, 0x05, 0xde, 0xad, 0x00, 0xbe, 0xef]));
+        let (parsed, _) = Packet::parse(&serialized).unwrap();
+        assert_eq!(
+            parsed.edns.as_ref().unwrap().options.items.last(),
+            Some(&unknown)
+        );
+        assert_eq!(parsed.serialize(u16::MAX as usize), serialized);
+    }
+
+    #[test]
+    fn test_inflated_counts() {
+        // a bare header claiming the maximum count in every section
+        let mut header = [0u8; Header::LENGTH];
+        header[4..].fill(0xff);
+        assert!(matches!(
+            Packet::parse(&header),
+            Err(PacketParseError::UnexpectedEOF(12))
+        ));
+
+        // room is only reserved for what the bytes after the header could hold
+        let header = Header::parse(header);
+        let packet = Packet::with_capacity(header.clone(), 0);
+        assert_eq!(packet.questions.capacity(), 0);
+        assert_eq!(packet.answers.capacity(), 0);
+        assert_eq!(packet.nameservers.capacity(), 0);
+        assert_eq!(packet.additional_records.capacity(), 0);
+        let packet = Packet::with_capacity(header.clone(), 22);
+        assert!(packet.questions.capacity() <= 4);
+        assert!(packet.answers.capacity() <= 2);
+        assert!(packet.additional_records.capacity() <= 2);
+        // honest counts below that are reserved as given
+        let packet = Packet::with_capacity(
+            Header {
+                answer_count: 1,
+                ..header
+            },
+            512,
+        );
+        assert!(packet.answers.capacity() >= 1);
+        assert!(packet.nameservers.capacity() <= 512 / Packet::MIN_RECORD_SIZE);
+    }
+
+    #[test]
+    fn test_parse_error_offset() {
+        // the question name's first label length becomes a reserved label tag
+        let mut corrupt = DNS_QUERY;
+        corrupt[12] = 0x40;
+        assert!(matches!(
+            Packet::parse(&corrupt),
+            Err(PacketParseError::CorruptName(12))
+        ));
+        // the answer's 4 byte RDATA starts at 40
+        assert!(matches!(
+            Packet::parse(&DNS_RESPONSE[..42]),
+            Err(PacketParseError::UnexpectedEOF(40))
+        ));
+    }
 }
@@ -5,7 +5,7 @@ use crate::{
     Name, PacketParseError, Type, TypeData,
 };
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Record {
     #[serde(rename = "domain")]
@@ -24,6 +24,22 @@ impl fmt::Display for Record {
 }
 
 impl Record {
+    /// RFC 1035 master file presentation, `name. ttl class type data` with a fully qualified owner.
+    /// `Display` keeps its older `name type ttl data` layout for existing log consumers.
+    pub fn to_zone_line(&self) -> String {
+        let type_ = match self.type_ {
+            Type::Other(x) => format!("TYPE{x}"),
+            type_ => type_.to_string(),
+        };
+        format!(
+            "{}.\t{}\t{}\t{type_}\t{}",
+            self.name,
+            self.ttl,
+            self.class,
+            self.data.to_zone_string()
+        )
+    }
+
     pub fn new(name: Name, ttl: u32, data: TypeData) -> Self {
         Self {
             name,
@@ -62,14 +78,17 @@ impl Record {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, strum::IntoStaticStr, strum::EnumString)]
+#[derive(
+    Clone, Copy, PartialEq, Eq, Hash, Debug, Default, strum::IntoStaticStr, strum::EnumString,
+)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u16)]
 pub enum Class {
     #[default]
     IN = 1,
     // CS,
-    // CH,
+    /// CHAOS, used for server identification (RFC 4892)
+    CH = 3,
     // HS,
     NONE = 254,
     ALL = 255,
@@ -80,6 +99,7 @@ impl fmt::Display for Class {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Class::IN => write!(f, "IN"),
+            Class::CH => write!(f, "CH"),
             Class::NONE => write!(f, "NONE"),
             Class::ALL => write!(f, "ALL"),
             Class::Other(class) => write!(f, "CLASS{class:03}"),
@@ -91,6 +111,7 @@ impl From<u16> for Class {
     fn from(value: u16) -> Self {
         match value {
             1 => Class::IN,
+            3 => Class::CH,
             254 => Class::NONE,
             255 => Class::ALL,
             _ => Class::Other(value),
@@ -102,6 +123,7 @@ impl From<Class> for u16 {
     fn from(value: Class) -> Self {
         match value {
             Class::IN => 1,
+            Class::CH => 3,
             Class::NONE => 254,
             Class::ALL => 255,
             Class::Other(x) => x,
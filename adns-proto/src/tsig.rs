@@ -10,6 +10,9 @@ use sha1::Sha1;
 use sha2::{Sha224, Sha256, Sha384, Sha512};
 use thiserror::Error;
 
+/// output size of the longest supported HMAC (hmac-sha512)
+const MAX_MAC_LEN: usize = 64;
+
 #[derive(Error, Debug)]
 pub enum TsigError {
     #[error("unknown algorithm")]
@@ -152,16 +155,31 @@ pub fn serialize_packet(
     mode: TsigMode,
     request_mac: Option<&[u8]>,
 ) -> SerializedPacket {
-    let (mut header, mut context) = packet.serialize_open();
     let mut data = TsigData {
         algorithm,
         time_signed: Utc::now().timestamp() as u64,
         fudge: 300,
         mac: vec![],
-        original_id: header.id,
+        original_id: packet.header.id,
         error: TsigResponseCode::NoError,
         other_data: vec![],
     };
+    // leave room for the largest TSIG record we could append, so truncation happens before signing
+    let reserved = {
+        let mut context = SerializeContext::default();
+        Record::new(
+            name.clone(),
+            0,
+            TypeData::TSIG(TsigData {
+                mac: vec![0; MAX_MAC_LEN],
+                other_data: vec![0; 6],
+                ..data.clone()
+            }),
+        )
+        .serialize(&mut context);
+        context.finalize().len()
+    };
+    let (mut header, mut context) = packet.serialize_open(max_size.saturating_sub(reserved));
     let (record, mac) = match calculate(
         key_lookup,
         context.current(),
@@ -184,10 +202,6 @@ pub fn serialize_packet(
     record.serialize(&mut context);
 
     let mut out = context.finalize();
-    if out.len() > max_size {
-        out.truncate(max_size);
-        header.is_truncated = true;
-    }
     out[..Header::LENGTH].copy_from_slice(&header.to_bytes());
     SerializedPacket { packet: out, mac }
 }
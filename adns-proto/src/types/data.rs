@@ -1,10 +1,10 @@
 use std::net::{Ipv4Addr, Ipv6Addr};
 
-use smallvec::{smallvec, SmallVec};
+use smallvec::SmallVec;
 
 use crate::{
     context::{DeserializeContext, SerializeContext},
-    Name, PacketParseError, Type,
+    DnskeyData, Name, NameParseError, PacketParseError, RrsigData, Type,
 };
 
 #[derive(Clone, PartialEq, Eq, Debug, PartialOrd, Ord)]
@@ -59,14 +59,35 @@ pub enum TypeData {
         fingerprint: Vec<u8>,
     },
 
+    /// RFC 4034 authenticated denial: the next owner name in canonical order and the types present at this owner
+    NSEC {
+        next: Name,
+        types: Vec<Type>,
+    },
+
+    /// RFC 4034 public key of a zone signing key
+    DNSKEY(DnskeyData),
+    /// RFC 4034 signature over the RRset of `type_covered` at the owner name
+    RRSIG(RrsigData),
+
+    OPT(OptData),
+
+    SVCB(SvcbData),
+    HTTPS(SvcbData),
+
     TSIG(TsigData),
 
+    OPENPGPKEY(Vec<u8>),
+
     URI {
         priority: u16,
         weight: u16,
         target: String,
     },
 
+    /// answered as the target's A/AAAA records under the owner name, so it may sit at a zone apex
+    ALIAS(Name),
+
     Other(Type, SmallVec<[u8; 32]>),
 }
 
@@ -82,6 +103,64 @@ pub struct SoaData {
     pub minimum: u32,
 }
 
+impl SoaData {
+    /// the RNAME form of a mailbox (RFC 1035 section 8), `john.doe@example.com` -> `john\.doe.example.com`
+    pub fn rname_from_email(email: &str) -> Result<Name, NameParseError> {
+        let (local, domain) = email.rsplit_once('@').ok_or(NameParseError::InvalidEmail)?;
+        if local.is_empty() {
+            return Err(NameParseError::EmptyLabel);
+        }
+        let mut rname = Name::from_segments([local])?;
+        for segment in domain.parse::<Name>()?.segments() {
+            rname.push_segment(segment)?;
+        }
+        Ok(rname)
+    }
+
+    /// the mailbox of the zone's administrator, the inverse of `rname_from_email`
+    pub fn rname_to_email(&self) -> String {
+        let mut segments = self.rname.segments();
+        let local = segments.next().unwrap_or_default();
+        format!("{local}@{}", segments.collect::<Vec<_>>().join("."))
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SvcbData {
+    /// 0 is AliasMode, anything else is ServiceMode
+    pub priority: u16,
+    /// an empty name is `.`, meaning the owner name
+    pub target: Name,
+    pub params: Vec<SvcParam>,
+}
+
+impl SvcbData {
+    pub fn is_alias(&self) -> bool {
+        self.priority == 0
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SvcParam {
+    pub key: u16,
+    pub value: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OptData {
+    pub items: Vec<OptItem>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OptItem {
+    pub code: u16,
+    pub data: Vec<u8>,
+}
+
 #[derive(Clone, PartialEq, Eq, Debug, PartialOrd, Ord)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TsigData {
@@ -130,6 +209,68 @@ impl From<TsigResponseCode> for u16 {
     }
 }
 
+/// shorthands for building records in code, names are parsed like `Name::from_str`
+impl TypeData {
+    pub fn ns(target: &str) -> Result<Self, NameParseError> {
+        Ok(TypeData::NS(target.parse()?))
+    }
+
+    pub fn cname(target: &str) -> Result<Self, NameParseError> {
+        Ok(TypeData::CNAME(target.parse()?))
+    }
+
+    pub fn ptr(target: &str) -> Result<Self, NameParseError> {
+        Ok(TypeData::PTR(target.parse()?))
+    }
+
+    pub fn mx(preference: u16, exchange: &str) -> Result<Self, NameParseError> {
+        Ok(TypeData::MX {
+            preference,
+            exchange: exchange.parse()?,
+        })
+    }
+
+    pub fn srv(
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: &str,
+    ) -> Result<Self, NameParseError> {
+        Ok(TypeData::SRV {
+            priority,
+            weight,
+            port,
+            target: target.parse()?,
+        })
+    }
+
+    /// one TXT record of one or more strings
+    pub fn txt(strings: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        TypeData::TXT(strings.into_iter().map(Into::into).collect())
+    }
+
+    /// `rname` is a mailbox in name form (`hostmaster.example.com`), see `SoaData::rname_from_email`
+    pub fn soa(
+        mname: &str,
+        rname: &str,
+        serial: u32,
+        refresh: u32,
+        retry: u32,
+        expire: u32,
+        minimum: u32,
+    ) -> Result<Self, NameParseError> {
+        Ok(TypeData::SOA(SoaData {
+            mname: mname.parse()?,
+            rname: rname.parse()?,
+            serial,
+            refresh,
+            retry,
+            expire,
+            minimum,
+        }))
+    }
+}
+
 impl TypeData {
     pub fn dns_type(&self) -> Type {
         match self {
@@ -147,8 +288,16 @@ impl TypeData {
             TypeData::CERT { .. } => Type::CERT,
             TypeData::DNAME(..) => Type::DNAME,
             TypeData::SSHFP { .. } => Type::SSHFP,
+            TypeData::NSEC { .. } => Type::NSEC,
+            TypeData::DNSKEY(..) => Type::DNSKEY,
+            TypeData::RRSIG(..) => Type::RRSIG,
+            TypeData::OPT(..) => Type::OPT,
+            TypeData::SVCB(..) => Type::SVCB,
+            TypeData::HTTPS(..) => Type::HTTPS,
             TypeData::TSIG { .. } => Type::TSIG,
+            TypeData::OPENPGPKEY(..) => Type::OPENPGPKEY,
             TypeData::URI { .. } => Type::URI,
+            TypeData::ALIAS(..) => Type::ALIAS,
             TypeData::Other(type_, ..) => *type_,
         }
     }
@@ -241,6 +390,38 @@ impl TypeData {
                 context.write_blob(fp_type.to_be_bytes());
                 context.write_blob(fingerprint);
             }
+            TypeData::NSEC { next, types } => {
+                // never lowercased, not even in canonical form (RFC 6840 section 5.1)
+                context.write_name_uncompressed(next);
+                context.write_blob(write_type_bitmap(types));
+            }
+            TypeData::DNSKEY(data) => data.serialize(context),
+            TypeData::RRSIG(data) => data.serialize(context),
+            TypeData::SVCB(SvcbData {
+                priority,
+                target,
+                params,
+            })
+            | TypeData::HTTPS(SvcbData {
+                priority,
+                target,
+                params,
+            }) => {
+                context.write_blob(priority.to_be_bytes());
+                context.write_name_uncompressed(target);
+                for param in params {
+                    context.write_blob(param.key.to_be_bytes());
+                    context.write_blob((param.value.len() as u16).to_be_bytes());
+                    context.write_blob(&param.value);
+                }
+            }
+            TypeData::OPT(OptData { items }) => {
+                for item in items {
+                    context.write_blob(item.code.to_be_bytes());
+                    context.write_blob((item.data.len() as u16).to_be_bytes());
+                    context.write_blob(&item.data);
+                }
+            }
             TypeData::TSIG(TsigData {
                 algorithm,
                 time_signed,
@@ -260,6 +441,7 @@ impl TypeData {
                 context.write_blob((other_data.len() as u16).to_be_bytes());
                 context.write_blob(other_data);
             }
+            TypeData::OPENPGPKEY(x) => context.write_blob(x),
             TypeData::URI {
                 priority,
                 weight,
@@ -269,6 +451,8 @@ impl TypeData {
                 context.write_blob(weight.to_be_bytes());
                 context.write_blob(target);
             }
+            // not a well-known type, so never compressed (RFC 3597 section 4)
+            TypeData::ALIAS(x) => context.write_name_uncompressed(x),
             TypeData::Other(_, x) => context.write_blob(x),
         }
     }
@@ -306,7 +490,7 @@ impl TypeData {
                 exchange: context.read_name()?,
             },
             Type::TXT => {
-                let mut out = smallvec![];
+                let mut out = SmallVec::with_capacity(context.count_cstrings());
                 while context.remaining() > 0 {
                     out.push(context.read_cstring()?);
                 }
@@ -332,22 +516,70 @@ impl TypeData {
                 type_: context.read(u16::from_be_bytes)?,
                 key_tag: context.read(u16::from_be_bytes)?,
                 algorithm: context.read_u8()?,
-                data: {
-                    let mut out = vec![0u8; context.remaining()];
-                    context.read_all(&mut out)?;
-                    out
-                },
+                data: context.read_remaining()?,
             },
             Type::DNAME => TypeData::DNAME(context.read_name()?),
             Type::SSHFP => TypeData::SSHFP {
                 algorithm: context.read_u8()?,
                 fp_type: context.read_u8()?,
-                fingerprint: {
-                    let mut out = vec![0u8; context.remaining()];
-                    context.read_all(&mut out)?;
-                    out
-                },
+                fingerprint: context.read_remaining()?,
             },
+            Type::NSEC => {
+                let next = context.read_name()?;
+                let offset = context.index();
+                TypeData::NSEC {
+                    next,
+                    types: read_type_bitmap(&context.read_remaining()?, offset)?,
+                }
+            }
+            Type::DNSKEY => TypeData::DNSKEY(DnskeyData {
+                flags: context.read(u16::from_be_bytes)?,
+                protocol: context.read_u8()?,
+                algorithm: context.read_u8()?,
+                public_key: context.read_remaining()?,
+            }),
+            Type::RRSIG => TypeData::RRSIG(RrsigData {
+                type_covered: context.read(u16::from_be_bytes)?.into(),
+                algorithm: context.read_u8()?,
+                labels: context.read_u8()?,
+                original_ttl: context.read(u32::from_be_bytes)?,
+                expiration: context.read(u32::from_be_bytes)?,
+                inception: context.read(u32::from_be_bytes)?,
+                key_tag: context.read(u16::from_be_bytes)?,
+                signer: context.read_name()?,
+                signature: context.read_remaining()?,
+            }),
+            Type::SVCB | Type::HTTPS => {
+                let data = SvcbData {
+                    priority: context.read(u16::from_be_bytes)?,
+                    target: context.read_name()?,
+                    params: {
+                        let mut params = vec![];
+                        while context.remaining() > 0 {
+                            let key = context.read(u16::from_be_bytes)?;
+                            let len = context.read(u16::from_be_bytes)?;
+                            let value = context.read_slice(len as usize)?.to_vec();
+                            params.push(SvcParam { key, value });
+                        }
+                        params
+                    },
+                };
+                if type_ == Type::SVCB {
+                    TypeData::SVCB(data)
+                } else {
+                    TypeData::HTTPS(data)
+                }
+            }
+            Type::OPT => {
+                let mut items = vec![];
+                while context.remaining() > 0 {
+                    let code = context.read(u16::from_be_bytes)?;
+                    let len = context.read(u16::from_be_bytes)?;
+                    let data = context.read_slice(len as usize)?.to_vec();
+                    items.push(OptItem { code, data });
+                }
+                TypeData::OPT(OptData { items })
+            }
             Type::TSIG => TypeData::TSIG(TsigData {
                 algorithm: context.read_name()?,
                 time_signed: {
@@ -357,9 +589,7 @@ impl TypeData {
                 fudge: context.read(u16::from_be_bytes)?,
                 mac: {
                     let len = context.read(u16::from_be_bytes)?;
-                    let mut out = vec![0u8; len as usize];
-                    context.read_all(&mut out)?;
-                    out
+                    context.read_slice(len as usize)?.to_vec()
                 },
                 original_id: context.read(u16::from_be_bytes)?,
                 error: context.read(u16::from_be_bytes)?.into(),
@@ -368,26 +598,66 @@ impl TypeData {
                         vec![]
                     } else {
                         let len = context.read(u16::from_be_bytes)?;
-                        let mut out = vec![0u8; len as usize];
-                        context.read_all(&mut out)?;
-                        out
+                        context.read_slice(len as usize)?.to_vec()
                     }
                 },
             }),
+            Type::OPENPGPKEY => TypeData::OPENPGPKEY(context.read_remaining()?),
             Type::URI => TypeData::URI {
                 priority: context.read(u16::from_be_bytes)?,
                 weight: context.read(u16::from_be_bytes)?,
-                target: {
-                    let mut out = vec![0u8; context.remaining()];
-                    context.read_all(&mut out)?;
-                    String::from_utf8(out).map_err(|e| e.utf8_error())?
-                },
+                target: std::str::from_utf8(context.read_slice(context.remaining())?)?.to_owned(),
             },
-            type_ => {
-                let mut all = smallvec![0u8; context.remaining()];
-                context.read_all(&mut all)?;
-                TypeData::Other(type_, all)
-            }
+            Type::ALIAS => TypeData::ALIAS(context.read_name()?),
+            type_ => TypeData::Other(
+                type_,
+                SmallVec::from_slice(context.read_slice(context.remaining())?),
+            ),
         })
     }
 }
+
+/// RFC 4034 section 4.1.2 type bit maps: per 256-type window, the window number, bitmap length, and bitmap
+fn write_type_bitmap(types: &[Type]) -> Vec<u8> {
+    let mut types: Vec<u16> = types.iter().map(|x| (*x).into()).collect();
+    types.sort_unstable();
+    types.dedup();
+    let mut out = vec![];
+    for window in types.chunk_by(|a, b| a >> 8 == b >> 8) {
+        let mut bitmap = [0u8; 32];
+        let mut len = 0;
+        for type_ in window {
+            let bit = (type_ & 0xff) as usize;
+            bitmap[bit / 8] |= 0x80 >> (bit % 8);
+            len = bit / 8 + 1;
+        }
+        out.push((window[0] >> 8) as u8);
+        out.push(len as u8);
+        out.extend_from_slice(&bitmap[..len]);
+    }
+    out
+}
+
+/// `offset` is where `data` starts in the packet, for errors
+fn read_type_bitmap(mut data: &[u8], mut offset: usize) -> Result<Vec<Type>, PacketParseError> {
+    let mut out = vec![];
+    while !data.is_empty() {
+        let [window, len, ..] = *data else {
+            return Err(PacketParseError::CorruptRecord(offset));
+        };
+        let len = len as usize;
+        if len == 0 || len > 32 || data.len() < len + 2 {
+            return Err(PacketParseError::CorruptRecord(offset));
+        }
+        for (i, byte) in data[2..len + 2].iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (0x80 >> bit) != 0 {
+                    out.push(Type::from(((window as u16) << 8) | (i * 8 + bit) as u16));
+                }
+            }
+        }
+        data = &data[len + 2..];
+        offset += len + 2;
+    }
+    Ok(out)
+}
@@ -1,9 +1,20 @@
-use std::{borrow::Cow, fmt, net::AddrParseError, num::ParseIntError};
+use std::{
+    borrow::Cow,
+    fmt,
+    net::{AddrParseError, Ipv4Addr, Ipv6Addr},
+    num::ParseIntError,
+};
 
+use base64::{engine::general_purpose, Engine};
+use chrono::{NaiveDateTime, TimeZone, Utc};
 use hex::FromHexError;
+use smallvec::SmallVec;
 use thiserror::Error;
 
-use crate::{NameParseError, SoaData, TsigData, Type, TypeData};
+use crate::{
+    DnskeyData, NameParseError, OptData, OptItem, RrsigData, SoaData, SvcParam, SvcbData, TsigData,
+    Type, TypeData,
+};
 
 #[derive(Error, Debug)]
 pub enum TypeDataParseError {
@@ -13,6 +24,8 @@ pub enum TypeDataParseError {
     NoArguments,
     #[error("missing expected argument")]
     MissingArgument,
+    #[error("unknown record type: {0}")]
+    UnknownType(String),
 
     #[error("invalid UTF8 in name: {0}")]
     UTF8Error(#[from] std::str::Utf8Error),
@@ -24,6 +37,59 @@ pub enum TypeDataParseError {
     ParseIntError(#[from] ParseIntError),
     #[error("failed to parse hex: {0}")]
     FromHexError(#[from] FromHexError),
+    #[error("failed to parse base64: {0}")]
+    Base64Error(#[from] base64::DecodeError),
+    #[error("malformed LOC value")]
+    MalformedLoc,
+    #[error("SOA rname {0} contains `@`, mailboxes are written with a `.` instead, see `SoaData::rname_from_email`")]
+    RnameContainsAt(String),
+    #[error("scoped address {0} is not valid in DNS")]
+    ScopedAddress(String),
+    #[error("invalid signature time: {0}")]
+    InvalidTime(String),
+}
+
+/// the mnemonic of `type_`, or `TYPE<n>` for types without one (RFC 3597 section 5)
+fn fmt_type(type_: Type) -> String {
+    match type_ {
+        Type::Other(x) => format!("TYPE{x}"),
+        type_ => type_.to_string(),
+    }
+}
+
+/// a type mnemonic, or `TYPE<n>` for any type (RFC 3597 section 5)
+pub fn parse_type(input: &str) -> Result<Type, TypeDataParseError> {
+    let input = input.to_ascii_uppercase();
+    match input
+        .strip_prefix("TYPE")
+        .and_then(|x| x.parse::<u16>().ok())
+    {
+        Some(x) => Ok(Type::from(x)),
+        None => input
+            .parse()
+            .map_err(|_| TypeDataParseError::UnknownType(input)),
+    }
+}
+
+/// RRSIG times are written `YYYYMMDDHHmmSS` in UTC (RFC 4034 section 3.2)
+const TIME_FORMAT: &str = "%Y%m%d%H%M%S";
+
+fn fmt_time(time: u32) -> String {
+    match Utc.timestamp_opt(time as i64, 0).single() {
+        Some(time) => time.format(TIME_FORMAT).to_string(),
+        None => time.to_string(),
+    }
+}
+
+/// the `YYYYMMDDHHmmSS` form, or plain seconds since the epoch
+fn parse_time(input: &str) -> Result<u32, TypeDataParseError> {
+    if input.len() != 14 {
+        return Ok(input.parse()?);
+    }
+    NaiveDateTime::parse_from_str(input, TIME_FORMAT)
+        .ok()
+        .and_then(|x| x.timestamp().try_into().ok())
+        .ok_or_else(|| TypeDataParseError::InvalidTime(input.to_string()))
 }
 
 fn fmt_arg(input: &str) -> Cow<'_, str> {
@@ -37,14 +103,18 @@ fn fmt_arg(input: &str) -> Cow<'_, str> {
 fn needs_escape(input: &str) -> bool {
     input
         .chars()
-        .any(|x| x == '"' || x.is_ascii_whitespace() || x == '\\')
+        .any(|x| x == '"' || x.is_ascii_whitespace() || x == '\\' || x.is_ascii_control())
 }
 
 fn do_escape(input: &str) -> String {
     let mut out = "\"".to_string();
     for c in input.chars() {
-        if c == '"' || c == '\\' || c.is_ascii_whitespace() {
+        if c == ' ' || c == '"' || c == '\\' {
             out.push('\\');
+        } else if c.is_ascii_control() {
+            // RFC 1035 \DDD decimal octet
+            out.push_str(&format!("\\{:03}", c as u8));
+            continue;
         }
         out.push(c);
     }
@@ -52,21 +122,40 @@ fn do_escape(input: &str) -> String {
     out
 }
 
-fn parse_args(input: &str) -> Result<Vec<String>, TypeDataParseError> {
+/// `keep_escaped_dots` leaves `\.` escaped for arguments that are parsed as names, where it means a dot within a label
+fn parse_args(input: &str, keep_escaped_dots: bool) -> Result<Vec<String>, TypeDataParseError> {
     let mut out = vec![];
-    let mut escaped = false;
     let mut quoted = false;
-    let mut current = String::new();
-    for c in input.trim().chars() {
-        if escaped {
-            current.push(c);
-            escaped = false;
-            continue;
-        }
+    let mut current: Vec<u8> = vec![];
+    let mut chars = input.trim().chars();
+    while let Some(c) = chars.next() {
         if c == '\\' {
-            escaped = true;
+            let Some(escaped) = chars.next() else {
+                return Err(TypeDataParseError::MalformedString);
+            };
+            if let Some(hundreds) = escaped.to_digit(10) {
+                // RFC 1035 \DDD decimal octet
+                let mut value = hundreds;
+                for _ in 0..2 {
+                    let digit = chars
+                        .next()
+                        .and_then(|x| x.to_digit(10))
+                        .ok_or(TypeDataParseError::MalformedString)?;
+                    value = value * 10 + digit;
+                }
+                current.push(
+                    value
+                        .try_into()
+                        .map_err(|_| TypeDataParseError::MalformedString)?,
+                );
+            } else if escaped == '.' && keep_escaped_dots {
+                current.extend(b"\\.");
+            } else {
+                current.extend(escaped.encode_utf8(&mut [0u8; 4]).as_bytes());
+            }
             continue;
-        } else if c == '"' && !quoted {
+        }
+        if c == '"' && !quoted {
             if !current.is_empty() {
                 out.push(std::mem::take(&mut current));
             }
@@ -74,30 +163,309 @@ fn parse_args(input: &str) -> Result<Vec<String>, TypeDataParseError> {
         } else if c == '"' && quoted {
             out.push(std::mem::take(&mut current));
             quoted = false;
-        } else if c.is_ascii_whitespace() {
+        } else if c.is_ascii_whitespace() && !quoted {
             if !current.is_empty() {
                 out.push(std::mem::take(&mut current));
             }
         } else {
-            current.push(c);
+            current.extend(c.encode_utf8(&mut [0u8; 4]).as_bytes());
         }
     }
-    if quoted || escaped {
+    if quoted {
         return Err(TypeDataParseError::MalformedString);
     }
     if !current.is_empty() {
         out.push(std::mem::take(&mut current));
     }
+    out.into_iter()
+        .map(|x| Ok(String::from_utf8(x).map_err(|e| e.utf8_error())?))
+        .collect()
+}
+
+const SVC_PARAM_KEYS: [&str; 7] = [
+    "mandatory",
+    "alpn",
+    "no-default-alpn",
+    "port",
+    "ipv4hint",
+    "ech",
+    "ipv6hint",
+];
+
+fn fmt_svc_param_key(key: u16) -> Cow<'static, str> {
+    match SVC_PARAM_KEYS.get(key as usize) {
+        Some(name) => Cow::Borrowed(*name),
+        None => Cow::Owned(format!("key{key}")),
+    }
+}
+
+fn parse_svc_param_key(input: &str) -> Result<u16, TypeDataParseError> {
+    match SVC_PARAM_KEYS.iter().position(|x| *x == input) {
+        Some(key) => Ok(key as u16),
+        None => Ok(input
+            .strip_prefix("key")
+            .ok_or(TypeDataParseError::MalformedString)?
+            .parse()?),
+    }
+}
+
+/// SvcParam values in RFC 9460 presentation format
+fn fmt_svc_param_value(key: u16, value: &[u8]) -> String {
+    match key {
+        0 => value
+            .chunks(2)
+            .map(|x| fmt_svc_param_key(u16::from_be_bytes([x[0], *x.get(1).unwrap_or(&0)])))
+            .collect::<Vec<_>>()
+            .join(","),
+        1 => {
+            let mut ids = vec![];
+            let mut value = value;
+            while let Some((len, rest)) = value.split_first() {
+                let len = (*len as usize).min(rest.len());
+                ids.push(String::from_utf8_lossy(&rest[..len]).into_owned());
+                value = &rest[len..];
+            }
+            ids.join(",")
+        }
+        3 if value.len() == 2 => u16::from_be_bytes([value[0], value[1]]).to_string(),
+        4 => value
+            .chunks_exact(4)
+            .map(|x| Ipv4Addr::from(<[u8; 4]>::try_from(x).unwrap()).to_string())
+            .collect::<Vec<_>>()
+            .join(","),
+        5 => general_purpose::STANDARD.encode(value),
+        6 => value
+            .chunks_exact(16)
+            .map(|x| Ipv6Addr::from(<[u8; 16]>::try_from(x).unwrap()).to_string())
+            .collect::<Vec<_>>()
+            .join(","),
+        _ => String::from_utf8_lossy(value).into_owned(),
+    }
+}
+
+fn parse_svc_param_value(key: u16, input: &str) -> Result<Vec<u8>, TypeDataParseError> {
+    let mut out = vec![];
+    match key {
+        0 => {
+            for key in input.split(',') {
+                out.extend(parse_svc_param_key(key)?.to_be_bytes());
+            }
+        }
+        1 => {
+            for id in input.split(',') {
+                out.push(
+                    id.len()
+                        .try_into()
+                        .map_err(|_| TypeDataParseError::MalformedString)?,
+                );
+                out.extend(id.as_bytes());
+            }
+        }
+        3 => out.extend(input.parse::<u16>()?.to_be_bytes()),
+        4 => {
+            for addr in input.split(',') {
+                out.extend(addr.parse::<Ipv4Addr>()?.octets());
+            }
+        }
+        5 => out = general_purpose::STANDARD.decode(input)?,
+        6 => {
+            for addr in input.split(',') {
+                out.extend(addr.parse::<Ipv6Addr>()?.octets());
+            }
+        }
+        _ => out.extend(input.as_bytes()),
+    }
     Ok(out)
 }
 
+fn fmt_svcb(f: &mut fmt::Formatter<'_>, data: &SvcbData) -> fmt::Result {
+    if data.target.is_empty() {
+        write!(f, "{} .", data.priority)?;
+    } else {
+        write!(f, "{} {}", data.priority, data.target)?;
+    }
+    for param in &data.params {
+        write!(f, " {}", fmt_svc_param_key(param.key))?;
+        if !param.value.is_empty() {
+            write!(
+                f,
+                "={}",
+                fmt_arg(&fmt_svc_param_value(param.key, &param.value))
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn parse_svcb(args: &[String]) -> Result<SvcbData, TypeDataParseError> {
+    let mut params = vec![];
+    // a quoted value is split from its `key=` by `parse_args`
+    let mut iter = args.iter().skip(2);
+    while let Some(arg) = iter.next() {
+        let (key, value) = match arg.split_once('=') {
+            Some((key, "")) => (key, iter.next().map(|x| &**x).unwrap_or_default()),
+            Some((key, value)) => (key, value),
+            None => (&**arg, ""),
+        };
+        let key = parse_svc_param_key(key)?;
+        params.push(SvcParam {
+            key,
+            value: parse_svc_param_value(key, value)?,
+        });
+    }
+    Ok(SvcbData {
+        priority: args
+            .first()
+            .ok_or(TypeDataParseError::MissingArgument)?
+            .parse()?,
+        target: args
+            .get(1)
+            .ok_or(TypeDataParseError::MissingArgument)?
+            .parse()?,
+        params,
+    })
+}
+
+/// LOC latitude and longitude are thousandths of an arc second offset by 2^31 (RFC 1876)
+const LOC_EQUATOR: i64 = 1 << 31;
+/// LOC altitude is centimeters offset from 100000m below the WGS 84 spheroid
+const LOC_ALTITUDE_BASE: i64 = 10_000_000;
+/// BIND's defaults for omitted size, horizontal and vertical precision: 1m, 10000m, 10m
+const LOC_DEFAULT_PRECISION: [u8; 3] = [0x12, 0x16, 0x13];
+
+/// parses a decimal with up to `digits` fractional digits as an integer scaled by 10^digits
+fn parse_decimal(input: &str, digits: u32) -> Result<i64, TypeDataParseError> {
+    let (int, frac) = input.split_once('.').unwrap_or((input, ""));
+    if frac.len() > digits as usize || !frac.bytes().all(|x| x.is_ascii_digit()) {
+        return Err(TypeDataParseError::MalformedLoc);
+    }
+    let value = int.parse::<i64>()?.abs() * 10i64.pow(digits)
+        + frac
+            .bytes()
+            .fold(0i64, |value, digit| value * 10 + (digit - b'0') as i64)
+            * 10i64.pow(digits - frac.len() as u32);
+    Ok(if int.starts_with('-') { -value } else { value })
+}
+
+fn parse_loc_coordinate<'a>(
+    args: &mut impl Iterator<Item = &'a String>,
+    hemispheres: [&str; 2],
+    max_degrees: i64,
+) -> Result<i32, TypeDataParseError> {
+    // degrees, minutes, thousandths of seconds
+    let mut parts = [0i64; 3];
+    let mut i = 0;
+    let sign = loop {
+        let arg = args.next().ok_or(TypeDataParseError::MissingArgument)?;
+        if let Some(index) = hemispheres.iter().position(|x| arg.eq_ignore_ascii_case(x)) {
+            break if index == 0 { 1 } else { -1 };
+        }
+        if i == parts.len() {
+            return Err(TypeDataParseError::MalformedLoc);
+        }
+        parts[i] = if i == 2 {
+            parse_decimal(arg, 3)?
+        } else {
+            arg.parse()?
+        };
+        i += 1;
+    };
+    let [degrees, minutes, seconds] = parts;
+    if i == 0 || degrees < 0 || !(0..60).contains(&minutes) || !(0..60_000).contains(&seconds) {
+        return Err(TypeDataParseError::MalformedLoc);
+    }
+    let value = (degrees * 3600 + minutes * 60) * 1000 + seconds;
+    if value > max_degrees * 3_600_000 {
+        return Err(TypeDataParseError::MalformedLoc);
+    }
+    Ok((LOC_EQUATOR + sign * value) as u32 as i32)
+}
+
+fn parse_loc_meters(input: &str) -> Result<i64, TypeDataParseError> {
+    parse_decimal(input.strip_suffix('m').unwrap_or(input), 2)
+}
+
+/// encodes centimeters as the LOC mantissa/exponent byte, rounding down
+fn parse_loc_precision(input: &str) -> Result<u8, TypeDataParseError> {
+    let mut mantissa = parse_loc_meters(input)?;
+    if mantissa < 0 {
+        return Err(TypeDataParseError::MalformedLoc);
+    }
+    let mut exponent = 0u8;
+    while mantissa > 9 {
+        mantissa /= 10;
+        exponent += 1;
+    }
+    if exponent > 9 {
+        return Err(TypeDataParseError::MalformedLoc);
+    }
+    Ok(((mantissa as u8) << 4) | exponent)
+}
+
+fn parse_loc(args: &[String]) -> Result<TypeData, TypeDataParseError> {
+    let mut iter = args.iter();
+    let latitude = parse_loc_coordinate(&mut iter, ["N", "S"], 90)?;
+    let longitude = parse_loc_coordinate(&mut iter, ["E", "W"], 180)?;
+    let altitude = LOC_ALTITUDE_BASE
+        + parse_loc_meters(iter.next().ok_or(TypeDataParseError::MissingArgument)?)?;
+    if !(0..=u32::MAX as i64).contains(&altitude) {
+        return Err(TypeDataParseError::MalformedLoc);
+    }
+    let mut precision = LOC_DEFAULT_PRECISION;
+    for (i, arg) in iter.enumerate() {
+        *precision
+            .get_mut(i)
+            .ok_or(TypeDataParseError::MalformedLoc)? = parse_loc_precision(arg)?;
+    }
+    let [size, horiz_pre, vert_pre] = precision;
+    Ok(TypeData::LOC {
+        version: 0,
+        size,
+        horiz_pre,
+        vert_pre,
+        latitude,
+        longitude,
+        altitude: altitude as u32 as i32,
+    })
+}
+
+fn fmt_loc_coordinate(f: &mut fmt::Formatter<'_>, raw: i32, hemispheres: [&str; 2]) -> fmt::Result {
+    let value = raw as u32 as i64 - LOC_EQUATOR;
+    let hemisphere = if value < 0 {
+        hemispheres[1]
+    } else {
+        hemispheres[0]
+    };
+    let value = value.abs();
+    write!(
+        f,
+        "{} {} {}.{:03} {}",
+        value / 3_600_000,
+        value / 60_000 % 60,
+        value / 1000 % 60,
+        value % 1000,
+        hemisphere
+    )
+}
+
+fn fmt_loc_precision(f: &mut fmt::Formatter<'_>, precision: u8) -> fmt::Result {
+    let centimeters = (precision >> 4) as u64 * 10u64.pow((precision & 0xf).min(9) as u32);
+    if centimeters.is_multiple_of(100) {
+        write!(f, "{}m", centimeters / 100)
+    } else {
+        write!(f, "{}.{:02}m", centimeters / 100, centimeters % 100)
+    }
+}
+
 impl fmt::Display for TypeData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             TypeData::A(x) => write!(f, "{x}")?,
-            TypeData::DNAME(x) | TypeData::NS(x) | TypeData::CNAME(x) | TypeData::PTR(x) => {
-                write!(f, "{x}")?
-            }
+            TypeData::DNAME(x)
+            | TypeData::NS(x)
+            | TypeData::CNAME(x)
+            | TypeData::PTR(x)
+            | TypeData::ALIAS(x) => write!(f, "{x}")?,
             TypeData::SOA(SoaData {
                 mname,
                 rname,
@@ -132,6 +500,32 @@ impl fmt::Display for TypeData {
                 }
             }
             TypeData::AAAA(x) => write!(f, "{x}")?,
+            TypeData::LOC {
+                version: 0,
+                size,
+                horiz_pre,
+                vert_pre,
+                latitude,
+                longitude,
+                altitude,
+            } => {
+                fmt_loc_coordinate(f, *latitude, ["N", "S"])?;
+                write!(f, " ")?;
+                fmt_loc_coordinate(f, *longitude, ["E", "W"])?;
+                let altitude = *altitude as u32 as i64 - LOC_ALTITUDE_BASE;
+                let sign = if altitude < 0 { "-" } else { "" };
+                write!(
+                    f,
+                    " {sign}{}.{:02}m",
+                    altitude.abs() / 100,
+                    altitude.abs() % 100
+                )?;
+                for precision in [size, horiz_pre, vert_pre] {
+                    write!(f, " ")?;
+                    fmt_loc_precision(f, *precision)?;
+                }
+            }
+            // RFC 1876 presentation only covers version 0
             TypeData::LOC {
                 version,
                 size,
@@ -177,6 +571,15 @@ impl fmt::Display for TypeData {
             } => {
                 write!(f, "{} {} {}", algorithm, fp_type, hex::encode(fingerprint))?;
             }
+            TypeData::SVCB(data) | TypeData::HTTPS(data) => fmt_svcb(f, data)?,
+            TypeData::OPT(OptData { items }) => {
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}:{}", item.code, hex::encode(&item.data))?;
+                }
+            }
             TypeData::TSIG(TsigData {
                 algorithm,
                 time_signed,
@@ -191,22 +594,116 @@ impl fmt::Display for TypeData {
                 hex::encode(mac),
                 hex::encode(other_data)
             )?,
+            TypeData::OPENPGPKEY(x) => write!(f, "{}", general_purpose::STANDARD.encode(x))?,
             TypeData::URI {
                 priority,
                 weight,
                 target,
             } => {
-                write!(f, "{} {} {}", priority, weight, target)?;
+                // the target is always quoted in presentation format (RFC 7553)
+                write!(f, "{} {} {}", priority, weight, do_escape(target))?;
+            }
+            TypeData::NSEC { next, types } => {
+                write!(f, "{next}")?;
+                for type_ in types {
+                    write!(f, " {}", fmt_type(*type_))?;
+                }
             }
+            TypeData::DNSKEY(DnskeyData {
+                flags,
+                protocol,
+                algorithm,
+                public_key,
+            }) => write!(
+                f,
+                "{flags} {protocol} {algorithm} {}",
+                general_purpose::STANDARD.encode(public_key)
+            )?,
+            TypeData::RRSIG(RrsigData {
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                expiration,
+                inception,
+                key_tag,
+                signer,
+                signature,
+            }) => write!(
+                f,
+                "{} {algorithm} {labels} {original_ttl} {} {} {key_tag} {signer} {}",
+                fmt_type(*type_covered),
+                fmt_time(*expiration),
+                fmt_time(*inception),
+                general_purpose::STANDARD.encode(signature)
+            )?,
             TypeData::Other(_, x) => write!(f, "{}", hex::encode(x))?,
         }
         Ok(())
     }
 }
 
+/// RFC 3597 section 5 generic data, `\\# <length> <hex>`
+fn parse_generic(input: &str) -> Result<SmallVec<[u8; 32]>, TypeDataParseError> {
+    let mut args = input.split_whitespace();
+    let length: usize = args
+        .next()
+        .ok_or(TypeDataParseError::MissingArgument)?
+        .parse()?;
+    let data = hex::decode(args.collect::<String>())?;
+    if data.len() != length {
+        return Err(TypeDataParseError::MalformedString);
+    }
+    Ok(data.into())
+}
+
 impl TypeData {
+    /// generic RFC 3597 presentation of data of types without one, `\\# <length> <hex>`. others are the same as `Display`
+    pub fn to_zone_string(&self) -> String {
+        match self {
+            TypeData::Other(_, x) if x.is_empty() => "\\# 0".to_string(),
+            TypeData::Other(_, x) => format!("\\# {} {}", x.len(), hex::encode(x)),
+            data => data.to_string(),
+        }
+    }
+
     pub fn parse_str(type_: Type, input: &str) -> Result<TypeData, TypeDataParseError> {
-        let args = parse_args(input)?;
+        if let (Type::Other(_), Some(generic)) = (type_, input.trim_start().strip_prefix("\\#")) {
+            return Ok(TypeData::Other(type_, parse_generic(generic)?));
+        }
+        let args = parse_args(
+            input,
+            matches!(
+                type_,
+                Type::NS
+                    | Type::CNAME
+                    | Type::ALIAS
+                    | Type::PTR
+                    | Type::DNAME
+                    | Type::NSEC
+                    | Type::RRSIG
+                    | Type::SOA
+                    | Type::MX
+                    | Type::SRV
+            ),
+        )?;
+        // an OPT record may have no options
+        if type_ == Type::OPT {
+            return Ok(TypeData::OPT(OptData {
+                items: args
+                    .iter()
+                    .map(|arg| {
+                        let (code, data) = arg
+                            .split_once(':')
+                            .ok_or(TypeDataParseError::MalformedString)?;
+                        Ok(OptItem {
+                            code: code.parse()?,
+                            data: hex::decode(data)?,
+                        })
+                    })
+                    .collect::<Result<_, TypeDataParseError>>()?,
+            }));
+        }
         let Some(first) = args.first() else {
             return Err(TypeDataParseError::NoArguments);
         };
@@ -215,12 +712,15 @@ impl TypeData {
             Type::A => TypeData::A(first.parse()?),
             Type::NS => TypeData::NS(first.parse()?),
             Type::CNAME => TypeData::CNAME(first.parse()?),
+            Type::ALIAS => TypeData::ALIAS(first.parse()?),
             Type::SOA => TypeData::SOA(SoaData {
                 mname: first.parse()?,
-                rname: args
-                    .get(1)
-                    .ok_or(TypeDataParseError::MissingArgument)?
-                    .parse()?,
+                rname: match args.get(1).ok_or(TypeDataParseError::MissingArgument)? {
+                    rname if rname.contains('@') => {
+                        return Err(TypeDataParseError::RnameContainsAt(rname.to_string()))
+                    }
+                    rname => rname.parse()?,
+                },
                 serial: args
                     .get(2)
                     .ok_or(TypeDataParseError::MissingArgument)?
@@ -258,7 +758,15 @@ impl TypeData {
                     .parse()?,
             },
             Type::TXT => TypeData::TXT(smallvec::smallvec![args.join(" ")]),
+            // zone indices only mean something on the host that wrote them
+            Type::AAAA if first.contains('%') => {
+                return Err(TypeDataParseError::ScopedAddress(first.clone()))
+            }
             Type::AAAA => TypeData::AAAA(first.parse()?),
+            // the raw numeric form predates RFC 1876 presentation support
+            Type::LOC if args.len() != 7 || args.iter().any(|x| x.parse::<i64>().is_err()) => {
+                parse_loc(&args)?
+            }
             Type::LOC => TypeData::LOC {
                 version: first.parse()?,
                 size: args
@@ -322,7 +830,13 @@ impl TypeData {
                     .parse()?,
                 fingerprint: hex::decode(args.get(2).ok_or(TypeDataParseError::MissingArgument)?)?,
             },
+            Type::SVCB => TypeData::SVCB(parse_svcb(&args)?),
+            Type::HTTPS => TypeData::HTTPS(parse_svcb(&args)?),
             // TSIG cannot be parsed
+            // base64 may be split by whitespace in master files
+            Type::OPENPGPKEY => {
+                TypeData::OPENPGPKEY(general_purpose::STANDARD.decode(args.concat())?)
+            }
             Type::URI => TypeData::URI {
                 priority: first.parse()?,
                 weight: args
@@ -330,11 +844,315 @@ impl TypeData {
                     .ok_or(TypeDataParseError::MissingArgument)?
                     .parse()?,
                 target: args
-                    .get(1)
+                    .get(2)
                     .ok_or(TypeDataParseError::MissingArgument)?
                     .clone(),
             },
+            Type::NSEC => TypeData::NSEC {
+                next: first.parse()?,
+                types: args[1..]
+                    .iter()
+                    .map(|x| parse_type(x))
+                    .collect::<Result<_, _>>()?,
+            },
+            Type::DNSKEY => TypeData::DNSKEY(DnskeyData {
+                flags: first.parse()?,
+                protocol: args
+                    .get(1)
+                    .ok_or(TypeDataParseError::MissingArgument)?
+                    .parse()?,
+                algorithm: args
+                    .get(2)
+                    .ok_or(TypeDataParseError::MissingArgument)?
+                    .parse()?,
+                // base64 may be split by whitespace in master files
+                public_key: general_purpose::STANDARD.decode(
+                    args.get(3..)
+                        .filter(|x| !x.is_empty())
+                        .ok_or(TypeDataParseError::MissingArgument)?
+                        .concat(),
+                )?,
+            }),
+            Type::RRSIG => TypeData::RRSIG(RrsigData {
+                type_covered: parse_type(first)?,
+                algorithm: args
+                    .get(1)
+                    .ok_or(TypeDataParseError::MissingArgument)?
+                    .parse()?,
+                labels: args
+                    .get(2)
+                    .ok_or(TypeDataParseError::MissingArgument)?
+                    .parse()?,
+                original_ttl: args
+                    .get(3)
+                    .ok_or(TypeDataParseError::MissingArgument)?
+                    .parse()?,
+                expiration: parse_time(args.get(4).ok_or(TypeDataParseError::MissingArgument)?)?,
+                inception: parse_time(args.get(5).ok_or(TypeDataParseError::MissingArgument)?)?,
+                key_tag: args
+                    .get(6)
+                    .ok_or(TypeDataParseError::MissingArgument)?
+                    .parse()?,
+                signer: args
+                    .get(7)
+                    .ok_or(TypeDataParseError::MissingArgument)?
+                    .parse()?,
+                signature: general_purpose::STANDARD.decode(
+                    args.get(8..)
+                        .filter(|x| !x.is_empty())
+                        .ok_or(TypeDataParseError::MissingArgument)?
+                        .concat(),
+                )?,
+            }),
             type_ => TypeData::Other(type_, hex::decode(first)?.into()),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constructors() {
+        let parsed = |type_, data| TypeData::parse_str(type_, data).unwrap();
+        assert_eq!(
+            TypeData::mx(10, "mail.example.com").unwrap(),
+            parsed(Type::MX, "10 mail.example.com")
+        );
+        assert_eq!(
+            TypeData::srv(0, 5, 5060, "sip.example.com").unwrap(),
+            parsed(Type::SRV, "0 5 5060 sip.example.com")
+        );
+        assert_eq!(
+            TypeData::txt(["v=spf1 -all", "second"]),
+            TypeData::TXT(vec!["v=spf1 -all".to_string(), "second".to_string()].into())
+        );
+        assert_eq!(
+            TypeData::soa(
+                "ns1.example.com",
+                "hostmaster.example.com",
+                1,
+                3600,
+                600,
+                86400,
+                300
+            )
+            .unwrap(),
+            parsed(
+                Type::SOA,
+                "ns1.example.com hostmaster.example.com 1 3600 600 86400 300"
+            )
+        );
+        assert!(matches!(
+            TypeData::cname("a..example.com"),
+            Err(NameParseError::EmptyLabel)
+        ));
+    }
+
+    #[test]
+    fn test_aaaa_forms() {
+        let expanded: Ipv6Addr = "2001:db8:0:0:0:0:0:1".parse().unwrap();
+        for form in [
+            "2001:db8::1",
+            "2001:DB8::1",
+            "2001:0db8:0000:0000:0000:0000:0000:0001",
+            "2001:db8:0:0:0:0:0:1",
+        ] {
+            assert_eq!(
+                TypeData::parse_str(Type::AAAA, form).unwrap(),
+                TypeData::AAAA(expanded),
+                "{form}"
+            );
+        }
+        assert_eq!(
+            TypeData::parse_str(Type::AAAA, "2001:db8::1")
+                .unwrap()
+                .to_string(),
+            "2001:db8::1"
+        );
+
+        // IPv4-mapped and embedded forms
+        let mapped = TypeData::parse_str(Type::AAAA, "::ffff:1.2.3.4").unwrap();
+        assert_eq!(
+            mapped,
+            TypeData::parse_str(Type::AAAA, "0:0:0:0:0:ffff:0102:0304").unwrap()
+        );
+        assert_eq!(
+            TypeData::parse_str(Type::AAAA, "64:ff9b::192.0.2.1").unwrap(),
+            TypeData::AAAA("64:ff9b::c000:201".parse().unwrap())
+        );
+        assert_eq!(
+            TypeData::parse_str(Type::AAAA, "::").unwrap(),
+            TypeData::AAAA(Ipv6Addr::UNSPECIFIED)
+        );
+
+        for invalid in ["fe80::1%eth0", "fe80::1%2"] {
+            assert!(matches!(
+                TypeData::parse_str(Type::AAAA, invalid),
+                Err(TypeDataParseError::ScopedAddress(_))
+            ));
+        }
+        for invalid in [
+            "2001:db8:::1",
+            "2001:db8::1::2",
+            "1:2:3:4:5:6:7:8:9",
+            "2001:db8::g",
+            "1.2.3.4",
+            "[2001:db8::1]",
+        ] {
+            assert!(
+                matches!(
+                    TypeData::parse_str(Type::AAAA, invalid),
+                    Err(TypeDataParseError::AddrParseError(_))
+                ),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_soa_rname() {
+        let rname = SoaData::rname_from_email("john.doe@example.com").unwrap();
+        assert_eq!(rname.segments().count(), 3);
+        assert_eq!(rname.to_string(), "john\\.doe.example.com");
+        let data = TypeData::parse_str(
+            Type::SOA,
+            "ns1.example.com john\\.doe.example.com 1 3600 600 86400 300",
+        )
+        .unwrap();
+        let TypeData::SOA(soa) = &data else {
+            panic!("not an SOA");
+        };
+        assert_eq!(soa.rname.segments().next(), Some("john.doe"));
+        assert_eq!(soa.rname_to_email(), "john.doe@example.com");
+        assert_eq!(
+            TypeData::parse_str(Type::SOA, &data.to_string()).unwrap(),
+            data
+        );
+
+        assert!(matches!(
+            TypeData::parse_str(
+                Type::SOA,
+                "ns1.example.com hostmaster@example.com 1 3600 600 86400 300"
+            ),
+            Err(TypeDataParseError::RnameContainsAt(_))
+        ));
+    }
+
+    #[test]
+    fn test_nsec() {
+        let data =
+            TypeData::parse_str(Type::NSEC, "host.example.com A MX RRSIG NSEC TYPE1234").unwrap();
+        assert_eq!(
+            data,
+            TypeData::NSEC {
+                next: "host.example.com".parse().unwrap(),
+                types: vec![
+                    Type::A,
+                    Type::MX,
+                    Type::RRSIG,
+                    Type::NSEC,
+                    Type::Other(1234)
+                ],
+            }
+        );
+        assert_eq!(
+            data.to_string(),
+            "host.example.com A MX RRSIG NSEC TYPE1234"
+        );
+    }
+
+    #[test]
+    fn test_generic_data() {
+        let type_ = Type::from(65280);
+        let data = TypeData::parse_str(type_, "\\# 4 0a00 0001").unwrap();
+        assert_eq!(data, TypeData::Other(type_, [10, 0, 0, 1][..].into()));
+        assert_eq!(data.to_zone_string(), "\\# 4 0a000001");
+        assert_eq!(
+            TypeData::parse_str(type_, "\\# 0")
+                .unwrap()
+                .to_zone_string(),
+            "\\# 0"
+        );
+        // the length must match the data
+        assert!(TypeData::parse_str(type_, "\\# 3 0a000001").is_err());
+        // bare hex is still accepted
+        assert_eq!(TypeData::parse_str(type_, "0a000001").unwrap(), data);
+        assert_eq!(parse_type("type65280").unwrap(), type_);
+    }
+
+    #[test]
+    fn test_loc() {
+        let data = TypeData::parse_str(Type::LOC, "52 N 4 E 0m").unwrap();
+        assert_eq!(
+            data,
+            TypeData::LOC {
+                version: 0,
+                size: 0x12,
+                horiz_pre: 0x16,
+                vert_pre: 0x13,
+                latitude: (LOC_EQUATOR + 52 * 3_600_000) as u32 as i32,
+                longitude: (LOC_EQUATOR + 4 * 3_600_000) as u32 as i32,
+                altitude: LOC_ALTITUDE_BASE as i32,
+            }
+        );
+        assert_eq!(
+            data.to_string(),
+            "52 0 0.000 N 4 0 0.000 E 0.00m 1m 10000m 10m"
+        );
+
+        let full = "42 21 54.000 N 71 6 18.000 W -24.00m 30m 10m 0.50m";
+        let data = TypeData::parse_str(Type::LOC, full).unwrap();
+        assert_eq!(data.to_string(), full);
+        assert_eq!(
+            TypeData::parse_str(Type::LOC, &data.to_string()).unwrap(),
+            data
+        );
+
+        assert!(TypeData::parse_str(Type::LOC, "91 N 4 E 0m").is_err());
+        assert!(TypeData::parse_str(Type::LOC, "52 N 4 E").is_err());
+    }
+
+    #[test]
+    fn test_txt_escapes() {
+        let data =
+            TypeData::parse_str(Type::TXT, r#""tab\009here" \"quoted\" caf\195\169"#).unwrap();
+        assert_eq!(
+            data,
+            TypeData::TXT(smallvec::smallvec!["tab\there \"quoted\" café".to_string()])
+        );
+        assert_eq!(data.to_string(), r#""tab\009here\ \"quoted\"\ café""#);
+        assert_eq!(
+            TypeData::parse_str(Type::TXT, &data.to_string()).unwrap(),
+            data
+        );
+
+        assert!(TypeData::parse_str(Type::TXT, r"bad\256").is_err());
+        assert!(TypeData::parse_str(Type::TXT, r"short\09").is_err());
+    }
+
+    #[test]
+    fn test_svcb() {
+        let input = r#"1 . alpn="h2,h3" port=8443 ipv4hint=192.0.2.1,192.0.2.2"#;
+        let data = TypeData::parse_str(Type::HTTPS, input).unwrap();
+        let TypeData::HTTPS(svcb) = &data else {
+            panic!("not HTTPS");
+        };
+        assert_eq!(svcb.priority, 1);
+        assert!(svcb.target.is_empty());
+        assert_eq!(svcb.params[0].value, b"\x02h2\x02h3");
+        assert_eq!(svcb.params[1].value, 8443u16.to_be_bytes());
+        assert_eq!(
+            data.to_string(),
+            "1 . alpn=h2,h3 port=8443 ipv4hint=192.0.2.1,192.0.2.2"
+        );
+        assert_eq!(
+            TypeData::parse_str(Type::HTTPS, &data.to_string()).unwrap(),
+            data
+        );
+
+        let alias = TypeData::parse_str(Type::SVCB, "0 svc.example.com").unwrap();
+        assert_eq!(alias.to_string(), "0 svc.example.com");
+    }
+}
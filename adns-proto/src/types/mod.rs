@@ -14,6 +14,7 @@ pub use data_parser::*;
     Copy,
     PartialEq,
     Eq,
+    Hash,
     Debug,
     FromRepr,
     strum::Display,
@@ -114,6 +115,9 @@ pub enum Type {
     TA = 32768,
     DLV,
 
+    // apex CNAME-like pseudo-record, resolved by the server (private use range, as in PowerDNS)
+    ALIAS = 65401,
+
     Other(u16),
 }
 
@@ -137,7 +141,15 @@ impl Type {
         *self >= Type::IXFR && *self <= Type::ALL
     }
 
+    /// EDNS and transaction signature pseudo-records, never stored in zones
+    pub fn is_meta_type(&self) -> bool {
+        matches!(self, Type::OPT | Type::TSIG | Type::TKEY)
+    }
+
     pub fn wants_by_query(&self, other: Type) -> bool {
+        if *self == Type::ALL {
+            return !other.is_meta_type() && !other.is_question_type();
+        }
         *self == other
     }
 }
@@ -1,10 +1,12 @@
-use std::{net::SocketAddr, path::PathBuf};
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 
+use adns_proto::Name;
 use adns_server::{
-    DynFileZoneProvider, FileZoneProvider, MergeZoneProvider, SendUpdates, StaticZoneProvider,
-    ZoneProvider,
+    DynFileZoneProvider, FileZoneProvider, MergeZoneProvider, SecondaryZoneProvider, SendUpdates,
+    StaticZoneProvider, ZoneProvider,
 };
-use adns_zone::Zone;
+use adns_zone::{InvalidRecordPolicy, SoaPrecedence, TsigKey};
+use ipnet::IpNet;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -18,6 +20,23 @@ pub struct Config {
 pub struct DnsServerConfig {
     pub udp_bind: SocketAddr,
     pub tcp_bind: SocketAddr,
+    #[serde(default = "default_max_tcp_response_size")]
+    pub max_tcp_response_size: u16,
+    /// largest UDP response sent, whatever buffer size the client advertises
+    #[serde(default = "default_max_udp_payload_size")]
+    pub max_udp_payload_size: u16,
+    /// seconds a TCP client has to send a query once it started
+    #[serde(default = "default_tcp_read_timeout")]
+    pub tcp_read_timeout: u64,
+    /// seconds a TCP connection may stay idle between queries
+    #[serde(default = "default_tcp_idle_timeout")]
+    pub tcp_idle_timeout: u64,
+    /// networks allowed to query this server, empty allows everyone
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_query: Vec<IpNet>,
+    /// what loading a zone does with records whose data doesn't parse, for every provider in `zone`
+    #[serde(default)]
+    pub invalid_records: InvalidRecordPolicy,
     pub zone: ZoneProviderConfig,
 }
 
@@ -25,10 +44,14 @@ pub struct DnsServerConfig {
 #[serde(rename_all = "snake_case", tag = "type")]
 pub enum ZoneProviderConfig {
     Static {
-        zone: Zone,
+        /// deserialized by `construct`, which applies `invalid_records` to it
+        zone: serde_yaml::Value,
     },
     File {
         path: PathBuf,
+        /// if set, `path` is an RFC 1035 master file for this zone rather than YAML
+        #[serde(default)]
+        origin: Option<Name>,
     },
     DynFile {
         path: PathBuf,
@@ -38,13 +61,80 @@ pub enum ZoneProviderConfig {
         bottom: Box<ZoneProviderConfig>,
         #[serde(default)]
         send_updates: SendUpdates,
+        #[serde(default)]
+        soa_precedence: SoaPrecedence,
+    },
+    Secondary {
+        primary: SocketAddr,
+        zone: Name,
+        #[serde(default)]
+        tsig: Option<SecondaryTsig>,
+        /// seconds between transfer attempts until the first one succeeds, after which the SOA timers are used
+        #[serde(default = "default_secondary_refresh")]
+        refresh: u64,
+        /// `zone` is an RFC 9432 catalog zone, whose member zones are served as well
+        #[serde(default)]
+        catalog: bool,
     },
     #[cfg(feature = "postgres")]
     Postgres(adns_server::db::DbConfig),
+    #[cfg(feature = "grpc")]
+    Grpc {
+        endpoint: String,
+        #[serde(default)]
+        node_id: String,
+        /// seconds before the first reconnection attempt, doubled per failure up to `max_backoff`
+        #[serde(default = "default_grpc_min_backoff")]
+        min_backoff: u64,
+        #[serde(default = "default_grpc_max_backoff")]
+        max_backoff: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SecondaryTsig {
+    pub name: Name,
+    pub algorithm: Name,
+    pub key: TsigKey,
+    /// accept `hmac-md5.sig-alg.reg.int` as `algorithm`
+    #[serde(default)]
+    pub allow_md5: bool,
+}
+
+fn default_secondary_refresh() -> u64 {
+    60
+}
+
+#[cfg(feature = "grpc")]
+fn default_grpc_min_backoff() -> u64 {
+    1
+}
+
+#[cfg(feature = "grpc")]
+fn default_grpc_max_backoff() -> u64 {
+    60
+}
+
+fn default_max_tcp_response_size() -> u16 {
+    adns_server::DEFAULT_MAX_TCP_RESPONSE_SIZE
+}
+
+fn default_max_udp_payload_size() -> u16 {
+    adns_server::DEFAULT_MAX_UDP_PAYLOAD_SIZE
+}
+
+fn default_tcp_read_timeout() -> u64 {
+    adns_server::DEFAULT_TCP_READ_TIMEOUT.as_secs()
+}
+
+fn default_tcp_idle_timeout() -> u64 {
+    adns_server::DEFAULT_TCP_IDLE_TIMEOUT.as_secs()
 }
 
 #[derive(Error, Debug)]
 pub enum ZoneProviderInitError {
+    #[error("{0}")]
+    Yaml(#[from] serde_yaml::Error),
     #[cfg(feature = "postgres")]
     #[error("{0}")]
     Postgres(#[from] adns_server::db::PostgresError),
@@ -52,24 +142,68 @@ pub enum ZoneProviderInitError {
 
 impl ZoneProviderConfig {
     #[async_recursion::async_recursion]
-    pub async fn construct(self) -> Result<Box<dyn ZoneProvider>, ZoneProviderInitError> {
+    pub async fn construct(
+        self,
+        invalid_records: InvalidRecordPolicy,
+    ) -> Result<Box<dyn ZoneProvider>, ZoneProviderInitError> {
         let provider: Box<dyn ZoneProvider> = match self {
-            ZoneProviderConfig::Static { zone } => Box::new(StaticZoneProvider(zone)),
-            ZoneProviderConfig::File { path } => Box::new(FileZoneProvider(path)),
-            ZoneProviderConfig::DynFile { path } => Box::new(DynFileZoneProvider(path)),
+            ZoneProviderConfig::Static { zone } => {
+                Box::new(StaticZoneProvider::from_yaml(zone, invalid_records)?)
+            }
+            ZoneProviderConfig::File { path, origin } => Box::new(FileZoneProvider {
+                path,
+                origin,
+                invalid_records,
+            }),
+            ZoneProviderConfig::DynFile { path } => {
+                Box::new(DynFileZoneProvider::new(path).with_invalid_records(invalid_records))
+            }
             ZoneProviderConfig::Merge {
                 top,
                 bottom,
                 send_updates,
-            } => Box::new(MergeZoneProvider::new(
-                top.construct().await?,
-                bottom.construct().await?,
-                send_updates,
-            )),
+                soa_precedence,
+            } => Box::new(
+                MergeZoneProvider::new(
+                    top.construct(invalid_records).await?,
+                    bottom.construct(invalid_records).await?,
+                    send_updates,
+                )
+                .with_soa_precedence(soa_precedence),
+            ),
+            ZoneProviderConfig::Secondary {
+                primary,
+                zone,
+                tsig,
+                refresh,
+                catalog,
+            } => Box::new(SecondaryZoneProvider {
+                primary,
+                zone,
+                allow_md5_tsig: tsig.as_ref().is_some_and(|tsig| tsig.allow_md5),
+                key: tsig.map(|tsig| (tsig.name, tsig.algorithm, tsig.key.0)),
+                refresh: Duration::from_secs(refresh),
+                catalog,
+            }),
             #[cfg(feature = "postgres")]
-            ZoneProviderConfig::Postgres(config) => {
-                Box::new(adns_server::db::DbZoneProvider::new(&config).await?)
-            }
+            ZoneProviderConfig::Postgres(config) => Box::new(
+                adns_server::db::DbZoneProvider::new(&config)
+                    .await?
+                    .with_invalid_records(invalid_records),
+            ),
+            #[cfg(feature = "grpc")]
+            ZoneProviderConfig::Grpc {
+                endpoint,
+                node_id,
+                min_backoff,
+                max_backoff,
+            } => Box::new(adns_server::GrpcZoneProvider {
+                endpoint,
+                node_id,
+                min_backoff: Duration::from_secs(min_backoff),
+                max_backoff: Duration::from_secs(max_backoff),
+                invalid_records,
+            }),
         };
         Ok(provider)
     }
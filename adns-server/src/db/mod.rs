@@ -1,21 +1,29 @@
 // TODO: CONFIG.database_init_stmts?
 
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::Arc,
+    time::Duration,
+};
 
 use adns_proto::{NameParseError, TypeDataParseError};
-use adns_zone::{Zone, ZoneUpdate};
+use adns_zone::{
+    EqualSerialPolicy, InvalidRecord, InvalidRecordPolicy, SerialPolicy, Zone, ZoneUpdate,
+};
 use bb8::{Pool, PooledConnection};
 use bb8_postgres::PostgresConnectionManager;
-use log::{error, info};
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tokio::sync::mpsc;
+use tokio::sync::mpsc::{self, error::TrySendError};
 use tokio_postgres::{
-    config::SslMode, tls::NoTlsStream, Client, Config, Connection, NoTls, Socket,
+    config::SslMode, error::SqlState, tls::NoTlsStream, Client, Config, Connection, NoTls, Socket,
 };
 
 use crate::{
     db::notify::{CockroachNotifier, PostgresNotifier},
+    zone_provider::log_skipped,
     ZoneProvider, ZoneProviderUpdate,
 };
 
@@ -51,6 +59,19 @@ pub enum PostgresError {
     TypeDataParse(#[from] TypeDataParseError),
     #[error("{0}")]
     Strum(#[from] strum::ParseError),
+    #[error("invalid record data: {0}")]
+    InvalidRecord(#[from] InvalidRecord),
+}
+
+impl PostgresError {
+    /// a serializable transaction lost a conflict with a concurrent one, and can be retried as-is
+    fn is_serialization_failure(&self) -> bool {
+        let error = match self {
+            PostgresError::Postgres(e) | PostgresError::Pool(bb8::RunError::User(e)) => e,
+            _ => return false,
+        };
+        error.code() == Some(&SqlState::T_R_SERIALIZATION_FAILURE)
+    }
 }
 
 fn default_port() -> u16 {
@@ -61,6 +82,10 @@ fn default_database() -> String {
     "adns".to_string()
 }
 
+fn default_update_workers() -> usize {
+    1
+}
+
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
 #[serde(rename_all = "snake_case")]
 pub enum DatabaseType {
@@ -80,6 +105,15 @@ pub struct DbConfig {
     pub database: String,
     pub username: String,
     pub password: String,
+    /// number of concurrent RFC2136 update workers. updates to the same zone are always applied in order
+    #[serde(default = "default_update_workers")]
+    pub update_workers: usize,
+    /// what updates do with a SOA that keeps the current serial but changes the data
+    #[serde(default)]
+    pub equal_serial: EqualSerialPolicy,
+    /// how the SOA serial moves when an update changes a zone's records
+    #[serde(default)]
+    pub serial_policy: SerialPolicy,
 }
 
 impl DbConfig {
@@ -102,6 +136,10 @@ impl DbConfig {
 pub struct DbZoneProvider {
     pool: DbPool,
     notifier: Arc<dyn NotifierSystem>,
+    update_workers: usize,
+    equal_serial: EqualSerialPolicy,
+    serial_policy: SerialPolicy,
+    invalid_records: InvalidRecordPolicy,
 }
 
 impl DbZoneProvider {
@@ -118,7 +156,8 @@ impl DbZoneProvider {
         let _ = db_config.connect_raw().await?;
         let manager = bb8_postgres::PostgresConnectionManager::new(config, NoTls);
         let pool = bb8::Pool::builder()
-            .max_size(10)
+            // leave room for the update workers alongside zone loads and notifications
+            .max_size(10.max(db_config.update_workers as u32 + 2))
             .connection_timeout(Duration::from_secs(15))
             .build(manager)
             .await?;
@@ -140,27 +179,106 @@ impl DbZoneProvider {
         };
 
         drop(conn);
-        Ok(Self { pool, notifier })
+        Ok(Self {
+            pool,
+            notifier,
+            update_workers: db_config.update_workers.max(1),
+            equal_serial: db_config.equal_serial,
+            serial_policy: db_config.serial_policy,
+            invalid_records: Default::default(),
+        })
+    }
+
+    pub fn with_invalid_records(mut self, invalid_records: InvalidRecordPolicy) -> Self {
+        self.invalid_records = invalid_records;
+        self
     }
 
     async fn try_load_zone(&self) -> Result<Zone, PostgresError> {
         let mut conn = self.pool.get().await?;
-        let zone = zone::load_current_zone(&mut conn).await?;
+        let (mut zone, skipped) = zone::load_current_zone(&mut conn, self.invalid_records).await?;
+        log_skipped("database", skipped);
+        // so updates the policy rejects are refused when they arrive, see `respond_update`
+        zone.equal_serial = self.equal_serial;
+        for zone in zone.zones.values_mut() {
+            zone.equal_serial = self.equal_serial;
+        }
         Ok(zone)
     }
 }
 
 const MAX_UPDATE_RETRY: usize = 3;
 
+/// serialization conflicts retried before an update is given up, waiting `CONFLICT_BACKOFF` doubled per conflict
+const MAX_CONFLICT_RETRY: u32 = 8;
+
+const CONFLICT_BACKOFF: Duration = Duration::from_millis(10);
+
+/// updates queued per worker. further updates for a backed up worker's zones are refused rather than holding up the rest
+const WORKER_QUEUE: usize = 64;
+
 async fn try_update(
     pool: &Pool<PostgresConnectionManager<NoTls>>,
     update: &ZoneUpdate,
+    equal_serial: EqualSerialPolicy,
+    serial_policy: SerialPolicy,
 ) -> Result<(), PostgresError> {
     let mut conn = pool.get().await?;
-    zone::apply_update(&mut conn, update).await?;
+    zone::apply_update(&mut conn, update, equal_serial, serial_policy).await?;
     Ok(())
 }
 
+async fn update_worker(
+    pool: DbPool,
+    notifier: Arc<dyn NotifierSystem>,
+    equal_serial: EqualSerialPolicy,
+    serial_policy: SerialPolicy,
+    mut updates: mpsc::Receiver<ZoneProviderUpdate>,
+) {
+    while let Some(update) = updates.recv().await {
+        // reloading is all a NOTIFY needs, the database is the source
+        if update.notify {
+            update.response.send(()).ok();
+            if let Err(e) = notifier.notify().await {
+                error!("failed to notify psql of NOTIFY: {e}");
+            }
+            continue;
+        }
+        let mut attempt = 1usize;
+        let mut conflicts = 0u32;
+        loop {
+            match try_update(&pool, &update.update, equal_serial, serial_policy).await {
+                Ok(_) => {
+                    update.response.send(()).ok();
+                    if let Err(e) = notifier.notify().await {
+                        error!("failed to notify psql of update: {e}");
+                    }
+                    break;
+                }
+                // conflicts between concurrent workers are expected, they don't count as failed attempts
+                Err(e) if e.is_serialization_failure() => {
+                    if conflicts >= MAX_CONFLICT_RETRY {
+                        error!("DNS update kept conflicting with concurrent updates: {e}, skipped");
+                        break;
+                    }
+                    debug!("DNS update conflicted with a concurrent update, retrying: {e}");
+                    tokio::time::sleep(CONFLICT_BACKOFF * 2u32.pow(conflicts)).await;
+                    conflicts += 1;
+                }
+                Err(e) => {
+                    if attempt >= MAX_UPDATE_RETRY {
+                        error!("failed to apply DNS update: {e}, skipped");
+                        break;
+                    }
+                    error!("failed to apply DNS update: {e}, trying again in 1 second ({attempt}/{MAX_UPDATE_RETRY})");
+                    attempt += 1;
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            };
+        }
+    }
+}
+
 #[async_trait::async_trait]
 impl ZoneProvider for DbZoneProvider {
     async fn run(
@@ -168,30 +286,35 @@ impl ZoneProvider for DbZoneProvider {
         sender: mpsc::Sender<Zone>,
         mut updates: mpsc::Receiver<ZoneProviderUpdate>,
     ) {
-        let pool2 = self.pool.clone();
-        let notifier2 = self.notifier.clone();
+        // each zone is pinned to one worker so its updates stay ordered
+        let workers: Vec<mpsc::Sender<ZoneProviderUpdate>> = (0..self.update_workers)
+            .map(|_| {
+                let (worker_sender, worker_receiver) = mpsc::channel(WORKER_QUEUE);
+                tokio::spawn(update_worker(
+                    self.pool.clone(),
+                    self.notifier.clone(),
+                    self.equal_serial,
+                    self.serial_policy,
+                    worker_receiver,
+                ));
+                worker_sender
+            })
+            .collect();
         tokio::spawn(async move {
             while let Some(update) = updates.recv().await {
-                let mut attempt = 1usize;
-                loop {
-                    match try_update(&pool2, &update.update).await {
-                        Ok(_) => {
-                            update.response.send(()).ok();
-                            if let Err(e) = notifier2.notify().await {
-                                error!("failed to notify psql of update: {e}");
-                            }
-                            break;
-                        }
-                        Err(e) => {
-                            if attempt >= MAX_UPDATE_RETRY {
-                                error!("failed to apply DNS update: {e}, skipped");
-                                break;
-                            }
-                            error!("failed to apply DNS update: {e}, trying again in 1 second ({attempt}/{MAX_UPDATE_RETRY})");
-                            attempt += 1;
-                            tokio::time::sleep(Duration::from_secs(1)).await;
-                        }
-                    };
+                let mut hasher = DefaultHasher::new();
+                update.update.zone_name.hash(&mut hasher);
+                let worker = &workers[hasher.finish() as usize % workers.len()];
+                // dropping the update answers it with SERVFAIL
+                match worker.try_send(update) {
+                    Ok(()) => (),
+                    Err(TrySendError::Full(update)) => {
+                        warn!(
+                            "update queue for {} is full, refusing update",
+                            update.update.zone_name
+                        );
+                    }
+                    Err(TrySendError::Closed(_)) => break,
                 }
             }
         });
@@ -1,7 +1,10 @@
 use std::collections::HashMap;
 
 use adns_proto::{Class, Name, Record, SoaData, Type, TypeData};
-use adns_zone::{TsigKey, Zone, ZoneUpdate, ZoneUpdateAction};
+use adns_zone::{
+    EqualSerialPolicy, InvalidRecord, InvalidRecordPolicy, SerialPolicy, TsigKey, Zone, ZoneUpdate,
+    ZoneUpdateAction,
+};
 use base64::{engine::general_purpose, Engine};
 use log::error;
 use tokio_postgres::{IsolationLevel, Row};
@@ -64,8 +67,8 @@ impl TryFrom<Row> for ZoneSoa {
 }
 
 struct ZoneNameserver {
-    id: Uuid,
     #[allow(dead_code)]
+    id: Uuid,
     zone_id: Uuid,
     name: Name,
 }
@@ -98,7 +101,7 @@ impl TryFrom<Row> for ZoneTsigKey {
             id: row.get(0),
             zone_id: row.get(1),
             name: row.get(2),
-            keydata: general_purpose::STANDARD_NO_PAD.decode(&row.get::<_, String>(3))?,
+            keydata: general_purpose::STANDARD_NO_PAD.decode(row.get::<_, String>(3))?,
         })
     }
 }
@@ -146,7 +149,38 @@ impl ZoneRecord {
     }
 }
 
-pub async fn load_current_zone(conn: &mut Conn) -> Result<Zone, PostgresError> {
+/// a stored record, `None` if its data doesn't parse and `invalid_records` skips it
+#[allow(clippy::result_large_err)]
+fn parse_record(
+    name: &str,
+    dns_type: &str,
+    ttl: i32,
+    data: &str,
+    invalid_records: InvalidRecordPolicy,
+    skipped: &mut Vec<InvalidRecord>,
+) -> Result<Option<Record>, PostgresError> {
+    let type_: Type = dns_type.parse()?;
+    let name: Name = name.parse()?;
+    match TypeData::parse_str(type_, data) {
+        Ok(data) => Ok(Some(Record {
+            name,
+            type_,
+            class: Class::IN,
+            ttl: ttl as u32,
+            data,
+        })),
+        Err(error) => {
+            invalid_records.handle(InvalidRecord { name, type_, error }, skipped)?;
+            Ok(None)
+        }
+    }
+}
+
+/// the zones in the database, and the records `invalid_records` skipped
+pub async fn load_current_zone(
+    conn: &mut Conn,
+    invalid_records: InvalidRecordPolicy,
+) -> Result<(Zone, Vec<InvalidRecord>), PostgresError> {
     let txn = conn
         .build_transaction()
         .isolation_level(IsolationLevel::RepeatableRead)
@@ -173,6 +207,35 @@ pub async fn load_current_zone(conn: &mut Conn) -> Result<Zone, PostgresError> {
                             authoritative: x.authoritative,
                             class: Class::IN,
                             allow_md5_tsig: x.allow_md5_tsig,
+                            classes: vec![],
+                            negative_ttl: None,
+                            axfr_ptr_check: None,
+                            delegation_check: None,
+                            rpz: vec![],
+                            signing_keys: vec![],
+                            require_cookie_for_large_udp: false,
+                            cookie_challenge: false,
+                            catalog: None,
+                            catalog_member: None,
+                            normalize_idna: false,
+                            notify_from: vec![],
+                            notify_targets: vec![],
+                            additional_ttl_cap: None,
+                            serial_policy: Default::default(),
+                            equal_serial: Default::default(),
+                            rrset_order: Default::default(),
+                            nameserver_order: Default::default(),
+                            standard_wildcards: false,
+                            server_id: None,
+                            identity_queries: Default::default(),
+                            identity_ttl: adns_zone::DEFAULT_IDENTITY_TTL,
+                            forwarding: None,
+                            reverse_zones: vec![],
+                            nxdomain_redirect: None,
+                            views: vec![],
+                            index: None,
+                            signatures: Default::default(),
+                            rotations: Default::default(),
                         },
                         x,
                     ),
@@ -197,11 +260,15 @@ pub async fn load_current_zone(conn: &mut Conn) -> Result<Zone, PostgresError> {
             }
         }
     }
-    for zone_nameserver in conn.query(r"SELECT * FROM zone_nameservers", &[]).await? {
+    // the table has no ordering column, sort by name so NS answers don't depend on the query plan
+    for zone_nameserver in conn
+        .query(r"SELECT * FROM zone_nameservers ORDER BY name ASC", &[])
+        .await?
+    {
         let zone_nameserver: Result<ZoneNameserver, _> = zone_nameserver.try_into();
         match zone_nameserver {
             Ok(x) => {
-                if let Some(zone) = zones.get_mut(&x.id) {
+                if let Some(zone) = zones.get_mut(&x.zone_id) {
                     zone.0.nameservers.push(x.name);
                 }
             }
@@ -223,23 +290,28 @@ pub async fn load_current_zone(conn: &mut Conn) -> Result<Zone, PostgresError> {
             }
         }
     }
-    for zone_record in conn
+    let mut skipped = vec![];
+    for row in conn
         .query(r"SELECT * FROM zone_records ORDER BY ordering ASC", &[])
         .await?
     {
-        let zone_record: Result<ZoneRecord, _> = zone_record.try_into();
-        match zone_record {
-            Ok(x) => {
-                if let Some(zone) = zones.get_mut(&x.zone_id) {
-                    zone.0.records.push(Record {
-                        name: x.name,
-                        type_: x.dns_type,
-                        class: Class::IN,
-                        ttl: x.ttl,
-                        data: x.data,
-                    });
+        let zone_id: Uuid = row.get(0);
+        let record = parse_record(
+            &row.get::<_, String>(2),
+            &row.get::<_, String>(3),
+            row.get(4),
+            &row.get::<_, String>(5),
+            invalid_records,
+            &mut skipped,
+        );
+        match record {
+            Ok(Some(record)) => {
+                if let Some(zone) = zones.get_mut(&zone_id) {
+                    zone.0.records.push(record);
                 }
             }
+            Ok(None) => (),
+            Err(e) if invalid_records == InvalidRecordPolicy::Fail => return Err(e),
             Err(e) => {
                 error!("failed to parse zone record: {e}");
             }
@@ -262,14 +334,49 @@ pub async fn load_current_zone(conn: &mut Conn) -> Result<Zone, PostgresError> {
             authoritative: true,
             class: Class::IN,
             allow_md5_tsig: false,
+            classes: vec![],
+            negative_ttl: None,
+            axfr_ptr_check: None,
+            delegation_check: None,
+            rpz: vec![],
+            signing_keys: vec![],
+            require_cookie_for_large_udp: false,
+            cookie_challenge: false,
+            catalog: None,
+            catalog_member: None,
+            normalize_idna: false,
+            notify_from: vec![],
+            notify_targets: vec![],
+            additional_ttl_cap: None,
+            serial_policy: Default::default(),
+            equal_serial: Default::default(),
+            rrset_order: Default::default(),
+            nameserver_order: Default::default(),
+            standard_wildcards: false,
+            server_id: None,
+            identity_queries: Default::default(),
+            identity_ttl: adns_zone::DEFAULT_IDENTITY_TTL,
+            forwarding: None,
+            reverse_zones: vec![],
+            nxdomain_redirect: None,
+            views: vec![],
+            index: None,
+            signatures: Default::default(),
+            rotations: Default::default(),
         });
     for (_id, (zone, db_zone)) in zones {
         root_zone.zones.insert(db_zone.domain, zone);
     }
-    Ok(root_zone)
+    Ok((root_zone, skipped))
 }
 
-pub async fn apply_update(conn: &mut Conn, zone_update: &ZoneUpdate) -> Result<(), PostgresError> {
+#[allow(clippy::result_large_err)]
+pub async fn apply_update(
+    conn: &mut Conn,
+    zone_update: &ZoneUpdate,
+    equal_serial: EqualSerialPolicy,
+    serial_policy: SerialPolicy,
+) -> Result<(), PostgresError> {
     let txn = conn
         .build_transaction()
         .isolation_level(IsolationLevel::Serializable)
@@ -299,18 +406,20 @@ pub async fn apply_update(conn: &mut Conn, zone_update: &ZoneUpdate) -> Result<(
         }
     };
 
+    let mut changed = false;
     'outer: for update in &zone_update.actions {
         match update {
             ZoneUpdateAction::DeleteRecords(name, None) => {
-                if name == &zone.domain {
-                    conn.execute(r"DELETE FROM zone_records WHERE zone_id = $1 AND name = $2 AND dns_type != 'SOA' AND dns_type != 'NS'", &[&zone.id, &name.lowercased()]).await?;
+                let deleted = if name == &zone.domain {
+                    conn.execute(r"DELETE FROM zone_records WHERE zone_id = $1 AND name = $2 AND dns_type != 'SOA' AND dns_type != 'NS'", &[&zone.id, &name.lowercased()]).await?
                 } else {
                     conn.execute(
                         r"DELETE FROM zone_records WHERE zone_id = $1 AND name = $2",
                         &[&zone.id, &name.lowercased()],
                     )
-                    .await?;
-                }
+                    .await?
+                };
+                changed |= deleted > 0;
             }
             ZoneUpdateAction::DeleteRecords(name, Some(type_)) => {
                 if name == &zone.domain
@@ -321,11 +430,13 @@ pub async fn apply_update(conn: &mut Conn, zone_update: &ZoneUpdate) -> Result<(
                     continue;
                 }
                 let type_str: &'static str = type_.into();
-                conn.execute(
-                    r"DELETE FROM zone_records WHERE zone_id = $1 AND name = $2 AND dns_type = $3",
-                    &[&zone.id, &name.lowercased(), &type_str],
-                )
-                .await?;
+                changed |= conn
+                    .execute(
+                        r"DELETE FROM zone_records WHERE zone_id = $1 AND name = $2 AND dns_type = $3",
+                        &[&zone.id, &name.lowercased(), &type_str],
+                    )
+                    .await?
+                    > 0;
             }
             ZoneUpdateAction::DeleteRecord(name, data) => {
                 if name == &zone.domain {
@@ -340,7 +451,7 @@ pub async fn apply_update(conn: &mut Conn, zone_update: &ZoneUpdate) -> Result<(
                     }
                 }
                 let type_str: &'static str = data.dns_type().into();
-                conn.execute(r"DELETE FROM zone_records WHERE zone_id = $1 AND name = $2 AND dns_type = $3 AND data = $4 LIMIT 1", &[&zone.id, &name.lowercased(), &type_str, &data.to_string()]).await?;
+                changed |= conn.execute(r"DELETE FROM zone_records WHERE zone_id = $1 AND name = $2 AND dns_type = $3 AND data = $4 LIMIT 1", &[&zone.id, &name.lowercased(), &type_str, &data.to_string()]).await? > 0;
             }
             ZoneUpdateAction::AddRecord(Record {
                 name,
@@ -375,15 +486,21 @@ pub async fn apply_update(conn: &mut Conn, zone_update: &ZoneUpdate) -> Result<(
                     continue;
                 }
                 if *type_ == Type::SOA {
-                    let TypeData::SOA(SoaData { serial: new_serial, .. }) = &data else {
+                    let TypeData::SOA(new_soa) = &data else {
                         continue;
                     };
                     if let Some(ZoneRecord {
-                        data: TypeData::SOA(SoaData { serial, .. }),
+                        data: TypeData::SOA(soa),
                         ..
                     }) = records.iter().find(|x| x.dns_type == Type::SOA)
                     {
-                        if serial > new_serial {
+                        if soa.serial > new_soa.serial {
+                            continue;
+                        }
+                        if soa.serial == new_soa.serial
+                            && soa != new_soa
+                            && !equal_serial.allows(name, soa.serial)
+                        {
                             continue;
                         }
                     }
@@ -394,6 +511,7 @@ pub async fn apply_update(conn: &mut Conn, zone_update: &ZoneUpdate) -> Result<(
                 {
                     if *type_ == Type::CNAME || *type_ == Type::SOA || *data == zone_record.data {
                         // update
+                        changed |= zone_record.ttl != ttl;
                         zone_record.ttl = ttl;
                         zone_record.save(conn).await?;
                         continue 'outer;
@@ -409,9 +527,70 @@ pub async fn apply_update(conn: &mut Conn, zone_update: &ZoneUpdate) -> Result<(
                 }
                 .insert_next_order(conn)
                 .await?;
+                changed = true;
             }
         }
     }
+    // an update bringing its own SOA sets the serial. INT4 holds the u32 serial's bits
+    if changed && !zone_update.sets_soa() {
+        if let Some(row) = conn
+            .query_opt(
+                r"SELECT soa_serial FROM zone_soas WHERE id = $1",
+                &[&zone.id],
+            )
+            .await?
+        {
+            let serial: i32 = row.get(0);
+            let next = serial_policy.next_serial(serial as u32) as i32;
+            conn.execute(
+                r"UPDATE zone_soas SET soa_serial = $2 WHERE id = $1",
+                &[&zone.id, &next],
+            )
+            .await?;
+        }
+    }
     txn.commit().await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_record() {
+        let mut skipped = vec![];
+        let record = parse_record(
+            "www.example.com",
+            "A",
+            300,
+            "192.0.2.1",
+            InvalidRecordPolicy::Fail,
+            &mut skipped,
+        )
+        .unwrap();
+        assert_eq!(
+            record.unwrap().to_string(),
+            "www.example.com A 300 192.0.2.1"
+        );
+
+        for invalid_records in [InvalidRecordPolicy::Skip, InvalidRecordPolicy::Fail] {
+            let record = parse_record(
+                "www.example.com",
+                "A",
+                300,
+                "not-an-address",
+                invalid_records,
+                &mut skipped,
+            );
+            match invalid_records {
+                InvalidRecordPolicy::Skip => assert!(record.unwrap().is_none()),
+                InvalidRecordPolicy::Fail => {
+                    assert!(matches!(record, Err(PostgresError::InvalidRecord(_))))
+                }
+            }
+        }
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].name, "www.example.com");
+    }
+}
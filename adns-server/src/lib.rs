@@ -12,8 +12,15 @@ pub mod db;
 
 #[cfg(test)]
 mod tests {
-    use adns_proto::{Record, TypeData};
+    use std::{net::SocketAddr, time::Duration};
+
+    use adns_proto::{Packet, Question, Record, Type, TypeData};
     use adns_zone::Zone;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+        sync::oneshot,
+    };
 
     use crate::{Server, StaticZoneProvider};
 
@@ -38,9 +45,127 @@ mod tests {
                 zones: Default::default(),
                 class: Default::default(),
                 allow_md5_tsig: false,
+                classes: vec![],
+                negative_ttl: None,
+                axfr_ptr_check: None,
+                delegation_check: None,
+                rpz: vec![],
+                signing_keys: vec![],
+                require_cookie_for_large_udp: false,
+                cookie_challenge: false,
+                catalog: None,
+                catalog_member: None,
+                normalize_idna: false,
+                notify_from: vec![],
+                notify_targets: vec![],
+                additional_ttl_cap: None,
+                serial_policy: Default::default(),
+                equal_serial: Default::default(),
+                rrset_order: Default::default(),
+                nameserver_order: Default::default(),
+                standard_wildcards: false,
+                server_id: None,
+                identity_queries: Default::default(),
+                identity_ttl: adns_zone::DEFAULT_IDENTITY_TTL,
+                forwarding: None,
+                reverse_zones: vec![],
+                nxdomain_redirect: None,
+                views: vec![],
+                index: None,
+                signatures: Default::default(),
+                rotations: Default::default(),
             }),
         )
         .run()
         .await;
     }
+
+    #[tokio::test]
+    async fn test_run_until() {
+        let addr: SocketAddr = "127.0.0.1:15354".parse().unwrap();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = tokio::spawn(
+            Server::new(addr, addr, StaticZoneProvider(Zone::default())).run_until(async {
+                let _ = stopped.await;
+            }),
+        );
+        let mut client = loop {
+            match TcpStream::connect(addr).await {
+                Ok(client) => break client,
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        };
+        let query = Packet {
+            questions: vec![Question::new(Type::A, "example.com").unwrap()],
+            ..Default::default()
+        }
+        .serialize(512);
+        client.write_u16(query.len() as u16).await.unwrap();
+        client.write_all(&query).await.unwrap();
+        let mut response = vec![0u8; client.read_u16().await.unwrap() as usize];
+        client.read_exact(&mut response).await.unwrap();
+        Packet::parse(&response).unwrap();
+
+        stop.send(()).unwrap();
+        // the idle connection is closed rather than holding up the shutdown
+        tokio::time::timeout(Duration::from_secs(2), server)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(client.read(&mut [0u8; 1]).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_authoritative_zones() {
+        // any free port, the test never connects
+        let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let mut zone = Zone::default();
+        let mut example = Zone {
+            authoritative: true,
+            ..Default::default()
+        };
+        example.zones.insert(
+            "sub.example.com".parse().unwrap(),
+            Zone {
+                authoritative: true,
+                ..Default::default()
+            },
+        );
+        zone.zones.insert("example.com".parse().unwrap(), example);
+        zone.zones.insert(
+            "example.net".parse().unwrap(),
+            Zone {
+                authoritative: true,
+                ..Default::default()
+            },
+        );
+        // delegated away, not ours
+        zone.zones.insert(
+            "delegated.example.net".parse().unwrap(),
+            Zone {
+                nameservers: vec!["ns1.delegated.example.net".parse().unwrap()],
+                ..Default::default()
+            },
+        );
+        let server = Server::new(addr, addr, StaticZoneProvider(zone));
+        assert!(server.authoritative_zones().is_empty());
+        let current_zone = server.current_zone();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = tokio::spawn(server.run_until(async {
+            let _ = stopped.await;
+        }));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while current_zone.load().zones.is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            current_zone.load().authoritative_zones(),
+            vec!["example.com", "sub.example.com", "example.net"]
+        );
+        stop.send(()).unwrap();
+        server.await.unwrap();
+    }
 }
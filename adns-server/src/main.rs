@@ -1,6 +1,9 @@
+use std::time::Duration;
+
 use adns_server::Server;
 use config::Config;
-use log::error;
+use log::{error, info};
+use tokio::sync::watch;
 
 mod config;
 
@@ -22,10 +25,21 @@ async fn main() {
     if let Some(prometheus_bind) = config.prometheus_bind {
         prometheus_exporter::start(prometheus_bind).expect("failed to load prometheus_exporter");
     }
+    let (shutdown_sender, shutdown) = watch::channel(false);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("received shutdown signal");
+        let _ = shutdown_sender.send(true);
+    });
     let mut servers = vec![];
     for server_config in config.servers {
+        let mut shutdown = shutdown.clone();
         servers.push(tokio::spawn(async move {
-            let zone_provider = match server_config.zone.construct().await {
+            let zone_provider = match server_config
+                .zone
+                .construct(server_config.invalid_records)
+                .await
+            {
                 Ok(x) => x,
                 Err(e) => {
                     error!("failed to init zone provider: {e}, dying...");
@@ -36,9 +50,33 @@ async fn main() {
                 server_config.udp_bind,
                 server_config.tcp_bind,
                 zone_provider,
-            );
-            server.run().await;
+            )
+            .with_max_tcp_response_size(server_config.max_tcp_response_size)
+            .with_max_udp_payload_size(server_config.max_udp_payload_size)
+            .with_tcp_read_timeout(Duration::from_secs(server_config.tcp_read_timeout))
+            .with_tcp_idle_timeout(Duration::from_secs(server_config.tcp_idle_timeout))
+            .with_allow_query(server_config.allow_query);
+            server
+                .run_until(async move {
+                    let _ = shutdown.wait_for(|x| *x).await;
+                })
+                .await;
         }))
     }
     futures::future::join_all(servers).await;
 }
+
+/// SIGTERM or ctrl-c
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler");
+        tokio::select! {
+            _ = sigterm.recv() => (),
+            _ = tokio::signal::ctrl_c() => (),
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
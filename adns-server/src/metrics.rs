@@ -1,6 +1,6 @@
 use prometheus::{
-    register_histogram_vec, register_int_counter_vec, register_int_gauge_vec, HistogramVec,
-    IntCounterVec, IntGaugeVec,
+    register_histogram_vec, register_int_counter_vec, register_int_gauge_vec, Encoder,
+    HistogramVec, IntCounterVec, IntGaugeVec, TextEncoder,
 };
 
 lazy_static::lazy_static! {
@@ -8,6 +8,33 @@ lazy_static::lazy_static! {
     pub static ref QUESTIONS: IntCounterVec = register_int_counter_vec!("adns_questions", "count of questions received", &["ipaddr", "name", "class", "type"]).unwrap();
     pub static ref UPDATES: IntCounterVec = register_int_counter_vec!("adns_updates", "count of RFC2136 updates attempted/processed", &["ipaddr", "name", "class", "type", "auth"]).unwrap();
     pub static ref AXFR: IntCounterVec = register_int_counter_vec!("adns_axfr", "count of AXFR attempted", &["ipaddr", "zone", "auth"]).unwrap();
+    pub static ref RESPONSES: IntCounterVec = register_int_counter_vec!("adns_responses", "count of responses sent, by response code", &["rcode"]).unwrap();
+    pub static ref TRANSPORT_RESPONSES: IntCounterVec = register_int_counter_vec!("adns_transport_responses", "count of responses sent, by transport", &["transport"]).unwrap();
+    pub static ref TRUNCATED: IntCounterVec = register_int_counter_vec!("adns_truncated", "count of responses sent with the TC bit set, by transport", &["transport"]).unwrap();
+    pub static ref NXDOMAIN_REDIRECTS: IntCounterVec = register_int_counter_vec!("adns_nxdomain_redirect", "count of NXDOMAIN answers rewritten by `nxdomain_redirect`", &["ipaddr"]).unwrap();
     pub static ref TCP_CONNECTIONS: IntGaugeVec = register_int_gauge_vec!("adns_connection", "inbound TCP connections", &["ipaddr"]).unwrap();
     pub static ref QUERY_US: HistogramVec = register_histogram_vec!("adns_query_us", "non-network query processing time", &[]).unwrap();
 }
+
+/// renders all registered metrics in the prometheus text exposition format,
+/// for serving from an existing HTTP endpoint or pushing to a gateway instead of `prometheus_exporter`
+pub fn render_metrics() -> String {
+    let mut out = vec![];
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut out)
+        .expect("failed to encode metrics");
+    String::from_utf8(out).expect("metrics are not UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_metrics() {
+        QUERY.with_label_values(&["192.0.2.1"]).inc();
+        let rendered = render_metrics();
+        assert!(rendered.contains("# TYPE adns_query counter"));
+        assert!(rendered.contains("adns_query{ipaddr=\"192.0.2.1\"}"));
+    }
+}
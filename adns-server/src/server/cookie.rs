@@ -0,0 +1,114 @@
+//! DNS Cookies (RFC 7873), with server cookies laid out as in RFC 9018
+
+use std::{
+    hash::Hasher,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use adns_proto::{Edns, OptItem};
+use lazy_static::lazy_static;
+use siphasher::sip::SipHasher24;
+
+pub const COOKIE_OPTION: u16 = 10;
+const CLIENT_COOKIE_LEN: usize = 8;
+const SERVER_COOKIE_LEN: usize = 16;
+const SERVER_COOKIE_VERSION: u8 = 1;
+/// server cookies are accepted for an hour after issue, and up to 5 minutes ahead of our clock (RFC 9018 section 4.3)
+const MAX_AGE: u32 = 3600;
+const MAX_SKEW: u32 = 300;
+
+lazy_static! {
+    // regenerated on restart, which only costs clients one round trip to learn a new cookie
+    static ref SECRET: [u8; 16] = uuid::Uuid::new_v4().into_bytes();
+}
+
+fn now() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as u32
+}
+
+fn server_cookie(client_cookie: &[u8], from: &str, timestamp: u32) -> [u8; SERVER_COOKIE_LEN] {
+    let mut out = [0u8; SERVER_COOKIE_LEN];
+    out[0] = SERVER_COOKIE_VERSION;
+    out[4..8].copy_from_slice(&timestamp.to_be_bytes());
+    let mut hasher = SipHasher24::new_with_key(&SECRET);
+    hasher.write(client_cookie);
+    hasher.write(&out[..8]);
+    hasher.write(from.as_bytes());
+    out[8..].copy_from_slice(&hasher.finish().to_be_bytes());
+    out
+}
+
+/// returns the client cookie from a query, and whether it came with a valid server cookie of ours
+pub fn check<'a>(edns: &'a Edns, from: &str) -> Option<(&'a [u8], bool)> {
+    let item = edns
+        .options
+        .items
+        .iter()
+        .find(|item| item.code == COOKIE_OPTION)?;
+    if item.data.len() < CLIENT_COOKIE_LEN {
+        return None;
+    }
+    let (client, server) = item.data.split_at(CLIENT_COOKIE_LEN);
+    if server.len() != SERVER_COOKIE_LEN || server[0] != SERVER_COOKIE_VERSION {
+        return Some((client, false));
+    }
+    let timestamp = u32::from_be_bytes(server[4..8].try_into().unwrap());
+    let now = now();
+    let fresh = now.wrapping_sub(timestamp) <= MAX_AGE || timestamp.wrapping_sub(now) <= MAX_SKEW;
+    Some((
+        client,
+        fresh && server_cookie(client, from, timestamp)[..] == *server,
+    ))
+}
+
+/// the COOKIE option for a response, echoing the client cookie with a fresh server cookie
+pub fn response_option(client_cookie: &[u8], from: &str) -> OptItem {
+    let mut data = client_cookie.to_vec();
+    data.extend(server_cookie(client_cookie, from, now()));
+    OptItem {
+        code: COOKIE_OPTION,
+        data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use adns_proto::OptData;
+
+    use super::*;
+
+    fn query(data: Vec<u8>) -> Edns {
+        Edns {
+            options: OptData {
+                items: vec![OptItem {
+                    code: COOKIE_OPTION,
+                    data,
+                }],
+            },
+            ..Edns::new(1232)
+        }
+    }
+
+    #[test]
+    fn test_cookie() {
+        let client = [1u8, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(
+            check(&query(client.to_vec()), "10.0.0.1"),
+            Some((&client[..], false))
+        );
+
+        let issued = response_option(&client, "10.0.0.1").data;
+        assert_eq!(
+            check(&query(issued.clone()), "10.0.0.1"),
+            Some((&client[..], true))
+        );
+        assert_eq!(
+            check(&query(issued), "10.0.0.2"),
+            Some((&client[..], false))
+        );
+        assert_eq!(check(&Edns::new(1232), "10.0.0.1"), None);
+    }
+}
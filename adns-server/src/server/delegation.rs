@@ -0,0 +1,285 @@
+//! optional detection of lame delegations when a zone is loaded, see `Zone::delegation_check`
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use adns_client::{DnsClient, DnsQueryError};
+use adns_proto::{Name, Question, ResponseCode, Type, TypeData};
+use adns_zone::{DelegationCheck, Zone};
+use log::{debug, warn};
+use thiserror::Error;
+
+/// each lookup and SOA query is abandoned after this long
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Error, Debug)]
+enum LameDelegation {
+    #[error("{0}")]
+    Query(#[from] DnsQueryError),
+    #[error("timed out")]
+    Timeout,
+    #[error("responded {0:?}")]
+    ResponseCode(ResponseCode),
+    #[error("is not authoritative")]
+    NotAuthoritative,
+    #[error("has no SOA for the zone")]
+    NoSoa,
+}
+
+/// non-authoritative subzones with their own nameservers, at any depth
+fn delegations<'a>(zone: &'a Zone, out: &mut Vec<(&'a Name, &'a Zone)>) {
+    for (name, subzone) in &zone.zones {
+        if subzone.is_delegation() {
+            out.push((name, subzone));
+        } else {
+            delegations(subzone, out);
+        }
+    }
+}
+
+fn addresses<'a>(records: impl IntoIterator<Item = &'a TypeData>) -> Vec<IpAddr> {
+    records
+        .into_iter()
+        .filter_map(|data| match data {
+            TypeData::A(ip) => Some(IpAddr::V4(*ip)),
+            TypeData::AAAA(ip) => Some(IpAddr::V6(*ip)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// `(delegated zone, name server, its addresses in the local zone)` for every delegation in `zone`
+fn nameservers(zone: &Zone) -> Vec<(Name, Name, Vec<IpAddr>)> {
+    let mut found = vec![];
+    delegations(zone, &mut found);
+    found
+        .into_iter()
+        .flat_map(|(name, delegation)| {
+            delegation.nameservers.iter().map(move |nameserver| {
+                let local = addresses(zone.lookup(nameserver, None).map(|x| &x.data));
+                (name.clone(), nameserver.clone(), local)
+            })
+        })
+        .collect()
+}
+
+/// the name servers of `zone` to check: all of them on the first load, otherwise only those that weren't checked
+/// with the same glue and settings when `previous` was loaded
+fn unchecked(previous: Option<&Zone>, zone: &Zone) -> Vec<(Name, Name, Vec<IpAddr>)> {
+    let checked = match previous {
+        Some(previous) if previous.delegation_check == zone.delegation_check => {
+            nameservers(previous)
+        }
+        _ => vec![],
+    };
+    nameservers(zone)
+        .into_iter()
+        .filter(|x| !checked.contains(x))
+        .collect()
+}
+
+/// asks the name servers of delegations in `zone` for its SOA in the background, if `delegation_check` is set.
+/// on reloads, only delegations that changed since `previous` are checked
+pub fn check_delegations(previous: Option<&Zone>, zone: &Zone) {
+    let Some(check) = &zone.delegation_check else {
+        return;
+    };
+    for (name, nameserver, local) in unchecked(previous, zone) {
+        tokio::spawn(check_nameserver(check.clone(), name, nameserver, local));
+    }
+}
+
+async fn resolve(forwarder: SocketAddr, nameserver: &Name) -> Result<Vec<IpAddr>, DnsQueryError> {
+    let mut client = DnsClient::new().await?;
+    let mut out = vec![];
+    for type_ in [Type::A, Type::AAAA] {
+        let question = Question {
+            name: nameserver.clone(),
+            type_,
+            class: Default::default(),
+        };
+        let response = client.query(forwarder, vec![question]).await?;
+        out.extend(addresses(response.answers.iter().map(|x| &x.data)));
+    }
+    Ok(out)
+}
+
+async fn check_nameserver(
+    check: DelegationCheck,
+    zone_name: Name,
+    nameserver: Name,
+    mut addresses: Vec<IpAddr>,
+) {
+    if addresses.is_empty() {
+        if let Some(forwarder) = check.forwarder {
+            match tokio::time::timeout(CHECK_TIMEOUT, resolve(forwarder, &nameserver)).await {
+                Ok(Ok(resolved)) => addresses = resolved,
+                Ok(Err(e)) => {
+                    warn!("failed to resolve {nameserver}, a name server of {zone_name}: {e}");
+                    return;
+                }
+                Err(_) => {
+                    warn!("resolving {nameserver}, a name server of {zone_name}, timed out");
+                    return;
+                }
+            }
+        }
+    }
+    if addresses.is_empty() {
+        warn!("lame delegation of {zone_name}: name server {nameserver} has no address");
+        return;
+    }
+    for ip in addresses {
+        let server = SocketAddr::new(ip, check.port);
+        match check_server(&zone_name, server).await {
+            Ok(()) => debug!("{nameserver} ({server}) serves {zone_name}"),
+            Err(e) => warn!("lame delegation of {zone_name}: {nameserver} ({server}) {e}"),
+        }
+    }
+}
+
+/// an authoritative SOA answer for `zone_name` from `server` shows it serves the delegated zone
+async fn check_server(zone_name: &Name, server: SocketAddr) -> Result<(), LameDelegation> {
+    let question = Question {
+        name: zone_name.clone(),
+        type_: Type::SOA,
+        class: Default::default(),
+    };
+    let response = tokio::time::timeout(CHECK_TIMEOUT, async {
+        let mut client = DnsClient::new().await?;
+        client.query(server, vec![question]).await
+    })
+    .await
+    .map_err(|_| LameDelegation::Timeout)??;
+    if response.header.response_code != ResponseCode::NoError {
+        return Err(LameDelegation::ResponseCode(response.header.response_code));
+    }
+    if !response.header.is_authoritative {
+        return Err(LameDelegation::NotAuthoritative);
+    }
+    if !response
+        .answers
+        .iter()
+        .any(|x| x.name == *zone_name && matches!(x.data, TypeData::SOA(_)))
+    {
+        return Err(LameDelegation::NoSoa);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use adns_proto::{Packet, QueryResponse, Record, SoaData};
+    use tokio::net::UdpSocket;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_check_server() {
+        let upstream = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = upstream.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            loop {
+                let (size, from) = upstream.recv_from(&mut buf).await.unwrap();
+                let (query, _) = Packet::parse(&buf[..size]).unwrap();
+                let name = query.questions[0].name.clone();
+                let mut response = query.clone();
+                response.header.query_response = QueryResponse::Response;
+                // only serves example.com, answering other names from its cache
+                response.header.is_authoritative = name == "example.com";
+                response.answers.push(Record::new(
+                    name,
+                    300,
+                    TypeData::SOA(SoaData {
+                        mname: "ns1.example.com".parse().unwrap(),
+                        rname: "hostmaster.example.com".parse().unwrap(),
+                        serial: 1,
+                        refresh: 3600,
+                        retry: 600,
+                        expire: 86400,
+                        minimum: 300,
+                    }),
+                ));
+                upstream
+                    .send_to(&response.serialize(512), from)
+                    .await
+                    .unwrap();
+            }
+        });
+
+        check_server(&"example.com".parse().unwrap(), addr)
+            .await
+            .unwrap();
+        assert!(matches!(
+            check_server(&"example.net".parse().unwrap(), addr).await,
+            Err(LameDelegation::NotAuthoritative)
+        ));
+    }
+
+    #[test]
+    fn test_delegations() {
+        let mut parent = Zone::default();
+        let mut child = Zone {
+            authoritative: false,
+            nameservers: vec!["ns1.sub.example.com".parse().unwrap()],
+            ..Default::default()
+        };
+        child
+            .zones
+            .insert("deeper.sub.example.com".parse().unwrap(), Zone::default());
+        parent
+            .zones
+            .insert("sub.example.com".parse().unwrap(), child);
+        let mut root = Zone::default();
+        root.zones.insert("example.com".parse().unwrap(), parent);
+        let mut found = vec![];
+        delegations(&root, &mut found);
+        assert_eq!(found.len(), 1);
+        assert_eq!(*found[0].0, "sub.example.com");
+    }
+
+    #[test]
+    fn test_unchecked() {
+        let zone = |glue: &str| {
+            let mut zone = Zone::parse_masterfile(
+                &format!(
+                    "
+$TTL 300
+$ORIGIN example.com.
+@       SOA ns1 hostmaster 1 3600 600 86400 300
+ns1.sub A {glue}
+"
+                ),
+                "example.com".parse().unwrap(),
+            )
+            .unwrap();
+            zone.zones.insert(
+                "sub.example.com".parse().unwrap(),
+                Zone {
+                    authoritative: false,
+                    nameservers: vec!["ns1.sub.example.com".parse().unwrap()],
+                    ..Default::default()
+                },
+            );
+            let mut root = Zone {
+                delegation_check: Some(DelegationCheck::default()),
+                ..Default::default()
+            };
+            root.zones.insert("example.com".parse().unwrap(), zone);
+            root
+        };
+        let first = zone("192.0.2.1");
+        let found = unchecked(None, &first);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].2, vec!["192.0.2.1".parse::<IpAddr>().unwrap()]);
+        // an unchanged delegation isn't checked again on reload, one with new glue is
+        assert!(unchecked(Some(&first), &zone("192.0.2.1")).is_empty());
+        assert_eq!(unchecked(Some(&first), &zone("192.0.2.2")).len(), 1);
+        let mut moved = zone("192.0.2.1");
+        moved.delegation_check.as_mut().unwrap().port = 5353;
+        assert_eq!(unchecked(Some(&first), &moved).len(), 1);
+    }
+}
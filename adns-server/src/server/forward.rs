@@ -0,0 +1,258 @@
+//! forwarding of queries outside our zones to upstream resolvers, see `Zone::forwarding`
+
+use std::{sync::Mutex, time::Duration};
+
+use adns_client::{DnsCache, DnsClient, DnsQueryError};
+use adns_proto::{extended_error, Packet, Question, ResponseCode};
+use adns_zone::Forwarding;
+use log::warn;
+
+/// upstream lookups are abandoned after this long, answering SERVFAIL
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// a server's forwarded responses, shared by all its queries. created on first use and recreated when the configured
+/// size or negative TTL cap changes
+#[derive(Default)]
+pub struct ForwardCache(Mutex<Option<DnsCache>>);
+
+impl ForwardCache {
+    fn with<R>(&self, forwarding: &Forwarding, f: impl FnOnce(&mut DnsCache) -> R) -> R {
+        let max_negative_ttl = Duration::from_secs(forwarding.max_negative_ttl as u64);
+        let mut cache = self.0.lock().unwrap();
+        let cache = match &mut *cache {
+            Some(cache)
+                if cache.capacity() == forwarding.cache_size
+                    && cache.max_negative_ttl() == max_negative_ttl =>
+            {
+                cache
+            }
+            cache => cache.insert(
+                DnsCache::new(forwarding.cache_size).with_max_negative_ttl(max_negative_ttl),
+            ),
+        };
+        f(cache)
+    }
+}
+
+async fn lookup(forwarding: &Forwarding, question: &Question) -> Result<Packet, DnsQueryError> {
+    let mut client = DnsClient::new().await?;
+    if forwarding.qname_minimization {
+        let mut packet = Packet::default();
+        match client.resolve(question.name.clone(), question.type_).await {
+            Ok(answers) => packet.answers = answers,
+            Err(DnsQueryError::ResponseCode(code)) => packet.header.response_code = code,
+            Err(e) => return Err(e),
+        }
+        return Ok(packet);
+    }
+    // truncated (TC) upstream responses are retried over TCP by the client, so the full answer is cached
+    let mut last_error = None;
+    for upstream in &forwarding.upstreams {
+        match client.query(*upstream, vec![question.clone()]).await {
+            Ok(packet)
+                if !matches!(
+                    packet.header.response_code,
+                    ResponseCode::ServerFailure | ResponseCode::Refused
+                ) =>
+            {
+                return Ok(packet);
+            }
+            Ok(packet) => {
+                last_error = Some(DnsQueryError::ResponseCode(packet.header.response_code))
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| DnsQueryError::NoNameServers(question.name.clone())))
+}
+
+/// answers `question` from the cache or an upstream, SERVFAIL with an Extended DNS Error if no upstream answered
+pub async fn forward(
+    cache: &ForwardCache,
+    forwarding: &Forwarding,
+    from: &str,
+    question: &Question,
+    mut response: Packet,
+) -> (Packet, Option<(u16, String)>) {
+    response.questions = vec![question.clone()];
+    response.header.recursion_desired = true;
+    response.header.recursion_available = true;
+    let upstream = match cache.with(forwarding, |cache| cache.get(question)) {
+        Some(cached) => cached,
+        None => match tokio::time::timeout(FORWARD_TIMEOUT, lookup(forwarding, question)).await {
+            Ok(Ok(upstream)) => {
+                cache.with(forwarding, |cache| cache.insert(question, &upstream));
+                upstream
+            }
+            Ok(Err(e)) => {
+                warn!("[{from}] forwarding {} failed: {e}", question.name);
+                response.header.response_code = ResponseCode::ServerFailure;
+                let info_code = match e {
+                    DnsQueryError::NoNameServers(_) | DnsQueryError::ResponseCode(_) => {
+                        extended_error::NO_REACHABLE_AUTHORITY
+                    }
+                    _ => extended_error::NETWORK_ERROR,
+                };
+                return (response, Some((info_code, e.to_string())));
+            }
+            Err(_) => {
+                warn!("[{from}] forwarding {} timed out", question.name);
+                response.header.response_code = ResponseCode::ServerFailure;
+                return (
+                    response,
+                    Some((
+                        extended_error::NO_REACHABLE_AUTHORITY,
+                        "upstream timed out".to_string(),
+                    )),
+                );
+            }
+        },
+    };
+    response.header.response_code = upstream.header.response_code;
+    response.answers = upstream.answers;
+    response.nameservers = upstream.nameservers;
+    response.additional_records = upstream.additional_records;
+    (response, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use adns_proto::{QueryResponse, Record, Type, TypeData};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, UdpSocket},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_forward() {
+        let upstream = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = upstream.local_addr().unwrap();
+        let queries = Arc::new(AtomicUsize::new(0));
+        let upstream_queries = queries.clone();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            loop {
+                let (size, from) = upstream.recv_from(&mut buf).await.unwrap();
+                upstream_queries.fetch_add(1, Ordering::SeqCst);
+                let (query, _) = Packet::parse(&buf[..size]).unwrap();
+                let mut response = query.clone();
+                response.header.query_response = QueryResponse::Response;
+                response.answers.push(Record::new(
+                    query.questions[0].name.clone(),
+                    300,
+                    TypeData::A("192.0.2.1".parse().unwrap()),
+                ));
+                upstream
+                    .send_to(&response.serialize(512), from)
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let forwarding = Forwarding {
+            upstreams: vec![addr],
+            cache_size: 10,
+            max_negative_ttl: 60,
+            qname_minimization: false,
+        };
+        let question = Question::new(Type::A, "www.example.net").unwrap();
+        let cache = ForwardCache::default();
+        for _ in 0..2 {
+            let (response, error) = forward(
+                &cache,
+                &forwarding,
+                "10.0.0.1",
+                &question,
+                Packet::default(),
+            )
+            .await;
+            assert!(error.is_none());
+            assert_eq!(response.header.response_code, ResponseCode::NoError);
+            assert!(response.header.recursion_available);
+            assert_eq!(response.answers.len(), 1);
+            assert_eq!(
+                response.answers[0].data,
+                TypeData::A("192.0.2.1".parse().unwrap())
+            );
+        }
+        // the second response came from the cache
+        assert_eq!(queries.load(Ordering::SeqCst), 1);
+        // which belongs to one server, another one asks upstream itself
+        forward(
+            &ForwardCache::default(),
+            &forwarding,
+            "10.0.0.1",
+            &question,
+            Packet::default(),
+        )
+        .await;
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_forward_truncated() {
+        let udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = udp.local_addr().unwrap();
+        let tcp = TcpListener::bind(addr).await.unwrap();
+        let answer = |query: &Packet| {
+            let mut response = query.clone();
+            response.header.query_response = QueryResponse::Response;
+            for i in 0..100u8 {
+                response.answers.push(Record::new(
+                    query.questions[0].name.clone(),
+                    300,
+                    TypeData::A([192, 0, 2, i].into()),
+                ));
+            }
+            response
+        };
+        // too large for UDP, so the UDP response is truncated and only the TCP one is complete
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            loop {
+                let (size, from) = udp.recv_from(&mut buf).await.unwrap();
+                let (query, _) = Packet::parse(&buf[..size]).unwrap();
+                udp.send_to(&answer(&query).serialize(512), from)
+                    .await
+                    .unwrap();
+            }
+        });
+        tokio::spawn(async move {
+            loop {
+                let (mut client, _) = tcp.accept().await.unwrap();
+                let mut query = vec![0u8; client.read_u16().await.unwrap() as usize];
+                client.read_exact(&mut query).await.unwrap();
+                let (query, _) = Packet::parse(&query).unwrap();
+                let response = answer(&query).serialize(u16::MAX as usize);
+                client.write_u16(response.len() as u16).await.unwrap();
+                client.write_all(&response).await.unwrap();
+            }
+        });
+
+        let forwarding = Forwarding {
+            upstreams: vec![addr],
+            cache_size: 10,
+            max_negative_ttl: 60,
+            qname_minimization: false,
+        };
+        let question = Question::new(Type::A, "www.example.net").unwrap();
+        let (response, error) = forward(
+            &ForwardCache::default(),
+            &forwarding,
+            "10.0.0.1",
+            &question,
+            Packet::default(),
+        )
+        .await;
+        assert!(error.is_none());
+        assert!(!response.header.is_truncated);
+        assert_eq!(response.answers.len(), 100);
+    }
+}
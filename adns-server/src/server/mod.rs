@@ -1,15 +1,18 @@
-use std::{io::ErrorKind, net::SocketAddr, sync::Arc, time::Duration};
+use std::{future::Future, io::ErrorKind, net::SocketAddr, sync::Arc, time::Duration};
 
-use adns_zone::Zone;
+use adns_proto::{Name, Packet};
+use adns_zone::{TsigKeys, Zone};
 use arc_swap::{ArcSwap, Guard};
-use log::{debug, error, info};
+use ipnet::IpNet;
+use log::{debug, error, info, warn};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::{TcpListener, TcpStream, UdpSocket},
-    sync::mpsc,
-    task::JoinHandle,
+    sync::{mpsc, watch},
+    task::JoinSet,
 };
 
+use self::forward::ForwardCache;
 use crate::{metrics, ZoneProvider, ZoneProviderUpdate};
 
 pub struct Server {
@@ -18,22 +21,124 @@ pub struct Server {
     receiver: mpsc::Receiver<Zone>,
     update_sender: mpsc::Sender<ZoneProviderUpdate>,
     current_zone: Arc<ArcSwap<Zone>>,
+    tsig_keys: Arc<ArcSwap<TsigKeys>>,
+    max_tcp_response_size: u16,
+    max_udp_payload_size: u16,
+    allow_query: Arc<[IpNet]>,
+    forward_cache: Arc<ForwardCache>,
+    tcp_timeouts: TcpTimeouts,
 }
 
+mod cookie;
+mod delegation;
+mod forward;
+mod notify;
 mod respond;
 mod respond_update;
 
+/// default largest UDP response we send regardless of the client's advertised EDNS buffer size, per the DNS flag day
+/// 2020 recommendation
+pub const DEFAULT_MAX_UDP_PAYLOAD_SIZE: u16 = 1232;
+
+/// UDP queries are received whole, whatever the response size limit, since signed UPDATEs and EDNS options can be large
+const MAX_UDP_QUERY_SIZE: usize = u16::MAX as usize;
+
+/// default cap on single-message TCP responses, the largest a DNS message can be
+pub const DEFAULT_MAX_TCP_RESPONSE_SIZE: u16 = u16::MAX;
+
+/// default time allowed to send a query's length and body once its first byte arrived
+pub const DEFAULT_TCP_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// default time a TCP connection may sit idle between queries
+pub const DEFAULT_TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// answering and writing a response, which a client that doesn't read it can hold up
+const TCP_RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy)]
+struct TcpTimeouts {
+    read: Duration,
+    idle: Duration,
+}
+
+/// how long `Server::run_until` waits for in-flight transactions before abandoning them
+const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// load-time transformations configured on the zone
+fn prepare_zone(zone: &mut Zone) {
+    if zone.normalize_idna {
+        zone.to_ascii_names();
+    }
+    for record in zone.drop_cname_conflicts() {
+        warn!(
+            "dropping {} {} {}: a CNAME must be the only record at its name",
+            record.name, record.type_, record.data
+        );
+    }
+    zone.inherit_orders();
+    zone.insert_reverse_zones();
+    zone.insert_catalog();
+    zone.insert_dnskeys();
+    zone.build_index();
+    for view in &mut zone.views {
+        prepare_zone(&mut view.zone);
+    }
+}
+
+async fn read_query(client: &mut TcpStream) -> Result<Vec<u8>, std::io::Error> {
+    let len = client.read_u16().await?;
+    let mut query = vec![0u8; len as usize];
+    client.read_exact(&mut query).await?;
+    Ok(query)
+}
+
+/// counts a serialized response sent over `transport`, and whether it had to be truncated
+fn count_response(transport: &str, response: &[u8]) {
+    metrics::TRANSPORT_RESPONSES
+        .with_label_values(&[transport])
+        .inc();
+    // TC is the second lowest bit of the header's third byte (RFC 1035 section 4.1.1)
+    if response.get(2).is_some_and(|x| x & 0x02 != 0) {
+        metrics::TRUNCATED.with_label_values(&[transport]).inc();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn tcp_transaction(
     client: &mut TcpStream,
+    query: &[u8],
     updater: &mpsc::Sender<ZoneProviderUpdate>,
     from: &str,
     zone: &Zone,
+    tsig_keys: &ArcSwap<TsigKeys>,
+    allow_query: &[IpNet],
+    forward_cache: &ForwardCache,
+    max_response_size: u16,
+    max_udp_payload_size: u16,
 ) -> Result<(), std::io::Error> {
-    let len = client.read_u16().await?;
-    let mut response = vec![0u8; len as usize];
-    client.read_exact(&mut response).await?;
-    if let Some(response) = respond::respond(true, zone, updater, from, &response).await {
-        let response = response.serialize(zone, u16::MAX as usize);
+    if let Some(response) = respond::respond(
+        true,
+        max_udp_payload_size,
+        zone,
+        &tsig_keys.load(),
+        allow_query,
+        forward_cache,
+        updater,
+        from,
+        query,
+    )
+    .await
+    {
+        // multi-message responses (zone transfers) are already chunked and must not be truncated
+        let max_size = if response.is_multi_message() {
+            u16::MAX
+        } else {
+            max_response_size
+        };
+        let response = response.serialize(zone, max_size as usize);
+        if let Some(first) = response.first() {
+            count_response("tcp", first);
+        }
         for response in response {
             client.write_u16(response.len() as u16).await?;
             client.write_all(&response).await?;
@@ -42,20 +147,67 @@ async fn tcp_transaction(
     Ok(())
 }
 
+/// serves transactions until the client disconnects or goes idle. a query must be sent in full within the read
+/// timeout once started, so slow clients can't hold connections open. on shutdown, the connection is closed
+/// once no transaction is in progress.
+#[allow(clippy::too_many_arguments)]
 async fn tcp_connection(
     mut client: TcpStream,
     updater: mpsc::Sender<ZoneProviderUpdate>,
     from: &str,
     zone: Guard<Arc<Zone>>,
+    tsig_keys: Arc<ArcSwap<TsigKeys>>,
+    allow_query: Arc<[IpNet]>,
+    forward_cache: Arc<ForwardCache>,
+    max_response_size: u16,
+    max_udp_payload_size: u16,
+    timeouts: TcpTimeouts,
+    mut shutdown: watch::Receiver<bool>,
 ) -> Result<(), std::io::Error> {
     metrics::TCP_CONNECTIONS.with_label_values(&[from]).inc();
     defer_lite::defer! {
         metrics::TCP_CONNECTIONS.with_label_values(&[from]).dec();
     };
+    let mut peek = [0u8; 1];
     loop {
+        tokio::select! {
+            _ = shutdown.changed() => return Ok(()),
+            // peeked rather than waiting for readiness, which may be left over from the last transaction
+            peeked = tokio::time::timeout(timeouts.idle, client.peek(&mut peek)) => match peeked {
+                Ok(peeked) => if peeked? == 0 {
+                    return Ok(());
+                },
+                Err(_) => {
+                    return Err(std::io::Error::new(
+                        ErrorKind::TimedOut,
+                        "dns connection idle",
+                    ))
+                }
+            },
+        }
+        let query = match tokio::time::timeout(timeouts.read, read_query(&mut client)).await {
+            Ok(query) => query?,
+            Err(_) => {
+                return Err(std::io::Error::new(
+                    ErrorKind::TimedOut,
+                    "dns query read timed out",
+                ))
+            }
+        };
         match tokio::time::timeout(
-            Duration::from_secs(30),
-            tcp_transaction(&mut client, &updater, from, &zone),
+            TCP_RESPONSE_TIMEOUT,
+            tcp_transaction(
+                &mut client,
+                &query,
+                &updater,
+                from,
+                &zone,
+                &tsig_keys,
+                &allow_query,
+                &forward_cache,
+                max_response_size,
+                max_udp_payload_size,
+            ),
         )
         .await
         {
@@ -86,13 +238,78 @@ impl Server {
             receiver,
             update_sender,
             current_zone: Arc::new(ArcSwap::new(Arc::new(Zone::default()))),
+            tsig_keys: Default::default(),
+            max_tcp_response_size: DEFAULT_MAX_TCP_RESPONSE_SIZE,
+            max_udp_payload_size: DEFAULT_MAX_UDP_PAYLOAD_SIZE,
+            allow_query: Arc::new([]),
+            forward_cache: Default::default(),
+            tcp_timeouts: TcpTimeouts {
+                read: DEFAULT_TCP_READ_TIMEOUT,
+                idle: DEFAULT_TCP_IDLE_TIMEOUT,
+            },
         }
     }
 
-    pub async fn run(mut self) {
+    /// Caps the size of single-message TCP responses, truncating with TC set if exceeded
+    pub fn with_max_tcp_response_size(mut self, size: u16) -> Self {
+        self.max_tcp_response_size = size;
+        self
+    }
+
+    /// Caps UDP responses, and the buffer size advertised in our OPT records. Clamped to at least 512 bytes
+    pub fn with_max_udp_payload_size(mut self, size: u16) -> Self {
+        self.max_udp_payload_size = size.max(Packet::MIN_UDP_SIZE);
+        self
+    }
+
+    /// How long a TCP client may take to send a query once it started sending it
+    pub fn with_tcp_read_timeout(mut self, timeout: Duration) -> Self {
+        self.tcp_timeouts.read = timeout;
+        self
+    }
+
+    /// How long a TCP connection may stay open without a query before it's closed
+    pub fn with_tcp_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.tcp_timeouts.idle = timeout;
+        self
+    }
+
+    /// Restricts queries to sources in these networks, others are answered with REFUSED. Empty allows everyone.
+    pub fn with_allow_query(mut self, allow_query: Vec<IpNet>) -> Self {
+        self.allow_query = allow_query.into();
+        self
+    }
+
+    /// Rotated TSIG keys, used in place of the zone's `tsig_keys` of the same name and in addition to the others.
+    /// Storing a new key set here rotates keys without reloading the zone, and it is kept across zone reloads.
+    pub fn tsig_keys(&self) -> Arc<ArcSwap<TsigKeys>> {
+        self.tsig_keys.clone()
+    }
+
+    /// The zone being served, replaced whenever the zone provider sends a new one. Empty until the first load.
+    pub fn current_zone(&self) -> Arc<ArcSwap<Zone>> {
+        self.current_zone.clone()
+    }
+
+    /// Apex names of the zones currently served authoritatively, see `Zone::authoritative_zones`
+    pub fn authoritative_zones(&self) -> Vec<Name> {
+        self.current_zone.load().authoritative_zones()
+    }
+
+    /// Serves until a listener fails, which only happens on socket errors.
+    pub async fn run(self) {
+        self.run_until(std::future::pending()).await
+    }
+
+    /// Serves until `shutdown` completes, then stops accepting queries and connections and waits for
+    /// in-flight transactions (up to a few seconds) before returning.
+    pub async fn run_until(mut self, shutdown: impl Future<Output = ()>) {
+        let (shutdown_sender, shutdown_receiver) = watch::channel(false);
         info!("Waiting for initial zone load...");
         match self.receiver.recv().await {
-            Some(zone) => {
+            Some(mut zone) => {
+                prepare_zone(&mut zone);
+                delegation::check_delegations(None, &zone);
                 self.current_zone.store(Arc::new(zone));
             }
             None => {
@@ -109,47 +326,68 @@ impl Server {
             }
         };
         info!("Listening on {} (UDP)", self.udp_bind);
-        let mut futures: Vec<JoinHandle<()>> = vec![];
         let current_zone = self.current_zone.clone();
         let mut receiver = self.receiver;
-        futures.push(tokio::spawn(async move {
-            while let Some(zone) = receiver.recv().await {
+        let mut zone_updater = tokio::spawn(async move {
+            while let Some(mut zone) = receiver.recv().await {
                 info!("updating zone...");
+                prepare_zone(&mut zone);
+                notify::notify_changes(&current_zone.load(), &zone);
+                delegation::check_delegations(Some(&current_zone.load()), &zone);
                 current_zone.store(Arc::new(zone));
             }
-        }));
+        });
         let current_zone = self.current_zone.clone();
+        let tsig_keys = self.tsig_keys.clone();
+        let allow_query = self.allow_query.clone();
+        let forward_cache = self.forward_cache.clone();
         let updater = self.update_sender.clone();
-        futures.push(tokio::spawn(async move {
+        let max_udp_payload_size = self.max_udp_payload_size;
+        let mut shutdown_udp = shutdown_receiver.clone();
+        let udp_listener = tokio::spawn(async move {
+            let mut responders = JoinSet::new();
+            let mut recv_buf = vec![0u8; MAX_UDP_QUERY_SIZE];
             loop {
-                let mut recv_buf = vec![0u8; 512];
-                let (size, from) = match udp.recv_from(&mut recv_buf[..]).await {
-                    Ok(x) => x,
-                    Err(e) => {
-                        error!("udp server failure: {e}");
-                        break;
-                    }
+                let (size, from) = tokio::select! {
+                    _ = shutdown_udp.changed() => break,
+                    Some(_) = responders.join_next(), if !responders.is_empty() => continue,
+                    received = udp.recv_from(&mut recv_buf[..]) => match received {
+                        Ok(x) => x,
+                        Err(e) => {
+                            error!("udp server failure: {e}");
+                            break;
+                        }
+                    },
                 };
-                recv_buf.truncate(size);
+                let query = recv_buf[..size].to_vec();
                 let zone = current_zone.load();
+                let keys = tsig_keys.load_full();
+                let allow_query = allow_query.clone();
+                let forward_cache = forward_cache.clone();
                 let udp = udp.clone();
                 let updater = updater.clone();
-                tokio::spawn(async move {
+                responders.spawn(async move {
                     match respond::respond(
                         false,
+                        max_udp_payload_size,
                         &zone,
+                        &keys,
+                        &allow_query,
+                        &forward_cache,
                         &updater,
                         &from.ip().to_string(),
-                        &recv_buf,
+                        &query,
                     )
                     .await
                     {
                         Some(packet) => {
-                            let serialized = packet.serialize(&zone, 512);
+                            let max_size = packet.udp_max_size.min(max_udp_payload_size) as usize;
+                            let serialized = packet.serialize(&zone, max_size);
                             if serialized.len() != 1 {
                                 error!("cannot send more than one packet for udp!");
                                 return;
                             }
+                            count_response("udp", &serialized[0]);
                             if let Err(e) = udp.send_to(&serialized[0], from).await {
                                 debug!("UDP send_to error: {e}");
                             }
@@ -160,30 +398,172 @@ impl Server {
                     }
                 });
             }
-        }));
+            drain("UDP", &mut responders).await;
+        });
         let tcp = match TcpListener::bind(self.tcp_bind).await {
             Ok(x) => x,
             Err(e) => {
                 error!("failed to bind to TCP port: {e}");
+                udp_listener.abort();
+                zone_updater.abort();
                 return;
             }
         };
         info!("Listening on {} (TCP)", self.tcp_bind);
         let current_zone = self.current_zone.clone();
+        let tsig_keys = self.tsig_keys.clone();
+        let allow_query = self.allow_query.clone();
+        let forward_cache = self.forward_cache.clone();
         let updater = self.update_sender.clone();
-        futures.push(tokio::spawn(async move {
-            while let Ok((client, from)) = tcp.accept().await {
+        let max_tcp_response_size = self.max_tcp_response_size;
+        let max_udp_payload_size = self.max_udp_payload_size;
+        let tcp_timeouts = self.tcp_timeouts;
+        let mut shutdown_tcp = shutdown_receiver.clone();
+        let tcp_listener = tokio::spawn(async move {
+            let mut connections = JoinSet::new();
+            loop {
+                let (client, from) = tokio::select! {
+                    _ = shutdown_tcp.changed() => break,
+                    Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+                    accepted = tcp.accept() => match accepted {
+                        Ok(x) => x,
+                        Err(e) => {
+                            error!("tcp server failure: {e}");
+                            break;
+                        }
+                    },
+                };
                 let zone = current_zone.load();
+                let tsig_keys = tsig_keys.clone();
+                let allow_query = allow_query.clone();
+                let forward_cache = forward_cache.clone();
                 let updater = updater.clone();
-                tokio::spawn(async move {
-                    if let Err(e) =
-                        tcp_connection(client, updater, &from.ip().to_string(), zone).await
+                let shutdown = shutdown_receiver.clone();
+                connections.spawn(async move {
+                    if let Err(e) = tcp_connection(
+                        client,
+                        updater,
+                        &from.ip().to_string(),
+                        zone,
+                        tsig_keys,
+                        allow_query,
+                        forward_cache,
+                        max_tcp_response_size,
+                        max_udp_payload_size,
+                        tcp_timeouts,
+                        shutdown,
+                    )
+                    .await
                     {
                         debug!("TCP connection error: {e}");
                     }
                 });
             }
-        }));
-        let _ = futures::future::select_all(&mut futures).await;
+            drain("TCP", &mut connections).await;
+        });
+        let mut listeners = vec![udp_listener, tcp_listener];
+        tokio::select! {
+            _ = shutdown => info!("Shutting down..."),
+            _ = futures::future::select_all(&mut listeners) => (),
+            _ = &mut zone_updater => error!("zone provider died, shutting down"),
+        }
+        // listeners stop on the first change, or when the sender is dropped
+        let _ = shutdown_sender.send(true);
+        for listener in listeners {
+            if !listener.is_finished() {
+                let _ = listener.await;
+            }
+        }
+        zone_updater.abort();
+    }
+}
+
+/// waits for `tasks` to finish, aborting any still running after `SHUTDOWN_DRAIN_TIMEOUT`
+async fn drain(protocol: &str, tasks: &mut JoinSet<()>) {
+    let finished = tokio::time::timeout(SHUTDOWN_DRAIN_TIMEOUT, async {
+        while tasks.join_next().await.is_some() {}
+    })
+    .await;
+    if finished.is_err() {
+        warn!(
+            "abandoning {} {protocol} transactions still in progress",
+            tasks.len()
+        );
+        tasks.shutdown().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use adns_proto::{Packet, PacketParseError, Record, TypeData};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn test_count_response() {
+        let truncated = metrics::TRUNCATED.with_label_values(&["test"]);
+        let mut packet = Packet::default();
+        count_response("test", &packet.serialize(512));
+        assert_eq!(
+            metrics::TRANSPORT_RESPONSES
+                .with_label_values(&["test"])
+                .get(),
+            1
+        );
+        assert_eq!(truncated.get(), 0);
+
+        for i in 0..64 {
+            packet.answers.push(Record::new(
+                "example.com".parse().unwrap(),
+                300,
+                TypeData::A([192, 0, 2, i].into()),
+            ));
+        }
+        let serialized = packet.serialize(512);
+        assert!(matches!(
+            Packet::parse(&serialized),
+            Err(PacketParseError::Truncated)
+        ));
+        count_response("test", &serialized);
+        assert_eq!(truncated.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_read_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        let (_shutdown, shutdown_receiver) = watch::channel(false);
+        let (updater, _) = mpsc::channel(1);
+        let zone = ArcSwap::new(Arc::new(Zone::default()));
+        let connection = tokio::spawn(async move {
+            tcp_connection(
+                server,
+                updater,
+                "test",
+                zone.load(),
+                Default::default(),
+                Arc::new([]),
+                Default::default(),
+                DEFAULT_MAX_TCP_RESPONSE_SIZE,
+                DEFAULT_MAX_UDP_PAYLOAD_SIZE,
+                TcpTimeouts {
+                    read: Duration::from_millis(100),
+                    idle: Duration::from_secs(30),
+                },
+                shutdown_receiver,
+            )
+            .await
+        });
+        // the start of a length prefix, and then nothing
+        client.write_all(&[0]).await.unwrap();
+        let result = tokio::time::timeout(Duration::from_secs(5), connection)
+            .await
+            .expect("slow query was not timed out")
+            .unwrap();
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::TimedOut);
     }
 }
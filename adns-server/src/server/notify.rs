@@ -0,0 +1,35 @@
+use std::net::SocketAddr;
+
+use adns_client::DnsClient;
+use adns_proto::Name;
+use adns_zone::Zone;
+use log::{info, warn};
+
+fn zone_changed(old: Option<&Zone>, new: &Zone) -> bool {
+    old.is_none_or(|old| {
+        old.soa != new.soa || old.nameservers != new.nameservers || old.records != new.records
+    })
+}
+
+/// sends a NOTIFY (RFC 1996) to the `notify_targets` of every zone that changed between `old` and `new`
+pub fn notify_changes(old: &Zone, new: &Zone) {
+    for (name, zone) in &new.zones {
+        if zone.notify_targets.is_empty() || !zone_changed(old.zones.get(name), zone) {
+            continue;
+        }
+        for target in &zone.notify_targets {
+            tokio::spawn(send_notify(name.clone(), *target));
+        }
+    }
+}
+
+async fn send_notify(zone: Name, target: SocketAddr) {
+    let result = match DnsClient::new().await {
+        Ok(mut client) => client.notify(target, zone.clone()).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(_) => info!("sent NOTIFY for {zone} to {target}"),
+        Err(e) => warn!("failed to send NOTIFY for {zone} to {target}: {e}"),
+    }
+}
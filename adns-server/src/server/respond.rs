@@ -1,57 +1,36 @@
-use std::{fmt::Write, time::Instant};
+use std::{
+    borrow::Cow,
+    fmt::Write,
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant},
+};
 
+use adns_client::DnsClient;
 use adns_proto::{
+    extended_error,
     tsig::{self, TsigError, TsigMode},
-    Class, Header, Name, Opcode, Packet, QueryResponse, Question, Record, ResponseCode, Type,
+    Class, Edns, Header, Name, Opcode, Packet, QueryResponse, Question, Record, ResponseCode, Type,
     TypeData, ValidatableTsig,
 };
-use adns_zone::{AnswerState, Zone, ZoneAnswer};
-use log::{info, warn};
+use adns_zone::{
+    answer_query_into, AnswerState, PolicyAction, TsigKeys, Zone, ZoneAnswer, ZoneUpdate,
+};
+use ipnet::IpNet;
+use log::{debug, info, warn};
 use smallvec::{smallvec, SmallVec};
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{
+    mpsc::{self, error::TrySendError},
+    oneshot,
+};
 
+use super::{
+    cookie,
+    forward::{forward, ForwardCache},
+};
 use crate::{metrics, ZoneProviderUpdate};
 
-struct QueryContext<'a> {
-    zone: &'a Zone,
-    question: &'a Question,
-    response: &'a mut ZoneAnswer,
-    state: &'a mut AnswerState,
-}
-
-impl<'a> QueryContext<'a> {
-    fn query(&mut self) -> usize {
-        let start = self.response.answers.len();
-        if self.question.name == "version.bind" && self.question.type_ == Type::TXT {
-            self.response.answers.push(Record::new(
-                "version.bind".parse().unwrap(),
-                3600,
-                TypeData::parse_str(Type::TXT, &format!("adns-{}", env!("CARGO_PKG_VERSION")))
-                    .unwrap(),
-            ));
-            *self.state = AnswerState::DomainSeen;
-            return self.response.answers.len() - start;
-        }
-        let substate = self
-            .zone
-            .answer(None, &Name::default(), self.question, self.response);
-        if substate > *self.state {
-            *self.state = substate;
-        }
-        if self.question.type_ == Type::A && self.response.answers.len() == start {
-            let mut question = self.question.clone();
-            question.type_ = Type::CNAME;
-            QueryContext {
-                zone: self.zone,
-                question: &question,
-                response: self.response,
-                state: self.state,
-            }
-            .query();
-        }
-        self.response.answers.len() - start
-    }
-}
+/// with `Zone::require_cookie_for_large_udp`, UDP responses to clients without a valid server cookie are truncated past this size
+const COOKIELESS_UDP_LIMIT: u16 = 512;
 
 fn log_query(from: &str, header: &Header, question: &Question, answers: &[Record]) {
     if answers.is_empty() {
@@ -84,6 +63,8 @@ fn log_query(from: &str, header: &Header, question: &Question, answers: &[Record
 
 struct TsigInfo {
     name: Name,
+    /// resolved when the request is validated, so responses are signed with the same key even if keys rotate meanwhile
+    key: Vec<u8>,
     request_mac: Vec<u8>,
     algorithm: Name,
 }
@@ -91,10 +72,29 @@ struct TsigInfo {
 pub struct PacketResponse {
     packet: SmallVec<[Packet; 1]>,
     tsig_info: Option<TsigInfo>,
+    /// UDP payload size advertised by the requestor
+    pub udp_max_size: u16,
+    /// Extended DNS Error (RFC 8914) info-code and text, sent if the requestor used EDNS
+    pub extended_error: Option<(u16, String)>,
 }
 
 impl PacketResponse {
-    pub fn serialize(self, zone: &Zone, max_size: usize) -> SmallVec<[Vec<u8>; 1]> {
+    fn with_extended_error(mut self, info_code: u16, text: &str) -> Self {
+        self.extended_error = Some((info_code, text.to_string()));
+        self
+    }
+
+    pub fn is_multi_message(&self) -> bool {
+        self.packet.len() > 1
+    }
+
+    pub fn serialize(mut self, zone: &Zone, max_size: usize) -> SmallVec<[Vec<u8>; 1]> {
+        if let (Some((info_code, text)), Some(edns)) = (
+            &self.extended_error,
+            self.packet.first_mut().and_then(|x| x.edns.as_mut()),
+        ) {
+            edns.push_extended_error(*info_code, text);
+        }
         let mut out = SmallVec::with_capacity(self.packet.len());
         let mut previous_mac: Vec<u8> = vec![];
         for (i, packet) in self.packet.into_iter().enumerate() {
@@ -107,7 +107,7 @@ impl PacketResponse {
                         TsigMode::TimersOnly
                     };
                     let serialized = tsig::serialize_packet(
-                        |name| zone.tsig_keys.get(name).map(|x| x.0.clone()),
+                        |_| Some(info.key.clone()),
                         packet,
                         max_size,
                         info.name.clone(),
@@ -131,13 +131,13 @@ impl From<Packet> for PacketResponse {
         PacketResponse {
             packet: smallvec![packet],
             tsig_info: None,
+            udp_max_size: Packet::MIN_UDP_SIZE,
+            extended_error: None,
         }
     }
 }
 
-fn respond_query(from: &str, zone: &Zone, packet: &Packet, mut response: Packet) -> Option<Packet> {
-    response.questions = packet.questions.clone();
-    let mut state = AnswerState::None;
+fn respond_query(from: &str, zone: &Zone, packet: &Packet, response: Packet) -> Option<Packet> {
     let from_str = from.to_string();
     for question in &packet.questions {
         metrics::QUESTIONS
@@ -148,77 +148,97 @@ fn respond_query(from: &str, zone: &Zone, packet: &Packet, mut response: Packet)
                 question.type_.into(),
             ])
             .inc();
-        let mut answer = ZoneAnswer::default();
-        QueryContext {
-            zone,
-            question,
-            response: &mut answer,
-            state: &mut state,
-        }
-        .query();
-        if answer.is_authoritative {
-            response.header.is_authoritative = true;
-        }
-        log_query(from, &packet.header, question, &answer.answers);
-        response.answers.extend(answer.answers);
-    }
-    for answer in &response.answers {
-        let Some(extra_resolve) = (match &answer.data {
-            TypeData::CNAME(name) => Some(name),
-            TypeData::MX { exchange, .. } => Some(exchange),
-            TypeData::SRV { target, .. } => Some(target),
-            _ => None,
-        }) else {
-            continue;
-        };
-        let question = Question {
-            name: extra_resolve.clone(),
-            type_: Type::A,
-            class: Default::default(),
-        };
-        let mut answer = ZoneAnswer::default();
-        QueryContext {
-            zone,
-            question: &question,
-            response: &mut answer,
-            state: &mut state,
-        }
-        .query();
-        if answer.is_authoritative {
-            response.header.is_authoritative = true;
-        }
-        log_query(from, &packet.header, &question, &answer.answers);
-        response.additional_records.extend(answer.answers);
     }
-    if response.header.is_authoritative
-        && response.answers.is_empty()
-        && state == AnswerState::DomainSeen
-    {
-        let mut answer = ZoneAnswer::default();
-        for question in &packet.questions {
-            let new_question = Question {
-                name: question.name.clone(),
-                type_: Type::SOA,
-                class: Default::default(),
-            };
-            QueryContext {
-                zone,
-                question: &new_question,
-                response: &mut answer,
-                state: &mut state,
-            }
-            .query();
-            if !answer.answers.is_empty() {
-                break;
-            }
-        }
-        response.nameservers.extend(answer.answers);
+    let answered = answer_query_into(zone, packet, response);
+    if answered.nxdomain_redirected {
+        metrics::NXDOMAIN_REDIRECTS
+            .with_label_values(&[&from_str])
+            .inc();
+        debug!("[{from}] redirected NXDOMAIN");
     }
-    if state == AnswerState::None {
-        response.header.response_code = ResponseCode::NameError;
+    for question in &packet.questions {
+        log_query(from, &packet.header, question, &answered.packet.answers);
     }
+    Some(answered.packet)
+}
 
-    Some(response)
+/// UDP responses over this many times the query size are amplification candidates, see `Zone::cookie_challenge`
+const COOKIE_CHALLENGE_FACTOR: usize = 3;
+
+/// replaces a large UDP answer to a client without a valid server cookie by an empty truncated response,
+/// which still carries our cookie if the client sent one. the client retries with the cookie or over TCP (RFC 7873 section 5.2.3)
+fn cookie_challenge(from: &str, query_len: usize, mut response: Packet) -> Packet {
+    let response_len = response.serialize(u16::MAX as usize).len();
+    if response_len <= query_len * COOKIE_CHALLENGE_FACTOR {
+        return response;
+    }
+    debug!(
+        "[{from}] cookie challenge for a {response_len} byte response to a {query_len} byte query"
+    );
+    response.answers.clear();
+    response.nameservers.clear();
+    response.additional_records.clear();
+    response.header.is_truncated = true;
+    response
+}
+
+/// RFC 1996 NOTIFY from a primary: ask the zone provider to re-check the zone. like transfers and updates, only the
+/// root zone and its direct subzones are matched, not those of `views`
+fn respond_notify(
+    from: &str,
+    zone: &Zone,
+    updater: &mpsc::Sender<ZoneProviderUpdate>,
+    packet: &Packet,
+    mut response: Packet,
+) -> Packet {
+    response.questions = packet.questions.clone();
+    let [question] = &packet.questions[..] else {
+        response.header.response_code = ResponseCode::FormatError;
+        return response;
+    };
+    let notified = if question.name.is_empty() {
+        Some(zone)
+    } else {
+        zone.zones.get(&question.name)
+    };
+    let Some(notified) = notified else {
+        response.header.response_code = ResponseCode::NotAuth;
+        return response;
+    };
+    let allowed = from
+        .parse::<IpAddr>()
+        .is_ok_and(|ip| notified.notify_from.contains(&ip));
+    if !allowed {
+        warn!("[{from}] refused NOTIFY for {}", question.name);
+        response.header.response_code = ResponseCode::Refused;
+        return response;
+    }
+    info!("[{from}] NOTIFY for {}", question.name);
+    // the provider acknowledges NOTIFYs only once it has re-checked, so don't wait on it
+    let (sender, _) = oneshot::channel();
+    let sent = updater.try_send(ZoneProviderUpdate {
+        update: ZoneUpdate {
+            zone_name: question.name.clone(),
+            actions: vec![],
+        },
+        notify: true,
+        response: sender,
+    });
+    if let Err(e) = sent {
+        // the primary retransmits NOTIFYs that aren't answered with NOERROR (RFC 1996 section 4.6)
+        let reason = match e {
+            TrySendError::Full(_) => "busy",
+            TrySendError::Closed(_) => "stopped",
+        };
+        warn!(
+            "[{from}] zone provider {reason}, failed NOTIFY for {}",
+            question.name
+        );
+        response.header.response_code = ResponseCode::ServerFailure;
+        return response;
+    }
+    response.header.is_authoritative = true;
+    response
 }
 
 fn axfr(packet: &Packet) -> Option<&Name> {
@@ -232,6 +252,96 @@ fn axfr(packet: &Packet) -> Option<&Name> {
     Some(&question.name)
 }
 
+/// answers `question` from the local zone, or from `forwarder` if the zone has no answer. `None` if the lookup failed
+async fn ptr_check_lookup(
+    zone: &Zone,
+    forwarder: Option<SocketAddr>,
+    question: Question,
+    from: &str,
+) -> Option<Vec<Record>> {
+    let mut answer = ZoneAnswer::default();
+    let mut state = AnswerState::None;
+    zone.query(&question, &mut answer, &mut state);
+    if !answer.answers.is_empty() {
+        return Some(answer.answers);
+    }
+    let Some(forwarder) = forwarder else {
+        return Some(vec![]);
+    };
+    let response = tokio::time::timeout(Duration::from_secs(5), async {
+        DnsClient::new()
+            .await?
+            .query(forwarder, vec![question.clone()])
+            .await
+    })
+    .await;
+    match response {
+        Ok(Ok(packet)) => Some(packet.answers),
+        Ok(Err(e)) => {
+            warn!(
+                "AXFR {} lookup of {} for {from} failed: {e}",
+                question.type_, question.name
+            );
+            None
+        }
+        Err(_) => {
+            warn!(
+                "AXFR {} lookup of {} for {from} timed out",
+                question.type_, question.name
+            );
+            None
+        }
+    }
+}
+
+/// checks the optional PTR requirement for AXFR clients: a PTR name matching the pattern must resolve back to the
+/// client's address (forward-confirmed reverse DNS), so a client can't pass by controlling only its reverse zone.
+/// fails closed if a lookup fails
+async fn axfr_ptr_allowed(zone: &Zone, from: &str) -> bool {
+    let Some(check) = &zone.axfr_ptr_check else {
+        return true;
+    };
+    let Ok(ip) = from.parse::<IpAddr>() else {
+        return false;
+    };
+    let question = Question {
+        name: Name::reverse_pointer(ip),
+        type_: Type::PTR,
+        class: Default::default(),
+    };
+    let Some(records) = ptr_check_lookup(zone, check.forwarder, question, from).await else {
+        return false;
+    };
+    let type_ = match ip {
+        IpAddr::V4(_) => Type::A,
+        IpAddr::V6(_) => Type::AAAA,
+    };
+    let names = records.into_iter().filter_map(|record| match record.data {
+        TypeData::PTR(name) if check.pattern.contains(&name) => Some(name),
+        _ => None,
+    });
+    for name in names {
+        let question = Question {
+            name,
+            type_,
+            class: Class::IN,
+        };
+        let Some(records) = ptr_check_lookup(zone, check.forwarder, question, from).await else {
+            return false;
+        };
+        let confirmed = records.iter().any(|record| match &record.data {
+            TypeData::A(address) => IpAddr::from(*address) == ip,
+            TypeData::AAAA(address) => IpAddr::from(*address) == ip,
+            _ => false,
+        });
+        if confirmed {
+            return true;
+        }
+    }
+    warn!("AXFR PTR check failed for {from}");
+    false
+}
+
 fn respond_axfr(
     root_zone: &Zone,
     axfr_name: &Name,
@@ -253,13 +363,7 @@ fn respond_axfr(
     };
     let mut answer = ZoneAnswer::default();
     let mut state = AnswerState::None;
-    QueryContext {
-        zone: root_zone,
-        question: &soa_question,
-        response: &mut answer,
-        state: &mut state,
-    }
-    .query();
+    root_zone.query(&soa_question, &mut answer, &mut state);
     let Some(soa) = answer.answers.pop() else {
         warn!("no SOA, cannot do AXFR for {}", axfr_name);
         response.header.response_code = ResponseCode::ServerFailure;
@@ -294,9 +398,81 @@ fn respond_axfr(
     out
 }
 
+/// the TSIG key `name`: a rotated one from `tsig_keys` if set, otherwise the zone's
+fn tsig_key(zone: &Zone, tsig_keys: &TsigKeys, name: &str) -> Option<Vec<u8>> {
+    tsig_keys
+        .get(name)
+        .or_else(|| zone.tsig_keys.get(name))
+        .map(|x| x.0.clone())
+}
+
+/// whether `from` is in one of the `allow_query` networks, an empty list allows every source
+fn query_allowed(allow_query: &[IpNet], from: &str) -> bool {
+    allow_query.is_empty()
+        || from
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| allow_query.iter().any(|net| net.contains(&ip)))
+}
+
+/// the mnemonic of `code` (RFC 1035 section 4.1.1, RFC 2136 section 2.2), for the `rcode` metric label
+fn rcode_label(code: ResponseCode) -> Cow<'static, str> {
+    Cow::Borrowed(match code {
+        ResponseCode::NoError => "NOERROR",
+        ResponseCode::FormatError => "FORMERR",
+        ResponseCode::ServerFailure => "SERVFAIL",
+        ResponseCode::NameError => "NXDOMAIN",
+        ResponseCode::NotImplemented => "NOTIMP",
+        ResponseCode::Refused => "REFUSED",
+        ResponseCode::YxDomain => "YXDOMAIN",
+        ResponseCode::YxRRSet => "YXRRSET",
+        ResponseCode::NxRRSet => "NXRRSET",
+        ResponseCode::NotAuth => "NOTAUTH",
+        ResponseCode::NotZone => "NOTZONE",
+        ResponseCode::Other(x) => return Cow::Owned(x.to_string()),
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn respond(
     is_tcp: bool,
+    max_udp_payload_size: u16,
     zone: &Zone,
+    tsig_keys: &TsigKeys,
+    allow_query: &[IpNet],
+    forward_cache: &ForwardCache,
+    updater: &mpsc::Sender<ZoneProviderUpdate>,
+    from: &str,
+    packet: &[u8],
+) -> Option<PacketResponse> {
+    let response = respond_packet(
+        is_tcp,
+        max_udp_payload_size,
+        zone,
+        tsig_keys,
+        allow_query,
+        forward_cache,
+        updater,
+        from,
+        packet,
+    )
+    .await?;
+    // a multi-message AXFR is one response
+    if let Some(packet) = response.packet.first() {
+        metrics::RESPONSES
+            .with_label_values(&[&rcode_label(packet.header.response_code)])
+            .inc();
+    }
+    Some(response)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn respond_packet(
+    is_tcp: bool,
+    max_udp_payload_size: u16,
+    zone: &Zone,
+    tsig_keys: &TsigKeys,
+    allow_query: &[IpNet],
+    forward_cache: &ForwardCache,
     updater: &mpsc::Sender<ZoneProviderUpdate>,
     from: &str,
     packet: &[u8],
@@ -307,11 +483,13 @@ pub async fn respond(
         metrics::QUERY_US.with_label_values(&[]).observe(elapsed);
     }
     metrics::QUERY.with_label_values(&[from]).inc();
+    let query_len = packet.len();
     let (packet, tsig_validatable) = match Packet::parse(packet) {
         Ok(x) => x,
         Err(e) => {
-            //TODO: debug
-            info!("failed to parse packet: {e}\n{}", hex::encode(packet));
+            info!("[{from}] failed to parse {} byte packet: {e}", packet.len());
+            // the raw packet may carry sensitive query data, only dump it when asked for
+            debug!("[{from}] unparseable packet: {}", hex::encode(packet));
             return None;
         }
     };
@@ -329,8 +507,27 @@ pub async fn respond(
             response_code: ResponseCode::NoError,
             ..Default::default()
         },
+        // only EDNS-aware clients get an OPT record back (RFC 6891 section 7)
+        edns: packet
+            .edns
+            .as_ref()
+            .map(|_| Edns::new(max_udp_payload_size)),
         ..Default::default()
     };
+    let cookie = packet
+        .edns
+        .as_ref()
+        .and_then(|edns| cookie::check(edns, from));
+    if let (Some(edns), Some((client_cookie, _))) = (&mut response.edns, cookie) {
+        edns.options
+            .items
+            .push(cookie::response_option(client_cookie, from));
+    }
+    let mut udp_max_size = packet.udp_max_size;
+    // cookieless clients can't be told apart from spoofed sources, so they only get small responses over UDP
+    if !is_tcp && zone.require_cookie_for_large_udp && !matches!(cookie, Some((_, true))) {
+        udp_max_size = udp_max_size.min(COOKIELESS_UDP_LIMIT);
+    }
 
     if packet.header.query_response != QueryResponse::Query
         || packet.header.response_code != ResponseCode::NoError
@@ -354,7 +551,7 @@ pub async fn respond(
         raw_packet[..Header::LENGTH].copy_from_slice(&new_header.to_bytes());
 
         match tsig::validate(
-            |name| zone.tsig_keys.get(name).map(|x| &x.0).cloned(),
+            |name| tsig_key(zone, tsig_keys, name),
             &raw_packet,
             &name,
             &tsig,
@@ -363,6 +560,7 @@ pub async fn respond(
             None,
         ) {
             Ok(mac) => Some(TsigInfo {
+                key: tsig_key(zone, tsig_keys, name.lowercased().as_ref()).unwrap_or_default(),
                 request_mac: mac,
                 name,
                 algorithm: tsig.algorithm,
@@ -381,10 +579,20 @@ pub async fn respond(
         None
     };
 
+    let mut extended_error = None;
     let response = match packet.header.opcode {
+        Opcode::Query if !query_allowed(allow_query, from) => {
+            debug!("[{from}] refused a query from outside allow_query");
+            response.header.response_code = ResponseCode::Refused;
+            extended_error = Some((
+                extended_error::PROHIBITED,
+                "source not allowed to query".to_string(),
+            ));
+            response
+        }
         Opcode::Query => {
             if let Some(axfr_name) = axfr(&packet) {
-                if tsig_info.is_none() || !is_tcp {
+                if tsig_info.is_none() || !is_tcp || !axfr_ptr_allowed(zone, from).await {
                     warn!("refused an AXFR");
                     metrics::AXFR
                         .with_label_values(&[from, axfr_name.raw(), "false"])
@@ -393,6 +601,11 @@ pub async fn respond(
                     return Some(PacketResponse {
                         packet: smallvec![response],
                         tsig_info,
+                        udp_max_size,
+                        extended_error: Some((
+                            extended_error::PROHIBITED,
+                            "zone transfer not allowed".to_string(),
+                        )),
                     });
                 }
                 metrics::AXFR
@@ -402,9 +615,49 @@ pub async fn respond(
                 return Some(PacketResponse {
                     packet: respond_axfr(zone, axfr_name, response, from),
                     tsig_info,
+                    udp_max_size,
+                    extended_error: None,
                 });
             }
-            respond_query(from, zone, &packet, response)?
+            // split-horizon, the rest of the transaction follows the root zone's settings
+            let view = match from.parse() {
+                Ok(ip) => zone.view_for(ip),
+                Err(_) => zone,
+            };
+            let response = match (&view.forwarding, &packet.questions[..]) {
+                (Some(forwarding), [question])
+                    if packet.header.recursion_desired
+                        && view.forwards(&question.name)
+                        && matches!(view.policy(question), None | Some(PolicyAction::Passthru)) =>
+                {
+                    // without allow_query anyone could use us as an open resolver, i.e. for amplification
+                    if allow_query.is_empty() {
+                        debug!(
+                            "[{from}] refused forwarding {} without allow_query",
+                            question.name
+                        );
+                        response.questions = vec![question.clone()];
+                        response.header.response_code = ResponseCode::Refused;
+                        extended_error = Some((
+                            extended_error::PROHIBITED,
+                            "forwarding requires allow_query".to_string(),
+                        ));
+                        response
+                    } else {
+                        let (response, error) =
+                            forward(forward_cache, forwarding, from, question, response).await;
+                        extended_error = error;
+                        response
+                    }
+                }
+                _ => respond_query(from, view, &packet, response)?,
+            };
+            let has_valid_cookie = matches!(cookie, Some((_, true)));
+            if !is_tcp && zone.cookie_challenge && !has_valid_cookie {
+                cookie_challenge(from, query_len, response)
+            } else {
+                response
+            }
         }
         Opcode::Update => {
             if tsig_info.is_none() {
@@ -421,7 +674,10 @@ pub async fn respond(
                         ])
                         .inc();
                 }
-                return Some(response.into());
+                return Some(
+                    PacketResponse::from(response)
+                        .with_extended_error(extended_error::PROHIBITED, "updates require TSIG"),
+                );
             }
 
             match super::respond_update::respond_update(from, zone, &packet, response) {
@@ -431,6 +687,7 @@ pub async fn respond(
                     if updater
                         .send(ZoneProviderUpdate {
                             update,
+                            notify: false,
                             response: sender,
                         })
                         .await
@@ -443,14 +700,23 @@ pub async fn respond(
                     }
                     if has_failed {
                         packet.header.response_code = ResponseCode::ServerFailure;
+                        extended_error = Some((
+                            extended_error::OTHER,
+                            "zone provider did not apply the update".to_string(),
+                        ));
                     }
                     packet
                 }
                 Err(packet) => packet,
             }
         }
+        Opcode::Notify => respond_notify(from, zone, updater, &packet, response),
         _ => {
             response.header.response_code = ResponseCode::NotImplemented;
+            extended_error = Some((
+                extended_error::NOT_SUPPORTED,
+                "opcode not supported".to_string(),
+            ));
             response
         }
     };
@@ -458,5 +724,463 @@ pub async fn respond(
     Some(PacketResponse {
         packet: smallvec![response],
         tsig_info,
+        udp_max_size,
+        extended_error,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use adns_proto::OptItem;
+    use adns_zone::{AxfrPtrCheck, Forwarding, IdentityQueries, TsigKey, View};
+
+    use super::*;
+    use crate::DEFAULT_MAX_UDP_PAYLOAD_SIZE;
+
+    #[test]
+    fn test_notify() {
+        let mut zone = Zone::default();
+        zone.zones.insert(
+            "example.com".parse().unwrap(),
+            Zone {
+                notify_from: vec!["10.0.0.1".parse().unwrap()],
+                ..Default::default()
+            },
+        );
+        let packet = Packet {
+            questions: vec![Question::new(Type::SOA, "example.com").unwrap()],
+            ..Default::default()
+        };
+        let (updater, mut updates) = mpsc::channel(2);
+
+        let response = respond_notify("10.0.0.2", &zone, &updater, &packet, Packet::default());
+        assert_eq!(response.header.response_code, ResponseCode::Refused);
+        assert!(updates.try_recv().is_err());
+
+        let response = respond_notify("10.0.0.1", &zone, &updater, &packet, Packet::default());
+        assert_eq!(response.header.response_code, ResponseCode::NoError);
+        let update = updates.try_recv().unwrap();
+        assert_eq!(update.update.zone_name, "example.com");
+        assert!(update.notify && update.update.actions.is_empty());
+
+        // the root zone is matched too
+        zone.notify_from = vec!["10.0.0.1".parse().unwrap()];
+        let root = Packet {
+            questions: vec![Question {
+                name: Name::default(),
+                type_: Type::SOA,
+                class: Class::IN,
+            }],
+            ..Default::default()
+        };
+        let response = respond_notify("10.0.0.1", &zone, &updater, &root, Packet::default());
+        assert_eq!(response.header.response_code, ResponseCode::NoError);
+        assert!(updates.try_recv().unwrap().update.zone_name.is_empty());
+
+        // with the provider backed up, the primary is told to retry rather than the NOTIFY being dropped
+        for _ in 0..2 {
+            respond_notify("10.0.0.1", &zone, &updater, &packet, Packet::default());
+        }
+        assert_eq!(
+            respond_notify("10.0.0.1", &zone, &updater, &packet, Packet::default())
+                .header
+                .response_code,
+            ResponseCode::ServerFailure
+        );
+        assert_eq!(updates.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_allow_query() {
+        let zone = Zone::default();
+        let (updater, _updates) = mpsc::channel(2);
+        let query = Packet {
+            questions: vec![Question::new(Type::A, "example.com").unwrap()],
+            ..Default::default()
+        }
+        .serialize(512);
+        let allow_query = ["10.0.0.0/8".parse().unwrap()];
+        for (from, code) in [
+            ("10.1.2.3", ResponseCode::NameError),
+            ("192.0.2.1", ResponseCode::Refused),
+        ] {
+            let responses = metrics::RESPONSES.with_label_values(&[&rcode_label(code)]);
+            let before = responses.get();
+            let response = respond(
+                false,
+                DEFAULT_MAX_UDP_PAYLOAD_SIZE,
+                &zone,
+                &zone.tsig_keys,
+                &allow_query,
+                &ForwardCache::default(),
+                &updater,
+                from,
+                &query,
+            )
+            .await
+            .unwrap();
+            assert_eq!(response.packet[0].header.response_code, code);
+            assert!(responses.get() > before);
+            if code == ResponseCode::Refused {
+                assert_eq!(
+                    response.extended_error.as_ref().map(|x| x.0),
+                    Some(extended_error::PROHIBITED)
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forwarding_requires_allow_query() {
+        let zone = Zone {
+            forwarding: Some(Forwarding {
+                // never reached, the query is refused first
+                upstreams: vec!["192.0.2.53:53".parse().unwrap()],
+                cache_size: 10,
+                max_negative_ttl: 60,
+                qname_minimization: false,
+            }),
+            ..Default::default()
+        };
+        let (updater, _updates) = mpsc::channel(2);
+        let query = Packet {
+            header: Header {
+                recursion_desired: true,
+                ..Default::default()
+            },
+            questions: vec![Question::new(Type::A, "www.example.net").unwrap()],
+            ..Default::default()
+        }
+        .serialize(512);
+        let response = respond(
+            false,
+            DEFAULT_MAX_UDP_PAYLOAD_SIZE,
+            &zone,
+            &zone.tsig_keys,
+            &[],
+            &ForwardCache::default(),
+            &updater,
+            "10.0.0.1",
+            &query,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            response.packet[0].header.response_code,
+            ResponseCode::Refused
+        );
+        assert_eq!(
+            response.extended_error.map(|x| x.0),
+            Some(extended_error::PROHIBITED)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rotated_tsig_keys() {
+        let mut zone = Zone::default();
+        zone.tsig_keys
+            .insert("transfer".to_string(), TsigKey(b"zone key".to_vec()));
+        let mut rotated = TsigKeys::default();
+        rotated.insert("transfer".to_string(), TsigKey(b"rotated key".to_vec()));
+        let (updater, _updates) = mpsc::channel(2);
+        let query = |key: &[u8]| {
+            let packet = Packet {
+                questions: vec![Question::new(Type::SOA, "example.com").unwrap()],
+                ..Default::default()
+            };
+            tsig::serialize_packet(
+                |_| Some(key.to_vec()),
+                packet,
+                u16::MAX as usize,
+                "transfer".parse().unwrap(),
+                "hmac-sha256".parse().unwrap(),
+                true,
+                TsigMode::Normal,
+                None,
+            )
+            .packet
+        };
+        for (key, code) in [
+            (&b"rotated key"[..], ResponseCode::NameError),
+            (&b"zone key"[..], ResponseCode::NotAuth),
+        ] {
+            let response = respond(
+                true,
+                DEFAULT_MAX_UDP_PAYLOAD_SIZE,
+                &zone,
+                &rotated,
+                &[],
+                &ForwardCache::default(),
+                &updater,
+                "10.0.0.1",
+                &query(key),
+            )
+            .await
+            .unwrap();
+            assert_eq!(response.packet[0].header.response_code, code);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_views() {
+        let spf = |text: &str| {
+            let zone = Zone::parse_masterfile(
+                &format!(
+                    "
+$TTL 300
+$ORIGIN example.com.
+@       SOA ns1 hostmaster 1 3600 600 86400 300
+        TXT \"{text}\"
+"
+                ),
+                "example.com".parse().unwrap(),
+            )
+            .unwrap();
+            let mut root = Zone::default();
+            root.zones.insert("example.com".parse().unwrap(), zone);
+            root
+        };
+        let mut zone = spf("v=spf1 -all");
+        zone.views.push(View {
+            clients: vec!["10.0.0.0/8".parse().unwrap()],
+            zone: spf("v=spf1 ip4:10.0.0.0/8 -all"),
+        });
+        let (updater, _updates) = mpsc::channel(2);
+        let query = Packet {
+            questions: vec![Question::new(Type::TXT, "example.com").unwrap()],
+            ..Default::default()
+        }
+        .serialize(512);
+        for (from, text) in [
+            ("10.1.2.3", "v=spf1 ip4:10.0.0.0/8 -all"),
+            ("192.0.2.1", "v=spf1 -all"),
+        ] {
+            let response = respond(
+                false,
+                DEFAULT_MAX_UDP_PAYLOAD_SIZE,
+                &zone,
+                &zone.tsig_keys,
+                &[],
+                &ForwardCache::default(),
+                &updater,
+                from,
+                &query,
+            )
+            .await
+            .unwrap();
+            assert_eq!(response.packet[0].answers[0].data, TypeData::txt([text]));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_identity_per_view() {
+        // external clients are refused, internal ones see the version
+        let mut zone = Zone {
+            identity_queries: IdentityQueries::Refuse,
+            ..Default::default()
+        };
+        zone.views.push(View {
+            clients: vec!["10.0.0.0/8".parse().unwrap()],
+            zone: Zone::default(),
+        });
+        let (updater, _updates) = mpsc::channel(2);
+        let query = Packet {
+            questions: vec![Question {
+                name: "version.bind".parse().unwrap(),
+                type_: Type::TXT,
+                class: Class::CH,
+            }],
+            ..Default::default()
+        }
+        .serialize(512);
+        for (from, code) in [
+            ("10.1.2.3", ResponseCode::NoError),
+            ("192.0.2.1", ResponseCode::Refused),
+        ] {
+            let response = respond(
+                false,
+                DEFAULT_MAX_UDP_PAYLOAD_SIZE,
+                &zone,
+                &zone.tsig_keys,
+                &[],
+                &ForwardCache::default(),
+                &updater,
+                from,
+                &query,
+            )
+            .await
+            .unwrap();
+            assert_eq!(response.packet[0].header.response_code, code);
+            assert_eq!(
+                response.packet[0].answers.len(),
+                (code == ResponseCode::NoError) as usize
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_opt_without_edns() {
+        let zone = Zone::default();
+        let (updater, _updates) = mpsc::channel(2);
+        let allow_query = ["10.0.0.0/8".parse().unwrap()];
+        for edns in [None, Some(Edns::new(1232))] {
+            let with_edns = edns.is_some();
+            let query = Packet {
+                questions: vec![Question::new(Type::A, "example.com").unwrap()],
+                edns,
+                ..Default::default()
+            }
+            .serialize(512);
+            let response = respond(
+                false,
+                4096,
+                &zone,
+                &zone.tsig_keys,
+                &allow_query,
+                &ForwardCache::default(),
+                &updater,
+                "192.0.2.1",
+                &query,
+            )
+            .await
+            .unwrap();
+            // the refusal has an extended error, which only EDNS clients get to see (RFC 6891 section 7)
+            assert!(response.extended_error.is_some());
+            let serialized = response.serialize(&zone, 512);
+            let (parsed, _) = Packet::parse(&serialized[0]).unwrap();
+            assert_eq!(parsed.header.response_code, ResponseCode::Refused);
+            assert_eq!(parsed.edns.is_some(), with_edns);
+            assert_eq!(parsed.header.additional_record_count, with_edns as u16);
+            if with_edns {
+                // the configured maximum is advertised, not the client's buffer size
+                assert_eq!(parsed.edns.as_ref().unwrap().udp_payload_size, 4096);
+                assert_eq!(
+                    parsed.edns.unwrap().extended_error().map(|x| x.0),
+                    Some(extended_error::PROHIBITED)
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cookie_challenge() {
+        let mut zone = Zone {
+            cookie_challenge: true,
+            ..Default::default()
+        };
+        for i in 0..10 {
+            zone.records.push(Record::new(
+                "example.com".parse().unwrap(),
+                300,
+                TypeData::TXT(
+                    vec![format!("record {i} with some padding to make it large")].into(),
+                ),
+            ));
+        }
+        let (updater, _updates) = mpsc::channel(2);
+        let query = |cookie: Vec<u8>| {
+            let mut edns = Edns::new(1232);
+            edns.options.items.push(OptItem {
+                code: cookie::COOKIE_OPTION,
+                data: cookie,
+            });
+            Packet {
+                questions: vec![Question::new(Type::TXT, "example.com").unwrap()],
+                edns: Some(edns),
+                ..Default::default()
+            }
+            .serialize(u16::MAX as usize)
+        };
+        let client = vec![1u8, 2, 3, 4, 5, 6, 7, 8];
+
+        let response = respond(
+            false,
+            DEFAULT_MAX_UDP_PAYLOAD_SIZE,
+            &zone,
+            &zone.tsig_keys,
+            &[],
+            &ForwardCache::default(),
+            &updater,
+            "10.0.0.1",
+            &query(client.clone()),
+        )
+        .await
+        .unwrap();
+        let challenge = &response.packet[0];
+        assert!(challenge.header.is_truncated);
+        assert!(challenge.answers.is_empty());
+        let issued = challenge.edns.as_ref().unwrap().options.items[0]
+            .data
+            .clone();
+        assert!(issued.starts_with(&client));
+
+        let response = respond(
+            false,
+            DEFAULT_MAX_UDP_PAYLOAD_SIZE,
+            &zone,
+            &zone.tsig_keys,
+            &[],
+            &ForwardCache::default(),
+            &updater,
+            "10.0.0.1",
+            &query(issued.clone()),
+        )
+        .await
+        .unwrap();
+        assert!(!response.packet[0].header.is_truncated);
+        assert_eq!(response.packet[0].answers.len(), 10);
+
+        let response = respond(
+            true,
+            DEFAULT_MAX_UDP_PAYLOAD_SIZE,
+            &zone,
+            &zone.tsig_keys,
+            &[],
+            &ForwardCache::default(),
+            &updater,
+            "10.0.0.1",
+            &query(client),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.packet[0].answers.len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_axfr_ptr_allowed() {
+        let mut zone = Zone::default();
+        assert!(axfr_ptr_allowed(&zone, "192.0.2.1").await);
+
+        zone.axfr_ptr_check = Some(AxfrPtrCheck {
+            pattern: "**.example.com".parse().unwrap(),
+            forwarder: None,
+        });
+        let records = [
+            (
+                "1.2.0.192.in-addr.arpa",
+                TypeData::PTR("transfer.example.com".parse().unwrap()),
+            ),
+            ("transfer.example.com", TypeData::A([192, 0, 2, 1].into())),
+            // claims a matching name that doesn't resolve back to it
+            (
+                "2.2.0.192.in-addr.arpa",
+                TypeData::PTR("transfer.example.com".parse().unwrap()),
+            ),
+            (
+                "3.2.0.192.in-addr.arpa",
+                TypeData::PTR("other.example.net".parse().unwrap()),
+            ),
+            ("other.example.net", TypeData::A([192, 0, 2, 3].into())),
+        ];
+        for (name, data) in records {
+            zone.records
+                .push(Record::new(name.parse().unwrap(), 300, data));
+        }
+        assert!(axfr_ptr_allowed(&zone, "192.0.2.1").await);
+        assert!(!axfr_ptr_allowed(&zone, "192.0.2.2").await);
+        assert!(!axfr_ptr_allowed(&zone, "192.0.2.3").await);
+        // no PTR record
+        assert!(!axfr_ptr_allowed(&zone, "192.0.2.4").await);
+        assert!(!axfr_ptr_allowed(&zone, "not an address").await);
+    }
+}
@@ -1,8 +1,8 @@
 use std::fmt::Write;
 
-use adns_proto::{Class, Packet, ResponseCode, Type, TypeData};
-use adns_zone::{Zone, ZoneUpdate, ZoneUpdateAction};
-use log::info;
+use adns_proto::{Class, Name, Packet, ResponseCode, SoaData, Type, TypeData};
+use adns_zone::{EqualSerialPolicy, Zone, ZoneUpdate, ZoneUpdateAction};
+use log::{info, warn};
 use thiserror::Error;
 
 use crate::metrics;
@@ -25,6 +25,17 @@ pub enum UpdateError {
     NameFound,
     #[error("prerequisite rrset found")]
     RRSetFound,
+    #[error("SOA data changed without a new serial")]
+    UnchangedSerial,
+}
+
+/// the SOA an update adding one at `name` would replace
+fn current_soa<'a>(zone: &'a Zone, zone_name: &Name, name: &Name) -> Option<&'a SoaData> {
+    let record = zone.records.iter().find_map(|record| match &record.data {
+        TypeData::SOA(soa) if record.name == *name => Some(soa),
+        _ => None,
+    });
+    record.or(zone.soa.as_ref().filter(|_| name == zone_name))
 }
 
 fn do_respond_update(from: &str, zone: &Zone, packet: &Packet) -> Result<ZoneUpdate, UpdateError> {
@@ -148,6 +159,19 @@ fn do_respond_update(from: &str, zone: &Zone, packet: &Packet) -> Result<ZoneUpd
                 if update.type_.is_question_type() {
                     return Err(UpdateError::FormatError);
                 }
+                // applying would silently keep the current SOA, tell the client instead
+                if let TypeData::SOA(new_soa) = &update.data {
+                    if zone.equal_serial == EqualSerialPolicy::Reject
+                        && current_soa(zone, &question.name, &update.name)
+                            .is_some_and(|soa| soa.serial == new_soa.serial && soa != new_soa)
+                    {
+                        warn!(
+                            "refused SOA update for {}: the data differs but serial {} is unchanged",
+                            update.name, new_soa.serial
+                        );
+                        return Err(UpdateError::UnchangedSerial);
+                    }
+                }
             }
             Class::ALL => {
                 if update.ttl != 0
@@ -211,6 +235,7 @@ fn do_respond_update(from: &str, zone: &Zone, packet: &Packet) -> Result<ZoneUpd
     Ok(zone_update)
 }
 
+#[allow(clippy::result_large_err)]
 pub fn respond_update(
     from: &str,
     zone: &Zone,
@@ -242,5 +267,62 @@ pub fn respond_update(
             response.header.response_code = ResponseCode::YxRRSet;
             Err(response)
         }
+        Err(UpdateError::UnchangedSerial) => {
+            response.header.response_code = ResponseCode::Refused;
+            Err(response)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use adns_proto::{Question, Record};
+
+    use super::*;
+
+    #[test]
+    fn test_unchanged_serial() {
+        let soa = |serial, refresh| SoaData {
+            mname: "ns1.example.com".parse().unwrap(),
+            rname: "admin.example.com".parse().unwrap(),
+            serial,
+            refresh,
+            retry: 10,
+            expire: 3600,
+            minimum: 600,
+        };
+        let mut zone = Zone::default();
+        zone.zones.insert(
+            "example.com".parse().unwrap(),
+            Zone {
+                soa: Some(soa(5, 3600)),
+                equal_serial: EqualSerialPolicy::Reject,
+                ..Default::default()
+            },
+        );
+        let update = |soa| Packet {
+            questions: vec![Question::new(Type::SOA, "example.com").unwrap()],
+            nameservers: vec![Record::new(
+                "example.com".parse().unwrap(),
+                300,
+                TypeData::SOA(soa),
+            )],
+            ..Default::default()
+        };
+        let code = |zone: &Zone, soa| match respond_update(
+            "test",
+            zone,
+            &update(soa),
+            Packet::default(),
+        ) {
+            Ok((_, response)) | Err(response) => response.header.response_code,
+        };
+
+        assert_eq!(code(&zone, soa(5, 7200)), ResponseCode::Refused);
+        // the same data, or a new serial, is fine
+        assert_eq!(code(&zone, soa(5, 3600)), ResponseCode::NoError);
+        assert_eq!(code(&zone, soa(6, 7200)), ResponseCode::NoError);
+        zone.zones[0].equal_serial = EqualSerialPolicy::Warn;
+        assert_eq!(code(&zone, soa(5, 7200)), ResponseCode::NoError);
     }
 }
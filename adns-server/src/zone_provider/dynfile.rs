@@ -1,12 +1,29 @@
 use std::path::PathBuf;
 
-use adns_zone::Zone;
+use adns_zone::{InvalidRecordPolicy, Zone};
 use log::{error, warn};
 use tokio::{select, sync::mpsc};
 
 use crate::{FileZoneProvider, ZoneProvider, ZoneProviderUpdate};
 
-pub struct DynFileZoneProvider(pub PathBuf);
+pub struct DynFileZoneProvider {
+    pub path: PathBuf,
+    pub invalid_records: InvalidRecordPolicy,
+}
+
+impl DynFileZoneProvider {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            invalid_records: Default::default(),
+        }
+    }
+
+    pub fn with_invalid_records(mut self, invalid_records: InvalidRecordPolicy) -> Self {
+        self.invalid_records = invalid_records;
+        self
+    }
+}
 
 #[async_trait::async_trait]
 impl ZoneProvider for DynFileZoneProvider {
@@ -15,18 +32,19 @@ impl ZoneProvider for DynFileZoneProvider {
         sender: mpsc::Sender<Zone>,
         mut updates: mpsc::Receiver<ZoneProviderUpdate>,
     ) {
-        if !self.0.exists() {
-            if let Some(parent) = self.0.parent() {
+        if !self.path.exists() {
+            if let Some(parent) = self.path.parent() {
                 tokio::fs::create_dir_all(parent)
                     .await
                     .expect("failed to create initial dyn zone parent dir");
             }
-            tokio::fs::write(&self.0, "{}")
+            tokio::fs::write(&self.path, "{}")
                 .await
                 .expect("failed to create initial dyn zone file");
         }
         let (file_sender, mut file_receiver) = mpsc::channel(10);
-        let mut file_provider = FileZoneProvider(self.0.clone());
+        let mut file_provider =
+            FileZoneProvider::new(self.path.clone()).with_invalid_records(self.invalid_records);
         let mut file_provider = tokio::spawn(async move {
             file_provider.run(file_sender, mpsc::channel(1).1).await;
         });
@@ -38,12 +56,17 @@ impl ZoneProvider for DynFileZoneProvider {
                         warn!("update receiver for dynfile died");
                         break;
                     };
+                    // the file is watched already, there is nothing else to refresh from
+                    if update.notify {
+                        update.response.send(()).ok();
+                        continue;
+                    }
                     let Some(current_zone) = &mut current_zone else {
                         warn!("discarding update received before zone loaded");
                         continue;
                     };
                     update.update.apply_to(current_zone);
-                    if let Err(e) = tokio::fs::write(&self.0, serde_yaml::to_string(&*current_zone).unwrap()).await {
+                    if let Err(e) = tokio::fs::write(&self.path, serde_yaml::to_string(&*current_zone).unwrap()).await {
                         error!("failed to write zone file for update: {e}");
                         continue;
                     }
@@ -87,7 +110,9 @@ mod tests {
         Server::new(
             "0.0.0.0:5053".parse().unwrap(),
             "0.0.0.0:5053".parse().unwrap(),
-            DynFileZoneProvider(Path::new("./src/zone_provider/test_zone_dyn.yaml").to_path_buf()),
+            DynFileZoneProvider::new(
+                Path::new("./src/zone_provider/test_zone_dyn.yaml").to_path_buf(),
+            ),
         )
         .run()
         .await;
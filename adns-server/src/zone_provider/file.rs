@@ -1,14 +1,46 @@
 use std::{path::PathBuf, time::Duration};
 
-use adns_zone::Zone;
-use log::{error, info};
+use adns_proto::Name;
+use adns_zone::{InvalidRecordPolicy, MasterFileError, Zone};
+use log::{error, info, warn};
 use really_notify::FileWatcherConfig;
 use thiserror::Error;
 use tokio::{select, sync::mpsc};
 
-use crate::{ZoneProvider, ZoneProviderUpdate};
+use crate::{
+    zone_provider::{load_yaml, log_skipped, warn_invalid},
+    ZoneProvider, ZoneProviderUpdate,
+};
 
-pub struct FileZoneProvider(pub PathBuf);
+pub struct FileZoneProvider {
+    pub path: PathBuf,
+    /// if set, `path` is an RFC 1035 master file for this zone rather than YAML
+    pub origin: Option<Name>,
+    pub invalid_records: InvalidRecordPolicy,
+}
+
+impl FileZoneProvider {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            origin: None,
+            invalid_records: Default::default(),
+        }
+    }
+
+    pub fn masterfile(path: PathBuf, origin: Name) -> Self {
+        Self {
+            path,
+            origin: Some(origin),
+            invalid_records: Default::default(),
+        }
+    }
+
+    pub fn with_invalid_records(mut self, invalid_records: InvalidRecordPolicy) -> Self {
+        self.invalid_records = invalid_records;
+        self
+    }
+}
 
 #[async_trait::async_trait]
 impl ZoneProvider for FileZoneProvider {
@@ -18,13 +50,16 @@ impl ZoneProvider for FileZoneProvider {
         updates: mpsc::Receiver<ZoneProviderUpdate>,
     ) {
         drop(updates);
+        if let Some(origin) = self.origin.clone() {
+            return self.run_masterfile(origin, sender).await;
+        }
         let zone = loop {
             match self.read_config().await {
                 Ok(x) => break x,
                 Err(e) => {
                     error!(
                         "failed to read initial zone file: {e} @ {}, retrying in one second",
-                        self.0.display()
+                        self.path.display()
                     );
                     tokio::time::sleep(Duration::from_secs(1)).await;
                 }
@@ -33,8 +68,14 @@ impl ZoneProvider for FileZoneProvider {
         if sender.send(zone).await.is_err() {
             return;
         }
-        let mut receiver = FileWatcherConfig::new(&self.0, "zone")
-            .with_parser(move |x| serde_yaml::from_slice(&x))
+        let source = self.path.display().to_string();
+        let invalid_records = self.invalid_records;
+        let mut receiver = FileWatcherConfig::new(&self.path, "zone")
+            .with_parser(move |x| {
+                let (zone, skipped) = load_yaml(&x, invalid_records)?;
+                log_skipped(&source, skipped);
+                Ok::<_, serde_yaml::Error>(zone)
+            })
             .start();
         loop {
             select! {
@@ -42,6 +83,7 @@ impl ZoneProvider for FileZoneProvider {
                     let Some(update) = update else {
                         return;
                     };
+                    warn_invalid(&self.path.display().to_string(), &update);
                     if sender.send(update).await.is_err() {
                         return;
                     }
@@ -60,14 +102,111 @@ pub enum FileZoneError {
     Io(#[from] std::io::Error),
     #[error("{0}")]
     Yaml(#[from] serde_yaml::Error),
+    #[error("{0}")]
+    MasterFile(#[from] MasterFileError),
+}
+
+/// notifies whenever any of `files` changes, until the receiver is dropped
+fn watch_files(files: &[PathBuf]) -> mpsc::Receiver<()> {
+    let (sender, receiver) = mpsc::channel(1);
+    for file in files {
+        let mut watcher = FileWatcherConfig::new(file, "zone").start();
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            // the first message is the initial contents, which are already loaded
+            watcher.recv().await;
+            loop {
+                select! {
+                    update = watcher.recv() => {
+                        if update.is_none() || sender.send(()).await.is_err() {
+                            return;
+                        }
+                    },
+                    _ = sender.closed() => {
+                        return;
+                    }
+                }
+            }
+        });
+    }
+    receiver
 }
 
 impl FileZoneProvider {
     async fn read_config(&self) -> Result<Zone, FileZoneError> {
-        info!("reading zone from {}", self.0.display());
-        Ok(serde_yaml::from_str(
-            &tokio::fs::read_to_string(&self.0).await?,
-        )?)
+        info!("reading zone from {}", self.path.display());
+        let input = tokio::fs::read(&self.path).await?;
+        let (zone, skipped) = load_yaml(&input, self.invalid_records)?;
+        log_skipped(&self.path.display().to_string(), skipped);
+        warn_invalid(&self.path.display().to_string(), &zone);
+        Ok(zone)
+    }
+
+    async fn read_masterfile(&self, origin: &Name) -> Result<(Zone, Vec<PathBuf>), FileZoneError> {
+        info!("reading zone {origin} from {}", self.path.display());
+        let path = self.path.clone();
+        let apex = origin.clone();
+        let invalid_records = self.invalid_records;
+        let (zone, files, skipped) = tokio::task::spawn_blocking(move || {
+            Zone::load_masterfile(&path, apex, invalid_records)
+        })
+        .await
+        .expect("master file loader panicked")?;
+        log_skipped(&self.path.display().to_string(), skipped);
+        for error in zone.validate_apex(origin) {
+            warn!("{}: {error}", self.path.display());
+        }
+        if origin.is_empty() {
+            return Ok((zone, files));
+        }
+        let mut root = Zone::default();
+        root.zones.insert(origin.clone(), zone);
+        Ok((root, files))
+    }
+
+    /// like the YAML mode, but also reloads when any `$INCLUDE`d file changes
+    async fn run_masterfile(&self, origin: Name, sender: mpsc::Sender<Zone>) {
+        let (zone, mut files) = loop {
+            match self.read_masterfile(&origin).await {
+                Ok(x) => break x,
+                Err(e) => {
+                    error!(
+                        "failed to read initial zone file: {e} @ {}, retrying in one second",
+                        self.path.display()
+                    );
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        };
+        if sender.send(zone).await.is_err() {
+            return;
+        }
+        loop {
+            let mut changes = watch_files(&files);
+            select! {
+                change = changes.recv() => {
+                    if change.is_none() {
+                        return;
+                    }
+                },
+                _ = sender.closed() => {
+                    return;
+                }
+            }
+            drop(changes);
+            match self.read_masterfile(&origin).await {
+                Ok((zone, new_files)) => {
+                    files = new_files;
+                    if sender.send(zone).await.is_err() {
+                        return;
+                    }
+                }
+                Err(e) => error!(
+                    "failed to reload zone file: {e} @ {}, waiting for further changes",
+                    self.path.display()
+                ),
+            }
+        }
     }
 }
 
@@ -75,8 +214,42 @@ impl FileZoneProvider {
 mod tests {
     use std::path::Path;
 
+    use adns_zone::InvalidRecordPolicy;
+
     use crate::{FileZoneProvider, Server};
 
+    #[tokio::test]
+    async fn test_invalid_records() {
+        let directory = std::env::temp_dir().join(format!("adns-invalid-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let yaml = directory.join("zone.yaml");
+        std::fs::write(
+            &yaml,
+            "records:\n- domain: good.example.com\n  type: A\n  data: 192.0.2.1\n- domain: bad.example.com\n  type: A\n  data: not-an-address\n",
+        )
+        .unwrap();
+        let masterfile = directory.join("zone.db");
+        std::fs::write(
+            &masterfile,
+            "$TTL 300\ngood A 192.0.2.1\nbad A not-an-address\n",
+        )
+        .unwrap();
+        let origin = "example.com".parse().unwrap();
+
+        let provider = FileZoneProvider::new(yaml.clone());
+        assert!(provider.read_config().await.is_err());
+        let provider = provider.with_invalid_records(InvalidRecordPolicy::Skip);
+        assert_eq!(provider.read_config().await.unwrap().records.len(), 1);
+
+        let provider = FileZoneProvider::masterfile(masterfile, origin);
+        let origin = provider.origin.clone().unwrap();
+        assert!(provider.read_masterfile(&origin).await.is_err());
+        let provider = provider.with_invalid_records(InvalidRecordPolicy::Skip);
+        let (zone, _) = provider.read_masterfile(&origin).await.unwrap();
+        assert_eq!(zone.zones[0].records.len(), 1);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
     #[tokio::test]
     async fn test_file_zone() {
         env_logger::Builder::new()
@@ -85,7 +258,7 @@ mod tests {
         Server::new(
             "0.0.0.0:5053".parse().unwrap(),
             "0.0.0.0:5053".parse().unwrap(),
-            FileZoneProvider(Path::new("./src/zone_provider/test_zone.yaml").to_path_buf()),
+            FileZoneProvider::new(Path::new("./src/zone_provider/test_zone.yaml").to_path_buf()),
         )
         .run()
         .await;
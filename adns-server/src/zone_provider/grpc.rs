@@ -0,0 +1,321 @@
+use std::{str::FromStr, time::Duration};
+
+use adns_proto::{Name, NameParseError, Record, Type, TypeData, TypeDataParseError};
+use adns_zone::{InvalidRecordPolicy, Zone, ZoneUpdate, ZoneUpdateAction};
+use log::{error, info, warn};
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tonic::codegen::http::uri::PathAndQuery;
+
+use crate::{
+    zone_provider::{load_yaml, log_skipped},
+    ZoneProvider, ZoneProviderUpdate,
+};
+
+/// messages of `proto/zone_provider.proto`, written out by hand so building doesn't need `protoc`
+pub mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct WatchRequest {
+        #[prost(string, tag = "1")]
+        pub node_id: String,
+        #[prost(uint64, tag = "2")]
+        pub version: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ZoneMessage {
+        #[prost(uint64, tag = "1")]
+        pub version: u64,
+        #[prost(oneof = "Body", tags = "2, 3")]
+        pub body: Option<Body>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Body {
+        #[prost(message, tag = "2")]
+        Snapshot(ZoneSnapshot),
+        #[prost(message, tag = "3")]
+        Delta(ZoneDelta),
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ZoneSnapshot {
+        #[prost(string, tag = "1")]
+        pub zone_yaml: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ZoneDelta {
+        #[prost(string, tag = "1")]
+        pub zone_name: String,
+        #[prost(message, repeated, tag = "2")]
+        pub remove: Vec<ZoneRecord>,
+        #[prost(message, repeated, tag = "3")]
+        pub add: Vec<ZoneRecord>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ZoneRecord {
+        #[prost(string, tag = "1")]
+        pub domain: String,
+        #[prost(string, tag = "2")]
+        pub r#type: String,
+        #[prost(uint32, tag = "3")]
+        pub ttl: u32,
+        #[prost(string, tag = "4")]
+        pub data: String,
+    }
+}
+
+const WATCH_PATH: &str = "/adns.zone_provider.v1.ZoneProvider/Watch";
+
+#[derive(Error, Debug)]
+pub enum GrpcZoneError {
+    #[error("transport error: {0}")]
+    Transport(#[from] tonic::transport::Error),
+    #[error("{0}")]
+    Status(#[from] Box<tonic::Status>),
+    #[error("invalid snapshot: {0}")]
+    Snapshot(#[from] serde_yaml::Error),
+    #[error("invalid name: {0}")]
+    Name(#[from] NameParseError),
+    #[error("unknown record type: {0}")]
+    Type(String),
+    #[error("invalid record data: {0}")]
+    Data(#[from] TypeDataParseError),
+    #[error("message without a snapshot or delta")]
+    EmptyMessage,
+    #[error("delta received before any snapshot")]
+    DeltaBeforeSnapshot,
+    #[error("expected version {expected}, got {received}")]
+    VersionGap { expected: u64, received: u64 },
+}
+
+/// Serves the zone streamed by a control plane over gRPC, see `proto/zone_provider.proto`.
+/// Reconnects with exponential backoff, resuming from the last applied version.
+/// RFC2136 updates are refused, the control plane is the source of truth.
+pub struct GrpcZoneProvider {
+    /// e.g. `http://control-plane:50051`
+    pub endpoint: String,
+    pub node_id: String,
+    /// first reconnection delay, doubled after each failed attempt
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+    /// applies to snapshots. a delta with invalid data fails, which asks for a fresh snapshot
+    pub invalid_records: InvalidRecordPolicy,
+}
+
+/// the zone as of `version`, built from the stream
+#[derive(Default)]
+struct StreamState {
+    zone: Option<Zone>,
+    version: u64,
+    invalid_records: InvalidRecordPolicy,
+}
+
+impl StreamState {
+    /// applies a snapshot or delta, returning the new zone to serve
+    fn apply(&mut self, message: proto::ZoneMessage) -> Result<&Zone, GrpcZoneError> {
+        match message.body.ok_or(GrpcZoneError::EmptyMessage)? {
+            proto::Body::Snapshot(snapshot) => {
+                let (zone, skipped) =
+                    load_yaml(snapshot.zone_yaml.as_bytes(), self.invalid_records)?;
+                log_skipped(&format!("zone version {}", message.version), skipped);
+                self.zone = Some(zone);
+            }
+            proto::Body::Delta(delta) => {
+                let zone = self
+                    .zone
+                    .as_mut()
+                    .ok_or(GrpcZoneError::DeltaBeforeSnapshot)?;
+                if message.version != self.version + 1 {
+                    return Err(GrpcZoneError::VersionGap {
+                        expected: self.version + 1,
+                        received: message.version,
+                    });
+                }
+                let mut actions = vec![];
+                for record in delta.remove {
+                    let record = convert_record(record)?;
+                    actions.push(ZoneUpdateAction::DeleteRecord(record.name, record.data));
+                }
+                for record in delta.add {
+                    actions.push(ZoneUpdateAction::AddRecord(convert_record(record)?));
+                }
+                ZoneUpdate {
+                    zone_name: delta.zone_name.parse()?,
+                    actions,
+                }
+                .apply_to(zone);
+            }
+        }
+        self.version = message.version;
+        Ok(self.zone.as_ref().unwrap())
+    }
+}
+
+fn convert_record(record: proto::ZoneRecord) -> Result<Record, GrpcZoneError> {
+    let type_ = Type::from_str(&record.r#type).map_err(|_| GrpcZoneError::Type(record.r#type))?;
+    Ok(Record::new(
+        Name::from_str(&record.domain)?,
+        record.ttl,
+        TypeData::parse_str(type_, &record.data)?,
+    ))
+}
+
+impl GrpcZoneProvider {
+    /// follows one `Watch` stream until it ends or fails. returns false once `sender` is closed.
+    async fn watch(
+        &self,
+        state: &mut StreamState,
+        sender: &mpsc::Sender<Zone>,
+        backoff: &mut Duration,
+    ) -> Result<bool, GrpcZoneError> {
+        let channel = tonic::transport::Endpoint::from_shared(self.endpoint.clone())?
+            .connect()
+            .await?;
+        let mut client = tonic::client::Grpc::new(channel);
+        client
+            .ready()
+            .await
+            .map_err(|e| Box::new(tonic::Status::unavailable(e.to_string())))?;
+        let request = proto::WatchRequest {
+            node_id: self.node_id.clone(),
+            version: state.version,
+        };
+        let mut stream = client
+            .server_streaming(
+                tonic::Request::new(request),
+                PathAndQuery::from_static(WATCH_PATH),
+                tonic::codec::ProstCodec::<proto::WatchRequest, proto::ZoneMessage>::default(),
+            )
+            .await
+            .map_err(Box::new)?
+            .into_inner();
+        info!("watching zone from {}", self.endpoint);
+        while let Some(message) = stream.message().await.map_err(Box::new)? {
+            let version = message.version;
+            let zone = match state.apply(message) {
+                Ok(zone) => zone.clone(),
+                Err(e) => {
+                    error!("failed to apply zone version {version}: {e}");
+                    // the current zone is kept until the fresh snapshot asked for on reconnecting
+                    state.version = 0;
+                    return Ok(true);
+                }
+            };
+            *backoff = self.min_backoff;
+            if sender.send(zone).await.is_err() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[async_trait::async_trait]
+impl ZoneProvider for GrpcZoneProvider {
+    async fn run(
+        &mut self,
+        sender: mpsc::Sender<Zone>,
+        mut updates: mpsc::Receiver<ZoneProviderUpdate>,
+    ) {
+        tokio::spawn(async move {
+            while let Some(update) = updates.recv().await {
+                // a NOTIFY has nothing to refresh, dropping the response fails RFC2136 updates
+                if update.notify {
+                    update.response.send(()).ok();
+                }
+            }
+        });
+        let mut state = StreamState {
+            invalid_records: self.invalid_records,
+            ..Default::default()
+        };
+        let mut backoff = self.min_backoff;
+        loop {
+            match self.watch(&mut state, &sender, &mut backoff).await {
+                Ok(false) => return,
+                Ok(true) => warn!("zone stream from {} ended, reconnecting", self.endpoint),
+                Err(e) => error!(
+                    "zone stream from {} failed: {e}, reconnecting in {backoff:?}",
+                    self.endpoint
+                ),
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(backoff) => (),
+                _ = sender.closed() => return,
+            }
+            backoff = (backoff * 2).min(self.max_backoff);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(domain: &str, data: &str) -> proto::ZoneRecord {
+        proto::ZoneRecord {
+            domain: domain.to_string(),
+            r#type: "A".to_string(),
+            ttl: 300,
+            data: data.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_stream_state() {
+        let mut state = StreamState::default();
+        let delta = |version, remove: Vec<proto::ZoneRecord>, add| proto::ZoneMessage {
+            version,
+            body: Some(proto::Body::Delta(proto::ZoneDelta {
+                zone_name: "example.com".to_string(),
+                remove,
+                add,
+            })),
+        };
+        assert!(matches!(
+            state.apply(delta(1, vec![], vec![])),
+            Err(GrpcZoneError::DeltaBeforeSnapshot)
+        ));
+
+        let snapshot = proto::ZoneMessage {
+            version: 5,
+            body: Some(proto::Body::Snapshot(proto::ZoneSnapshot {
+                zone_yaml: "
+zones:
+  example.com:
+    records:
+    - domain: www.example.com
+      type: A
+      data: 192.0.2.1
+"
+                .to_string(),
+            })),
+        };
+        let zone = state.apply(snapshot).unwrap();
+        assert_eq!(zone.zones[0].records.len(), 1);
+
+        let zone = state
+            .apply(delta(
+                6,
+                vec![record("www.example.com", "192.0.2.1")],
+                vec![record("www.example.com", "192.0.2.2")],
+            ))
+            .unwrap();
+        assert_eq!(
+            zone.zones[0].records[0].data,
+            TypeData::A("192.0.2.2".parse().unwrap())
+        );
+        assert_eq!(state.version, 6);
+
+        assert!(matches!(
+            state.apply(delta(8, vec![], vec![])),
+            Err(GrpcZoneError::VersionGap {
+                expected: 7,
+                received: 8
+            })
+        ));
+    }
+}
@@ -1,4 +1,4 @@
-use adns_zone::Zone;
+use adns_zone::{SoaPrecedence, Zone};
 use serde::{Deserialize, Serialize};
 use tokio::{select, sync::mpsc};
 
@@ -16,6 +16,7 @@ pub struct MergeZoneProvider<TOP: ZoneProvider, BOTTOM: ZoneProvider> {
     top: Option<TOP>,
     bottom: Option<BOTTOM>,
     send_updates: SendUpdates,
+    soa_precedence: SoaPrecedence,
 }
 
 impl<TOP: ZoneProvider, BOTTOM: ZoneProvider> MergeZoneProvider<TOP, BOTTOM> {
@@ -24,8 +25,15 @@ impl<TOP: ZoneProvider, BOTTOM: ZoneProvider> MergeZoneProvider<TOP, BOTTOM> {
             top: Some(top),
             bottom: Some(bottom),
             send_updates,
+            soa_precedence: SoaPrecedence::default(),
         }
     }
+
+    /// which SOA is served when both `top` and `bottom` define one. defaults to the higher serial
+    pub fn with_soa_precedence(mut self, soa_precedence: SoaPrecedence) -> Self {
+        self.soa_precedence = soa_precedence;
+        self
+    }
 }
 
 #[async_trait::async_trait]
@@ -58,7 +66,7 @@ impl<TOP: ZoneProvider, BOTTOM: ZoneProvider> ZoneProvider for MergeZoneProvider
                     current_top_zone = Some(top_zone);
                     if let (Some(top), Some(bottom)) = (&current_top_zone, &current_bottom_zone) {
                         let mut zone = bottom.clone();
-                        zone.merge_from(top.clone());
+                        zone.merge_from_with(top.clone(), self.soa_precedence);
                         if sender.send(zone).await.is_err() {
                             break;
                         }
@@ -71,7 +79,7 @@ impl<TOP: ZoneProvider, BOTTOM: ZoneProvider> ZoneProvider for MergeZoneProvider
                     current_bottom_zone = Some(bottom_zone);
                     if let (Some(top), Some(bottom)) = (&current_top_zone, &current_bottom_zone) {
                         let mut zone = bottom.clone();
-                        zone.merge_from(top.clone());
+                        zone.merge_from_with(top.clone(), self.soa_precedence);
                         if sender.send(zone).await.is_err() {
                             break;
                         }
@@ -119,10 +127,12 @@ mod tests {
             "0.0.0.0:5053".parse().unwrap(),
             "0.0.0.0:5053".parse().unwrap(),
             MergeZoneProvider::new(
-                DynFileZoneProvider(
+                DynFileZoneProvider::new(
                     Path::new("./src/zone_provider/test_zone_dyn.yaml").to_path_buf(),
                 ),
-                FileZoneProvider(Path::new("./src/zone_provider/test_zone.yaml").to_path_buf()),
+                FileZoneProvider::new(
+                    Path::new("./src/zone_provider/test_zone.yaml").to_path_buf(),
+                ),
                 SendUpdates::ToTop,
             ),
         )
@@ -1,6 +1,12 @@
-use adns_zone::{Zone, ZoneUpdate};
+use adns_zone::{InvalidRecord, Zone, ZoneUpdate};
+use log::{error, warn};
 use tokio::sync::{mpsc, oneshot};
 
+mod yaml;
+#[cfg(any(feature = "file_zone", feature = "grpc"))]
+pub(crate) use yaml::load_yaml;
+pub(crate) use yaml::load_yaml_value;
+
 mod static_provider;
 pub use static_provider::StaticZoneProvider;
 
@@ -14,9 +20,33 @@ mod dynfile;
 pub use dynfile::DynFileZoneProvider;
 mod merge;
 pub use merge::{MergeZoneProvider, SendUpdates};
+mod secondary;
+pub use secondary::SecondaryZoneProvider;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(feature = "grpc")]
+pub use grpc::{proto as grpc_proto, GrpcZoneError, GrpcZoneProvider};
+
+/// Logs the problems `Zone::validate` finds in a freshly loaded zone, once per load rather than per query
+pub(crate) fn warn_invalid(source: &str, zone: &Zone) {
+    for error in zone.validate() {
+        warn!("{source}: {error}");
+    }
+}
+
+/// Logs the records `InvalidRecordPolicy::Skip` left out of a zone loaded from `source`
+pub(crate) fn log_skipped(source: &str, skipped: Vec<InvalidRecord>) {
+    for record in skipped {
+        error!("{source}: skipping record with invalid data: {record}");
+    }
+}
 
+/// An RFC2136 update for a provider to apply, or a NOTIFY asking the provider to refresh
+/// `update.zone_name` from its source.
 pub struct ZoneProviderUpdate {
     pub update: ZoneUpdate,
+    /// a NOTIFY (RFC 1996), carrying no actions. an update may have no actions either, if it only has prerequisites
+    pub notify: bool,
     // must be sent AFTER the zone is updated and sent back upstream
     pub response: oneshot::Sender<()>,
 }
@@ -0,0 +1,356 @@
+use std::{net::SocketAddr, time::Duration};
+
+use adns_client::{DnsClient, DnsQueryError};
+use adns_proto::{Name, Packet, Question, Record, ResponseCode, SoaData, Type, TypeData};
+use adns_zone::Zone;
+use log::{error, info};
+use tokio::{select, sync::mpsc};
+
+use crate::{ZoneProvider, ZoneProviderUpdate};
+
+/// the shortest wait between SOA checks, so a zero refresh or retry doesn't poll the primary in a loop
+const MIN_REFRESH: Duration = Duration::from_secs(30);
+
+/// Serves `zone` as a secondary, transferring it from `primary` with AXFR and re-checking the primary's SOA serial
+/// every SOA refresh interval, or on NOTIFY from the primary. `refresh` is the retry interval until the first transfer succeeds.
+/// waits are never shorter than 30 seconds.
+pub struct SecondaryZoneProvider {
+    pub primary: SocketAddr,
+    pub zone: Name,
+    /// TSIG `(key name, algorithm, key)` for the SOA check and transfer
+    pub key: Option<(Name, Name, Vec<u8>)>,
+    /// allow `key` to use HMAC-MD5
+    pub allow_md5_tsig: bool,
+    pub refresh: Duration,
+    /// `zone` is an RFC 9432 catalog zone: its member zones are transferred from `primary` as well, and are
+    /// added and removed as the catalog changes
+    pub catalog: bool,
+}
+
+#[async_trait::async_trait]
+impl ZoneProvider for SecondaryZoneProvider {
+    async fn run(
+        &mut self,
+        sender: mpsc::Sender<Zone>,
+        mut updates: mpsc::Receiver<ZoneProviderUpdate>,
+    ) {
+        let mut root = Zone::default();
+        let mut current: Option<SoaData> = None;
+        loop {
+            let wait = match self.check(&self.zone, current.as_ref()).await {
+                Ok(transferred) => {
+                    let mut changed = false;
+                    if let Some((zone, soa)) = transferred {
+                        info!(
+                            "transferred {} serial {} from {}",
+                            self.zone, soa.serial, self.primary
+                        );
+                        root.zones.insert(self.zone.clone(), zone);
+                        current = Some(soa);
+                        changed = true;
+                    }
+                    if self.catalog {
+                        changed |= self.update_members(&mut root).await;
+                    }
+                    if changed && sender.send(served(&root)).await.is_err() {
+                        return;
+                    }
+                    Duration::from_secs(current.as_ref().unwrap().refresh as u64)
+                }
+                Err(e) => {
+                    error!(
+                        "failed to transfer {} from {}: {e}",
+                        self.zone, self.primary
+                    );
+                    current
+                        .as_ref()
+                        .map(|soa| Duration::from_secs(soa.retry as u64))
+                        .unwrap_or(self.refresh)
+                }
+            };
+            let deadline = tokio::time::Instant::now() + wait.max(MIN_REFRESH);
+            loop {
+                select! {
+                    _ = tokio::time::sleep_until(deadline) => break,
+                    Some(update) = updates.recv() => {
+                        // RFC2136 updates can't be applied to a secondary, dropping the response fails them
+                        if update.notify {
+                            update.response.send(()).ok();
+                            break;
+                        }
+                    },
+                    _ = sender.closed() => return,
+                }
+            }
+        }
+    }
+}
+
+/// the zones of `root` that were transferred, leaving out catalog members not transferred yet
+fn served(root: &Zone) -> Zone {
+    let mut root = root.clone();
+    root.zones.retain(|_, zone| zone.soa.is_some());
+    root
+}
+
+/// the serial of the SOA record answered in `response`
+fn primary_serial(response: &Packet) -> Result<u32, DnsQueryError> {
+    if response.header.response_code != ResponseCode::NoError {
+        return Err(DnsQueryError::ResponseCode(response.header.response_code));
+    }
+    response
+        .answers
+        .iter()
+        .find_map(|record| match &record.data {
+            TypeData::SOA(soa) => Some(soa.serial),
+            _ => None,
+        })
+        .ok_or(DnsQueryError::MissingSoa)
+}
+
+/// whether `serial` is greater than `current` in RFC 1982 serial number arithmetic. serials exactly 2^31 apart are
+/// undefined, and not treated as newer
+fn serial_newer(serial: u32, current: u32) -> bool {
+    (serial.wrapping_sub(current) as i32) > 0
+}
+
+impl SecondaryZoneProvider {
+    /// provisions the members listed by the catalog zone in `root`: transfers new and changed ones and drops those no
+    /// longer listed. returns whether any changed
+    async fn update_members(&self, root: &mut Zone) -> bool {
+        let members = root.zones[&self.zone].catalog_members(&self.zone);
+        let mut changed = root.zones.iter().any(|(name, zone)| {
+            zone.catalog_member.as_ref() == Some(&self.zone) && !members.contains(name)
+        });
+        root.apply_catalog(&self.zone, &members);
+        for member in &members {
+            let current = root.zones[member].soa.clone();
+            match self.check(member, current.as_ref()).await {
+                Ok(Some((mut zone, soa))) => {
+                    info!(
+                        "transferred catalog member {member} serial {} from {}",
+                        soa.serial, self.primary
+                    );
+                    zone.catalog_member = Some(self.zone.clone());
+                    root.zones.insert(member.clone(), zone);
+                    changed = true;
+                }
+                Ok(None) => (),
+                Err(e) => error!(
+                    "failed to transfer catalog member {member} from {}: {e}",
+                    self.primary
+                ),
+            }
+        }
+        changed
+    }
+
+    /// transfers zone `name` if the primary's serial is newer than `current`, per RFC 1982 serial number arithmetic
+    async fn check(
+        &self,
+        name: &Name,
+        current: Option<&SoaData>,
+    ) -> Result<Option<(Zone, SoaData)>, DnsQueryError> {
+        let mut client = DnsClient::new().await?.with_md5_tsig(self.allow_md5_tsig);
+        if let Some(current) = current {
+            let question = Question {
+                name: name.clone(),
+                type_: Type::SOA,
+                class: Default::default(),
+            };
+            let response = match &self.key {
+                Some((name, algorithm, key)) => {
+                    client
+                        .query_signed(
+                            self.primary,
+                            vec![question],
+                            name.clone(),
+                            algorithm.clone(),
+                            key.clone(),
+                        )
+                        .await?
+                }
+                None => client.query(self.primary, vec![question]).await?,
+            };
+            if !serial_newer(primary_serial(&response)?, current.serial) {
+                return Ok(None);
+            }
+        }
+        let records = client
+            .axfr(self.primary, name.clone(), self.key.clone())
+            .await?;
+        self.build_zone(name, records).map(Some)
+    }
+
+    fn build_zone(
+        &self,
+        name: &Name,
+        records: Vec<Record>,
+    ) -> Result<(Zone, SoaData), DnsQueryError> {
+        let mut zone = Zone {
+            authoritative: true,
+            ..Default::default()
+        };
+        let mut soa = None;
+        for record in records {
+            match record.data {
+                TypeData::SOA(data) if record.name == *name => soa = Some(data),
+                TypeData::NS(ns) if record.name == *name => zone.nameservers.push(ns),
+                _ => zone.records.push(record),
+            }
+        }
+        // the transfer starts with an SOA, but not necessarily the zone's own
+        let soa = soa.ok_or(DnsQueryError::MalformedTransfer)?;
+        zone.soa = Some(soa.clone());
+        zone.notify_from.push(self.primary.ip());
+        Ok((zone, soa))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use adns_proto::QueryResponse;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    fn soa(name: &str) -> Record {
+        Record::new(
+            name.parse().unwrap(),
+            300,
+            TypeData::SOA(SoaData {
+                mname: "ns1.example.com".parse().unwrap(),
+                rname: "hostmaster.example.com".parse().unwrap(),
+                serial: 1,
+                refresh: 3600,
+                retry: 600,
+                expire: 86400,
+                minimum: 300,
+            }),
+        )
+    }
+
+    #[test]
+    fn test_build_zone() {
+        let provider = SecondaryZoneProvider {
+            primary: "192.0.2.53:53".parse().unwrap(),
+            zone: "example.com".parse().unwrap(),
+            key: None,
+            allow_md5_tsig: false,
+            refresh: Duration::from_secs(60),
+            catalog: false,
+        };
+        let soa = soa("example.com");
+        let ns = Record::new(
+            "example.com".parse().unwrap(),
+            300,
+            TypeData::NS("ns1.example.com".parse().unwrap()),
+        );
+
+        let (zone, _) = provider
+            .build_zone(&provider.zone, vec![soa.clone(), ns.clone(), soa])
+            .unwrap();
+        // the transferred name servers are ours, not a delegation
+        assert!(zone.authoritative && !zone.is_delegation());
+
+        assert!(matches!(
+            provider.build_zone(&provider.zone, vec![ns]),
+            Err(DnsQueryError::MalformedTransfer)
+        ));
+    }
+
+    #[test]
+    fn test_serial_newer() {
+        assert!(serial_newer(2, 1));
+        assert!(!serial_newer(1, 1));
+        // an older serial doesn't trigger a transfer
+        assert!(!serial_newer(1, 2));
+        // serials wrap around
+        assert!(serial_newer(0, u32::MAX));
+        assert!(serial_newer(5, u32::MAX - 5));
+        assert!(!serial_newer(u32::MAX, 0));
+        assert!(!serial_newer(1 << 31, 0));
+    }
+
+    #[test]
+    fn test_primary_serial() {
+        let mut response = Packet::default();
+        assert!(matches!(
+            primary_serial(&response),
+            Err(DnsQueryError::MissingSoa)
+        ));
+        response.answers.push(soa("example.com"));
+        assert_eq!(primary_serial(&response).unwrap(), 1);
+        // a refusal is an error, not a changed serial
+        response.header.response_code = ResponseCode::Refused;
+        assert!(matches!(
+            primary_serial(&response),
+            Err(DnsQueryError::ResponseCode(ResponseCode::Refused))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_catalog() {
+        let catalog: Name = "catalog.invalid".parse().unwrap();
+        let mut primary = Zone {
+            catalog: Some(catalog.clone()),
+            ..Default::default()
+        };
+        primary.zones.insert(
+            "example.com".parse().unwrap(),
+            Zone {
+                records: vec![Record::new(
+                    "www.example.com".parse().unwrap(),
+                    300,
+                    TypeData::A("192.0.2.1".parse().unwrap()),
+                )],
+                ..Default::default()
+            },
+        );
+        primary.insert_catalog();
+
+        // answers AXFRs of the catalog and its member with a single message each
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut client, _) = listener.accept().await.unwrap();
+                let mut query = vec![0u8; client.read_u16().await.unwrap() as usize];
+                client.read_exact(&mut query).await.unwrap();
+                let (query, _) = Packet::parse(&query).unwrap();
+                let name = query.questions[0].name.clone();
+                let mut response = query.clone();
+                response.header.query_response = QueryResponse::Response;
+                response.answers.push(soa(name.raw()));
+                response
+                    .answers
+                    .extend(primary.zones[&name].records.iter().cloned());
+                response.answers.push(soa(name.raw()));
+                let response = response.serialize(u16::MAX as usize);
+                client.write_u16(response.len() as u16).await.unwrap();
+                client.write_all(&response).await.unwrap();
+            }
+        });
+
+        let mut provider = SecondaryZoneProvider {
+            primary: addr,
+            zone: catalog.clone(),
+            key: None,
+            allow_md5_tsig: false,
+            refresh: Duration::from_secs(60),
+            catalog: true,
+        };
+        let (sender, mut receiver) = mpsc::channel(1);
+        let (_updater, updates) = mpsc::channel(1);
+        tokio::spawn(async move { provider.run(sender, updates).await });
+        let zone = receiver.recv().await.unwrap();
+        assert!(zone.zones.contains_key(&catalog));
+        let member = &zone.zones[&"example.com".parse::<Name>().unwrap()];
+        assert_eq!(member.catalog_member.as_ref(), Some(&catalog));
+        assert!(member.authoritative);
+        assert_eq!(member.records.len(), 1);
+    }
+}
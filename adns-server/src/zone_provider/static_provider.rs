@@ -1,10 +1,25 @@
-use adns_zone::Zone;
+use adns_zone::{InvalidRecordPolicy, Zone};
 use tokio::sync::mpsc;
 
-use crate::{ZoneProvider, ZoneProviderUpdate};
+use crate::{
+    zone_provider::{load_yaml_value, log_skipped, warn_invalid},
+    ZoneProvider, ZoneProviderUpdate,
+};
 
 pub struct StaticZoneProvider(pub Zone);
 
+impl StaticZoneProvider {
+    /// the zone embedded in a config file, with `invalid_records` applied to its records
+    pub fn from_yaml(
+        zone: serde_yaml::Value,
+        invalid_records: InvalidRecordPolicy,
+    ) -> Result<Self, serde_yaml::Error> {
+        let (zone, skipped) = load_yaml_value(zone, invalid_records)?;
+        log_skipped("static zone", skipped);
+        Ok(Self(zone))
+    }
+}
+
 #[async_trait::async_trait]
 impl ZoneProvider for StaticZoneProvider {
     async fn run(
@@ -13,8 +28,33 @@ impl ZoneProvider for StaticZoneProvider {
         updates: mpsc::Receiver<ZoneProviderUpdate>,
     ) {
         drop(updates);
+        warn_invalid("static zone", &self.0);
         sender.send(self.0.clone()).await.ok();
         std::mem::forget(sender);
         futures::future::pending::<()>().await;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_records() {
+        let zone: serde_yaml::Value = serde_yaml::from_str(
+            "
+records:
+- domain: good.example.com
+  type: A
+  data: 192.0.2.1
+- domain: bad.example.com
+  type: A
+  data: not-an-address
+",
+        )
+        .unwrap();
+        assert!(StaticZoneProvider::from_yaml(zone.clone(), InvalidRecordPolicy::Fail).is_err());
+        let provider = StaticZoneProvider::from_yaml(zone, InvalidRecordPolicy::Skip).unwrap();
+        assert_eq!(provider.0.records.len(), 1);
+    }
+}
@@ -0,0 +1,144 @@
+use adns_proto::{Name, Type, TypeData};
+use adns_zone::{InvalidRecord, InvalidRecordPolicy, Zone};
+use serde::{de::Error as _, Deserialize};
+use serde_yaml::Value;
+
+/// the parts of a zone file record needed to check its data
+#[derive(Deserialize)]
+struct YamlRecord {
+    domain: Name,
+    #[serde(rename = "type")]
+    type_: Type,
+    data: String,
+}
+
+/// deserializes a YAML zone, applying `invalid_records` to the records of it, its subzones and views.
+/// returns the zone and the records it skipped
+#[cfg(any(feature = "file_zone", feature = "grpc"))]
+pub(crate) fn load_yaml(
+    input: &[u8],
+    invalid_records: InvalidRecordPolicy,
+) -> Result<(Zone, Vec<InvalidRecord>), serde_yaml::Error> {
+    load_yaml_value(serde_yaml::from_slice(input)?, invalid_records)
+}
+
+/// like `load_yaml`, for a zone that's already parsed, i.e. embedded in a config file
+pub(crate) fn load_yaml_value(
+    mut value: Value,
+    invalid_records: InvalidRecordPolicy,
+) -> Result<(Zone, Vec<InvalidRecord>), serde_yaml::Error> {
+    let mut skipped = vec![];
+    filter_records(&mut value, invalid_records, &mut skipped)?;
+    Ok((serde_yaml::from_value(value)?, skipped))
+}
+
+/// drops the records with invalid data from every `records` and `rpz` list in `value`, before it's
+/// deserialized into a `Zone`. malformed entries are left for deserializing to reject
+fn filter_records(
+    value: &mut Value,
+    invalid_records: InvalidRecordPolicy,
+    skipped: &mut Vec<InvalidRecord>,
+) -> Result<(), serde_yaml::Error> {
+    match value {
+        Value::Mapping(map) => {
+            for (key, item) in map.iter_mut() {
+                match (key.as_str(), item) {
+                    (Some("records" | "rpz"), Value::Sequence(records)) => {
+                        let mut kept = Vec::with_capacity(records.len());
+                        for record in std::mem::take(records) {
+                            if check_record(&record, invalid_records, skipped)? {
+                                kept.push(record);
+                            }
+                        }
+                        *records = kept;
+                    }
+                    (_, item) => filter_records(item, invalid_records, skipped)?,
+                }
+            }
+        }
+        Value::Sequence(items) => {
+            for item in items {
+                filter_records(item, invalid_records, skipped)?;
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// whether to keep `record`
+fn check_record(
+    record: &Value,
+    invalid_records: InvalidRecordPolicy,
+    skipped: &mut Vec<InvalidRecord>,
+) -> Result<bool, serde_yaml::Error> {
+    let Ok(record) = serde_yaml::from_value::<YamlRecord>(record.clone()) else {
+        return Ok(true);
+    };
+    let Err(error) = TypeData::parse_str(record.type_, &record.data) else {
+        return Ok(true);
+    };
+    let record = InvalidRecord {
+        name: record.domain,
+        type_: record.type_,
+        error,
+    };
+    invalid_records
+        .handle(record, skipped)
+        .map_err(serde_yaml::Error::custom)?;
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ZONE: &str = r#"
+records:
+- domain: good.example.com
+  type: A
+  data: 192.0.2.1
+- domain: bad.example.com
+  type: A
+  data: not-an-address
+"#;
+
+    fn load(
+        input: &str,
+        invalid_records: InvalidRecordPolicy,
+    ) -> Result<(Zone, Vec<InvalidRecord>), serde_yaml::Error> {
+        load_yaml_value(serde_yaml::from_str(input).unwrap(), invalid_records)
+    }
+
+    #[test]
+    fn test_load_yaml() {
+        let (zone, skipped) = load(ZONE, InvalidRecordPolicy::Skip).unwrap();
+        assert_eq!(zone.records.len(), 1);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].name, "bad.example.com");
+
+        assert!(load(ZONE, InvalidRecordPolicy::Fail).is_err());
+        assert!(serde_yaml::from_str::<Zone>(ZONE).is_err());
+
+        // subzones, views and response policies are checked too
+        let nested = r#"
+zones:
+  sub.example.com:
+    records:
+    - domain: bad.sub.example.com
+      type: AAAA
+      data: 192.0.2.1
+views:
+- clients: [10.0.0.0/8]
+  zone:
+    rpz:
+    - domain: bad.example.com
+      type: A
+      data: not-an-address
+"#;
+        let (zone, skipped) = load(nested, InvalidRecordPolicy::Skip).unwrap();
+        assert_eq!(skipped.len(), 2);
+        assert!(zone.zones[0].records.is_empty());
+        assert!(zone.views[0].zone.rpz.is_empty());
+    }
+}
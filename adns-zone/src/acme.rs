@@ -0,0 +1,115 @@
+use adns_proto::{Name, NameParseError, Record, Type, TypeData};
+
+use crate::{Zone, ZoneUpdate, ZoneUpdateAction};
+
+/// short, so validation servers don't see stale tokens. the lowest TTL updates allow
+pub const ACME_CHALLENGE_TTL: u32 = 60;
+
+/// `_acme-challenge.<domain>`, wildcard domains share the challenge name of their parent (RFC 8555 section 8.4)
+fn challenge_name(domain: &Name) -> Result<Name, NameParseError> {
+    let mut segments = domain.segments().peekable();
+    if segments.peek() == Some(&"*") {
+        segments.next();
+    }
+    Name::from_segments(std::iter::once("_acme-challenge").chain(segments))
+}
+
+impl Zone {
+    /// the subzone holding `name`, "" for this zone
+    fn update_zone_name(&self, name: &Name) -> Name {
+        self.zones
+            .keys()
+            .filter(|zone_name| name.ends_with(zone_name))
+            .max_by_key(|zone_name| zone_name.len())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn apply_acme_update(
+        &mut self,
+        domain: &Name,
+        action: impl FnOnce(Name) -> ZoneUpdateAction,
+    ) -> Result<ZoneUpdate, NameParseError> {
+        let name = challenge_name(domain)?;
+        let update = ZoneUpdate {
+            zone_name: self.update_zone_name(&name),
+            actions: vec![action(name)],
+        };
+        update.apply_to(self);
+        Ok(update)
+    }
+
+    /// adds an ACME DNS-01 challenge TXT for `domain`. challenges for the same name (e.g. for `example.com`
+    /// and `*.example.com`) are kept side by side. returns the update applied, to persist or forward it.
+    pub fn set_acme_challenge(
+        &mut self,
+        domain: &Name,
+        token: &str,
+    ) -> Result<ZoneUpdate, NameParseError> {
+        self.apply_acme_update(domain, |name| {
+            ZoneUpdateAction::AddRecord(Record::new(
+                name,
+                ACME_CHALLENGE_TTL,
+                TypeData::TXT(vec![token.to_string()].into()),
+            ))
+        })
+    }
+
+    /// removes one challenge for `domain`, leaving any others in progress
+    pub fn remove_acme_challenge(
+        &mut self,
+        domain: &Name,
+        token: &str,
+    ) -> Result<ZoneUpdate, NameParseError> {
+        self.apply_acme_update(domain, |name| {
+            ZoneUpdateAction::DeleteRecord(name, TypeData::TXT(vec![token.to_string()].into()))
+        })
+    }
+
+    /// removes every challenge for `domain`
+    pub fn clear_acme_challenge(&mut self, domain: &Name) -> Result<ZoneUpdate, NameParseError> {
+        self.apply_acme_update(domain, |name| {
+            ZoneUpdateAction::DeleteRecords(name, Some(Type::TXT))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_concurrent_challenges() {
+        let mut zone = Zone::default();
+        zone.zones
+            .insert("example.com".parse().unwrap(), Zone::default());
+        let challenge: Name = "_acme-challenge.example.com".parse().unwrap();
+        let tokens = |zone: &Zone| -> Vec<String> {
+            zone.lookup(&challenge, Some(Type::TXT))
+                .map(|x| x.data.to_string())
+                .collect()
+        };
+
+        let update = zone
+            .set_acme_challenge(&"example.com".parse().unwrap(), "token-a")
+            .unwrap();
+        assert_eq!(update.zone_name, "example.com");
+        zone.set_acme_challenge(&"*.example.com".parse().unwrap(), "token-b")
+            .unwrap();
+        // setting the same token again doesn't duplicate it
+        zone.set_acme_challenge(&"example.com".parse().unwrap(), "token-a")
+            .unwrap();
+        assert_eq!(tokens(&zone), vec!["token-a", "token-b"]);
+        assert!(zone.zones[0]
+            .records
+            .iter()
+            .all(|x| x.ttl == ACME_CHALLENGE_TTL));
+
+        zone.remove_acme_challenge(&"example.com".parse().unwrap(), "token-a")
+            .unwrap();
+        assert_eq!(tokens(&zone), vec!["token-b"]);
+        zone.clear_acme_challenge(&"example.com".parse().unwrap())
+            .unwrap();
+        assert!(tokens(&zone).is_empty());
+    }
+}
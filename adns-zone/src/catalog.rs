@@ -0,0 +1,129 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use adns_proto::{Name, Record, SoaData, TypeData};
+
+use crate::Zone;
+
+/// RFC 9432 catalog zone schema version
+const CATALOG_VERSION: &str = "2";
+
+/// stable member zone label: FNV-1a of the lowercased zone name
+fn member_id(zone: &Name) -> String {
+    let hash = zone
+        .lowercased()
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+    format!("{hash:016x}")
+}
+
+impl Zone {
+    /// builds an RFC 9432 catalog zone named `catalog` listing every zone in `self.zones`
+    pub fn catalog_zone(&self, catalog: &Name) -> Zone {
+        let invalid: Name = "invalid".parse().unwrap();
+        let mut records = vec![Record::new(
+            format!("version.{catalog}").parse().unwrap(),
+            0,
+            TypeData::TXT(vec![CATALOG_VERSION.to_string()].into()),
+        )];
+        for member in self.zones.keys().filter(|name| *name != catalog) {
+            records.push(Record::new(
+                format!("{}.zones.{catalog}", member_id(member))
+                    .parse()
+                    .unwrap(),
+                0,
+                TypeData::PTR(member.clone()),
+            ));
+        }
+        Zone {
+            records,
+            // a fresh serial on every load so secondaries notice membership changes
+            soa: Some(SoaData {
+                mname: invalid.clone(),
+                rname: invalid.clone(),
+                serial: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs() as u32,
+                refresh: 3600,
+                retry: 600,
+                expire: 2147483646,
+                minimum: 0,
+            }),
+            nameservers: vec![invalid],
+            authoritative: true,
+            ..Default::default()
+        }
+    }
+
+    /// adds the catalog zone configured by `Zone::catalog`, if any
+    pub fn insert_catalog(&mut self) {
+        if let Some(catalog) = self.catalog.clone() {
+            let zone = self.catalog_zone(&catalog);
+            self.zones.insert(catalog, zone);
+        }
+    }
+
+    /// member zones listed in a catalog zone (the `PTR` records under `zones.<catalog>`)
+    pub fn catalog_members(&self, catalog: &Name) -> Vec<Name> {
+        let Ok(members) = format!("*.zones.{catalog}").parse::<Name>() else {
+            return vec![];
+        };
+        self.records
+            .iter()
+            .filter(|record| members.contains(&record.name))
+            .filter_map(|record| match &record.data {
+                TypeData::PTR(member) => Some(member.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// provisions the member zones of a consumed catalog: adds an empty zone for each new member and
+    /// removes zones that were provisioned from the catalog but are no longer listed
+    pub fn apply_catalog(&mut self, catalog: &Name, members: &[Name]) {
+        self.zones.retain(|name, zone| {
+            zone.catalog_member.as_ref() != Some(catalog) || members.contains(name)
+        });
+        for member in members {
+            self.zones.entry(member.clone()).or_insert_with(|| Zone {
+                catalog_member: Some(catalog.clone()),
+                ..Default::default()
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalog_round_trip() {
+        let catalog: Name = "catalog.invalid".parse().unwrap();
+        let mut primary = Zone {
+            catalog: Some(catalog.clone()),
+            ..Default::default()
+        };
+        primary
+            .zones
+            .insert("example.com".parse().unwrap(), Zone::default());
+        primary
+            .zones
+            .insert("example.net".parse().unwrap(), Zone::default());
+        primary.insert_catalog();
+        let catalog_zone = &primary.zones[&catalog];
+        assert_eq!(catalog_zone.records.len(), 3);
+
+        let members = catalog_zone.catalog_members(&catalog);
+        assert_eq!(members, vec!["example.com", "example.net"]);
+
+        let mut secondary = Zone::default();
+        secondary.apply_catalog(&catalog, &members);
+        assert_eq!(secondary.zones.len(), 2);
+        secondary.apply_catalog(&catalog, &members[..1]);
+        assert_eq!(secondary.zones.len(), 1);
+        assert!(secondary.zones.contains_key(&members[0]));
+    }
+}
@@ -0,0 +1,720 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use adns_proto::{Class, DnskeyData, Name, Record, RrsigData, Type, TypeData};
+use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+
+use crate::{is_standard_wildcard, is_wildcard, Zone};
+
+/// Where a zone signing key is in a rollover (RFC 6781 section 4.1.1).
+/// Pre-publish rolls a new key `published` -> `active` while the old one goes `active` -> `retiring`;
+/// double-signature keeps both `active` for a TTL before retiring the old key.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyState {
+    /// in the DNSKEY RRset so resolvers cache it, but not signing yet
+    Published,
+    /// in the DNSKEY RRset and signing
+    #[default]
+    Active,
+    /// no longer signing, kept in the DNSKEY RRset until signatures made with it expire
+    Retiring,
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ZoneSigningKey {
+    /// DNSSEC algorithm number, 13 (ECDSAP256SHA256) by default
+    #[serde(default = "default_algorithm")]
+    pub algorithm: u8,
+    #[serde_as(as = "serde_with::base64::Base64")]
+    pub private_key: Vec<u8>,
+    #[serde(default)]
+    pub state: KeyState,
+}
+
+/// ECDSA P-256 with SHA-256 (RFC 6605), the only algorithm signed online so far
+const ECDSAP256SHA256: u8 = 13;
+
+fn default_algorithm() -> u8 {
+    ECDSAP256SHA256
+}
+
+/// RRSIGs are valid from an hour before signing, allowing for clock skew, until a week after
+const SIGNATURE_INCEPTION_OFFSET: u32 = 60 * 60;
+const SIGNATURE_VALIDITY: u32 = 7 * 24 * 60 * 60;
+
+const DNSKEY_TTL: u32 = 3600;
+
+/// cached RRSIGs are reused for a day, after which a fresh one replaces it with most of its validity left
+const SIGNATURE_REFRESH: u32 = 24 * 60 * 60;
+/// the cache is cleared when it grows past this many signatures, e.g. from many distinct NSECs
+const MAX_CACHED_SIGNATURES: usize = 1 << 16;
+
+/// RRSIGs by the data they sign, minus their validity period. clones of a zone share the cache, a zone loaded
+/// anew starts with an empty one
+#[derive(Default, Clone)]
+pub struct SignatureCache(Arc<Mutex<HashMap<Vec<u8>, Record>>>);
+
+impl std::fmt::Debug for SignatureCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let len = self.0.lock().unwrap().len();
+        f.debug_tuple("SignatureCache").field(&len).finish()
+    }
+}
+
+impl SignatureCache {
+    /// `key.sign(signer, rrset, now)`, reusing a signature made less than `SIGNATURE_REFRESH` before `now`
+    fn sign(
+        &self,
+        key: &ZoneSigningKey,
+        signer: &Name,
+        rrset: &[Record],
+        now: u32,
+    ) -> Option<Record> {
+        let first = rrset.first()?;
+        let cache_key = RrsigData {
+            type_covered: first.type_,
+            algorithm: key.algorithm,
+            labels: 0,
+            original_ttl: rrset.iter().map(|x| x.ttl).min()?,
+            expiration: 0,
+            inception: 0,
+            key_tag: key.dnskey()?.key_tag(),
+            signer: signer.clone(),
+            signature: vec![],
+        }
+        .signed_data(rrset);
+        let mut cache = self.0.lock().unwrap();
+        if let Some(cached) = cache.get(&cache_key) {
+            let fresh = |rrsig: &RrsigData| {
+                let made = rrsig.inception.saturating_add(SIGNATURE_INCEPTION_OFFSET);
+                (made..made.saturating_add(SIGNATURE_REFRESH)).contains(&now)
+            };
+            if matches!(&cached.data, TypeData::RRSIG(rrsig) if fresh(rrsig)) {
+                let mut cached = cached.clone();
+                // the owner's case follows the query
+                cached.name = first.name.clone();
+                return Some(cached);
+            }
+        }
+        let signed = key.sign(signer, rrset, now)?;
+        if cache.len() >= MAX_CACHED_SIGNATURES {
+            cache.clear();
+        }
+        cache.insert(cache_key, signed.clone());
+        Some(signed)
+    }
+}
+
+impl ZoneSigningKey {
+    /// none for algorithms other than ECDSAP256SHA256, or a private key that isn't a valid P-256 scalar
+    fn signing_key(&self) -> Option<SigningKey> {
+        if self.algorithm != ECDSAP256SHA256 {
+            return None;
+        }
+        SigningKey::from_slice(&self.private_key).ok()
+    }
+
+    /// the public key for the apex DNSKEY RRset, flagged as a combined signing key
+    pub fn dnskey(&self) -> Option<DnskeyData> {
+        let key = self.signing_key()?;
+        Some(DnskeyData {
+            flags: DnskeyData::ZONE_KEY | DnskeyData::SECURE_ENTRY_POINT,
+            protocol: 3,
+            algorithm: self.algorithm,
+            // the uncompressed point without its leading 0x04 (RFC 6605 section 4)
+            public_key: key.verifying_key().to_encoded_point(false).as_bytes()[1..].to_vec(),
+        })
+    }
+
+    /// an RRSIG by `signer` over `rrset`, whose records share a name, type and class. valid from shortly before
+    /// `now` (seconds since the unix epoch) for `SIGNATURE_VALIDITY`
+    pub fn sign(&self, signer: &Name, rrset: &[Record], now: u32) -> Option<Record> {
+        let key = self.signing_key()?;
+        let first = rrset.first()?;
+        let ttl = rrset.iter().map(|x| x.ttl).min()?;
+        let mut rrsig = RrsigData {
+            type_covered: first.type_,
+            algorithm: self.algorithm,
+            labels: first.name.segments().filter(|x| *x != "*").count() as u8,
+            original_ttl: ttl,
+            expiration: now.saturating_add(SIGNATURE_VALIDITY),
+            inception: now.saturating_sub(SIGNATURE_INCEPTION_OFFSET),
+            key_tag: self.dnskey()?.key_tag(),
+            signer: signer.clone(),
+            signature: vec![],
+        };
+        let signature: Signature = key.sign(&rrsig.signed_data(rrset));
+        rrsig.signature = signature.to_bytes().to_vec();
+        Some(Record::new(first.name.clone(), ttl, TypeData::RRSIG(rrsig)))
+    }
+}
+
+/// NSEC TTL for zones without an SOA, otherwise the SOA minimum is used (RFC 4034 section 4)
+const DEFAULT_NSEC_TTL: u32 = 300;
+
+/// whether the NSEC at `owner` pointing to `next` covers `name`, in canonical order (`Name::canonical_cmp`).
+/// the last NSEC in the chain points back to the apex.
+fn nsec_covers(owner: &Name, next: &Name, name: &Name) -> bool {
+    owner.canonical_cmp(name).is_lt()
+        && (name.canonical_cmp(next).is_lt() || next.canonical_cmp(owner).is_le())
+}
+
+/// the DNSKEY RRset of the published `keys`, at `apex`
+fn dnskey_records(keys: &[ZoneSigningKey], apex: &Name) -> Vec<Record> {
+    keys.iter()
+        .filter_map(|key| key.dnskey())
+        .map(|dnskey| Record::new(apex.clone(), DNSKEY_TTL, TypeData::DNSKEY(dnskey)))
+        .collect()
+}
+
+fn is_nsec_rrsig(record: &Record) -> bool {
+    matches!(&record.data, TypeData::RRSIG(rrsig) if rrsig.type_covered == Type::NSEC)
+}
+
+impl Zone {
+    /// keys that should generate RRSIGs. more than one during a double-signature rollover
+    pub fn active_keys(&self) -> impl Iterator<Item = &ZoneSigningKey> {
+        self.signing_keys
+            .iter()
+            .filter(|key| key.state == KeyState::Active)
+    }
+
+    /// the DNSKEY RRset of this zone's published keys, at `apex`
+    pub fn dnskey_records(&self, apex: &Name) -> Vec<Record> {
+        dnskey_records(&self.signing_keys, apex)
+    }
+
+    /// adds the `dnskey_records` of this zone and every subzone at their apex, so they are served and listed in NSECs.
+    /// authoritative subzones without keys of their own are signed with this zone's (see `sign_records`), so this
+    /// zone's keys are published at their apex instead
+    pub fn insert_dnskeys(&mut self) {
+        let inherited = self.signing_keys.clone();
+        self.insert_dnskeys_at(&Name::default(), &inherited);
+    }
+
+    fn insert_dnskeys_at(&mut self, apex: &Name, inherited: &[ZoneSigningKey]) {
+        let records = if self.signing_keys.is_empty() && self.authoritative {
+            dnskey_records(inherited, apex)
+        } else {
+            self.dnskey_records(apex)
+        };
+        for record in records {
+            if !self.records.contains(&record) {
+                self.records.push(record);
+            }
+        }
+        for (name, zone) in self.zones.iter_mut() {
+            zone.insert_dnskeys_at(name, inherited);
+        }
+    }
+
+    /// the deepest subzone holding the `type_` RRset at `name` and its apex, or this zone
+    fn zone_holding(&self, name: &Name, type_: Type) -> (Name, &Zone) {
+        self.subzone_holding(name, type_)
+            .map_or((Name::default(), self), |(zone_name, zone)| {
+                (zone_name.clone(), zone)
+            })
+    }
+
+    /// RRSIGs over each RRset in `records`, made at `now` by the active keys of the zone holding it (or this zone's,
+    /// if it has none of its own). RRsets already signed, from non-authoritative zones, or that are RRSIGs are skipped.
+    pub fn sign_records(&self, records: &[Record], now: u32) -> Vec<Record> {
+        let mut rrsets: Vec<(&Name, Type, Class)> = vec![];
+        for record in records {
+            let rrset = (&record.name, record.type_, record.class);
+            if record.type_ != Type::RRSIG && !rrsets.contains(&rrset) {
+                rrsets.push(rrset);
+            }
+        }
+        let mut out = vec![];
+        for (name, type_, class) in rrsets {
+            let signed = records.iter().any(|x| {
+                &x.name == name
+                    && matches!(&x.data, TypeData::RRSIG(rrsig) if rrsig.type_covered == type_)
+            });
+            let (apex, zone) = self.zone_holding(name, type_);
+            if signed || !zone.authoritative {
+                continue;
+            }
+            let keys = if zone.signing_keys.is_empty() {
+                self
+            } else {
+                zone
+            };
+            let rrset: Vec<Record> = records
+                .iter()
+                .filter(|x| &x.name == name && x.type_ == type_ && x.class == class)
+                .cloned()
+                .collect();
+            out.extend(
+                keys.active_keys()
+                    .filter_map(|key| self.signatures.sign(key, &apex, &rrset, now)),
+            );
+        }
+        out
+    }
+
+    /// NSEC records spanning the names of this zone (not its subzones) in canonical order, from `apex` around
+    /// back to it. records with custom wildcard patterns (`**`, `*+`) have no place in the chain and are left out.
+    /// every name lists RRSIG, as the chain is signed online along with the rest of the response.
+    /// delegations to subzones are in the chain with their NS, records below them (glue) are not.
+    pub fn nsec_chain(&self, apex: &Name) -> Vec<Record> {
+        let delegations = self
+            .zones
+            .iter()
+            .filter(|(_, zone)| zone.is_delegation())
+            .map(|(name, _)| name)
+            .collect();
+        self.nsec_chain_with(apex, delegations)
+    }
+
+    fn nsec_chain_with(&self, apex: &Name, delegations: Vec<&Name>) -> Vec<Record> {
+        let below_delegation =
+            |name: &Name| delegations.iter().any(|x| name != *x && name.ends_with(x));
+        let mut types: HashMap<&Name, Vec<Type>> = HashMap::new();
+        types.entry(apex).or_default();
+        for record in &self.records {
+            if (!is_wildcard(&record.name) || is_standard_wildcard(&record.name))
+                && !below_delegation(&record.name)
+            {
+                types.entry(&record.name).or_default().push(record.type_);
+            }
+        }
+        for delegation in &delegations {
+            types.entry(delegation).or_default().push(Type::NS);
+        }
+        if let Some(apex_types) = types.get_mut(apex) {
+            if self.soa.is_some() {
+                apex_types.push(Type::SOA);
+            }
+            if !self.nameservers.is_empty() {
+                apex_types.push(Type::NS);
+            }
+        }
+        let mut names: Vec<(&Name, Vec<Type>)> = types.into_iter().collect();
+        names.sort_by(|a, b| a.0.canonical_cmp(b.0));
+        let ttl = self.soa.as_ref().map_or(DEFAULT_NSEC_TTL, |x| x.minimum);
+        let nexts: Vec<Name> = names
+            .iter()
+            .cycle()
+            .skip(1)
+            .take(names.len())
+            .map(|(name, _)| (*name).clone())
+            .collect();
+        names
+            .into_iter()
+            .zip(nexts)
+            .map(|((name, mut types), next)| {
+                types.push(Type::RRSIG);
+                types.push(Type::NSEC);
+                types.sort_by_key(|x| u16::from(*x));
+                types.dedup();
+                Record::new(name.clone(), ttl, TypeData::NSEC { next, types })
+            })
+            .collect()
+    }
+
+    /// the NSEC records of `zone` at `apex`, one of this zone's subzones or itself, in canonical order: its own,
+    /// or for zones with signing keys but none of their own, its `nsec_chain`. delegations listed beside that zone
+    /// rather than inside it are part of its chain too. built once by `Zone::build_index`
+    pub(crate) fn nsec_records(&self, apex: &Name, zone: &Zone) -> Vec<Record> {
+        let mut nsecs: Vec<Record> = zone
+            .records
+            .iter()
+            .filter(|x| x.type_ == Type::NSEC)
+            .cloned()
+            .collect();
+        if nsecs.is_empty() && !(self.signing_keys.is_empty() && zone.signing_keys.is_empty()) {
+            let delegations = self
+                .zones
+                .iter()
+                .chain(&zone.zones)
+                .filter(|(name, subzone)| {
+                    subzone.is_delegation() && *name != apex && name.ends_with(apex)
+                })
+                .map(|(name, _)| name)
+                .collect();
+            return zone.nsec_chain_with(apex, delegations);
+        }
+        nsecs.sort_by(|a, b| a.name.canonical_cmp(&b.name));
+        nsecs
+    }
+
+    /// the `nsec_records` of the zone holding `name`, from its index when current, and that zone
+    fn nsec_chain_holding(&self, name: &Name) -> (Cow<'_, [Record]>, &Zone) {
+        let (apex, zone) = self.zone_holding(name, Type::NSEC);
+        let chain = match zone.current_index() {
+            Some(index) => Cow::Borrowed(&index.nsec[..]),
+            None => Cow::Owned(self.nsec_records(&apex, zone)),
+        };
+        (chain, zone)
+    }
+
+    /// RRSIGs over the NSEC at `owner`, stored in `zone`
+    fn nsec_rrsigs<'a>(zone: &'a Zone, owner: &'a Name) -> impl Iterator<Item = Record> + 'a {
+        zone.records_named(owner)
+            .into_iter()
+            .filter(move |x| &x.name == owner && is_nsec_rrsig(x))
+            .cloned()
+    }
+
+    /// RFC 4035 section 3.1.3.1 NODATA proof: the NSEC at `name` listing the types it has, and its RRSIGs.
+    /// from the NSEC records of the zone holding `name`, or generated for zones with signing keys
+    pub fn nsec_nodata(&self, name: &Name) -> Vec<Record> {
+        let (chain, zone) = self.nsec_chain_holding(name);
+        let Ok(i) = chain.binary_search_by(|x| x.name.canonical_cmp(name)) else {
+            return vec![];
+        };
+        std::iter::once(chain[i].clone())
+            .chain(Self::nsec_rrsigs(zone, name))
+            .collect()
+    }
+
+    /// RFC 4035 section 3.1.3.2 NXDOMAIN proof from the NSEC records of the zone holding `name`: the NSEC covering
+    /// `name`, the NSEC covering the wildcard at its closest encloser, and their RRSIGs.
+    /// zones with signing keys but no NSEC records get a generated chain, see `nsec_chain`. empty otherwise.
+    pub fn nsec_denial(&self, name: &Name) -> Vec<Record> {
+        let (chain, zone) = self.nsec_chain_holding(name);
+        let Some(last) = chain.last() else {
+            return vec![];
+        };
+        // the NSEC before `target` in canonical order, or the last one wrapping around to the apex
+        let covering = |target: &Name| {
+            let i = chain.partition_point(|x| x.name.canonical_cmp(target).is_lt());
+            let nsec = i.checked_sub(1).map_or(last, |i| &chain[i]);
+            match &nsec.data {
+                TypeData::NSEC { next, .. } if nsec_covers(&nsec.name, next, target) => Some(nsec),
+                _ => None,
+            }
+        };
+        let exists = |candidate: &Name| {
+            self.zones.contains_key(candidate)
+                || zone
+                    .records_named(candidate)
+                    .iter()
+                    .any(|x| &x.name == candidate)
+        };
+        let segments: Vec<&str> = name.segments().collect();
+        let closest_encloser = (1..=segments.len())
+            .filter_map(|skip| Name::from_segments(&segments[skip..]).ok())
+            .find(|x| x.is_empty() || exists(x))
+            .unwrap_or_default();
+        let Ok(wildcard) =
+            Name::from_segments(std::iter::once("*").chain(closest_encloser.segments()))
+        else {
+            return vec![];
+        };
+        let mut out: Vec<Record> = vec![];
+        for target in [name, &wildcard] {
+            let Some(nsec) = covering(target) else {
+                continue;
+            };
+            if out.contains(nsec) {
+                continue;
+            }
+            out.push(nsec.clone());
+            out.extend(Self::nsec_rrsigs(zone, &nsec.name));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollover_states() {
+        let zone: Zone = serde_yaml::from_str(
+            "
+signing_keys:
+- private_key: AAAA
+- private_key: AQEB
+  state: published
+- private_key: AgIC
+  state: retiring
+",
+        )
+        .unwrap();
+        assert_eq!(zone.signing_keys.len(), 3);
+        let active: Vec<_> = zone.active_keys().collect();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].private_key, vec![0, 0, 0]);
+        assert_eq!(active[0].algorithm, 13);
+
+        let zone: Zone = serde_yaml::from_str(
+            "
+zones:
+  example.com:
+    signing_keys:
+    - private_key: AAAA
+",
+        )
+        .unwrap();
+        let subzone = &zone.zones[&"example.com".parse::<Name>().unwrap()];
+        assert_eq!(subzone.active_keys().count(), 1);
+        let yaml = serde_yaml::to_string(&zone).unwrap();
+        assert_eq!(
+            serde_yaml::from_str::<Zone>(&yaml).unwrap().zones[0]
+                .signing_keys
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_nsec_denial() {
+        let zone = Zone::parse_masterfile(
+            "
+$TTL 300
+$ORIGIN example.com.
+@       SOA ns1 hostmaster 1 3600 600 86400 300
+        NSEC b A NSEC SOA
+b       A 192.0.2.1
+        NSEC d A NSEC RRSIG
+        RRSIG NSEC 13 3 300 20300101000000 20200101000000 12345 example.com. AAAA
+d       A 192.0.2.2
+        NSEC example.com. A NSEC
+",
+            "example.com".parse().unwrap(),
+        )
+        .unwrap();
+        let proof = zone.nsec_denial(&"c.example.com".parse().unwrap());
+        let owners: Vec<String> = proof
+            .iter()
+            .map(|x| format!("{} {}", x.name, x.type_))
+            .collect();
+        // c is covered by b -> d, the wildcard *.example.com by example.com -> b
+        assert_eq!(
+            owners,
+            vec![
+                "b.example.com NSEC",
+                "b.example.com RRSIG",
+                "example.com NSEC"
+            ]
+        );
+        // past the last name, covered by the wrap-around NSEC
+        let proof = zone.nsec_denial(&"z.example.com".parse().unwrap());
+        assert_eq!(proof[0].name, "d.example.com".parse::<Name>().unwrap());
+        assert!(Zone::default()
+            .nsec_denial(&"c.example.com".parse().unwrap())
+            .is_empty());
+
+        // another zone's NSECs prove nothing about names in an unsigned zone
+        let mut root = Zone::default();
+        root.zones.insert("example.com".parse().unwrap(), zone);
+        root.zones
+            .insert("example.net".parse().unwrap(), Zone::default());
+        assert!(root
+            .nsec_denial(&"c.example.net".parse().unwrap())
+            .is_empty());
+        assert!(root.nsec_nodata(&"example.net".parse().unwrap()).is_empty());
+        assert_eq!(root.nsec_denial(&"c.example.com".parse().unwrap()).len(), 3);
+    }
+
+    #[test]
+    fn test_generated_nsec() {
+        let apex: Name = "example.com".parse().unwrap();
+        let signed = Zone::parse_masterfile(
+            "
+$TTL 300
+$ORIGIN example.com.
+@       SOA ns1 hostmaster 1 3600 600 86400 60
+d       A 192.0.2.2
+B       A 192.0.2.1
+        TXT hello
+",
+            apex.clone(),
+        )
+        .unwrap();
+        let chain: Vec<String> = signed
+            .nsec_chain(&apex)
+            .iter()
+            .map(|x| format!("{} {} {}", x.name, x.ttl, x.data))
+            .collect();
+        assert_eq!(
+            chain,
+            vec![
+                "example.com 60 B.example.com SOA RRSIG NSEC",
+                "B.example.com 60 d.example.com A TXT RRSIG NSEC",
+                "d.example.com 60 example.com A RRSIG NSEC",
+            ]
+        );
+
+        let mut zone = Zone::default();
+        zone.zones.insert(apex, signed);
+        let c: Name = "c.example.com".parse().unwrap();
+        // nothing is generated without signing keys
+        assert!(zone.nsec_denial(&c).is_empty());
+        zone.signing_keys.push(ZoneSigningKey {
+            algorithm: 13,
+            private_key: vec![0; 32],
+            state: KeyState::Active,
+        });
+        let owners: Vec<String> = zone
+            .nsec_denial(&c)
+            .iter()
+            .map(|x| x.name.to_string())
+            .collect();
+        assert_eq!(owners, vec!["B.example.com", "example.com"]);
+        let nodata = zone.nsec_nodata(&"d.example.com".parse().unwrap());
+        assert_eq!(nodata.len(), 1);
+        assert_eq!(nodata[0].data.to_string(), "example.com A RRSIG NSEC");
+
+        // the index holds the chain, built once, and answers the same
+        zone.build_index();
+        assert_eq!(zone.zones[0].current_index().unwrap().nsec.len(), 3);
+        let indexed: Vec<String> = zone
+            .nsec_denial(&c)
+            .iter()
+            .map(|x| x.name.to_string())
+            .collect();
+        assert_eq!(indexed, owners);
+        assert_eq!(zone.nsec_nodata(&"d.example.com".parse().unwrap()), nodata);
+    }
+
+    fn verify(dnskey: &DnskeyData, rrsig: &RrsigData, rrset: &[Record]) -> bool {
+        use p256::ecdsa::{signature::Verifier, VerifyingKey};
+        let key =
+            VerifyingKey::from_sec1_bytes(&[&[4u8][..], &dnskey.public_key].concat()).unwrap();
+        let signature = Signature::from_slice(&rrsig.signature).unwrap();
+        key.verify(&rrsig.signed_data(rrset), &signature).is_ok()
+    }
+
+    #[test]
+    fn test_sign() {
+        let key = ZoneSigningKey {
+            algorithm: 13,
+            private_key: vec![7; 32],
+            state: KeyState::Active,
+        };
+        let dnskey = key.dnskey().unwrap();
+        assert_eq!(dnskey.flags, 257);
+        assert_eq!(dnskey.public_key.len(), 64);
+        let rrset = vec![Record::new(
+            "www.example.net".parse().unwrap(),
+            3600,
+            TypeData::A("192.0.2.1".parse().unwrap()),
+        )];
+        // signatures cover the canonical (lowercased) RRset
+        let signed = key
+            .sign(&"example.net".parse().unwrap(), &rrset, 1700000000)
+            .unwrap();
+        let TypeData::RRSIG(rrsig) = &signed.data else {
+            panic!("not an RRSIG");
+        };
+        assert_eq!(rrsig.key_tag, dnskey.key_tag());
+        assert_eq!(rrsig.labels, 3);
+        assert_eq!(rrsig.inception, 1700000000 - SIGNATURE_INCEPTION_OFFSET);
+        assert!(verify(&dnskey, rrsig, &rrset));
+        let mut upper = rrset.clone();
+        upper[0].name = "WWW.Example.NET".parse().unwrap();
+        assert!(verify(&dnskey, rrsig, &upper));
+    }
+
+    #[test]
+    fn test_sign_records() {
+        let apex: Name = "example.com".parse().unwrap();
+        let mut signed = Zone::parse_masterfile(
+            "
+$TTL 300
+$ORIGIN example.com.
+@       SOA ns1 hostmaster 1 3600 600 86400 60
+www     A 192.0.2.1
+        A 192.0.2.2
+",
+            apex.clone(),
+        )
+        .unwrap();
+        signed.signing_keys.push(ZoneSigningKey {
+            algorithm: 13,
+            private_key: vec![1; 32],
+            state: KeyState::Active,
+        });
+        let mut zone = Zone::default();
+        zone.zones.insert(apex.clone(), signed);
+        zone.zones.insert(
+            "sub.example.com".parse().unwrap(),
+            Zone {
+                nameservers: vec!["ns1.sub.example.com".parse().unwrap()],
+                ..Default::default()
+            },
+        );
+        zone.insert_dnskeys();
+        let dnskeys: Vec<&Record> = zone.lookup(&apex, Some(Type::DNSKEY)).collect();
+        assert_eq!(dnskeys.len(), 1);
+        let TypeData::DNSKEY(dnskey) = &dnskeys[0].data else {
+            panic!("not a DNSKEY");
+        };
+
+        let www: Name = "www.example.com".parse().unwrap();
+        let mut records: Vec<Record> = zone.lookup(&www, None).cloned().collect();
+        // delegations are signed by the child
+        records.push(Record::new(
+            "sub.example.com".parse().unwrap(),
+            300,
+            TypeData::ns("ns1.sub.example.com").unwrap(),
+        ));
+        let signatures = zone.sign_records(&records, 1700000000);
+        assert_eq!(signatures.len(), 1);
+        let TypeData::RRSIG(rrsig) = &signatures[0].data else {
+            panic!("not an RRSIG");
+        };
+        assert_eq!(rrsig.signer, apex);
+        assert_eq!(rrsig.type_covered, Type::A);
+        assert!(verify(dnskey, rrsig, &records[..2]));
+
+        // signatures are reused until they're due for a refresh
+        let inception = |signatures: &[Record]| match &signatures[0].data {
+            TypeData::RRSIG(rrsig) => rrsig.inception,
+            _ => panic!("not an RRSIG"),
+        };
+        let later = zone.sign_records(&records, 1700000000 + 600);
+        assert_eq!(inception(&later), rrsig.inception);
+        let refreshed = zone.sign_records(&records, 1700000000 + SIGNATURE_REFRESH);
+        assert!(inception(&refreshed) > rrsig.inception);
+
+        // already signed RRsets are left alone
+        records.extend(signatures);
+        assert!(zone.sign_records(&records, 1700000000).is_empty());
+
+        // a subzone without keys of its own is signed with the root's, which are published at its apex
+        let mut zone = Zone {
+            signing_keys: zone.zones[&apex].signing_keys.clone(),
+            ..Default::default()
+        };
+        let apex: Name = "example.org".parse().unwrap();
+        zone.zones.insert(
+            apex.clone(),
+            Zone {
+                authoritative: true,
+                records: vec![Record::new(
+                    "www.example.org".parse().unwrap(),
+                    300,
+                    TypeData::A("192.0.2.1".parse().unwrap()),
+                )],
+                ..Default::default()
+            },
+        );
+        zone.insert_dnskeys();
+        let dnskeys: Vec<&Record> = zone.lookup(&apex, Some(Type::DNSKEY)).collect();
+        assert_eq!(dnskeys.len(), 1);
+        let TypeData::DNSKEY(dnskey) = &dnskeys[0].data else {
+            panic!("not a DNSKEY");
+        };
+        let records = zone.zones[&apex].records[..1].to_vec();
+        let signatures = zone.sign_records(&records, 1700000000);
+        let TypeData::RRSIG(rrsig) = &signatures[0].data else {
+            panic!("not an RRSIG");
+        };
+        assert_eq!(rrsig.signer, apex);
+        assert!(verify(dnskey, rrsig, &records));
+    }
+}
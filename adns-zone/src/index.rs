@@ -0,0 +1,167 @@
+use std::collections::{HashMap, HashSet};
+
+use adns_proto::{Name, Record};
+use smallvec::SmallVec;
+
+use crate::Zone;
+
+/// positions in `Zone::records` by owner name. wildcard owners can match many names and are always scanned.
+#[derive(Debug, Default)]
+pub struct RecordIndex {
+    names: HashMap<Name, SmallVec<[usize; 2]>>,
+    wildcards: Vec<usize>,
+    /// every owner name and its ancestors, so empty non-terminals exist too
+    existing: HashSet<Name>,
+    /// the zone's NSEC chain in canonical order, see `Zone::nsec_records`
+    pub(crate) nsec: Vec<Record>,
+    /// `records.len()` when built, a cheap guard against using an index that is out of date
+    len: usize,
+}
+
+pub(crate) fn is_wildcard(name: &Name) -> bool {
+    name.segments().any(|x| matches!(x, "*" | "**" | "*+"))
+}
+
+impl RecordIndex {
+    fn build(records: &[Record], nsec: Vec<Record>) -> Self {
+        let mut index = RecordIndex {
+            nsec,
+            len: records.len(),
+            ..Default::default()
+        };
+        for (i, record) in records.iter().enumerate() {
+            if is_wildcard(&record.name) {
+                index.wildcards.push(i);
+            } else {
+                index.names.entry(record.name.clone()).or_default().push(i);
+            }
+            let segments: Vec<&str> = record.name.segments().collect();
+            for i in 0..=segments.len() {
+                let Ok(name) = Name::from_segments(&segments[i..]) else {
+                    break;
+                };
+                // the remaining ancestors were added along with this name
+                if !index.existing.insert(name) {
+                    break;
+                }
+            }
+        }
+        index
+    }
+
+    /// candidate positions for `name` in storage order
+    fn lookup(&self, name: &Name) -> SmallVec<[usize; 2]> {
+        let mut out: SmallVec<[usize; 2]> = self.names.get(name).cloned().unwrap_or_default();
+        if !self.wildcards.is_empty() {
+            out.extend(self.wildcards.iter().copied());
+            out.sort_unstable();
+        }
+        out
+    }
+}
+
+impl Zone {
+    /// indexes `records` by name in this zone and all subzones, so `answer` doesn't scan every record, and builds
+    /// their NSEC chains for negative answers. must be rebuilt after modifying `records` directly, `ZoneUpdate`s drop
+    /// the index of the zones they touch.
+    pub fn build_index(&mut self) {
+        let nsec = self.nsec_records(&Name::default(), self);
+        self.build_index_with(nsec);
+    }
+
+    fn build_index_with(&mut self, nsec: Vec<Record>) {
+        // chains are built from the parent, which knows the delegations beside each subzone
+        let chains: Vec<Vec<Record>> = self
+            .zones
+            .iter()
+            .map(|(name, zone)| self.nsec_records(name, zone))
+            .collect();
+        self.index = Some(RecordIndex::build(&self.records, nsec).into());
+        for (zone, nsec) in self.zones.values_mut().zip(chains) {
+            zone.build_index_with(nsec);
+        }
+    }
+
+    /// the index, unless it is out of date
+    pub(crate) fn current_index(&self) -> Option<&RecordIndex> {
+        self.index
+            .as_deref()
+            .filter(|index| index.len == self.records.len())
+    }
+
+    /// whether any record is owned by `name` or a name below it, using the index when it is current
+    pub(crate) fn name_exists(&self, name: &Name) -> bool {
+        match self.current_index() {
+            Some(index) => index.existing.contains(name),
+            None => self
+                .records
+                .iter()
+                .any(|record| record.name.ends_with(name)),
+        }
+    }
+
+    /// records whose owner matches `name`, using the index when it is current
+    pub(crate) fn records_named<'a>(&'a self, name: &'a Name) -> Vec<&'a Record> {
+        match self.current_index() {
+            Some(index) => index
+                .lookup(name)
+                .into_iter()
+                .map(|i| &self.records[i])
+                .filter(|record| record.name.contains(name))
+                .collect(),
+            _ => self
+                .records
+                .iter()
+                .filter(|record| record.name.contains(name))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use adns_proto::{Question, Type, TypeData};
+
+    use super::*;
+    use crate::ZoneAnswer;
+
+    #[test]
+    fn test_index() {
+        let mut zone = Zone::default();
+        for (name, ip) in [
+            ("a.example.com", "192.0.2.1"),
+            ("*.example.com", "192.0.2.2"),
+            ("A.example.com", "192.0.2.3"),
+            ("b.example.com", "192.0.2.4"),
+        ] {
+            zone.records.push(Record::new(
+                name.parse().unwrap(),
+                300,
+                TypeData::A(ip.parse().unwrap()),
+            ));
+        }
+        let answer = |zone: &Zone, name: &str| {
+            let mut answer = ZoneAnswer::default();
+            zone.answer(
+                None,
+                &Default::default(),
+                &Question::new(Type::A, name).unwrap(),
+                &mut answer,
+            );
+            answer
+                .answers
+                .iter()
+                .map(|x| x.data.to_string())
+                .collect::<Vec<_>>()
+        };
+        let unindexed = answer(&zone, "a.example.com");
+        zone.build_index();
+        assert_eq!(answer(&zone, "a.example.com"), unindexed);
+        assert_eq!(unindexed, vec!["192.0.2.1", "192.0.2.2", "192.0.2.3"]);
+        assert_eq!(answer(&zone, "c.example.com"), vec!["192.0.2.2"]);
+
+        // a stale index is ignored
+        zone.records.pop();
+        assert_eq!(answer(&zone, "b.example.com"), vec!["192.0.2.2"]);
+    }
+}
@@ -0,0 +1,69 @@
+use adns_proto::{Name, Type, TypeDataParseError};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// what loading a zone does with a record whose data doesn't parse
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum InvalidRecordPolicy {
+    /// drop the record and load the rest, so one typo can't take a whole zone offline.
+    /// the record silently goes unanswered though, so the skips should be logged
+    Skip,
+    /// reject the zone, keeping whatever was served before it
+    #[default]
+    Fail,
+}
+
+/// a record dropped under `InvalidRecordPolicy::Skip`
+#[derive(Error, Debug)]
+#[error("{name} {type_}: {error}")]
+pub struct InvalidRecord {
+    pub name: Name,
+    pub type_: Type,
+    pub error: TypeDataParseError,
+}
+
+impl InvalidRecordPolicy {
+    /// applies this policy to `record`: adds it to `skipped` if it skips, otherwise gives it back as the error to
+    /// fail the load with
+    pub fn handle(
+        self,
+        record: InvalidRecord,
+        skipped: &mut Vec<InvalidRecord>,
+    ) -> Result<(), InvalidRecord> {
+        match self {
+            InvalidRecordPolicy::Skip => {
+                skipped.push(record);
+                Ok(())
+            }
+            InvalidRecordPolicy::Fail => Err(record),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Zone;
+
+    #[test]
+    fn test_invalid_record_policy() {
+        let masterfile = "
+$TTL 300
+good A 192.0.2.1
+bad  A not-an-address
+";
+        let origin: Name = "example.com".parse().unwrap();
+        let (zone, skipped) = Zone::parse_masterfile_with(
+            masterfile,
+            origin.clone(),
+            InvalidRecordPolicy::Skip,
+            &mut |_| unreachable!(),
+        )
+        .unwrap();
+        assert_eq!(zone.records.len(), 1);
+        assert_eq!(skipped[0].type_, Type::A);
+        let zone = Zone::parse_masterfile(masterfile, origin.clone());
+        assert!(matches!(zone, Err(crate::MasterFileError::Data(4, _))));
+    }
+}
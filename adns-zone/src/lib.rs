@@ -1,12 +1,50 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, OnceLock},
+};
+
 use adns_proto::{Class, Name, Question, Record, SoaData, Type, TypeData, TypeDataParseError};
 use indexmap::{map::Entry, IndexMap};
-use log::warn;
+use ipnet::IpNet;
+use log::{debug, warn};
 use serde::{ser::SerializeSeq, Deserialize, Serialize};
 use serde_with::{serde_as, DeserializeAs, SerializeAs};
 
 mod updates;
 pub use updates::*;
 
+mod rpz;
+pub use rpz::*;
+
+mod dnssec;
+pub use dnssec::*;
+
+mod catalog;
+
+mod reverse;
+pub use reverse::*;
+
+mod validate;
+pub use validate::*;
+
+mod invalid;
+pub use invalid::*;
+
+mod query;
+pub use query::*;
+
+mod acme;
+pub use acme::*;
+
+mod masterfile;
+pub use masterfile::*;
+
+mod order;
+pub use order::*;
+
+mod index;
+pub use index::*;
+
 struct VecRecordConvert;
 
 impl SerializeAs<Vec<Record>> for VecRecordConvert {
@@ -28,10 +66,16 @@ impl<'de> DeserializeAs<'de, Vec<Record>> for VecRecordConvert {
         deserializer: D,
     ) -> Result<Vec<Record>, D::Error> {
         let from = Vec::<ZoneRecord>::deserialize(deserializer)?;
-        from.into_iter()
-            .map(|x| -> Result<Record, _> { x.try_into() })
-            .collect::<Result<Vec<Record>, TypeDataParseError>>()
-            .map_err(serde::de::Error::custom)
+        let mut records = Vec::with_capacity(from.len());
+        for record in from {
+            let (name, type_) = (record.domain.clone(), record.type_);
+            let record = record
+                .try_into()
+                .map_err(|error| InvalidRecord { name, type_, error })
+                .map_err(serde::de::Error::custom)?;
+            records.push(record);
+        }
+        Ok(records)
     }
 }
 
@@ -83,17 +127,252 @@ pub struct Zone {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub nameservers: Vec<Name>,
     #[serde(default)]
-    pub tsig_keys: IndexMap<String, TsigKey>,
+    pub tsig_keys: TsigKeys,
     #[serde(default = "serde_true", skip_serializing_if = "serde_is_true")]
     pub authoritative: bool,
     #[serde(skip)]
     pub class: Class,
+    /// classes this zone answers in. empty means only `class`, which is IN. subzones with none inherit their parent's
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub classes: Vec<Class>,
     #[serde(default)]
     pub allow_md5_tsig: bool,
+    /// overrides the SOA minimum as the TTL of the SOA in negative (NXDOMAIN/NODATA) answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub negative_ttl: Option<u32>,
+    /// if set, AXFR clients must also have a PTR record matching a pattern
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub axfr_ptr_check: Option<AxfrPtrCheck>,
+    /// if set on the root zone, the name servers of every delegation are asked for its SOA whenever the zone
+    /// is loaded, warning about lame delegations. needs network access at load time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delegation_check: Option<DelegationCheck>,
+    /// response policy records, consulted before the zone. see `PolicyAction`
+    #[serde_as(as = "VecRecordConvert")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rpz: Vec<Record>,
+    /// zone signing keys, used to sign answers as they're sent. several may be listed at once to roll over without
+    /// downtime, see `KeyState`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signing_keys: Vec<ZoneSigningKey>,
+    /// truncate UDP responses over 512 bytes unless the client presented a valid DNS Cookie, to limit amplification
+    #[serde(default)]
+    pub require_cookie_for_large_udp: bool,
+    /// answer UDP queries without a valid DNS Cookie whose response would be much larger than the query with only TC and our cookie
+    #[serde(default)]
+    pub cookie_challenge: bool,
+    /// if set, an RFC 9432 catalog zone of this name listing all zones is served
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub catalog: Option<Name>,
+    /// networks to serve generated PTR zones for, from this zone's A/AAAA records. see `Zone::generate_reverse`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reverse_zones: Vec<IpNet>,
+    /// the catalog this zone was provisioned from, see `Zone::apply_catalog`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub catalog_member: Option<Name>,
+    /// store internationalized names in punycode (A-label) form on load, see `Zone::to_ascii_names`
+    #[serde(default)]
+    pub normalize_idna: bool,
+    /// primaries allowed to send NOTIFY for this zone
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notify_from: Vec<IpAddr>,
+    /// secondaries sent a NOTIFY whenever this zone changes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notify_targets: Vec<SocketAddr>,
+    /// caps the TTL of glue and other additional section records, independently of the answer section. only on the
+    /// root zone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub additional_ttl_cap: Option<u32>,
+    /// how the SOA serial is bumped when an update changes this zone's records
+    #[serde(default, skip_serializing_if = "SerialPolicy::is_default")]
+    pub serial_policy: SerialPolicy,
+    /// what updates do with a SOA record that has the current serial but different data
+    #[serde(default, skip_serializing_if = "EqualSerialPolicy::is_default")]
+    pub equal_serial: EqualSerialPolicy,
+    /// order of multi-record RRsets in answers, for DNS round-robin
+    #[serde(default, skip_serializing_if = "RrsetOrder::is_default")]
+    pub rrset_order: RrsetOrder,
+    /// order of `nameservers` in apex NS answers and referrals. the default keeps them as listed
+    #[serde(default, skip_serializing_if = "RrsetOrder::is_default")]
+    pub nameserver_order: RrsetOrder,
+    /// `*.<name>` owners match like RFC 4592 wildcards: any number of labels, and only names that don't exist,
+    /// below the closest existing ancestor. `**`, `*+` and inner `*` patterns keep matching as before
+    #[serde(default)]
+    pub standard_wildcards: bool,
+    /// answered to `hostname.bind` and `id.server` CH TXT queries (RFC 4892), to tell instances of a server apart
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+    /// how `version.bind`, `hostname.bind` and `id.server` CH TXT queries are answered
+    #[serde(default, skip_serializing_if = "IdentityQueries::is_default")]
+    pub identity_queries: IdentityQueries,
+    /// TTL of the answers to those queries
+    #[serde(
+        default = "default_identity_ttl",
+        skip_serializing_if = "is_default_identity_ttl"
+    )]
+    pub identity_ttl: u32,
+    /// if set, recursive queries for names outside this zone and its subzones are forwarded upstream. only on servers
+    /// with `allow_query` set, others refuse them rather than act as an open resolver
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forwarding: Option<Forwarding>,
+    /// if set, A/AAAA queries that would be answered NXDOMAIN get these addresses instead, e.g. for a captive portal.
+    /// this lies to clients and can't be validated, so DNSSEC (DO) queries are never redirected. only on the root zone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nxdomain_redirect: Option<NxdomainRedirect>,
+    /// split-horizon: queries from a view's clients are answered from its zone instead, the first match winning.
+    /// only on the root zone
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub views: Vec<View>,
+    /// name lookup index over `records`, see `Zone::build_index`. cleared by updates
+    #[serde(skip)]
+    pub index: Option<Arc<RecordIndex>>,
+    /// RRSIGs made by `sign_records`, reused across answers
+    #[serde(skip)]
+    pub signatures: SignatureCache,
+    /// where `RrsetOrder::Cyclic` starts each RRset in the next answer
+    #[serde(skip)]
+    pub rotations: Rotations,
+}
+
+/// a zone served to some clients in place of the root zone. it's a complete zone, nothing is inherited
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct View {
+    /// source networks the view applies to
+    pub clients: Vec<IpNet>,
+    pub zone: Zone,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AxfrPtrCheck {
+    /// matched with `Name::contains`, i.e. `**.example.com`
+    pub pattern: Name,
+    /// resolver to query when the PTR record is not in the local zone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forwarder: Option<SocketAddr>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DelegationCheck {
+    /// resolver to query for name server addresses that are not in the local zone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forwarder: Option<SocketAddr>,
+    /// port the delegated name servers are asked on
+    #[serde(default = "default_delegation_check_port")]
+    pub port: u16,
+}
+
+impl Default for DelegationCheck {
+    fn default() -> Self {
+        DelegationCheck {
+            forwarder: None,
+            port: default_delegation_check_port(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NxdomainRedirect {
+    /// answered for the queried name, A or AAAA by the question's type
+    pub addresses: Vec<IpAddr>,
+    #[serde(default = "default_redirect_ttl")]
+    pub ttl: u32,
+}
+
+fn default_delegation_check_port() -> u16 {
+    53
+}
+
+/// TTL of answers to identity queries unless `Zone::identity_ttl` is set
+pub const DEFAULT_IDENTITY_TTL: u32 = 3600;
+
+fn default_identity_ttl() -> u32 {
+    DEFAULT_IDENTITY_TTL
+}
+
+fn is_default_identity_ttl(value: &u32) -> bool {
+    *value == DEFAULT_IDENTITY_TTL
+}
+
+fn default_redirect_ttl() -> u32 {
+    60
+}
+
+fn default_forward_cache_size() -> usize {
+    10000
+}
+
+fn default_forward_max_negative_ttl() -> u32 {
+    3600
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Forwarding {
+    /// resolvers tried in order until one answers
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub upstreams: Vec<SocketAddr>,
+    /// how many responses are cached until their TTLs expire
+    #[serde(default = "default_forward_cache_size")]
+    pub cache_size: usize,
+    /// cap in seconds on how long NXDOMAIN/NODATA responses are cached
+    #[serde(default = "default_forward_max_negative_ttl")]
+    pub max_negative_ttl: u32,
+    /// resolve iteratively from the root servers with QNAME minimization (RFC 9156) instead of asking `upstreams`
+    #[serde(default)]
+    pub qname_minimization: bool,
+}
+
+/// answers to CH TXT server identification queries (RFC 4892)
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IdentityQueries {
+    /// the adns version for `version.bind`, `server_id` for `hostname.bind` and `id.server`
+    #[default]
+    Answer,
+    /// the same text for all of them, hiding the version and instance
+    Text(String),
+    /// REFUSED, as if the server didn't support them
+    Refuse,
+}
+
+impl IdentityQueries {
+    fn is_default(&self) -> bool {
+        *self == IdentityQueries::default()
+    }
+}
+
+/// `*.<name>` with no other wildcard labels, the RFC 4592 form
+pub(crate) fn is_standard_wildcard(name: &Name) -> bool {
+    let mut segments = name.segments();
+    segments.next() == Some("*") && !segments.any(|x| matches!(x, "*" | "**" | "*+"))
+}
+
+/// which SOA `Zone::merge_from_with` keeps when both zones define one for the same name
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SoaPrecedence {
+    /// the one with the higher serial, the top zone's if they're equal
+    #[default]
+    HigherSerial,
+    /// always the top zone's
+    Top,
+    /// always the bottom zone's
+    Bottom,
+}
+
+impl SoaPrecedence {
+    fn top_wins(&self, bottom: &SoaData, top: &SoaData) -> bool {
+        match self {
+            SoaPrecedence::HigherSerial => top.serial >= bottom.serial,
+            SoaPrecedence::Top => true,
+            SoaPrecedence::Bottom => false,
+        }
+    }
+}
+
+/// the settings a subzone can have. root-only settings like `forwarding` or `views` are rejected rather than ignored
 #[serde_as]
 #[derive(Default, Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct SubZone {
     //todo: some kind of indexmap structure?
     #[serde_as(as = "VecRecordConvert")]
@@ -101,10 +380,35 @@ pub struct SubZone {
     pub records: Vec<Record>,
     #[serde(default = "serde_true", skip_serializing_if = "serde_is_true")]
     pub authoritative: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub classes: Vec<Class>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub soa: Option<SoaData>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub nameservers: Vec<Name>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub negative_ttl: Option<u32>,
+    #[serde(default, skip_serializing_if = "SerialPolicy::is_default")]
+    pub serial_policy: SerialPolicy,
+    /// what updates do with a SOA record that has the current serial but different data
+    #[serde(default, skip_serializing_if = "EqualSerialPolicy::is_default")]
+    pub equal_serial: EqualSerialPolicy,
+    /// order of multi-record RRsets in answers, for DNS round-robin
+    #[serde(default, skip_serializing_if = "RrsetOrder::is_default")]
+    pub rrset_order: RrsetOrder,
+    #[serde(default, skip_serializing_if = "RrsetOrder::is_default")]
+    pub nameserver_order: RrsetOrder,
+    #[serde(default)]
+    pub standard_wildcards: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reverse_zones: Vec<IpNet>,
+    /// this zone's own keys, otherwise it's signed with the root zone's
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signing_keys: Vec<ZoneSigningKey>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notify_from: Vec<IpAddr>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notify_targets: Vec<SocketAddr>,
 }
 
 impl From<SubZone> for Zone {
@@ -115,9 +419,38 @@ impl From<SubZone> for Zone {
             tsig_keys: Default::default(),
             authoritative: value.authoritative,
             class: Default::default(),
+            classes: value.classes,
             allow_md5_tsig: Default::default(),
             soa: value.soa,
             nameservers: value.nameservers,
+            negative_ttl: value.negative_ttl,
+            axfr_ptr_check: None,
+            delegation_check: None,
+            rpz: vec![],
+            signing_keys: value.signing_keys,
+            require_cookie_for_large_udp: false,
+            cookie_challenge: false,
+            catalog: None,
+            catalog_member: None,
+            normalize_idna: false,
+            notify_from: value.notify_from,
+            notify_targets: value.notify_targets,
+            additional_ttl_cap: None,
+            serial_policy: value.serial_policy,
+            equal_serial: value.equal_serial,
+            rrset_order: value.rrset_order,
+            nameserver_order: value.nameserver_order,
+            standard_wildcards: value.standard_wildcards,
+            server_id: None,
+            identity_queries: Default::default(),
+            identity_ttl: DEFAULT_IDENTITY_TTL,
+            forwarding: None,
+            reverse_zones: value.reverse_zones,
+            nxdomain_redirect: None,
+            views: vec![],
+            index: None,
+            signatures: Default::default(),
+            rotations: Default::default(),
         }
     }
 }
@@ -127,8 +460,19 @@ impl From<Zone> for SubZone {
         SubZone {
             records: value.records,
             authoritative: value.authoritative,
+            classes: value.classes,
             soa: value.soa,
             nameservers: value.nameservers,
+            negative_ttl: value.negative_ttl,
+            serial_policy: value.serial_policy,
+            equal_serial: value.equal_serial,
+            rrset_order: value.rrset_order,
+            nameserver_order: value.nameserver_order,
+            standard_wildcards: value.standard_wildcards,
+            reverse_zones: value.reverse_zones,
+            signing_keys: value.signing_keys,
+            notify_from: value.notify_from,
+            notify_targets: value.notify_targets,
         }
     }
 }
@@ -137,30 +481,43 @@ impl From<Zone> for SubZone {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct TsigKey(#[serde_as(as = "serde_with::base64::Base64")] pub Vec<u8>);
 
+/// TSIG keys by lowercased key name
+pub type TsigKeys = IndexMap<String, TsigKey>;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum AnswerState {
     None,
     DomainSeen,
+    /// the name is at or below a non-authoritative subzone, a referral was generated
+    Delegated,
 }
 
 #[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct ZoneAnswer {
     pub is_authoritative: bool,
     pub answers: Vec<Record>,
+    /// NS records for the authority section when the name is delegated
+    pub referral: Vec<Record>,
 }
 
 impl Zone {
     pub fn merge_from(&mut self, other: Zone) {
-        for record in other.records {
-            ZoneUpdateAction::AddRecord(record).apply_to(&Name::default(), self);
-        }
+        self.merge_from_with(other, SoaPrecedence::default());
+    }
+
+    /// merges `other` (the top) over this zone (the bottom), with `soa_precedence` picking the SOA where both have one
+    pub fn merge_from_with(&mut self, other: Zone, soa_precedence: SoaPrecedence) {
+        self.rpz.extend(other.rpz);
+        self.merge_records(&Name::default(), other.records, other.soa, soa_precedence);
         for (zone_name, new_zone) in other.zones {
             match self.zones.entry(zone_name.clone()) {
                 Entry::Occupied(mut current_zone) => {
-                    let current_zone = current_zone.get_mut();
-                    for record in new_zone.records {
-                        ZoneUpdateAction::AddRecord(record).apply_to(&zone_name, current_zone);
-                    }
+                    current_zone.get_mut().merge_records(
+                        &zone_name,
+                        new_zone.records,
+                        new_zone.soa,
+                        soa_precedence,
+                    );
                 }
                 Entry::Vacant(v) => {
                     v.insert(new_zone);
@@ -169,54 +526,320 @@ impl Zone {
         }
     }
 
+    fn merge_records(
+        &mut self,
+        zone_name: &Name,
+        records: Vec<Record>,
+        soa: Option<SoaData>,
+        soa_precedence: SoaPrecedence,
+    ) {
+        if let Some(top) = soa {
+            if self
+                .soa
+                .as_ref()
+                .is_none_or(|bottom| soa_precedence.top_wins(bottom, &top))
+            {
+                self.soa = Some(top);
+            }
+        }
+        for record in records {
+            // SOAs bypass the update serial guard, so the outcome doesn't depend on which zone is applied first
+            let TypeData::SOA(top) = &record.data else {
+                ZoneUpdateAction::AddRecord(record).apply_to(zone_name, self);
+                continue;
+            };
+            match self
+                .records
+                .iter_mut()
+                .find(|x| x.name == record.name && x.type_ == Type::SOA)
+            {
+                Some(current) => {
+                    if let TypeData::SOA(bottom) = &current.data {
+                        if soa_precedence.top_wins(bottom, top) {
+                            *current = record;
+                        }
+                    }
+                }
+                None => self.records.push(record),
+            }
+        }
+    }
+
+    /// rewrites owner names, zone names and name targets to punycode A-labels so they match queries on the wire.
+    /// names that aren't valid IDNs are left as-is.
+    pub fn to_ascii_names(&mut self) {
+        fn convert(name: &mut Name) {
+            match name.to_ascii() {
+                Ok(ascii) => *name = ascii,
+                Err(e) => warn!("leaving name {name} as-is: {e}"),
+            }
+        }
+        for record in self.records.iter_mut().chain(self.rpz.iter_mut()) {
+            convert(&mut record.name);
+            match &mut record.data {
+                TypeData::NS(target)
+                | TypeData::CNAME(target)
+                | TypeData::PTR(target)
+                | TypeData::DNAME(target)
+                | TypeData::ALIAS(target)
+                | TypeData::MX {
+                    exchange: target, ..
+                }
+                | TypeData::SRV { target, .. } => convert(target),
+                _ => (),
+            }
+        }
+        self.nameservers.iter_mut().for_each(convert);
+        self.zones = std::mem::take(&mut self.zones)
+            .into_iter()
+            .map(|(mut name, mut zone)| {
+                convert(&mut name);
+                zone.to_ascii_names();
+                (name, zone)
+            })
+            .collect();
+    }
+
+    /// the SOA record for the most specific zone with an SOA containing `name`, for the authority section of negative
+    /// answers. subzones without an SOA are skipped, so the owner and data always come from the same zone.
+    /// `negative_ttl` is inherited from the root zone, the TTL defaults to the SOA minimum.
+    pub fn negative_soa(&self, name: &Name) -> Option<Record> {
+        // at a delegation point, only DS queries are answered negatively, by the parent
+        let subzone = self
+            .zones
+            .iter()
+            .filter(|(zone_name, zone)| {
+                zone.soa.is_some() && holds(zone_name, zone, name, Type::DS)
+            })
+            .max_by_key(|(zone_name, _)| zone_name.len());
+        let zone_name = subzone
+            .map(|(zone_name, _)| zone_name.clone())
+            .unwrap_or_default();
+        let soa = match subzone {
+            Some((_, zone)) => zone.soa.clone()?,
+            None => self.soa.clone()?,
+        };
+        let ttl = subzone
+            .and_then(|(_, zone)| zone.negative_ttl)
+            .or(self.negative_ttl)
+            .unwrap_or(soa.minimum);
+        Some(Record::new(zone_name, ttl, TypeData::SOA(soa)))
+    }
+
+    /// the deepest subzone holding the `type_` RRset at `name`. DS and NSEC records at a delegation point are the
+    /// parent's, so the delegation doesn't hold them
+    pub(crate) fn subzone_holding(&self, name: &Name, type_: Type) -> Option<(&Name, &Zone)> {
+        self.zones
+            .iter()
+            .filter(|(zone_name, zone)| holds(zone_name, zone, name, type_))
+            .max_by_key(|(zone_name, _)| zone_name.len())
+    }
+
     pub fn answer(
         &self,
         parent_zone: Option<&Zone>,
         zone_name: &Name,
         question: &Question,
         response: &mut ZoneAnswer,
+    ) -> AnswerState {
+        if (parent_zone.is_none() || !self.classes.is_empty())
+            && !self.answers_class(question.class)
+        {
+            return AnswerState::None;
+        }
+        let answers_start = response.answers.len();
+        let referral_start = response.referral.len();
+        let state = self.answer_records(parent_zone, zone_name, question, response);
+        // the data is shared between classes, so it is answered in the class asked for
+        if question.class != Class::ALL {
+            for record in response.answers[answers_start..]
+                .iter_mut()
+                .chain(&mut response.referral[referral_start..])
+            {
+                record.class = question.class;
+            }
+        }
+        state
+    }
+
+    /// apex names of the authoritative subzones at any depth, in configuration order
+    pub fn authoritative_zones(&self) -> Vec<Name> {
+        let mut out = vec![];
+        for (name, zone) in &self.zones {
+            if zone.authoritative {
+                out.push(name.clone());
+            }
+            out.extend(zone.authoritative_zones());
+        }
+        out
+    }
+
+    /// a non-authoritative subzone with its own nameservers, answered with a referral to them
+    pub fn is_delegation(&self) -> bool {
+        !self.authoritative && !self.nameservers.is_empty()
+    }
+
+    /// whether `name` is outside all of this zone's data and should be forwarded, see `forwarding`
+    pub fn forwards(&self, name: &Name) -> bool {
+        self.forwarding.is_some()
+            && !self.zones.keys().any(|zone_name| name.ends_with(zone_name))
+            && self.records_named(name).is_empty()
+    }
+
+    /// the zone answering queries from `client`: the first view whose networks contain it, or this zone
+    pub fn view_for(&self, client: IpAddr) -> &Zone {
+        self.views
+            .iter()
+            .find(|view| view.clients.iter().any(|net| net.contains(&client)))
+            .map_or(self, |view| &view.zone)
+    }
+
+    /// records owned by exactly `name`, optionally of one type, from this zone and the subzones containing it.
+    /// unlike `answer`, wildcards aren't expanded and nothing is synthesized.
+    pub fn lookup<'a>(
+        &'a self,
+        name: &'a Name,
+        type_: Option<Type>,
+    ) -> impl Iterator<Item = &'a Record> + 'a {
+        let mut out = vec![];
+        self.lookup_into(name, type_, &mut out);
+        out.into_iter()
+    }
+
+    fn lookup_into<'a>(&'a self, name: &'a Name, type_: Option<Type>, out: &mut Vec<&'a Record>) {
+        out.extend(
+            self.records_named(name)
+                .into_iter()
+                .filter(|record| record.name == *name && type_.is_none_or(|x| record.type_ == x)),
+        );
+        for (zone_name, zone) in &self.zones {
+            if name.ends_with(zone_name) {
+                zone.lookup_into(name, type_, out);
+            }
+        }
+    }
+
+    /// every record in this zone and its subzones, with the name of the zone holding it (the root for this zone's own)
+    pub fn all_records(&self) -> impl Iterator<Item = (&Name, &Record)> {
+        static ROOT: OnceLock<Name> = OnceLock::new();
+        self.records_in(ROOT.get_or_init(Name::default))
+    }
+
+    fn records_in<'a>(
+        &'a self,
+        zone_name: &'a Name,
+    ) -> Box<dyn Iterator<Item = (&'a Name, &'a Record)> + 'a> {
+        Box::new(
+            self.records
+                .iter()
+                .map(move |record| (zone_name, record))
+                .chain(
+                    self.zones
+                        .iter()
+                        .flat_map(|(name, zone)| zone.records_in(name)),
+                ),
+        )
+    }
+
+    /// records answering for `name`, see `standard_wildcards`
+    fn matching_records<'a>(&'a self, zone_name: &Name, name: &'a Name) -> Vec<&'a Record> {
+        let records = self.records_named(name);
+        if !self.standard_wildcards {
+            return records;
+        }
+        let mut out: Vec<&Record> = records
+            .into_iter()
+            .filter(|record| !is_standard_wildcard(&record.name) || record.name == *name)
+            .collect();
+        if !out.is_empty() || self.name_exists(name) || !name.ends_with(zone_name) {
+            return out;
+        }
+        // the closest encloser is the nearest ancestor that exists, at the latest the zone apex (RFC 4592 section 3.3.1)
+        let segments: Vec<&str> = name.segments().collect();
+        for i in 1..=segments.len() {
+            let Ok(ancestor) = Name::from_segments(&segments[i..]) else {
+                return out;
+            };
+            if !self.name_exists(&ancestor) && ancestor != *zone_name {
+                continue;
+            }
+            let Ok(source) = Name::from_segments(std::iter::once("*").chain(ancestor.segments()))
+            else {
+                return out;
+            };
+            out.extend(self.records.iter().filter(|record| record.name == source));
+            break;
+        }
+        out
+    }
+
+    /// whether questions of `class` are answered from this zone, see `classes`
+    pub fn answers_class(&self, class: Class) -> bool {
+        if class == Class::ALL {
+            return true;
+        }
+        if self.classes.is_empty() {
+            return class == self.class;
+        }
+        self.classes.contains(&class)
+    }
+
+    fn answer_records(
+        &self,
+        parent_zone: Option<&Zone>,
+        zone_name: &Name,
+        question: &Question,
+        response: &mut ZoneAnswer,
     ) -> AnswerState {
         response.is_authoritative = self.authoritative;
+        let mut state = AnswerState::None;
         if &question.name == zone_name {
-            match question.type_ {
-                Type::SOA => {
-                    if let Some(soa) = self
-                        .soa
-                        .clone()
-                        .or_else(|| parent_zone.and_then(|x| x.soa.clone()))
-                    {
-                        response.answers.push(Record::new(
-                            zone_name.clone(),
-                            60,
-                            TypeData::SOA(soa),
-                        ));
-                    } else {
-                        warn!("no SOA specified for zone {}", zone_name);
-                    }
-                    return AnswerState::DomainSeen;
+            if question.type_.wants_by_query(Type::SOA) {
+                if let Some(soa) = self
+                    .soa
+                    .clone()
+                    .or_else(|| parent_zone.and_then(|x| x.soa.clone()))
+                {
+                    response
+                        .answers
+                        .push(Record::new(zone_name.clone(), 60, TypeData::SOA(soa)));
+                } else {
+                    // reported on load by `Zone::validate`
+                    debug!("no SOA specified for zone {}", zone_name);
                 }
-                Type::NS => {
-                    #[allow(clippy::unnecessary_unwrap)]
-                    let nameservers = if self.nameservers.is_empty() && parent_zone.is_some() {
-                        &parent_zone.unwrap().nameservers
-                    } else {
-                        &self.nameservers
-                    };
-                    for nameserver in nameservers {
-                        response.answers.push(Record::new(
-                            zone_name.clone(),
-                            3600,
-                            TypeData::NS(nameserver.clone()),
-                        ));
-                    }
-                    return AnswerState::DomainSeen;
+                state = AnswerState::DomainSeen;
+            }
+            if question.type_.wants_by_query(Type::NS) {
+                #[allow(clippy::unnecessary_unwrap)]
+                let nameservers = if self.nameservers.is_empty() && parent_zone.is_some() {
+                    &parent_zone.unwrap().nameservers
+                } else {
+                    &self.nameservers
+                };
+                let start = response.answers.len();
+                for nameserver in nameservers {
+                    response.answers.push(Record::new(
+                        zone_name.clone(),
+                        3600,
+                        TypeData::NS(nameserver.clone()),
+                    ));
                 }
-                _ => (),
+                self.nameserver_order
+                    .apply(&self.rotations, &mut response.answers[start..]);
+                state = AnswerState::DomainSeen;
+            }
+            if matches!(question.type_, Type::SOA | Type::NS) {
+                return state;
             }
         }
-        let mut state = AnswerState::None;
-        for record in &self.records {
-            if !record.name.contains(&question.name) {
+        // wildcards don't apply at or below a delegation (RFC 4592 section 2.2.2), only glue is answered from here
+        let delegated = self
+            .zones
+            .iter()
+            .any(|(name, zone)| zone.is_delegation() && question.name.ends_with(name));
+        let start = response.answers.len();
+        for record in self.matching_records(zone_name, &question.name) {
+            if delegated && record.name != question.name {
                 continue;
             }
             state = AnswerState::DomainSeen;
@@ -231,10 +854,33 @@ impl Zone {
                 data: record.data.clone(),
             });
         }
+        self.rrset_order
+            .apply(&self.rotations, &mut response.answers[start..]);
         for (name, zone) in &self.zones {
             if !question.name.ends_with(name) {
                 continue;
             }
+            // refer to the child instead of answering from it
+            if zone.is_delegation() {
+                // except for the DS RRset, which the parent side serves (RFC 4035 section 3.1.4.1)
+                if question.type_ == Type::DS && question.name == *name {
+                    state = state.max(AnswerState::DomainSeen);
+                    continue;
+                }
+                response.is_authoritative = false;
+                let start = response.referral.len();
+                for nameserver in &zone.nameservers {
+                    response.referral.push(Record::new(
+                        name.clone(),
+                        3600,
+                        TypeData::NS(nameserver.clone()),
+                    ));
+                }
+                zone.nameserver_order
+                    .apply(&zone.rotations, &mut response.referral[start..]);
+                state = state.max(AnswerState::Delegated);
+                continue;
+            }
             let substate = zone.answer(Some(self), name, question, response);
             if substate > state {
                 state = substate;
@@ -293,3 +939,441 @@ impl From<Record> for ZoneRecord {
         }
     }
 }
+
+/// whether the subzone `zone` at `zone_name` holds the `type_` RRset at `name`
+fn holds(zone_name: &Name, zone: &Zone, name: &Name, type_: Type) -> bool {
+    name.ends_with(zone_name)
+        && !(zone_name == name && zone.is_delegation() && matches!(type_, Type::DS | Type::NSEC))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_zone(authoritative: bool) -> Zone {
+        let mut sub = Zone {
+            authoritative,
+            nameservers: vec!["ns1.sub.example.com".parse().unwrap()],
+            ..Default::default()
+        };
+        sub.records.push(Record::new(
+            "sub.example.com".parse().unwrap(),
+            300,
+            TypeData::A("10.0.0.1".parse().unwrap()),
+        ));
+        let mut zone = Zone {
+            authoritative: true,
+            ..Default::default()
+        };
+        zone.zones.insert("sub.example.com".parse().unwrap(), sub);
+        zone
+    }
+
+    #[test]
+    fn test_subzone_notify() {
+        let zone: Zone = serde_yaml::from_str(
+            r#"
+zones:
+  example-3.com:
+    notify_from: [192.0.2.1]
+    notify_targets: ["192.0.2.2:53"]
+"#,
+        )
+        .unwrap();
+        let subzone = &zone.zones[&"example-3.com".parse::<Name>().unwrap()];
+        assert!(!subzone.notify_from.is_empty());
+        assert_eq!(
+            subzone.notify_from,
+            vec!["192.0.2.1".parse::<IpAddr>().unwrap()]
+        );
+        assert_eq!(
+            subzone.notify_targets,
+            vec!["192.0.2.2:53".parse::<SocketAddr>().unwrap()]
+        );
+        let round_trip: Zone =
+            serde_yaml::from_str(&serde_yaml::to_string(&zone).unwrap()).unwrap();
+        assert_eq!(
+            round_trip.zones[&"example-3.com".parse::<Name>().unwrap()].notify_targets,
+            subzone.notify_targets
+        );
+    }
+
+    #[test]
+    fn test_subzone_root_only_settings() {
+        for setting in [
+            "additional_ttl_cap: 60",
+            "forwarding: { upstreams: [\"192.0.2.53:53\"] }",
+            "views: []",
+        ] {
+            let yaml = format!("zones:\n  example.com:\n    {setting}\n");
+            let error = serde_yaml::from_str::<Zone>(&yaml).unwrap_err();
+            assert!(
+                error.to_string().contains("unknown field"),
+                "{setting}: {error}"
+            );
+        }
+        // the same settings are accepted on the root zone
+        assert!(serde_yaml::from_str::<Zone>("additional_ttl_cap: 60").is_ok());
+    }
+
+    #[test]
+    fn test_lookup() {
+        let mut zone = test_zone(true);
+        zone.records.push(Record::new(
+            "*.example.com".parse().unwrap(),
+            300,
+            TypeData::A("10.0.0.2".parse().unwrap()),
+        ));
+        zone.build_index();
+        let name: Name = "sub.example.com".parse().unwrap();
+        let found: Vec<&Record> = zone.lookup(&name, None).collect();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].data, TypeData::A("10.0.0.1".parse().unwrap()));
+        assert_eq!(zone.lookup(&name, Some(Type::AAAA)).count(), 0);
+
+        let all: Vec<(&Name, &Record)> = zone.all_records().collect();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].0, &Name::default());
+        assert_eq!(all[0].1.name, "*.example.com");
+        assert_eq!(all[1].0, &name);
+    }
+
+    #[test]
+    fn test_delegation_referral() {
+        let zone = test_zone(false);
+        let question = Question::new(Type::A, "sub.example.com").unwrap();
+        let mut answer = ZoneAnswer::default();
+        let state = zone.answer(None, &Name::default(), &question, &mut answer);
+        assert_eq!(state, AnswerState::Delegated);
+        assert!(!answer.is_authoritative);
+        assert!(answer.answers.is_empty());
+        assert_eq!(answer.referral.len(), 1);
+        assert_eq!(answer.referral[0].name, "sub.example.com");
+        assert_eq!(
+            answer.referral[0].data,
+            TypeData::NS("ns1.sub.example.com".parse().unwrap())
+        );
+
+        let question = Question::new(Type::A, "www.sub.example.com").unwrap();
+        let mut answer = ZoneAnswer::default();
+        let state = zone.answer(None, &Name::default(), &question, &mut answer);
+        assert_eq!(state, AnswerState::Delegated);
+        assert_eq!(answer.referral.len(), 1);
+    }
+
+    #[test]
+    fn test_wildcard_below_delegation() {
+        for standard_wildcards in [false, true] {
+            let mut zone = test_zone(false);
+            zone.standard_wildcards = standard_wildcards;
+            for (name, ip) in [
+                ("*.example.com", "10.0.0.2"),
+                ("**.example.com", "10.0.0.3"),
+                ("ns1.sub.example.com", "10.0.0.4"),
+            ] {
+                zone.records.push(Record::new(
+                    name.parse().unwrap(),
+                    300,
+                    TypeData::A(ip.parse().unwrap()),
+                ));
+            }
+            let answer = |name: &str| {
+                let question = Question::new(Type::A, name).unwrap();
+                let mut answer = ZoneAnswer::default();
+                let state = zone.answer(None, &Name::default(), &question, &mut answer);
+                (state, answer)
+            };
+
+            let (state, below) = answer("x.sub.example.com");
+            assert_eq!(state, AnswerState::Delegated);
+            assert!(below.answers.is_empty());
+            assert_eq!(below.referral.len(), 1);
+            // glue is still there for the referral
+            let (_, glue) = answer("ns1.sub.example.com");
+            assert_eq!(glue.answers.len(), 1);
+            assert_eq!(
+                glue.answers[0].data,
+                TypeData::A("10.0.0.4".parse().unwrap())
+            );
+            // outside the delegation, the wildcards apply as before
+            let (state, outside) = answer("x.example.com");
+            assert_eq!(state, AnswerState::DomainSeen);
+            assert!(!outside.answers.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_authoritative_subzone() {
+        let zone = test_zone(true);
+        let question = Question::new(Type::A, "sub.example.com").unwrap();
+        let mut answer = ZoneAnswer::default();
+        let state = zone.answer(None, &Name::default(), &question, &mut answer);
+        assert_eq!(state, AnswerState::DomainSeen);
+        assert!(answer.is_authoritative);
+        assert!(answer.referral.is_empty());
+        assert_eq!(answer.answers.len(), 1);
+        assert_eq!(
+            answer.answers[0].data,
+            TypeData::A("10.0.0.1".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_to_ascii_names() {
+        let mut zone = Zone::default();
+        let mut sub = Zone::default();
+        sub.records.push(Record::new(
+            "www.münchen.example".parse().unwrap(),
+            300,
+            TypeData::CNAME("münchen.example".parse().unwrap()),
+        ));
+        zone.zones.insert("münchen.example".parse().unwrap(), sub);
+        zone.to_ascii_names();
+
+        let (name, sub) = zone.zones.first().unwrap();
+        assert_eq!(name, "xn--mnchen-3ya.example");
+        assert_eq!(sub.records[0].name, "www.xn--mnchen-3ya.example");
+        assert_eq!(
+            sub.records[0].data,
+            TypeData::CNAME("xn--mnchen-3ya.example".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_negative_ttl() {
+        let mut zone = test_zone(true);
+        zone.soa = Some(SoaData {
+            mname: "ns1.example.com".parse().unwrap(),
+            rname: "admin.example.com".parse().unwrap(),
+            serial: 1,
+            refresh: 3600,
+            retry: 10,
+            expire: 3600,
+            minimum: 600,
+        });
+        let name: Name = "missing.sub.example.com".parse().unwrap();
+
+        // the subzone has no SOA of its own, so the root's is used with the root as owner
+        let soa = zone.negative_soa(&name).unwrap();
+        assert_eq!(soa.name, Name::default());
+        assert_eq!(soa.ttl, 600);
+
+        zone.negative_ttl = Some(30);
+        assert_eq!(zone.negative_soa(&name).unwrap().ttl, 30);
+
+        let mut sub_soa = zone.soa.clone().unwrap();
+        sub_soa.serial = 2;
+        zone.zones[0].soa = Some(sub_soa.clone());
+        let soa = zone.negative_soa(&name).unwrap();
+        assert_eq!(soa.name, "sub.example.com");
+        assert_eq!(soa.data, TypeData::SOA(sub_soa));
+        assert_eq!(soa.ttl, 30);
+
+        zone.zones[0].negative_ttl = Some(5);
+        assert_eq!(zone.negative_soa(&name).unwrap().ttl, 5);
+    }
+
+    #[test]
+    fn test_standard_wildcards() {
+        let mut zone = Zone::default();
+        for (name, ip) in [
+            ("*.example.com", "192.0.2.1"),
+            ("host.example.com", "192.0.2.2"),
+        ] {
+            zone.records.push(Record::new(
+                name.parse().unwrap(),
+                300,
+                TypeData::A(ip.parse().unwrap()),
+            ));
+        }
+        let answer = |zone: &Zone, name: &str, type_| {
+            let mut response = ZoneAnswer::default();
+            let question = Question::new(type_, name).unwrap();
+            let state = zone.answer(None, &Name::default(), &question, &mut response);
+            let answers = response
+                .answers
+                .iter()
+                .map(|x| (x.name.to_string(), x.data.to_string()))
+                .collect::<Vec<_>>();
+            (state, answers)
+        };
+        let synthesized = |name: &str| {
+            (
+                AnswerState::DomainSeen,
+                vec![(name.to_string(), "192.0.2.1".to_string())],
+            )
+        };
+
+        // the custom scheme: one label, and alongside existing names
+        assert_eq!(
+            answer(&zone, "a.example.com", Type::A),
+            synthesized("a.example.com")
+        );
+        assert_eq!(answer(&zone, "host.example.com", Type::A).1.len(), 2);
+        assert_eq!(
+            answer(&zone, "b.a.example.com", Type::A).0,
+            AnswerState::None
+        );
+
+        zone.standard_wildcards = true;
+        assert_eq!(
+            answer(&zone, "a.example.com", Type::A),
+            synthesized("a.example.com")
+        );
+        assert_eq!(
+            answer(&zone, "b.a.example.com", Type::A),
+            synthesized("b.a.example.com")
+        );
+        assert_eq!(
+            answer(&zone, "host.example.com", Type::A).1,
+            vec![("host.example.com".to_string(), "192.0.2.2".to_string())]
+        );
+        // an existing name isn't synthesized for other types, nor are names below it
+        assert_eq!(
+            answer(&zone, "host.example.com", Type::TXT),
+            (AnswerState::DomainSeen, vec![])
+        );
+        assert_eq!(
+            answer(&zone, "a.host.example.com", Type::A).0,
+            AnswerState::None
+        );
+
+        // the index knows the same names, including empty non-terminals
+        zone.records.push(Record::new(
+            "deep.empty.example.com".parse().unwrap(),
+            300,
+            TypeData::A("192.0.2.3".parse().unwrap()),
+        ));
+        zone.build_index();
+        assert_eq!(
+            answer(&zone, "b.a.example.com", Type::A),
+            synthesized("b.a.example.com")
+        );
+        // an empty non-terminal is no wildcard match, and is the closest encloser of names below it
+        assert_eq!(answer(&zone, "empty.example.com", Type::A).1, vec![]);
+        assert_eq!(answer(&zone, "a.empty.example.com", Type::A).1, vec![]);
+        assert_eq!(
+            answer(&zone, "a.host.example.com", Type::A).0,
+            AnswerState::None
+        );
+    }
+
+    #[test]
+    fn test_merge_soa() {
+        let soa = |serial| SoaData {
+            mname: "ns1.example.com".parse().unwrap(),
+            rname: "admin.example.com".parse().unwrap(),
+            serial,
+            refresh: 3600,
+            retry: 10,
+            expire: 3600,
+            minimum: 600,
+        };
+        let zone = |serial| {
+            let mut zone = test_zone(true);
+            zone.soa = Some(soa(serial));
+            zone.zones[0].records.push(Record::new(
+                "sub.example.com".parse().unwrap(),
+                300,
+                TypeData::SOA(soa(serial)),
+            ));
+            zone
+        };
+        let merged_serials = |bottom: u32, top: u32, precedence| {
+            let mut merged = zone(bottom);
+            merged.merge_from_with(zone(top), precedence);
+            let records = merged.zones[0]
+                .lookup(&"sub.example.com".parse().unwrap(), Some(Type::SOA))
+                .map(|x| match &x.data {
+                    TypeData::SOA(soa) => soa.serial,
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>();
+            (merged.soa.unwrap().serial, records)
+        };
+
+        for (bottom, top) in [(5, 3), (3, 5)] {
+            assert_eq!(
+                merged_serials(bottom, top, SoaPrecedence::HigherSerial),
+                (5, vec![5])
+            );
+            assert_eq!(
+                merged_serials(bottom, top, SoaPrecedence::Top),
+                (top, vec![top])
+            );
+            assert_eq!(
+                merged_serials(bottom, top, SoaPrecedence::Bottom),
+                (bottom, vec![bottom])
+            );
+        }
+    }
+
+    #[test]
+    fn test_any_query() {
+        let mut zone = test_zone(true);
+        zone.zones[0].records.push(Record::new(
+            "sub.example.com".parse().unwrap(),
+            300,
+            TypeData::TXT(vec!["test".to_string()].into()),
+        ));
+        zone.zones[0].records.push(Record::new(
+            "alias.sub.example.com".parse().unwrap(),
+            300,
+            TypeData::CNAME("sub.example.com".parse().unwrap()),
+        ));
+
+        let question = Question::new(Type::ALL, "sub.example.com").unwrap();
+        let mut answer = ZoneAnswer::default();
+        let state = zone.answer(None, &Name::default(), &question, &mut answer);
+        assert_eq!(state, AnswerState::DomainSeen);
+        let types = answer.answers.iter().map(|x| x.type_).collect::<Vec<_>>();
+        assert_eq!(types, vec![Type::NS, Type::A, Type::TXT]);
+
+        let question = Question::new(Type::ALL, "alias.sub.example.com").unwrap();
+        let mut answer = ZoneAnswer::default();
+        let state = zone.answer(None, &Name::default(), &question, &mut answer);
+        assert_eq!(state, AnswerState::DomainSeen);
+        assert_eq!(answer.answers.len(), 1);
+        assert_eq!(answer.answers[0].type_, Type::CNAME);
+    }
+
+    #[test]
+    fn test_classes() {
+        let mut zone = test_zone(true);
+        let mut question = Question::new(Type::A, "sub.example.com").unwrap();
+        question.class = Class::CH;
+        let mut answer = ZoneAnswer::default();
+        let state = zone.answer(None, &Name::default(), &question, &mut answer);
+        assert_eq!(state, AnswerState::None);
+        assert!(answer.answers.is_empty());
+
+        zone.classes = vec![Class::IN, Class::CH];
+        let state = zone.answer(None, &Name::default(), &question, &mut answer);
+        assert_eq!(state, AnswerState::DomainSeen);
+        assert_eq!(answer.answers.len(), 1);
+        assert_eq!(answer.answers[0].class, Class::CH);
+    }
+
+    #[test]
+    fn test_uri_round_trip() {
+        let zone: Zone = serde_yaml::from_str(
+            r#"
+records:
+- domain: _http._tcp.example.com
+  type: URI
+  data: 10 1 "https://example.com/"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            zone.records[0].data,
+            TypeData::URI {
+                priority: 10,
+                weight: 1,
+                target: "https://example.com/".to_string(),
+            }
+        );
+        let reloaded: Zone = serde_yaml::from_str(&serde_yaml::to_string(&zone).unwrap()).unwrap();
+        assert_eq!(reloaded.records[0].data, zone.records[0].data);
+    }
+}
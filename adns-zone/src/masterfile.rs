@@ -0,0 +1,580 @@
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use adns_proto::{Class, Name, NameParseError, Record, Type, TypeData, TypeDataParseError};
+use thiserror::Error;
+
+use crate::{InvalidRecord, InvalidRecordPolicy, Zone};
+
+#[derive(Error, Debug)]
+pub enum MasterFileError {
+    #[error("line {0}: unbalanced parentheses")]
+    UnbalancedParentheses(usize),
+    #[error("line {0}: unterminated quoted string")]
+    UnterminatedQuote(usize),
+    #[error("line {0}: unknown directive {1}")]
+    UnknownDirective(usize, String),
+    #[error("line {0}: missing argument")]
+    MissingArgument(usize),
+    #[error("line {0}: no owner name and no previous record")]
+    MissingOwner(usize),
+    #[error("line {0}: no TTL given and no $TTL or previous record")]
+    MissingTtl(usize),
+    #[error("line {0}: invalid TTL '{1}'")]
+    InvalidTtl(usize, String),
+    #[error("line {0}: unknown record type '{1}'")]
+    UnknownType(usize, String),
+    #[error("line {0}: failed to parse name: {1}")]
+    Name(usize, NameParseError),
+    #[error("line {0}: failed to parse record data: {1}")]
+    Data(usize, TypeDataParseError),
+    #[error("line {0}: $INCLUDE nested too deeply")]
+    IncludeDepth(usize),
+    #[error("{0}: {1}")]
+    Included(String, Box<MasterFileError>),
+    #[error("failed to read {0}: {1}")]
+    Io(String, std::io::Error),
+}
+
+/// one logical entry, possibly spanning several lines inside parentheses
+struct Entry {
+    line: usize,
+    /// entries starting with whitespace reuse the previous owner name
+    inherit_owner: bool,
+    /// tokens are kept as written (quotes and escapes intact) so RDATA can be handed to `TypeData::parse_str`
+    tokens: Vec<String>,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Entry>, MasterFileError> {
+    let mut entries = vec![];
+    let mut line = 1;
+    let mut depth = 0usize;
+    let mut quoted = false;
+    let mut current = Entry {
+        line,
+        inherit_owner: false,
+        tokens: vec![],
+    };
+    let mut token = String::new();
+    let mut chars = input.chars().peekable();
+    let mut line_start = true;
+    while let Some(c) = chars.next() {
+        if line_start && depth == 0 {
+            current.inherit_owner = c == ' ' || c == '\t';
+            current.line = line;
+        }
+        line_start = false;
+        match c {
+            '\\' => {
+                token.push(c);
+                if let Some(escaped) = chars.next() {
+                    token.push(escaped);
+                }
+            }
+            '"' => {
+                token.push(c);
+                quoted = !quoted;
+            }
+            _ if quoted => {
+                if c == '\n' {
+                    return Err(MasterFileError::UnterminatedQuote(line));
+                }
+                token.push(c);
+            }
+            ';' => while chars.next_if(|c| *c != '\n').is_some() {},
+            '(' | ')' | ' ' | '\t' | '\r' | '\n' => {
+                if !token.is_empty() {
+                    current.tokens.push(std::mem::take(&mut token));
+                }
+                match c {
+                    '(' => depth += 1,
+                    ')' => {
+                        depth = depth
+                            .checked_sub(1)
+                            .ok_or(MasterFileError::UnbalancedParentheses(line))?;
+                    }
+                    '\n' => {
+                        if depth == 0 && !current.tokens.is_empty() {
+                            entries.push(std::mem::replace(
+                                &mut current,
+                                Entry {
+                                    line,
+                                    inherit_owner: false,
+                                    tokens: vec![],
+                                },
+                            ));
+                        }
+                        line += 1;
+                        line_start = true;
+                    }
+                    _ => (),
+                }
+            }
+            _ => token.push(c),
+        }
+    }
+    if quoted {
+        return Err(MasterFileError::UnterminatedQuote(line));
+    }
+    if depth != 0 {
+        return Err(MasterFileError::UnbalancedParentheses(line));
+    }
+    if !token.is_empty() {
+        current.tokens.push(token);
+    }
+    if !current.tokens.is_empty() {
+        entries.push(current);
+    }
+    Ok(entries)
+}
+
+/// resolves `@` and names without a trailing dot against `origin`
+fn absolute_name(token: &str, origin: &Name) -> String {
+    if token == "@" {
+        return origin.to_string();
+    }
+    if token.ends_with('.') && !token.ends_with("\\.") {
+        return token.to_string();
+    }
+    if origin.is_empty() {
+        token.to_string()
+    } else {
+        format!("{token}.{origin}")
+    }
+}
+
+/// a TTL in seconds, or BIND-style units like `1h30m`
+fn parse_ttl(token: &str) -> Option<u32> {
+    if let Ok(ttl) = token.parse() {
+        return Some(ttl);
+    }
+    let mut total = 0u32;
+    let mut value: Option<u32> = None;
+    for c in token.chars() {
+        if let Some(digit) = c.to_digit(10) {
+            value = Some(value.unwrap_or(0).checked_mul(10)?.checked_add(digit)?);
+            continue;
+        }
+        let multiplier = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 60 * 60 * 24,
+            'w' => 60 * 60 * 24 * 7,
+            _ => return None,
+        };
+        total = total.checked_add(value.take()?.checked_mul(multiplier)?)?;
+    }
+    if value.is_some() {
+        return None;
+    }
+    Some(total)
+}
+
+/// indices of RDATA fields holding domain names, which are relative to the origin
+fn name_fields(type_: Type) -> &'static [usize] {
+    match type_ {
+        Type::NS | Type::CNAME | Type::ALIAS | Type::PTR | Type::DNAME | Type::NSEC => &[0],
+        Type::SOA => &[0, 1],
+        Type::MX | Type::SVCB | Type::HTTPS => &[1],
+        Type::SRV => &[3],
+        Type::RRSIG => &[7],
+        _ => &[],
+    }
+}
+
+/// nested `$INCLUDE`s deeper than this are assumed to be a cycle
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// state carried across a master file and the files it includes
+struct MasterFileParser<'a> {
+    apex: Name,
+    default_ttl: Option<u32>,
+    last_ttl: Option<u32>,
+    zone: Zone,
+    /// reads the file named by an `$INCLUDE`
+    include: &'a mut dyn FnMut(&str) -> Result<String, MasterFileError>,
+    depth: usize,
+    invalid_records: InvalidRecordPolicy,
+    skipped: Vec<InvalidRecord>,
+}
+
+impl MasterFileParser<'_> {
+    fn parse(&mut self, input: &str, origin: Name) -> Result<(), MasterFileError> {
+        let mut origin = origin;
+        let mut last_owner: Option<Name> = None;
+
+        for entry in tokenize(input)? {
+            let line = entry.line;
+            let mut tokens = entry.tokens.iter().map(String::as_str).peekable();
+            let parse_name = |token: &str, origin: &Name| {
+                if token == "@" {
+                    return Ok(origin.clone().into_fqdn());
+                }
+                Name::from_str(token)
+                    .and_then(|name| name.qualify(origin))
+                    .map_err(|e| MasterFileError::Name(line, e))
+            };
+            if !entry.inherit_owner {
+                if let Some(directive) = tokens.peek().filter(|x| x.starts_with('$')) {
+                    let directive = directive.to_ascii_uppercase();
+                    tokens.next();
+                    let argument = tokens
+                        .next()
+                        .ok_or(MasterFileError::MissingArgument(line))?;
+                    match &*directive {
+                        "$ORIGIN" => origin = parse_name(argument, &origin)?,
+                        "$TTL" => {
+                            self.default_ttl = Some(parse_ttl(argument).ok_or_else(|| {
+                                MasterFileError::InvalidTtl(line, argument.to_string())
+                            })?)
+                        }
+                        "$INCLUDE" => {
+                            // the included file starts from the given origin, and ours is restored after it
+                            let include_origin = match tokens.next() {
+                                Some(x) => parse_name(x, &origin)?,
+                                None => origin.clone(),
+                            };
+                            if self.depth >= MAX_INCLUDE_DEPTH {
+                                return Err(MasterFileError::IncludeDepth(line));
+                            }
+                            let input = (self.include)(argument)?;
+                            self.depth += 1;
+                            let result = self.parse(&input, include_origin);
+                            self.depth -= 1;
+                            result.map_err(|e| {
+                                MasterFileError::Included(argument.to_string(), Box::new(e))
+                            })?;
+                        }
+                        _ => return Err(MasterFileError::UnknownDirective(line, directive)),
+                    }
+                    continue;
+                }
+            }
+
+            let owner = if entry.inherit_owner {
+                last_owner
+                    .clone()
+                    .ok_or(MasterFileError::MissingOwner(line))?
+            } else {
+                parse_name(tokens.next().unwrap(), &origin)?
+            };
+            last_owner = Some(owner.clone());
+
+            // TTL and class may appear in either order before the type
+            let mut ttl = None;
+            let mut class = None;
+            let type_ = loop {
+                let token = tokens
+                    .next()
+                    .ok_or(MasterFileError::MissingArgument(line))?;
+                let upper = token.to_ascii_uppercase();
+                if ttl.is_none() && token.starts_with(|c: char| c.is_ascii_digit()) {
+                    ttl = Some(
+                        parse_ttl(token)
+                            .ok_or_else(|| MasterFileError::InvalidTtl(line, token.to_string()))?,
+                    );
+                } else if let Some(parsed) = class
+                    .is_none()
+                    .then(|| Class::from_str(&upper).ok())
+                    .flatten()
+                {
+                    class = Some(parsed);
+                } else {
+                    break adns_proto::parse_type(&upper)
+                        .map_err(|_| MasterFileError::UnknownType(line, token.to_string()))?;
+                }
+            };
+
+            let mut rdata: Vec<String> = tokens.map(str::to_string).collect();
+            for &index in name_fields(type_) {
+                if let Some(field) = rdata.get_mut(index) {
+                    *field = absolute_name(field, &origin);
+                }
+            }
+            if type_ == Type::SOA {
+                for field in rdata.iter_mut().skip(2) {
+                    if let Some(value) = parse_ttl(field) {
+                        *field = value.to_string();
+                    }
+                }
+            }
+            let data = match TypeData::parse_str(type_, &rdata.join(" ")) {
+                Ok(x) => x,
+                Err(error) => {
+                    let record = InvalidRecord {
+                        name: owner,
+                        type_,
+                        error,
+                    };
+                    self.invalid_records
+                        .handle(record, &mut self.skipped)
+                        .map_err(|e| MasterFileError::Data(line, e.error))?;
+                    continue;
+                }
+            };
+
+            let ttl = ttl
+                .or(self.default_ttl)
+                .or(self.last_ttl)
+                .ok_or(MasterFileError::MissingTtl(line))?;
+            self.last_ttl = Some(ttl);
+
+            match data {
+                TypeData::SOA(soa) if owner == self.apex => self.zone.soa = Some(soa),
+                TypeData::NS(ns) if owner == self.apex => self.zone.nameservers.push(ns),
+                data => self.zone.records.push(Record {
+                    name: owner,
+                    type_,
+                    class: class.unwrap_or_default(),
+                    ttl,
+                    data,
+                }),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Zone {
+    /// parses an RFC 1035 master file (BIND zone file) for the zone `origin`.
+    /// the apex SOA and NS records become `soa` and `nameservers`, everything else is added to `records` with absolute names.
+    /// the result is authoritative and meant to be inserted into a parent's `zones` under `origin`.
+    /// `$INCLUDE` is rejected, see `Zone::load_masterfile`.
+    /// records whose data doesn't parse fail it, see `Zone::parse_masterfile_with` to skip them instead.
+    pub fn parse_masterfile(input: &str, origin: Name) -> Result<Zone, MasterFileError> {
+        let (zone, _) =
+            Self::parse_masterfile_with(input, origin, InvalidRecordPolicy::Fail, &mut |file| {
+                Err(MasterFileError::Io(
+                    file.to_string(),
+                    std::io::Error::new(
+                        std::io::ErrorKind::Unsupported,
+                        "$INCLUDE is only supported when loading from a file",
+                    ),
+                ))
+            })?;
+        Ok(zone)
+    }
+
+    /// like `Zone::parse_masterfile`, with `include` reading the files named by `$INCLUDE` directives and
+    /// `invalid_records` applied to records whose data doesn't parse. returns the zone and the records it skipped
+    pub fn parse_masterfile_with(
+        input: &str,
+        origin: Name,
+        invalid_records: InvalidRecordPolicy,
+        include: &mut dyn FnMut(&str) -> Result<String, MasterFileError>,
+    ) -> Result<(Zone, Vec<InvalidRecord>), MasterFileError> {
+        let mut parser = MasterFileParser {
+            apex: origin.clone(),
+            default_ttl: None,
+            last_ttl: None,
+            zone: Zone {
+                authoritative: true,
+                ..Default::default()
+            },
+            include,
+            depth: 0,
+            invalid_records,
+            skipped: vec![],
+        };
+        parser.parse(input, origin)?;
+        Ok((parser.zone, parser.skipped))
+    }
+
+    /// reads and parses the master file at `path`, following `$INCLUDE`s relative to its directory.
+    /// returns the zone, every file read, including `path`, so callers can watch them for changes, and the records
+    /// `invalid_records` skipped.
+    pub fn load_masterfile(
+        path: &Path,
+        origin: Name,
+        invalid_records: InvalidRecordPolicy,
+    ) -> Result<(Zone, Vec<PathBuf>, Vec<InvalidRecord>), MasterFileError> {
+        let read = |path: &Path| {
+            std::fs::read_to_string(path)
+                .map_err(|e| MasterFileError::Io(path.display().to_string(), e))
+        };
+        let input = read(path)?;
+        let directory = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let mut files = vec![path.to_path_buf()];
+        let (zone, skipped) =
+            Self::parse_masterfile_with(&input, origin, invalid_records, &mut |file| {
+                let path = directory.join(file);
+                let input = read(&path)?;
+                files.push(path);
+                Ok(input)
+            })?;
+        Ok((zone, files, skipped))
+    }
+
+    /// emits the zone at `origin` in RFC 1035 master file format, readable by BIND and `Zone::parse_masterfile`.
+    /// names are written fully qualified. subzones in `zones` are not included, they are zones of their own.
+    pub fn to_masterfile(&self, origin: Name) -> String {
+        fn fqdn(name: &Name) -> String {
+            format!("{name}.")
+        }
+        let mut out = String::new();
+        writeln!(out, "$ORIGIN .").unwrap();
+        let apex = if origin.is_empty() {
+            ".".to_string()
+        } else {
+            fqdn(&origin)
+        };
+        if let Some(soa) = &self.soa {
+            writeln!(
+                out,
+                "{apex}\t60\tIN\tSOA\t{} {} (\n\t\t\t\t{} ; serial\n\t\t\t\t{} ; refresh\n\t\t\t\t{} ; retry\n\t\t\t\t{} ; expire\n\t\t\t\t{} ) ; minimum",
+                fqdn(&soa.mname),
+                fqdn(&soa.rname),
+                soa.serial,
+                soa.refresh,
+                soa.retry,
+                soa.expire,
+                soa.minimum,
+            )
+            .unwrap();
+        }
+        for nameserver in &self.nameservers {
+            writeln!(out, "{apex}\t3600\tIN\tNS\t{}", fqdn(nameserver)).unwrap();
+        }
+        for record in &self.records {
+            writeln!(out, "{}", record.to_zone_line()).unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_masterfile() {
+        let input = r#"
+$TTL 1h
+$ORIGIN example.com.
+@   IN  SOA ns1 hostmaster (
+        2024010101 ; serial
+        1d         ; refresh
+        2h 4w 300 )
+    IN  NS  ns1
+    IN  NS  ns2.example.net.
+    IN  MX  10 mail
+ns1 300 IN A 192.0.2.1
+www     A   192.0.2.2
+        AAAA 2001:db8::1
+txt IN 60 TXT "hello; (world)"
+unknown TYPE65280 \# 4 0a000001
+empty   TYPE65281 \# 0
+$ORIGIN sub
+host CNAME www.example.com.
+"#;
+        let zone = Zone::parse_masterfile(input, "example.com".parse().unwrap()).unwrap();
+        // the apex NS records are ours, not a delegation
+        assert!(zone.authoritative && !zone.is_delegation());
+        let soa = zone.soa.clone().unwrap();
+        assert_eq!(soa.mname, "ns1.example.com".parse::<Name>().unwrap());
+        assert_eq!(soa.rname, "hostmaster.example.com".parse::<Name>().unwrap());
+        assert_eq!(soa.serial, 2024010101);
+        assert_eq!(soa.refresh, 86400);
+        assert_eq!(soa.retry, 7200);
+        assert_eq!(soa.expire, 2419200);
+        assert_eq!(soa.minimum, 300);
+        assert_eq!(
+            zone.nameservers,
+            vec![
+                "ns1.example.com".parse::<Name>().unwrap(),
+                "ns2.example.net".parse().unwrap()
+            ]
+        );
+        let records = zone
+            .records
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            records,
+            vec![
+                "example.com MX 3600 10 mail.example.com",
+                "ns1.example.com A 300 192.0.2.1",
+                "www.example.com A 3600 192.0.2.2",
+                "www.example.com AAAA 3600 2001:db8::1",
+                "txt.example.com TXT 60 \"hello;\\ (world)\"",
+                "unknown.example.com Other 3600 0a000001",
+                "empty.example.com Other 3600 ",
+                "host.sub.example.com CNAME 3600 www.example.com",
+            ]
+        );
+
+        let exported = zone.to_masterfile("example.com".parse().unwrap());
+        assert!(exported.starts_with(
+            "$ORIGIN .\nexample.com.\t60\tIN\tSOA\tns1.example.com. hostmaster.example.com. (\n"
+        ));
+        let reparsed = Zone::parse_masterfile(&exported, "example.com".parse().unwrap()).unwrap();
+        assert_eq!(reparsed.soa, zone.soa);
+        assert_eq!(reparsed.nameservers, zone.nameservers);
+        assert_eq!(reparsed.records, zone.records);
+        // types without a presentation format are exported in the generic one
+        assert!(exported.contains("\tTYPE65280\t\\# 4 0a000001\n"));
+        assert!(exported.contains("\tTYPE65281\t\\# 0\n"));
+
+        assert!(matches!(
+            Zone::parse_masterfile("a A 192.0.2.1", Name::default()),
+            Err(MasterFileError::MissingTtl(1))
+        ));
+        assert!(matches!(
+            Zone::parse_masterfile("$TTL 60\na A (192.0.2.1", Name::default()),
+            Err(MasterFileError::UnbalancedParentheses(2))
+        ));
+    }
+
+    #[test]
+    fn test_masterfile_include() {
+        let directory = std::env::temp_dir().join(format!("adns-include-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(
+            directory.join("zone.db"),
+            "$TTL 300\n$ORIGIN 2.0.192.in-addr.arpa.\n$INCLUDE hosts.db 1.0.192.in-addr.arpa.\n2 PTR b.example.com.\n",
+        )
+        .unwrap();
+        std::fs::write(directory.join("hosts.db"), "1 PTR a.example.com.\n").unwrap();
+
+        let origin: Name = "in-addr.arpa".parse().unwrap();
+        let (zone, files, skipped) = Zone::load_masterfile(
+            &directory.join("zone.db"),
+            origin.clone(),
+            InvalidRecordPolicy::Fail,
+        )
+        .unwrap();
+        assert!(skipped.is_empty());
+        let records = zone
+            .records
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            records,
+            vec![
+                "1.1.0.192.in-addr.arpa PTR 300 a.example.com",
+                "2.2.0.192.in-addr.arpa PTR 300 b.example.com",
+            ]
+        );
+        assert_eq!(
+            files,
+            vec![directory.join("zone.db"), directory.join("hosts.db")]
+        );
+
+        std::fs::write(directory.join("hosts.db"), "$INCLUDE hosts.db\n").unwrap();
+        assert!(matches!(
+            Zone::load_masterfile(
+                &directory.join("zone.db"),
+                origin,
+                InvalidRecordPolicy::Fail
+            ),
+            Err(MasterFileError::Included(..))
+        ));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
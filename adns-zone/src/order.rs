@@ -0,0 +1,245 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use adns_proto::{Name, Record, Type};
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::Zone;
+
+/// most RRsets whose rotation is tracked, all start over when it's reached
+const MAX_ROTATIONS: usize = 1 << 16;
+
+/// how many times each RRset, by owner name and type, was rotated by `RrsetOrder::Cyclic`. clones of a zone share
+/// the counters, a zone loaded anew starts over
+#[derive(Default, Clone)]
+pub struct Rotations(Arc<Mutex<HashMap<(Name, Type), usize>>>);
+
+impl std::fmt::Debug for Rotations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let len = self.0.lock().unwrap().len();
+        f.debug_tuple("Rotations").field(&len).finish()
+    }
+}
+
+impl Rotations {
+    /// advances the RRset's counter, so successive answers with it start from a different record
+    fn next(&self, name: &Name, type_: Type) -> usize {
+        let mut rotations = self.0.lock().unwrap();
+        let key = (name.clone(), type_);
+        if rotations.len() >= MAX_ROTATIONS && !rotations.contains_key(&key) {
+            rotations.clear();
+        }
+        let rotation = rotations.entry(key).or_default();
+        let out = *rotation;
+        *rotation = rotation.wrapping_add(1);
+        out
+    }
+}
+
+/// how records of an RRset are ordered in answers
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RrsetOrder {
+    /// the parent zone's order, see `Zone::inherit_orders`. `fixed` at the root
+    #[default]
+    Inherit,
+    /// as stored in the zone
+    Fixed,
+    /// rotated by one position per answer
+    Cyclic,
+    /// shuffled per answer
+    Random,
+}
+
+impl RrsetOrder {
+    pub(crate) fn is_default(&self) -> bool {
+        *self == RrsetOrder::default()
+    }
+
+    /// reorders each RRset (records of one type) among the positions it already occupies in `records`
+    pub fn apply(&self, rotations: &Rotations, records: &mut [Record]) {
+        if matches!(self, RrsetOrder::Inherit | RrsetOrder::Fixed) {
+            return;
+        }
+        let mut types = records.iter().map(|x| x.type_).collect::<Vec<_>>();
+        types.sort_unstable();
+        types.dedup();
+        for type_ in types {
+            let positions: Vec<usize> = (0..records.len())
+                .filter(|i| records[*i].type_ == type_)
+                .collect();
+            if positions.len() < 2 {
+                continue;
+            }
+            let mut rrset: Vec<Record> = positions.iter().map(|i| records[*i].clone()).collect();
+            match self {
+                RrsetOrder::Inherit | RrsetOrder::Fixed => unreachable!(),
+                RrsetOrder::Cyclic => {
+                    let offset = rotations.next(&rrset[0].name, type_) % rrset.len();
+                    rrset.rotate_left(offset);
+                }
+                RrsetOrder::Random => rrset.shuffle(&mut rand::thread_rng()),
+            }
+            for (position, record) in positions.into_iter().zip(rrset) {
+                records[position] = record;
+            }
+        }
+    }
+}
+
+impl Zone {
+    /// resolves `RrsetOrder::Inherit` in the subzones at any depth to their parent's order
+    pub fn inherit_orders(&mut self) {
+        let (rrset_order, nameserver_order) = (self.rrset_order, self.nameserver_order);
+        for zone in self.zones.values_mut() {
+            if zone.rrset_order == RrsetOrder::Inherit {
+                zone.rrset_order = rrset_order;
+            }
+            if zone.nameserver_order == RrsetOrder::Inherit {
+                zone.nameserver_order = nameserver_order;
+            }
+            zone.inherit_orders();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use adns_proto::{Question, Type, TypeData};
+
+    use super::*;
+    use crate::ZoneAnswer;
+
+    #[test]
+    fn test_rrset_order() {
+        let mut zone = Zone {
+            rrset_order: RrsetOrder::Cyclic,
+            ..Default::default()
+        };
+        for i in 1..=4 {
+            zone.records.push(Record::new(
+                "example.com".parse().unwrap(),
+                300,
+                TypeData::A(format!("192.0.2.{i}").parse().unwrap()),
+            ));
+        }
+        zone.records.push(Record::new(
+            "example.com".parse().unwrap(),
+            300,
+            TypeData::TXT(vec!["fixed".to_string()].into()),
+        ));
+        let question = Question::new(Type::ALL, "example.com").unwrap();
+        let mut firsts = vec![];
+        for _ in 0..4 {
+            let mut answer = ZoneAnswer::default();
+            zone.answer(None, &Default::default(), &question, &mut answer);
+            assert_eq!(answer.answers.len(), 5);
+            assert_eq!(answer.answers[4].type_, Type::TXT);
+            // rotation keeps the relative order
+            let addresses: Vec<String> = answer.answers[..4]
+                .iter()
+                .map(|x| x.data.to_string())
+                .collect();
+            let start = addresses[0].clone();
+            let expected: Vec<String> = (0..4)
+                .map(|i| {
+                    let first: u8 = start.rsplit('.').next().unwrap().parse().unwrap();
+                    format!("192.0.2.{}", (first - 1 + i) % 4 + 1)
+                })
+                .collect();
+            assert_eq!(addresses, expected);
+            firsts.push(start);
+        }
+        firsts.sort();
+        firsts.dedup();
+        assert!(firsts.len() > 1);
+    }
+
+    #[test]
+    fn test_rotation_per_rrset() {
+        let mut zone = Zone {
+            rrset_order: RrsetOrder::Cyclic,
+            ..Default::default()
+        };
+        for name in ["a.example.com", "b.example.com"] {
+            for i in 1..=2 {
+                zone.records.push(Record::new(
+                    name.parse().unwrap(),
+                    300,
+                    TypeData::A(format!("192.0.2.{i}").parse().unwrap()),
+                ));
+            }
+        }
+        let first = |name: &str| {
+            let mut answer = ZoneAnswer::default();
+            let question = Question::new(Type::A, name).unwrap();
+            zone.answer(None, &Default::default(), &question, &mut answer);
+            answer.answers[0].data.to_string()
+        };
+        // answers for another RRset in between don't skip a step of the rotation
+        for _ in 0..3 {
+            assert_eq!(first("a.example.com"), "192.0.2.1");
+            assert_eq!(first("b.example.com"), "192.0.2.1");
+            assert_eq!(first("b.example.com"), "192.0.2.2");
+            assert_eq!(first("a.example.com"), "192.0.2.2");
+        }
+    }
+
+    #[test]
+    fn test_inherit_orders() {
+        let mut zone: Zone = serde_yaml::from_str(
+            "
+rrset_order: cyclic
+zones:
+  example.com: {}
+  example.net:
+    rrset_order: fixed
+",
+        )
+        .unwrap();
+        zone.zones[0]
+            .zones
+            .insert("sub.example.com".parse().unwrap(), Zone::default());
+        zone.inherit_orders();
+        let example_com = &zone.zones[&"example.com".parse::<Name>().unwrap()];
+        assert_eq!(example_com.rrset_order, RrsetOrder::Cyclic);
+        assert_eq!(
+            example_com.zones[&"sub.example.com".parse::<Name>().unwrap()].rrset_order,
+            RrsetOrder::Cyclic
+        );
+        assert_eq!(
+            zone.zones[&"example.net".parse::<Name>().unwrap()].rrset_order,
+            RrsetOrder::Fixed
+        );
+        assert_eq!(example_com.nameserver_order, RrsetOrder::Inherit);
+    }
+
+    #[test]
+    fn test_nameserver_order() {
+        let mut sub = Zone {
+            authoritative: true,
+            nameserver_order: RrsetOrder::Cyclic,
+            ..Default::default()
+        };
+        for i in 1..=3 {
+            sub.nameservers
+                .push(format!("ns{i}.example.com").parse().unwrap());
+        }
+        let mut zone = Zone::default();
+        zone.zones.insert("example.com".parse().unwrap(), sub);
+        let question = Question::new(Type::NS, "example.com").unwrap();
+        let mut firsts = vec![];
+        for _ in 0..3 {
+            let mut answer = ZoneAnswer::default();
+            zone.answer(None, &Default::default(), &question, &mut answer);
+            assert_eq!(answer.answers.len(), 3);
+            firsts.push(answer.answers[0].data.to_string());
+        }
+        firsts.sort();
+        firsts.dedup();
+        assert!(firsts.len() > 1);
+    }
+}
@@ -0,0 +1,1065 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use adns_proto::{
+    Class, Edns, Header, Name, Packet, QueryResponse, Question, Record, ResponseCode, Type,
+    TypeData,
+};
+use log::warn;
+
+use crate::{AnswerState, IdentityQueries, PolicyAction, Zone, ZoneAnswer};
+
+/// the EDNS payload size `answer_query` advertises, per the DNS flag day 2020 recommendation
+const EDNS_PAYLOAD_SIZE: u16 = 1232;
+
+struct QueryContext<'a> {
+    zone: &'a Zone,
+    question: &'a Question,
+    response: &'a mut ZoneAnswer,
+    state: &'a mut AnswerState,
+}
+
+impl<'a> QueryContext<'a> {
+    fn query(&mut self) -> usize {
+        let start = self.response.answers.len();
+        if self.chaos() {
+            return self.response.answers.len() - start;
+        }
+        let substate = self
+            .zone
+            .answer(None, &Name::default(), self.question, self.response);
+        if substate > *self.state {
+            *self.state = substate;
+        }
+        if matches!(self.question.type_, Type::SVCB | Type::HTTPS) {
+            self.follow_svcb_alias(start);
+        }
+        if matches!(self.question.type_, Type::A | Type::AAAA)
+            && self.response.answers.len() == start
+            && self.response.referral.is_empty()
+        {
+            self.follow_alias();
+        }
+        // a DS query at a delegation point is answered by the parent, where a CNAME lookup would find the referral
+        if !matches!(self.question.type_, Type::CNAME | Type::ALL | Type::DS)
+            && self.response.answers.len() == start
+            && self.response.referral.is_empty()
+        {
+            let mut question = self.question.clone();
+            question.type_ = Type::CNAME;
+            QueryContext {
+                zone: self.zone,
+                question: &question,
+                response: self.response,
+                state: self.state,
+            }
+            .query();
+        }
+        self.response.answers.len() - start
+    }
+
+    /// CHAOS class server identification (RFC 4892). returns false for names that aren't special, which go to the zone
+    fn chaos(&mut self) -> bool {
+        if !is_identity_query(self.question) {
+            return false;
+        }
+        let text = match &self.zone.identity_queries {
+            IdentityQueries::Answer if self.question.name == "version.bind" => {
+                Some(format!("adns-{}", env!("CARGO_PKG_VERSION")))
+            }
+            IdentityQueries::Answer => self.zone.server_id.clone(),
+            IdentityQueries::Text(text) => Some(text.clone()),
+            // refused by `respond_query`
+            IdentityQueries::Refuse => None,
+        };
+        let Some(text) = text else {
+            return false;
+        };
+        *self.state = (*self.state).max(AnswerState::DomainSeen);
+        if self.question.type_.wants_by_query(Type::TXT) {
+            let mut record = Record::new(
+                self.question.name.clone(),
+                self.zone.identity_ttl,
+                TypeData::TXT(vec![text].into()),
+            );
+            record.class = Class::CH;
+            self.response.answers.push(record);
+        }
+        true
+    }
+
+    /// AliasMode SVCB/HTTPS records are followed like a CNAME (RFC 9460 section 2.4.2)
+    fn follow_svcb_alias(&mut self, start: usize) {
+        let mut seen = start;
+        for _ in 0..MAX_SVCB_ALIAS_DEPTH {
+            let Some(target) =
+                self.response.answers[seen..]
+                    .iter()
+                    .find_map(|answer| match &answer.data {
+                        TypeData::SVCB(data) | TypeData::HTTPS(data)
+                            if data.is_alias() && !data.target.is_empty() =>
+                        {
+                            Some(data.target.clone())
+                        }
+                        _ => None,
+                    })
+            else {
+                return;
+            };
+            seen = self.response.answers.len();
+            let question = Question {
+                name: target,
+                type_: self.question.type_,
+                class: self.question.class,
+            };
+            self.zone
+                .answer(None, &Name::default(), &question, self.response);
+        }
+    }
+
+    /// an ALIAS at the queried name is answered with its target's addresses under the queried name.
+    /// targets are only resolved from the zones we serve
+    fn follow_alias(&mut self) {
+        let mut name = self.question.name.clone();
+        let mut ttl = u32::MAX;
+        for _ in 0..MAX_CNAME_CHAIN {
+            let mut found = ZoneAnswer::default();
+            let question = Question {
+                name: name.clone(),
+                type_: Type::ALL,
+                class: self.question.class,
+            };
+            self.zone
+                .answer(None, &Name::default(), &question, &mut found);
+            if !found.referral.is_empty() {
+                return;
+            }
+            if name != self.question.name {
+                let addresses: Vec<Record> = found
+                    .answers
+                    .iter()
+                    .filter(|x| x.type_ == self.question.type_)
+                    .map(|x| Record {
+                        name: self.question.name.clone(),
+                        ttl: x.ttl.min(ttl),
+                        ..x.clone()
+                    })
+                    .collect();
+                if !addresses.is_empty() {
+                    self.response.answers.extend(addresses);
+                    return;
+                }
+            }
+            // the first step only follows ALIAS, a CNAME at the queried name is answered as is
+            let Some((next, next_ttl)) = found.answers.iter().find_map(|x| match &x.data {
+                TypeData::ALIAS(target) => Some((target.clone(), x.ttl)),
+                TypeData::CNAME(target) if name != self.question.name => {
+                    Some((target.clone(), x.ttl))
+                }
+                _ => None,
+            }) else {
+                return;
+            };
+            ttl = ttl.min(next_ttl);
+            name = next;
+        }
+    }
+}
+
+const MAX_SVCB_ALIAS_DEPTH: usize = 8;
+
+const MAX_CNAME_CHAIN: usize = 16;
+
+/// answers `question`, following any CNAME chain within the zone. returns false on a cycle or overlong chain
+fn resolve_question(
+    zone: &Zone,
+    question: &Question,
+    response: &mut ZoneAnswer,
+    state: &mut AnswerState,
+) -> bool {
+    let mut question = question.clone();
+    let mut seen = vec![question.name.clone()];
+    loop {
+        let start = response.answers.len();
+        QueryContext {
+            zone,
+            question: &question,
+            response,
+            state,
+        }
+        .query();
+        if matches!(question.type_, Type::CNAME | Type::ALL) || !response.referral.is_empty() {
+            return true;
+        }
+        let Some(target) = response.answers[start..]
+            .iter()
+            .find_map(|answer| match &answer.data {
+                TypeData::CNAME(target) if answer.name == question.name => Some(target.clone()),
+                _ => None,
+            })
+        else {
+            return true;
+        };
+        if seen.contains(&target) || seen.len() > MAX_CNAME_CHAIN {
+            return false;
+        }
+        seen.push(target.clone());
+        question.name = target;
+    }
+}
+
+/// a response built by `answer_query_into`
+pub struct AnsweredQuery {
+    pub packet: Packet,
+    /// NXDOMAIN was rewritten by `Zone::nxdomain_redirect`
+    pub nxdomain_redirected: bool,
+}
+
+/// answers a standard query from `zone` alone, without any I/O: no forwarding, zone transfers, updates or
+/// cookies. the reply echoes the query's ID and opcode, and has an OPT record if the query did
+pub fn answer_query(zone: &Zone, packet: &Packet) -> Packet {
+    let response = Packet {
+        header: Header {
+            id: packet.header.id,
+            query_response: QueryResponse::Response,
+            opcode: packet.header.opcode,
+            ..Default::default()
+        },
+        edns: packet.edns.as_ref().map(|_| Edns::new(EDNS_PAYLOAD_SIZE)),
+        ..Default::default()
+    };
+    answer_query_into(zone, packet, response).packet
+}
+
+impl Zone {
+    /// answers `question` like `answer`, but also serves CHAOS identity queries and follows ALIAS records,
+    /// AliasMode SVCB/HTTPS records and CNAMEs at the queried name. returns the number of answers added
+    pub fn query(
+        &self,
+        question: &Question,
+        response: &mut ZoneAnswer,
+        state: &mut AnswerState,
+    ) -> usize {
+        QueryContext {
+            zone: self,
+            question,
+            response,
+            state,
+        }
+        .query()
+    }
+}
+
+/// answers the questions of `packet` from `zone` into `response`, which carries the header and EDNS to reply with
+pub fn answer_query_into(zone: &Zone, packet: &Packet, mut response: Packet) -> AnsweredQuery {
+    response.questions = packet.questions.clone();
+    let mut state = AnswerState::None;
+    let mut policy_nxdomain = false;
+    let mut server_failure = false;
+    let mut refused = false;
+    let mut nxdomain_redirected = false;
+    for question in &packet.questions {
+        if zone.identity_queries == IdentityQueries::Refuse && is_identity_query(question) {
+            refused = true;
+            continue;
+        }
+        let mut answer = ZoneAnswer::default();
+        match zone.policy(question) {
+            None | Some(PolicyAction::Passthru) => {
+                if !resolve_question(zone, question, &mut answer, &mut state) {
+                    warn!("CNAME loop or overlong chain at {}", question.name);
+                    server_failure = true;
+                }
+            }
+            Some(PolicyAction::NxDomain) => {
+                answer.is_authoritative = true;
+                policy_nxdomain = true;
+            }
+            Some(PolicyAction::NoData) => {
+                answer.is_authoritative = true;
+                state = state.max(AnswerState::DomainSeen);
+            }
+            Some(PolicyAction::LocalData(records)) => {
+                answer.is_authoritative = true;
+                answer.answers = records;
+                state = state.max(AnswerState::DomainSeen);
+            }
+        }
+        if answer.is_authoritative {
+            response.header.is_authoritative = true;
+        }
+        response.answers.extend(answer.answers);
+        response.nameservers.extend(answer.referral);
+    }
+    let answers = response.answers.iter().map(|x| (x, false));
+    for (answer, is_referral) in answers.chain(response.nameservers.iter().map(|x| (x, true))) {
+        let Some((extra_resolve, extra_types)) = (match &answer.data {
+            // glue outside the delegated zone is ignored by resolvers, which look those names up themselves
+            TypeData::NS(name) if is_referral && !name.ends_with(&answer.name) => None,
+            TypeData::NS(name) => Some((name, &[Type::A][..])),
+            TypeData::MX { exchange, .. } => Some((exchange, &[Type::A][..])),
+            TypeData::SRV { target, .. } => Some((target, &[Type::A][..])),
+            // ServiceMode, `.` targets the owner name
+            TypeData::SVCB(data) | TypeData::HTTPS(data) if !data.is_alias() => Some((
+                if data.target.is_empty() {
+                    &answer.name
+                } else {
+                    &data.target
+                },
+                &[Type::A, Type::AAAA][..],
+            )),
+            _ => None,
+        }) else {
+            continue;
+        };
+        for type_ in extra_types {
+            let question = Question {
+                name: extra_resolve.clone(),
+                type_: *type_,
+                class: Default::default(),
+            };
+            let mut answer = ZoneAnswer::default();
+            QueryContext {
+                zone,
+                question: &question,
+                response: &mut answer,
+                state: &mut state,
+            }
+            .query();
+            if answer.is_authoritative {
+                response.header.is_authoritative = true;
+            }
+            response
+                .additional_records
+                .extend(answer.answers.into_iter().map(|mut record| {
+                    if let Some(cap) = zone.additional_ttl_cap {
+                        record.ttl = record.ttl.min(cap);
+                    }
+                    record
+                }));
+        }
+    }
+    dedup_records(&mut response.answers);
+    dedup_records(&mut response.nameservers);
+    dedup_records(&mut response.additional_records);
+    // referrals are never authoritative, even if the glue came from an authoritative zone
+    if response.answers.is_empty() && !response.nameservers.is_empty() {
+        response.header.is_authoritative = false;
+    }
+    let dnssec_ok = packet.edns.as_ref().is_some_and(|edns| edns.dnssec_ok);
+    if state == AnswerState::None
+        && !policy_nxdomain
+        && !refused
+        && !server_failure
+        && !dnssec_ok
+        && nxdomain_redirect(zone, packet, &mut response)
+    {
+        nxdomain_redirected = true;
+        state = AnswerState::DomainSeen;
+    }
+    if response.header.is_authoritative
+        && response.answers.is_empty()
+        && state != AnswerState::Delegated
+    {
+        if let Some(soa) = packet
+            .questions
+            .iter()
+            .find_map(|question| zone.negative_soa(&question.name))
+        {
+            response.nameservers.push(soa);
+        }
+    }
+    if refused {
+        response.header.response_code = ResponseCode::Refused;
+    } else if server_failure {
+        response.header.response_code = ResponseCode::ServerFailure;
+    } else if state == AnswerState::None || policy_nxdomain {
+        response.header.response_code = ResponseCode::NameError;
+        // authenticated denial for signed zones, policy rewrites have nothing to prove
+        if !policy_nxdomain && dnssec_ok {
+            for question in &packet.questions {
+                response
+                    .nameservers
+                    .extend(zone.nsec_denial(&question.name));
+            }
+            dedup_records(&mut response.nameservers);
+        }
+    } else if dnssec_ok
+        && state == AnswerState::DomainSeen
+        && response.header.is_authoritative
+        && response.answers.is_empty()
+    {
+        for question in &packet.questions {
+            response
+                .nameservers
+                .extend(zone.nsec_nodata(&question.name));
+        }
+        dedup_records(&mut response.nameservers);
+    }
+    // online signing, for zones with active signing keys
+    if dnssec_ok {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as u32;
+        for section in [
+            &mut response.answers,
+            &mut response.nameservers,
+            &mut response.additional_records,
+        ] {
+            let signatures = zone.sign_records(section, now);
+            section.extend(signatures);
+        }
+    }
+
+    AnsweredQuery {
+        packet: response,
+        nxdomain_redirected,
+    }
+}
+
+/// answers A/AAAA questions with `Zone::nxdomain_redirect`'s addresses in place of NXDOMAIN. returns false, leaving
+/// the response as is, if it isn't configured or has no address for a question
+fn nxdomain_redirect(zone: &Zone, packet: &Packet, response: &mut Packet) -> bool {
+    let Some(redirect) = &zone.nxdomain_redirect else {
+        return false;
+    };
+    let mut answers = vec![];
+    for question in &packet.questions {
+        let start = answers.len();
+        answers.extend(redirect.addresses.iter().filter_map(|ip| {
+            let data = match (ip, question.type_) {
+                (IpAddr::V4(ip), Type::A) => TypeData::A(*ip),
+                (IpAddr::V6(ip), Type::AAAA) => TypeData::AAAA(*ip),
+                _ => return None,
+            };
+            Some(Record::new(question.name.clone(), redirect.ttl, data))
+        }));
+        if answers.len() == start {
+            return false;
+        }
+    }
+    // it's not our data
+    response.header.is_authoritative = false;
+    response.nameservers.clear();
+    response.additional_records.clear();
+    response.answers = answers;
+    true
+}
+
+/// `version.bind`, `hostname.bind` or `id.server` in the CH class
+fn is_identity_query(question: &Question) -> bool {
+    question.class == Class::CH
+        && (question.name == "version.bind"
+            || question.name == "hostname.bind"
+            || question.name == "id.server")
+}
+
+/// drops repeated identical records, keeping the first occurrence
+fn dedup_records(records: &mut Vec<Record>) {
+    // duplicates can only share an owner and type, so only compare within each RRset
+    let mut rrsets: HashMap<(&Name, Type), Vec<&Record>> = HashMap::new();
+    let keep: Vec<bool> = records
+        .iter()
+        .map(|record| {
+            let rrset = rrsets.entry((&record.name, record.type_)).or_default();
+            if rrset.contains(&record) {
+                false
+            } else {
+                rrset.push(record);
+                true
+            }
+        })
+        .collect();
+    let mut keep = keep.into_iter();
+    records.retain(|_| keep.next().unwrap_or(true));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{KeyState, NxdomainRedirect, ZoneSigningKey};
+
+    fn cname_zone(links: &[(&str, &str)]) -> Zone {
+        let mut records: Vec<Record> = links
+            .iter()
+            .map(|(name, target)| {
+                Record::new(
+                    name.parse().unwrap(),
+                    300,
+                    TypeData::CNAME(target.parse().unwrap()),
+                )
+            })
+            .collect();
+        records.push(Record::new(
+            "c.example.com".parse().unwrap(),
+            300,
+            TypeData::A("1.2.3.4".parse().unwrap()),
+        ));
+        Zone {
+            records,
+            authoritative: true,
+            ..Default::default()
+        }
+    }
+
+    fn query(zone: &Zone, name: &str) -> Packet {
+        let packet = Packet {
+            questions: vec![Question::new(Type::A, name).unwrap()],
+            ..Default::default()
+        };
+        answer_query_into(zone, &packet, Packet::default()).packet
+    }
+
+    #[test]
+    fn test_answer_query() {
+        let zone = cname_zone(&[]);
+        let mut packet = Packet {
+            questions: vec![Question::new(Type::A, "c.example.com").unwrap()],
+            ..Default::default()
+        };
+        packet.header.id = 1234;
+        packet.edns = Some(Edns::new(4096));
+        let response = answer_query(&zone, &packet);
+        assert_eq!(response.header.id, 1234);
+        assert_eq!(response.header.query_response, QueryResponse::Response);
+        assert!(response.header.is_authoritative);
+        assert_eq!(response.questions[0].name, packet.questions[0].name);
+        assert_eq!(
+            response.answers[0].data,
+            TypeData::A("1.2.3.4".parse().unwrap())
+        );
+        assert_eq!(response.edns.unwrap().udp_payload_size, EDNS_PAYLOAD_SIZE);
+    }
+
+    #[test]
+    fn test_cname_chain() {
+        let zone = cname_zone(&[
+            ("a.example.com", "b.example.com"),
+            ("b.example.com", "c.example.com"),
+        ]);
+        let response = query(&zone, "a.example.com");
+        assert_eq!(response.header.response_code, ResponseCode::NoError);
+        assert_eq!(
+            response.answers,
+            vec![
+                Record::new(
+                    "a.example.com".parse().unwrap(),
+                    300,
+                    TypeData::CNAME("b.example.com".parse().unwrap()),
+                ),
+                Record::new(
+                    "b.example.com".parse().unwrap(),
+                    300,
+                    TypeData::CNAME("c.example.com".parse().unwrap()),
+                ),
+                Record::new(
+                    "c.example.com".parse().unwrap(),
+                    300,
+                    TypeData::A("1.2.3.4".parse().unwrap()),
+                ),
+            ]
+        );
+        assert!(response.additional_records.is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_records() {
+        let mut zone = cname_zone(&[]);
+        zone.records.push(zone.records[0].clone());
+        let response = query(&zone, "c.example.com");
+        assert_eq!(
+            response.answers,
+            vec![Record::new(
+                "c.example.com".parse().unwrap(),
+                300,
+                TypeData::A("1.2.3.4".parse().unwrap()),
+            )]
+        );
+    }
+
+    #[test]
+    fn test_cname_loop() {
+        let zone = cname_zone(&[
+            ("a.example.com", "b.example.com"),
+            ("b.example.com", "a.example.com"),
+        ]);
+        let response = query(&zone, "a.example.com");
+        assert_eq!(response.header.response_code, ResponseCode::ServerFailure);
+    }
+
+    #[test]
+    fn test_cname_self_loop() {
+        let zone = cname_zone(&[("a.example.com", "a.example.com")]);
+        let response = query(&zone, "a.example.com");
+        assert_eq!(response.header.response_code, ResponseCode::ServerFailure);
+        assert_eq!(response.answers.len(), 1);
+    }
+
+    #[test]
+    fn test_additional_ttl_cap() {
+        let mut zone = cname_zone(&[]);
+        zone.records.push(Record::new(
+            "example.com".parse().unwrap(),
+            300,
+            TypeData::MX {
+                preference: 10,
+                exchange: "c.example.com".parse().unwrap(),
+            },
+        ));
+        zone.additional_ttl_cap = Some(60);
+        let packet = Packet {
+            questions: vec![Question::new(Type::MX, "example.com").unwrap()],
+            ..Default::default()
+        };
+        let response = answer_query_into(&zone, &packet, Packet::default()).packet;
+        assert_eq!(response.answers[0].ttl, 300);
+        assert_eq!(response.additional_records.len(), 1);
+        assert_eq!(response.additional_records[0].ttl, 60);
+    }
+
+    #[test]
+    fn test_in_bailiwick_glue() {
+        let mut zone = Zone::default();
+        for (name, ip) in [
+            ("ns1.sub.example.com", "192.0.2.53"),
+            ("ns.example.net", "192.0.2.54"),
+        ] {
+            zone.records.push(Record::new(
+                name.parse().unwrap(),
+                300,
+                TypeData::A(ip.parse().unwrap()),
+            ));
+        }
+        zone.zones.insert(
+            "sub.example.com".parse().unwrap(),
+            Zone {
+                authoritative: false,
+                nameservers: vec![
+                    "ns1.sub.example.com".parse().unwrap(),
+                    "ns.example.net".parse().unwrap(),
+                ],
+                ..Default::default()
+            },
+        );
+        let packet = Packet {
+            questions: vec![Question::new(Type::A, "www.sub.example.com").unwrap()],
+            ..Default::default()
+        };
+        let response = answer_query_into(&zone, &packet, Packet::default()).packet;
+        assert_eq!(response.nameservers.len(), 2);
+        assert_eq!(response.additional_records.len(), 1);
+        assert_eq!(response.additional_records[0].name, "ns1.sub.example.com");
+    }
+
+    #[test]
+    fn test_chaos() {
+        let zone: Zone = serde_yaml::from_str("server_id: ns1-test").unwrap();
+        assert!(!serde_yaml::to_string(&zone)
+            .unwrap()
+            .contains("identity_ttl"));
+        let chaos = |name: &str| Packet {
+            questions: vec![Question {
+                name: name.parse().unwrap(),
+                type_: Type::TXT,
+                class: Class::CH,
+            }],
+            ..Default::default()
+        };
+        let response = answer_query_into(&zone, &chaos("hostname.bind"), Packet::default()).packet;
+        assert_eq!(response.answers.len(), 1);
+        assert_eq!(response.answers[0].class, Class::CH);
+        assert_eq!(response.answers[0].data.to_string(), "ns1-test");
+        let response = answer_query_into(&zone, &chaos("version.bind"), Packet::default()).packet;
+        assert!(response.answers[0].data.to_string().starts_with("adns-"));
+        assert_eq!(response.answers[0].ttl, 3600);
+
+        let response =
+            answer_query_into(&Zone::default(), &chaos("id.server"), Packet::default()).packet;
+        assert_eq!(response.header.response_code, ResponseCode::NameError);
+
+        let hidden = Zone {
+            identity_queries: IdentityQueries::Text("hidden".to_string()),
+            identity_ttl: 0,
+            ..zone.clone()
+        };
+        for name in ["version.bind", "hostname.bind"] {
+            let response = answer_query_into(&hidden, &chaos(name), Packet::default()).packet;
+            assert_eq!(response.answers[0].data.to_string(), "hidden");
+            assert_eq!(response.answers[0].ttl, 0);
+        }
+        let refusing = Zone {
+            identity_queries: IdentityQueries::Refuse,
+            ..zone.clone()
+        };
+        let response =
+            answer_query_into(&refusing, &chaos("version.bind"), Packet::default()).packet;
+        assert_eq!(response.header.response_code, ResponseCode::Refused);
+        assert!(response.answers.is_empty());
+
+        // the IN class is answered from the zone as usual
+        let packet = Packet {
+            questions: vec![Question::new(Type::TXT, "version.bind").unwrap()],
+            ..Default::default()
+        };
+        let response = answer_query_into(&zone, &packet, Packet::default()).packet;
+        assert!(response.answers.is_empty());
+    }
+
+    #[test]
+    fn test_nsec_nxdomain() {
+        let signed = Zone::parse_masterfile(
+            "
+$TTL 300
+$ORIGIN example.com.
+@       SOA ns1 hostmaster 1 3600 600 86400 300
+        NSEC b A NSEC SOA
+b       A 192.0.2.1
+        NSEC example.com. A NSEC
+",
+            "example.com".parse().unwrap(),
+        )
+        .unwrap();
+        let mut zone = Zone::default();
+        zone.zones.insert("example.com".parse().unwrap(), signed);
+        let mut packet = Packet {
+            questions: vec![Question::new(Type::A, "c.example.com").unwrap()],
+            ..Default::default()
+        };
+        let response = answer_query_into(&zone, &packet, Packet::default()).packet;
+        assert_eq!(response.header.response_code, ResponseCode::NameError);
+        assert!(response.nameservers.iter().all(|x| x.type_ != Type::NSEC));
+
+        let mut edns = Edns::new(1232);
+        edns.dnssec_ok = true;
+        packet.edns = Some(edns);
+        let response = answer_query_into(&zone, &packet, Packet::default()).packet;
+        assert_eq!(response.header.response_code, ResponseCode::NameError);
+        let nsec: Vec<&Name> = response
+            .nameservers
+            .iter()
+            .filter(|x| x.type_ == Type::NSEC)
+            .map(|x| &x.name)
+            .collect();
+        assert_eq!(
+            nsec,
+            vec![
+                &"b.example.com".parse::<Name>().unwrap(),
+                &"example.com".parse().unwrap()
+            ]
+        );
+
+        // NODATA is proven by the NSEC at the name itself
+        packet.questions = vec![Question::new(Type::TXT, "b.example.com").unwrap()];
+        let response = answer_query_into(&zone, &packet, Packet::default()).packet;
+        assert_eq!(response.header.response_code, ResponseCode::NoError);
+        assert!(response.answers.is_empty());
+        assert!(response
+            .nameservers
+            .iter()
+            .any(|x| x.type_ == Type::NSEC && x.name == "b.example.com"));
+    }
+
+    #[test]
+    fn test_ds_at_delegation() {
+        let mut parent = Zone::parse_masterfile(
+            "
+$TTL 300
+$ORIGIN example.com.
+@       SOA ns1 hostmaster 1 3600 600 86400 300
+@       NS ns1
+ns1     A 192.0.2.1
+",
+            "example.com".parse().unwrap(),
+        )
+        .unwrap();
+        parent.signing_keys.push(ZoneSigningKey {
+            algorithm: 13,
+            private_key: vec![1; 32],
+            state: KeyState::Active,
+        });
+        parent.records.push(Record::new(
+            "secure.example.com".parse().unwrap(),
+            300,
+            TypeData::Other(Type::DS, smallvec::smallvec![1, 2, 13, 2, 0xab]),
+        ));
+        let mut zone = Zone::default();
+        zone.zones.insert("example.com".parse().unwrap(), parent);
+        for child in ["secure.example.com", "insecure.example.com"] {
+            zone.zones.insert(
+                child.parse().unwrap(),
+                Zone {
+                    nameservers: vec![format!("ns1.{child}").parse().unwrap()],
+                    ..Default::default()
+                },
+            );
+        }
+        zone.insert_dnskeys();
+        let mut edns = Edns::new(1232);
+        edns.dnssec_ok = true;
+        let query = |type_: Type, name: &str| {
+            let packet = Packet {
+                questions: vec![Question::new(type_, name).unwrap()],
+                edns: Some(edns.clone()),
+                ..Default::default()
+            };
+            answer_query_into(&zone, &packet, Packet::default()).packet
+        };
+
+        // the parent answers for the DS RRset itself, signed with its own key
+        let response = query(Type::DS, "secure.example.com");
+        assert!(response.header.is_authoritative);
+        let types: Vec<Type> = response.answers.iter().map(|x| x.type_).collect();
+        assert_eq!(types, vec![Type::DS, Type::RRSIG]);
+        assert!(
+            matches!(&response.answers[1].data, TypeData::RRSIG(rrsig) if rrsig.signer == "example.com")
+        );
+        assert!(response.nameservers.is_empty());
+
+        // no DS: a signed NSEC at the delegation proves it's insecure
+        let response = query(Type::DS, "insecure.example.com");
+        assert_eq!(response.header.response_code, ResponseCode::NoError);
+        assert!(response.header.is_authoritative);
+        assert!(response.answers.is_empty());
+        let types: Vec<Type> = response.nameservers.iter().map(|x| x.type_).collect();
+        assert_eq!(types, vec![Type::SOA, Type::NSEC, Type::RRSIG, Type::RRSIG]);
+        assert_eq!(response.nameservers[0].name, "example.com");
+        let TypeData::NSEC { types, .. } = &response.nameservers[1].data else {
+            panic!("expected an NSEC");
+        };
+        assert_eq!(response.nameservers[1].name, "insecure.example.com");
+        assert!(types.contains(&Type::NS) && !types.contains(&Type::DS));
+
+        // other queries are still referred to the child, unsigned
+        let response = query(Type::A, "www.secure.example.com");
+        assert!(!response.header.is_authoritative);
+        let types: Vec<Type> = response.nameservers.iter().map(|x| x.type_).collect();
+        assert_eq!(types, vec![Type::NS]);
+    }
+
+    #[test]
+    fn test_nxdomain_redirect() {
+        let mut zone = cname_zone(&[]);
+        zone.nxdomain_redirect = Some(NxdomainRedirect {
+            addresses: vec!["10.0.0.1".parse().unwrap()],
+            ttl: 60,
+        });
+        let mut packet = Packet {
+            questions: vec![Question::new(Type::A, "missing.example.com").unwrap()],
+            ..Default::default()
+        };
+        let response = answer_query_into(&zone, &packet, Packet::default()).packet;
+        assert_eq!(response.header.response_code, ResponseCode::NoError);
+        assert!(!response.header.is_authoritative);
+        assert_eq!(
+            response.answers,
+            vec![Record::new(
+                "missing.example.com".parse().unwrap(),
+                60,
+                TypeData::A("10.0.0.1".parse().unwrap())
+            )]
+        );
+
+        // existing names are untouched, as are types without a configured address and DNSSEC queries
+        packet.questions = vec![Question::new(Type::A, "c.example.com").unwrap()];
+        let response = answer_query_into(&zone, &packet, Packet::default()).packet;
+        assert_eq!(
+            response.answers[0].data,
+            TypeData::A("1.2.3.4".parse().unwrap())
+        );
+        packet.questions = vec![Question::new(Type::AAAA, "missing.example.com").unwrap()];
+        let response = answer_query_into(&zone, &packet, Packet::default()).packet;
+        assert_eq!(response.header.response_code, ResponseCode::NameError);
+        packet.questions = vec![Question::new(Type::A, "missing.example.com").unwrap()];
+        let mut edns = Edns::new(1232);
+        edns.dnssec_ok = true;
+        packet.edns = Some(edns);
+        let response = answer_query_into(&zone, &packet, Packet::default()).packet;
+        assert_eq!(response.header.response_code, ResponseCode::NameError);
+    }
+
+    #[test]
+    fn test_online_signing() {
+        let mut signed = Zone::parse_masterfile(
+            "
+$TTL 300
+$ORIGIN example.com.
+@       SOA ns1 hostmaster 1 3600 600 86400 300
+www     A 192.0.2.1
+",
+            "example.com".parse().unwrap(),
+        )
+        .unwrap();
+        signed.signing_keys.push(ZoneSigningKey {
+            algorithm: 13,
+            private_key: vec![1; 32],
+            state: KeyState::Active,
+        });
+        let mut zone = Zone::default();
+        zone.zones.insert("example.com".parse().unwrap(), signed);
+        zone.insert_dnskeys();
+        let mut packet = Packet {
+            questions: vec![Question::new(Type::A, "www.example.com").unwrap()],
+            ..Default::default()
+        };
+        let response = answer_query_into(&zone, &packet, Packet::default()).packet;
+        assert!(response.answers.iter().all(|x| x.type_ != Type::RRSIG));
+
+        let mut edns = Edns::new(1232);
+        edns.dnssec_ok = true;
+        packet.edns = Some(edns);
+        let response = answer_query_into(&zone, &packet, Packet::default()).packet;
+        let types: Vec<Type> = response.answers.iter().map(|x| x.type_).collect();
+        assert_eq!(types, vec![Type::A, Type::RRSIG]);
+
+        // the DNSKEY RRset is served at the apex, and NODATA proofs are signed too
+        packet.questions = vec![Question::new(Type::DNSKEY, "example.com").unwrap()];
+        let response = answer_query_into(&zone, &packet, Packet::default()).packet;
+        let types: Vec<Type> = response.answers.iter().map(|x| x.type_).collect();
+        assert_eq!(types, vec![Type::DNSKEY, Type::RRSIG]);
+        packet.questions = vec![Question::new(Type::TXT, "www.example.com").unwrap()];
+        let response = answer_query_into(&zone, &packet, Packet::default()).packet;
+        let types: Vec<Type> = response.nameservers.iter().map(|x| x.type_).collect();
+        assert_eq!(types, vec![Type::SOA, Type::NSEC, Type::RRSIG, Type::RRSIG]);
+    }
+
+    #[test]
+    fn test_apex_alias() {
+        let apex = Zone::parse_masterfile(
+            "
+$TTL 300
+$ORIGIN example.com.
+@       SOA ns1 hostmaster 1 3600 600 86400 300
+        NS ns1
+        ALIAS www
+www     60 A 192.0.2.1
+        AAAA 2001:db8::1
+ns1     A 192.0.2.53
+",
+            "example.com".parse().unwrap(),
+        )
+        .unwrap();
+        let mut zone = Zone::default();
+        zone.zones.insert("example.com".parse().unwrap(), apex);
+        let ask = |type_: Type| {
+            let packet = Packet {
+                questions: vec![Question::new(type_, "example.com").unwrap()],
+                ..Default::default()
+            };
+            answer_query_into(&zone, &packet, Packet::default()).packet
+        };
+
+        let response = ask(Type::A);
+        assert_eq!(response.header.response_code, ResponseCode::NoError);
+        assert_eq!(
+            response.answers,
+            vec![Record::new(
+                "example.com".parse().unwrap(),
+                60,
+                TypeData::A("192.0.2.1".parse().unwrap()),
+            )]
+        );
+        let response = ask(Type::AAAA);
+        assert_eq!(response.answers.len(), 1);
+        assert_eq!(response.answers[0].name, "example.com");
+        // the SOA and NS records are still served next to the ALIAS
+        let response = ask(Type::SOA);
+        assert_eq!(response.answers.len(), 1);
+        assert_eq!(response.answers[0].type_, Type::SOA);
+        let response = ask(Type::NS);
+        assert!(!response.answers.is_empty());
+        assert!(response.answers.iter().all(|x| x.type_ == Type::NS));
+        // other types are NODATA rather than following the ALIAS
+        let response = ask(Type::MX);
+        assert_eq!(response.header.response_code, ResponseCode::NoError);
+        assert!(response.answers.is_empty());
+    }
+
+    #[test]
+    fn test_svcb_alias() {
+        let https = |name: &str, data: &str| {
+            Record::new(
+                name.parse().unwrap(),
+                300,
+                TypeData::parse_str(Type::HTTPS, data).unwrap(),
+            )
+        };
+        let mut zone = Zone {
+            records: vec![
+                https("example.com", "0 svc.example.com"),
+                https("svc.example.com", "1 . alpn=h2"),
+                Record::new(
+                    "svc.example.com".parse().unwrap(),
+                    300,
+                    TypeData::A("192.0.2.1".parse().unwrap()),
+                ),
+                Record::new(
+                    "svc.example.com".parse().unwrap(),
+                    300,
+                    TypeData::AAAA("2001:db8::1".parse().unwrap()),
+                ),
+                https("loop.example.com", "0 loop.example.com"),
+            ],
+            authoritative: true,
+            ..Default::default()
+        };
+        let ask = |zone: &Zone, name: &str| {
+            let packet = Packet {
+                questions: vec![Question::new(Type::HTTPS, name).unwrap()],
+                ..Default::default()
+            };
+            answer_query_into(zone, &packet, Packet::default()).packet
+        };
+
+        // the AliasMode target's ServiceMode record is answered, with its addresses as additional records
+        let response = ask(&zone, "example.com");
+        assert_eq!(response.header.response_code, ResponseCode::NoError);
+        assert_eq!(
+            response.answers,
+            vec![zone.records[0].clone(), zone.records[1].clone()]
+        );
+        let mut additional: Vec<Type> = response
+            .additional_records
+            .iter()
+            .map(|x| x.type_)
+            .collect();
+        additional.sort();
+        assert_eq!(additional, vec![Type::A, Type::AAAA]);
+
+        // following a loop stops after a bounded number of aliases, and the repeats are deduplicated
+        let response = ask(&zone, "loop.example.com");
+        assert_eq!(response.answers, vec![zone.records[4].clone()]);
+
+        // an alias to a name without SVCB records answers just the alias
+        zone.records.drain(1..4);
+        let response = ask(&zone, "example.com");
+        assert_eq!(response.answers, vec![zone.records[0].clone()]);
+    }
+
+    #[test]
+    fn test_dedup_records() {
+        let a = |name: &str, ip: &str| {
+            Record::new(name.parse().unwrap(), 300, TypeData::A(ip.parse().unwrap()))
+        };
+        let mut records = vec![
+            a("a.example.com", "10.0.0.1"),
+            a("b.example.com", "10.0.0.1"),
+            a("a.example.com", "10.0.0.2"),
+            a("a.example.com", "10.0.0.1"),
+            a("b.example.com", "10.0.0.1"),
+        ];
+        dedup_records(&mut records);
+        // the first of each duplicate is kept, in the original order
+        assert_eq!(
+            records,
+            vec![
+                a("a.example.com", "10.0.0.1"),
+                a("b.example.com", "10.0.0.1"),
+                a("a.example.com", "10.0.0.2"),
+            ]
+        );
+    }
+}
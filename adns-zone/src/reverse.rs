@@ -0,0 +1,217 @@
+use std::net::IpAddr;
+
+use adns_proto::{Name, Record, TypeData};
+use ipnet::IpNet;
+
+use crate::{index::is_wildcard, Zone};
+
+/// the `in-addr.arpa`/`ip6.arpa` zones holding the PTRs of `network`, with the part of it each covers. a prefix that
+/// isn't a whole octet (IPv4) or nibble (IPv6) is split into the zones of the next longer one, so a /20 is served
+/// as 16 /24 zones and never claims addresses outside of it
+pub fn reverse_zones(network: IpNet) -> Vec<(Name, IpNet)> {
+    let (step, total) = match network {
+        IpNet::V4(_) => (8, 4),
+        IpNet::V6(_) => (4, 32),
+    };
+    let prefix_len = network.prefix_len().div_ceil(step) * step;
+    let Ok(subnets) = network.subnets(prefix_len) else {
+        return vec![];
+    };
+    subnets
+        .map(|subnet| {
+            let pointer = Name::reverse_pointer(subnet.network());
+            let skip = total - (prefix_len / step) as usize;
+            let name = Name::from_segments(pointer.segments().skip(skip)).unwrap();
+            (name, subnet)
+        })
+        .collect()
+}
+
+impl Zone {
+    /// A/AAAA records in `network` from this zone and its subzones, wildcards excluded
+    fn addresses_in<'a>(&'a self, network: IpNet, out: &mut Vec<(&'a Record, IpAddr)>) {
+        for record in &self.records {
+            let ip = match &record.data {
+                TypeData::A(ip) => IpAddr::V4(*ip),
+                TypeData::AAAA(ip) => IpAddr::V6(*ip),
+                _ => continue,
+            };
+            if network.contains(&ip) && !is_wildcard(&record.name) {
+                out.push((record, ip));
+            }
+        }
+        for zone in self.zones.values() {
+            zone.addresses_in(network, out);
+        }
+    }
+
+    /// builds the reverse zone of `network` with a PTR for every address of it in this zone's A/AAAA records.
+    /// an address with several names gets a PTR for each. the SOA and name servers are this zone's
+    pub fn generate_reverse(&self, network: IpNet) -> Zone {
+        let mut addresses = vec![];
+        self.addresses_in(network, &mut addresses);
+        let mut records: Vec<Record> = addresses
+            .into_iter()
+            .map(|(record, ip)| {
+                Record::new(
+                    Name::reverse_pointer(ip),
+                    record.ttl,
+                    TypeData::PTR(record.name.clone()),
+                )
+            })
+            .collect();
+        records.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.data.cmp(&b.data)));
+        records.dedup_by(|a, b| a.name == b.name && a.data == b.data);
+        Zone {
+            records,
+            soa: self.soa.clone(),
+            nameservers: self.nameservers.clone(),
+            authoritative: true,
+            ..Default::default()
+        }
+    }
+
+    /// adds the reverse zones configured by `Zone::reverse_zones` on this zone and its subzones. PTRs already
+    /// in a configured zone of the same name take precedence, generated ones are only added at names without any
+    pub fn insert_reverse_zones(&mut self) {
+        let mut generated = vec![];
+        for zone in std::iter::once(&*self).chain(self.zones.values()) {
+            for network in &zone.reverse_zones {
+                for (name, subnet) in reverse_zones(*network) {
+                    generated.push((name, zone.generate_reverse(subnet)));
+                }
+            }
+        }
+        for (name, reverse) in generated {
+            let Some(existing) = self.zones.get_mut(&name) else {
+                self.zones.insert(name, reverse);
+                continue;
+            };
+            let missing: Vec<Record> = reverse
+                .records
+                .into_iter()
+                .filter(|record| {
+                    !existing
+                        .records
+                        .iter()
+                        .any(|x| x.name == record.name && matches!(x.data, TypeData::PTR(_)))
+                })
+                .collect();
+            existing.records.extend(missing);
+            existing.index = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reverse_zones() {
+        let names = |network: &str| -> Vec<String> {
+            reverse_zones(network.parse().unwrap())
+                .into_iter()
+                .map(|(name, _)| name.to_string())
+                .collect()
+        };
+        assert_eq!(names("10.1.0.0/16"), vec!["1.10.in-addr.arpa"]);
+        let split = names("10.1.16.0/20");
+        assert_eq!(split.len(), 16);
+        assert_eq!(split[0], "16.1.10.in-addr.arpa");
+        assert_eq!(split[15], "31.1.10.in-addr.arpa");
+        assert_eq!(names("192.0.2.1/32"), vec!["1.2.0.192.in-addr.arpa"]);
+        assert_eq!(names("2001:db8::/32"), vec!["8.b.d.0.1.0.0.2.ip6.arpa"]);
+        assert_eq!(
+            names("2001:db8::/31"),
+            vec!["8.b.d.0.1.0.0.2.ip6.arpa", "9.b.d.0.1.0.0.2.ip6.arpa"]
+        );
+    }
+
+    #[test]
+    fn test_generate_reverse() {
+        let mut forward = Zone::parse_masterfile(
+            "
+$TTL 300
+$ORIGIN example.com.
+@       SOA ns1 hostmaster 1 3600 600 86400 300
+www     A 10.1.0.1
+web     A 10.1.0.1
+mail    A 10.1.2.3
+other   A 192.0.2.1
+*       A 10.1.0.9
+**.any  A 10.1.0.10
+*+.some A 10.1.0.11
+v6      AAAA 2001:db8::1
+",
+            "example.com".parse().unwrap(),
+        )
+        .unwrap();
+        forward.reverse_zones = vec!["10.1.0.0/16".parse().unwrap()];
+        let mut zone = Zone::default();
+        zone.zones.insert("example.com".parse().unwrap(), forward);
+        // a hand-written PTR wins over the generated one
+        zone.zones.insert(
+            "1.10.in-addr.arpa".parse().unwrap(),
+            Zone {
+                records: vec![Record::new(
+                    "3.2.1.10.in-addr.arpa".parse().unwrap(),
+                    300,
+                    TypeData::PTR("smtp.example.com".parse().unwrap()),
+                )],
+                ..Default::default()
+            },
+        );
+        zone.insert_reverse_zones();
+
+        let ptrs = |name: &str| -> Vec<String> {
+            zone.lookup(&name.parse().unwrap(), None)
+                .map(|x| x.data.to_string())
+                .collect()
+        };
+        assert_eq!(
+            ptrs("1.0.1.10.in-addr.arpa"),
+            vec!["web.example.com", "www.example.com"]
+        );
+        assert_eq!(ptrs("3.2.1.10.in-addr.arpa"), vec!["smtp.example.com"]);
+        assert!(ptrs("9.0.1.10.in-addr.arpa").is_empty());
+        assert!(ptrs("10.0.1.10.in-addr.arpa").is_empty());
+        assert!(ptrs("11.0.1.10.in-addr.arpa").is_empty());
+        assert!(ptrs("1.2.0.192.in-addr.arpa").is_empty());
+    }
+
+    #[test]
+    fn test_generate_reverse_partial() {
+        let mut forward = Zone::parse_masterfile(
+            "
+$TTL 300
+$ORIGIN example.com.
+@       SOA ns1 hostmaster 1 3600 600 86400 300
+inside  A 10.1.17.1
+outside A 10.1.32.1
+",
+            "example.com".parse().unwrap(),
+        )
+        .unwrap();
+        forward.reverse_zones = vec!["10.1.16.0/20".parse().unwrap()];
+        let mut zone = Zone::default();
+        zone.zones.insert("example.com".parse().unwrap(), forward);
+        zone.insert_reverse_zones();
+
+        // only the /24s of the /20 are served, not the whole /16
+        assert!(zone
+            .zones
+            .contains_key(&"17.1.10.in-addr.arpa".parse::<Name>().unwrap()));
+        assert!(!zone
+            .zones
+            .contains_key(&"1.10.in-addr.arpa".parse::<Name>().unwrap()));
+        assert!(!zone
+            .zones
+            .contains_key(&"32.1.10.in-addr.arpa".parse::<Name>().unwrap()));
+        let ptrs: Vec<String> = zone
+            .lookup(&"1.17.1.10.in-addr.arpa".parse().unwrap(), None)
+            .map(|x| x.data.to_string())
+            .collect();
+        assert_eq!(ptrs, vec!["inside.example.com"]);
+    }
+}
@@ -0,0 +1,123 @@
+use adns_proto::{Question, Record, Type, TypeData};
+
+use crate::Zone;
+
+/// Response policy for a query name, encoded RPZ-style as records in `Zone::rpz`:
+/// `CNAME .` is NXDOMAIN, `CNAME *` is NODATA, `CNAME rpz-passthru` answers normally, and any other records are served in place of the zone.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PolicyAction {
+    NxDomain,
+    NoData,
+    Passthru,
+    /// records to answer with, already filtered by question type
+    LocalData(Vec<Record>),
+}
+
+impl Zone {
+    /// exact owner names take precedence over wildcard patterns
+    pub fn policy(&self, question: &Question) -> Option<PolicyAction> {
+        let owner = self
+            .rpz
+            .iter()
+            .find(|record| record.name == question.name)
+            .or_else(|| {
+                self.rpz
+                    .iter()
+                    .find(|record| record.name.contains(&question.name))
+            })
+            .map(|record| &record.name)?;
+        let rules = self
+            .rpz
+            .iter()
+            .filter(|record| &record.name == owner)
+            .collect::<Vec<_>>();
+
+        for rule in &rules {
+            let TypeData::CNAME(target) = &rule.data else {
+                continue;
+            };
+            if target.is_empty() {
+                return Some(PolicyAction::NxDomain);
+            } else if target == "*" {
+                return Some(PolicyAction::NoData);
+            } else if target == "rpz-passthru" {
+                return Some(PolicyAction::Passthru);
+            }
+        }
+
+        Some(PolicyAction::LocalData(
+            rules
+                .into_iter()
+                .filter(|rule| {
+                    question.type_.wants_by_query(rule.type_) || rule.type_ == Type::CNAME
+                })
+                .map(|rule| Record {
+                    name: question.name.clone(),
+                    ..rule.clone()
+                })
+                .collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use adns_proto::Name;
+
+    use super::*;
+
+    fn rule(name: &str, data: TypeData) -> Record {
+        Record::new(name.parse().unwrap(), 300, data)
+    }
+
+    fn cname(target: &str) -> TypeData {
+        TypeData::CNAME(target.parse::<Name>().unwrap())
+    }
+
+    #[test]
+    fn test_policy() {
+        let zone = Zone {
+            rpz: vec![
+                rule("blocked.example.com", cname(".")),
+                rule("**.ads.example.com", cname(".")),
+                rule("good.ads.example.com", cname("rpz-passthru")),
+                rule("empty.example.com", cname("*")),
+                rule(
+                    "sinkhole.example.com",
+                    TypeData::A("10.0.0.1".parse().unwrap()),
+                ),
+            ],
+            ..Default::default()
+        };
+        let policy = |type_, name| zone.policy(&Question::new(type_, name).unwrap());
+
+        assert_eq!(
+            policy(Type::A, "blocked.example.com"),
+            Some(PolicyAction::NxDomain)
+        );
+        assert_eq!(
+            policy(Type::A, "x.ads.example.com"),
+            Some(PolicyAction::NxDomain)
+        );
+        assert_eq!(
+            policy(Type::A, "good.ads.example.com"),
+            Some(PolicyAction::Passthru)
+        );
+        assert_eq!(
+            policy(Type::A, "empty.example.com"),
+            Some(PolicyAction::NoData)
+        );
+        assert_eq!(
+            policy(Type::A, "sinkhole.example.com"),
+            Some(PolicyAction::LocalData(vec![rule(
+                "sinkhole.example.com",
+                TypeData::A("10.0.0.1".parse().unwrap())
+            )]))
+        );
+        assert_eq!(
+            policy(Type::AAAA, "sinkhole.example.com"),
+            Some(PolicyAction::LocalData(vec![]))
+        );
+        assert_eq!(policy(Type::A, "example.com"), None);
+    }
+}
@@ -1,7 +1,105 @@
-use adns_proto::{Name, Record, SoaData, Type, TypeData};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use adns_proto::{Name, Record, Type, TypeData};
+use log::warn;
+use serde::{Deserialize, Serialize};
 
 use crate::Zone;
 
+/// how the SOA serial moves when an update changes a zone's records
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SerialPolicy {
+    /// leave the serial to the client
+    Keep,
+    /// serial + 1
+    #[default]
+    Increment,
+    /// `YYYYMMDDNN`, today's date with a two digit change counter, or + 1 if the serial is already past it
+    Date,
+}
+
+/// what an update does with a SOA that keeps the current serial but changes the data.
+/// secondaries assume equal serials mean equal data, so this usually means two writers disagree.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EqualSerialPolicy {
+    /// replace the SOA
+    #[default]
+    Accept,
+    /// replace the SOA and log a warning
+    Warn,
+    /// keep the current SOA and log a warning. the server refuses such updates before they're applied
+    Reject,
+}
+
+impl EqualSerialPolicy {
+    pub(crate) fn is_default(&self) -> bool {
+        *self == EqualSerialPolicy::default()
+    }
+
+    /// whether a SOA for `owner` with the current `serial` but different data may replace the current one
+    pub fn allows(&self, owner: &Name, serial: u32) -> bool {
+        match self {
+            EqualSerialPolicy::Accept => true,
+            EqualSerialPolicy::Warn => {
+                warn!("SOA update for {owner} changes the data but keeps serial {serial}");
+                true
+            }
+            EqualSerialPolicy::Reject => {
+                warn!("rejected SOA update for {owner}: the data differs but serial {serial} is unchanged");
+                false
+            }
+        }
+    }
+}
+
+impl SerialPolicy {
+    pub(crate) fn is_default(&self) -> bool {
+        *self == SerialPolicy::default()
+    }
+
+    pub fn next_serial(&self, serial: u32) -> u32 {
+        match self {
+            SerialPolicy::Keep => serial,
+            SerialPolicy::Increment => serial.wrapping_add(1),
+            SerialPolicy::Date => {
+                let days = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs()
+                    / 86400;
+                let (year, month, day) = civil_from_days(days as i64);
+                let today = (year as u32 * 10000 + month * 100 + day) * 100;
+                if serial < today {
+                    today
+                } else {
+                    serial.wrapping_add(1)
+                }
+            }
+        }
+    }
+}
+
+/// proleptic Gregorian (year, month, day) of a day count since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
 pub struct ZoneUpdate {
     /// "" for root zone, "name" for 2nd level zone
     pub zone_name: Name,
@@ -15,20 +113,37 @@ pub enum ZoneUpdateAction {
 }
 
 impl ZoneUpdate {
+    /// whether the update adds an SOA, which brings its own serial so the serial policy doesn't apply
+    pub fn sets_soa(&self) -> bool {
+        self.actions.iter().any(|action| {
+            matches!(action, ZoneUpdateAction::AddRecord(record) if record.type_ == Type::SOA)
+        })
+    }
+
     pub fn apply_to(&self, root_zone: &mut Zone) {
         let zone = if self.zone_name.is_empty() {
             root_zone
         } else {
             root_zone.zones.entry(self.zone_name.clone()).or_default()
         };
+        let mut changed = false;
         for action in &self.actions {
-            action.apply_to(&self.zone_name, zone);
+            changed |= action.apply_to(&self.zone_name, zone);
+        }
+        if changed && !self.sets_soa() {
+            let policy = zone.serial_policy;
+            if let Some(soa) = &mut zone.soa {
+                soa.serial = policy.next_serial(soa.serial);
+            }
         }
     }
 }
 
 impl ZoneUpdateAction {
-    pub fn apply_to(&self, zone_name: &Name, zone: &mut Zone) {
+    /// applies the action to `zone`, returning whether its records changed
+    pub fn apply_to(&self, zone_name: &Name, zone: &mut Zone) -> bool {
+        zone.index = None;
+        let before = zone.records.len();
         match self {
             ZoneUpdateAction::DeleteRecords(name, None) => {
                 if name == zone_name {
@@ -40,13 +155,15 @@ impl ZoneUpdateAction {
                 } else {
                     zone.records.retain(|record| &record.name != name);
                 }
+                zone.records.len() != before
             }
             ZoneUpdateAction::DeleteRecords(name, Some(type_)) => {
                 if name == zone_name && (*type_ == Type::SOA || *type_ == Type::NS) {
-                    return;
+                    return false;
                 }
                 zone.records
                     .retain(|record| &record.name != name || record.type_ != *type_);
+                zone.records.len() != before
             }
             ZoneUpdateAction::DeleteRecord(name, data) => {
                 if name == zone_name
@@ -54,11 +171,12 @@ impl ZoneUpdateAction {
                         || (data.dns_type() == Type::NS
                             && zone.records.iter().filter(|x| x.type_ == Type::NS).count() <= 1))
                 {
-                    return;
+                    return false;
                 }
                 zone.records.retain(|record| {
                     &record.name != name || record.type_ != data.dns_type() || &record.data != data
                 });
+                zone.records.len() != before
             }
             ZoneUpdateAction::AddRecord(record) => {
                 let mut record = record.clone();
@@ -71,25 +189,31 @@ impl ZoneUpdateAction {
                             .map(|x| x.type_ != Type::CNAME)
                             .unwrap_or_default()
                     {
-                        return;
+                        return false;
                     }
                 } else if zone.records.iter().any(|x| x.type_ == Type::CNAME) {
-                    return;
+                    return false;
                 }
                 if record.type_ == Type::SOA {
-                    let Record { data: TypeData::SOA(SoaData { serial: new_serial, .. }), .. } = &record else {
-                        return;
+                    let Record { data: TypeData::SOA(new_soa), .. } = &record else {
+                        return false;
                     };
                     if let Some(Record {
-                        data: TypeData::SOA(SoaData { serial, .. }),
+                        data: TypeData::SOA(soa),
                         ..
                     }) = zone
                         .records
                         .iter()
                         .find(|x| x.name == record.name && x.type_ == Type::SOA)
                     {
-                        if serial > new_serial {
-                            return;
+                        if soa.serial > new_soa.serial {
+                            return false;
+                        }
+                        if soa.serial == new_soa.serial
+                            && soa != new_soa
+                            && !zone.equal_serial.allows(&record.name, soa.serial)
+                        {
+                            return false;
                         }
                     }
                 }
@@ -100,12 +224,119 @@ impl ZoneUpdateAction {
                         || record.type_ == Type::SOA
                         || record.data == zone_record.data
                     {
+                        let changed = *zone_record != record;
                         *zone_record = record;
-                        return;
+                        return changed;
                     }
                 }
                 zone.records.push(record);
+                true
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use adns_proto::SoaData;
+
+    use super::*;
+
+    #[test]
+    fn test_serial_bump() {
+        let mut root = Zone::default();
+        root.zones.insert(
+            "example.com".parse().unwrap(),
+            Zone {
+                soa: Some(SoaData {
+                    mname: "ns1.example.com".parse().unwrap(),
+                    rname: "hostmaster.example.com".parse().unwrap(),
+                    serial: 5,
+                    refresh: 3600,
+                    retry: 600,
+                    expire: 86400,
+                    minimum: 300,
+                }),
+                ..Default::default()
+            },
+        );
+        let record = Record::new(
+            "a.example.com".parse().unwrap(),
+            300,
+            TypeData::A("192.0.2.1".parse().unwrap()),
+        );
+        let update = ZoneUpdate {
+            zone_name: "example.com".parse().unwrap(),
+            actions: vec![ZoneUpdateAction::AddRecord(record)],
+        };
+        let serial = |root: &Zone| root.zones[0].soa.as_ref().unwrap().serial;
+        update.apply_to(&mut root);
+        assert_eq!(serial(&root), 6);
+        // no change, no bump
+        update.apply_to(&mut root);
+        assert_eq!(serial(&root), 6);
+
+        // an update bringing its own SOA sets the serial
+        let soa = Record::new(
+            "example.com".parse().unwrap(),
+            300,
+            TypeData::SOA(root.zones[0].soa.clone().unwrap()),
+        );
+        let update = ZoneUpdate {
+            zone_name: "example.com".parse().unwrap(),
+            actions: vec![
+                ZoneUpdateAction::DeleteRecords("a.example.com".parse().unwrap(), None),
+                ZoneUpdateAction::AddRecord(soa),
+            ],
+        };
+        assert!(update.sets_soa());
+        update.apply_to(&mut root);
+        assert!(root.zones[0].records.iter().all(|x| x.type_ == Type::SOA));
+        assert_eq!(serial(&root), 6);
+
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19723), (2024, 1, 1));
+        assert_eq!(SerialPolicy::Date.next_serial(1), SerialPolicy::Date.next_serial(0));
+        assert_eq!(SerialPolicy::Date.next_serial(4_000_000_000), 4_000_000_001);
+        assert_eq!(SerialPolicy::Keep.next_serial(7), 7);
+    }
+
+    #[test]
+    fn test_equal_serial() {
+        let soa = |minimum| {
+            Record::new(
+                "example.com".parse().unwrap(),
+                3600,
+                TypeData::SOA(SoaData {
+                    mname: "ns1.example.com".parse().unwrap(),
+                    rname: "hostmaster.example.com".parse().unwrap(),
+                    serial: 5,
+                    refresh: 3600,
+                    retry: 600,
+                    expire: 86400,
+                    minimum,
+                }),
+            )
+        };
+        let zone_name: Name = "example.com".parse().unwrap();
+        for (policy, replaced) in [
+            (EqualSerialPolicy::Accept, true),
+            (EqualSerialPolicy::Warn, true),
+            (EqualSerialPolicy::Reject, false),
+        ] {
+            let mut zone = Zone {
+                records: vec![soa(300)],
+                equal_serial: policy,
+                ..Default::default()
+            };
+            ZoneUpdateAction::AddRecord(soa(60)).apply_to(&zone_name, &mut zone);
+            assert_eq!(zone.records.len(), 1);
+            assert_eq!(zone.records[0] == soa(60), replaced, "{policy:?}");
+            // resending the current SOA is never a conflict
+            let current = zone.records[0].clone();
+            zone.equal_serial = EqualSerialPolicy::Reject;
+            ZoneUpdateAction::AddRecord(current.clone()).apply_to(&zone_name, &mut zone);
+            assert_eq!(zone.records, vec![current]);
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::HashSet;
+
+use adns_proto::{Name, Record, Type, TypeData};
+use thiserror::Error;
+
+use crate::Zone;
+
+/// a problem with a zone's data that would make it answer wrongly, found by `Zone::validate`
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ZoneValidationError {
+    #[error("zone {0} has no SOA, neither its own nor its parent's")]
+    MissingSoa(Name),
+    #[error("zone {0} has no name servers, neither its own nor its parent's")]
+    MissingNameservers(Name),
+    #[error("{0} has a CNAME alongside other records, see `Zone::drop_cname_conflicts`")]
+    CnameAndOtherData(Name),
+    #[error("{type_} at {name} points to {target}, which has no A or AAAA record")]
+    DanglingTarget {
+        name: Name,
+        type_: Type,
+        target: Name,
+    },
+}
+
+impl Zone {
+    /// checks this zone and its subzones for missing SOAs and name servers, CNAMEs that aren't alone at their name,
+    /// and MX/SRV targets inside our authoritative zones without addresses. meant to be run on load
+    pub fn validate(&self) -> Vec<ZoneValidationError> {
+        let mut out = vec![];
+        self.validate_zone(self, None, None, &Name::default(), &mut out);
+        out
+    }
+
+    /// like `validate`, but for a zone that isn't wrapped in a root zone yet, such as a freshly loaded master file.
+    /// the zone itself is checked as the apex `name`, even if that is the root
+    pub fn validate_apex(&self, name: &Name) -> Vec<ZoneValidationError> {
+        let mut out = vec![];
+        self.validate_zone(self, Some(name), None, name, &mut out);
+        out
+    }
+
+    fn validate_zone(
+        &self,
+        root: &Zone,
+        apex: Option<&Name>,
+        parent: Option<&Zone>,
+        name: &Name,
+        out: &mut Vec<ZoneValidationError>,
+    ) {
+        // a root zone that is only a container for its subzones needs no SOA or name servers of its own
+        if (parent.is_some() || apex.is_some()) && self.authoritative {
+            if self.soa.is_none() && parent.is_none_or(|x| x.soa.is_none()) {
+                out.push(ZoneValidationError::MissingSoa(name.clone()));
+            }
+            if self.nameservers.is_empty() && parent.is_none_or(|x| x.nameservers.is_empty()) {
+                out.push(ZoneValidationError::MissingNameservers(name.clone()));
+            }
+        }
+
+        let with_data: HashSet<&Name> = self
+            .records
+            .iter()
+            .filter(|x| !may_accompany_cname(x.type_))
+            .map(|x| &x.name)
+            .collect();
+        let mut reported: HashSet<&Name> = HashSet::new();
+        for record in &self.records {
+            if record.type_ == Type::CNAME
+                && with_data.contains(&record.name)
+                && reported.insert(&record.name)
+            {
+                out.push(ZoneValidationError::CnameAndOtherData(record.name.clone()));
+            }
+            let target = match &record.data {
+                TypeData::MX { exchange, .. } => exchange,
+                TypeData::SRV { target, .. } => target,
+                _ => continue,
+            };
+            // `.` is a null MX (RFC 7505) or an unavailable service (RFC 2782)
+            let served = root.is_served(target).unwrap_or_else(|| {
+                apex.is_some_and(|apex| root.authoritative && target.ends_with(apex))
+            });
+            if !target.is_empty() && served && !root.has_address(target) {
+                out.push(ZoneValidationError::DanglingTarget {
+                    name: record.name.clone(),
+                    type_: record.type_,
+                    target: target.clone(),
+                });
+            }
+        }
+
+        for (zone_name, zone) in &self.zones {
+            zone.validate_zone(root, apex, Some(self), zone_name, out);
+        }
+    }
+
+    /// enforces that a CNAME is alone at its name (RFC 1034 section 3.6.2) in this zone and its subzones, like
+    /// updates do, by dropping the other records there. returns the dropped records
+    pub fn drop_cname_conflicts(&mut self) -> Vec<Record> {
+        let cnames: HashSet<Name> = self
+            .records
+            .iter()
+            .filter(|x| x.type_ == Type::CNAME)
+            .map(|x| x.name.clone())
+            .collect();
+        let mut dropped = vec![];
+        if !cnames.is_empty() {
+            let (kept, conflicting) = std::mem::take(&mut self.records)
+                .into_iter()
+                .partition(|x| may_accompany_cname(x.type_) || !cnames.contains(&x.name));
+            self.records = kept;
+            dropped = conflicting;
+        }
+        if !dropped.is_empty() {
+            self.index = None;
+        }
+        for zone in self.zones.values_mut() {
+            dropped.extend(zone.drop_cname_conflicts());
+        }
+        dropped
+    }
+
+    /// whether the deepest subzone holding `name` is authoritative, so a missing record there is really missing.
+    /// `None` if no subzone holds `name`
+    fn is_served(&self, name: &Name) -> Option<bool> {
+        let (_, zone) = self
+            .zones
+            .iter()
+            .filter(|(zone_name, _)| name.ends_with(zone_name))
+            .max_by_key(|(zone_name, _)| zone_name.len())?;
+        Some(zone.is_served(name).unwrap_or(zone.authoritative))
+    }
+
+    fn has_address(&self, name: &Name) -> bool {
+        self.lookup(name, None)
+            .any(|x| matches!(x.type_, Type::A | Type::AAAA))
+    }
+}
+
+/// DNSSEC records at a CNAME are allowed (RFC 4035 section 2.5)
+fn may_accompany_cname(type_: Type) -> bool {
+    matches!(type_, Type::CNAME | Type::RRSIG | Type::NSEC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut zone = Zone::default();
+        let valid = Zone::parse_masterfile(
+            "
+$TTL 300
+$ORIGIN example.com.
+@       SOA ns1 hostmaster 1 3600 600 86400 300
+@       NS ns1
+@       MX 10 mail
+@       MX 20 missing
+_sip._tcp SRV 0 0 5060 .
+ns1     A 192.0.2.1
+mail    A 192.0.2.2
+www     CNAME example.com.
+ftp     CNAME example.com.
+ftp     TXT \"files\"
+",
+            "example.com".parse().unwrap(),
+        )
+        .unwrap();
+        zone.zones.insert("example.com".parse().unwrap(), valid);
+        zone.zones.insert(
+            "example.net".parse().unwrap(),
+            Zone {
+                authoritative: true,
+                records: vec![],
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(
+            zone.validate(),
+            vec![
+                ZoneValidationError::DanglingTarget {
+                    name: "example.com".parse().unwrap(),
+                    type_: Type::MX,
+                    target: "missing.example.com".parse().unwrap(),
+                },
+                ZoneValidationError::CnameAndOtherData("ftp.example.com".parse().unwrap()),
+                ZoneValidationError::MissingSoa("example.net".parse().unwrap()),
+                ZoneValidationError::MissingNameservers("example.net".parse().unwrap()),
+            ]
+        );
+
+        let dropped = zone.drop_cname_conflicts();
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(dropped[0].type_, Type::TXT);
+        let ftp: Name = "ftp.example.com".parse().unwrap();
+        let left: Vec<Type> = zone.lookup(&ftp, None).map(|x| x.type_).collect();
+        assert_eq!(left, vec![Type::CNAME]);
+        assert_eq!(zone.validate().len(), 3);
+    }
+
+    #[test]
+    fn test_validate_apex() {
+        let zone = Zone::parse_masterfile(
+            "
+$TTL 300
+@       MX 10 mail
+www     CNAME mail
+www     A 192.0.2.1
+",
+            Name::default(),
+        )
+        .unwrap();
+        // the wrapping root zone is not checked for an SOA
+        assert_eq!(
+            zone.validate(),
+            vec![ZoneValidationError::CnameAndOtherData(
+                "www".parse().unwrap()
+            )]
+        );
+        assert_eq!(
+            zone.validate_apex(&Name::default()),
+            vec![
+                ZoneValidationError::MissingSoa(Name::default()),
+                ZoneValidationError::MissingNameservers(Name::default()),
+                ZoneValidationError::DanglingTarget {
+                    name: Name::default(),
+                    type_: Type::MX,
+                    target: "mail".parse().unwrap(),
+                },
+                ZoneValidationError::CnameAndOtherData("www".parse().unwrap()),
+            ]
+        );
+    }
+}